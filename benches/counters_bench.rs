@@ -0,0 +1,60 @@
+#![feature(test)]
+//! The cost of the engine's counters (see `engine::Cnt`).  The
+//! benchmarks below exercise the paths where the engine counts
+//! (creating, evaluating, dirtying and cleaning thunks); compare
+//! their times with and without the `counters` feature:
+//!
+//! ```text
+//! cargo bench --bench counters_bench
+//! cargo bench --bench counters_bench --no-default-features
+//! ```
+#[macro_use]
+extern crate adapton;
+extern crate test;
+use self::test::Bencher;
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::engine::manage::*;
+use std::rc::Rc;
+
+const CHAIN_LEN:usize = 100;
+
+/// A chain of `CHAIN_LEN` thunks, each adding one to its predecessor.
+fn chain(start:&Art<usize>) -> Art<usize> {
+    let mut t = start.clone();
+    for i in 0..CHAIN_LEN {
+        t = thunk(NameChoice::Nominal(name_of_usize(i)), prog_pt!("link"),
+                  Rc::new(Box::new(|t:Art<usize>, ()| force(&t) + 1)), t, ());
+    }
+    t
+}
+
+#[bench]
+fn benchmark_dcg_chain_change(b: &mut Bencher) {
+    init_dcg();
+    let c = cell(name_of_str("c"), 0);
+    let t = chain(&c);
+    let mut i = 0;
+    b.iter(|| { i += 1; set(&c, i); test::black_box(force(&t)) })
+}
+
+#[bench]
+fn benchmark_dcg_chain_clean(b: &mut Bencher) {
+    init_dcg();
+    let c = cell(name_of_str("c"), 0);
+    // Each change stops at this thunk, so the engine cleans the chain.
+    let zero = thunk(NameChoice::Nominal(name_of_str("zero")), prog_pt!("zero"),
+                     Rc::new(Box::new(|c:Art<usize>, ()| { let _ = force(&c); 0 })), c.clone(), ());
+    let t = chain(&zero);
+    let mut i = 0;
+    b.iter(|| { i += 1; set(&c, i); test::black_box(force(&t)) })
+}
+
+#[bench]
+fn benchmark_dcg_chain_create(b: &mut Bencher) {
+    b.iter(|| {
+        init_dcg();
+        let c = cell(name_of_str("c"), 0);
+        test::black_box(force(&chain(&c)))
+    })
+}
@@ -0,0 +1,55 @@
+#![feature(test)]
+//! Dirtying and cleaning the edges of a node with a high fanout: a
+//! thunk that observes `FANOUT` thunks, each of which observes an
+//! input cell.  The engine finds each such edge by its index (see
+//! `SuccIndex`), so that these benchmarks take time linear in
+//! `FANOUT`, not quadratic:
+//!
+//! ```text
+//! cargo bench --bench fanout_bench
+//! ```
+#[macro_use]
+extern crate adapton;
+extern crate test;
+use self::test::Bencher;
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::engine::manage::*;
+use std::rc::Rc;
+
+const FANOUT:usize = 50_000;
+
+/// The input cells, and the thunk that sums (the thunks over) them;
+/// with `zero`, each thunk over a cell ignores its value.
+fn fanout(zero:bool) -> (Vec<Art<usize>>, Art<usize>) {
+    let cells : Vec<Art<usize>> = (0..FANOUT).map(|i| cell(name_of_usize(i), i)).collect();
+    let obs : Vec<Art<usize>> = cells.iter().enumerate().map(|(i, c)| {
+        thunk(NameChoice::Nominal(name_pair(name_of_str("obs"), name_of_usize(i))), prog_pt!("obs"),
+              Rc::new(Box::new(|c:Art<usize>, zero:bool| if zero { let _ = force(&c); 0 } else { force(&c) })),
+              c.clone(), zero)
+    }).collect();
+    let sum = thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+                    Rc::new(Box::new(|obs:Rc<Vec<Art<usize>>>, ()| obs.iter().map(|t| force(t)).sum::<usize>())),
+                    Rc::new(obs), ());
+    (cells, sum)
+}
+
+#[bench]
+fn benchmark_fanout_change(b: &mut Bencher) {
+    init_dcg();
+    let (cells, sum) = fanout(false);
+    force(&sum);
+    let mut i = 0;
+    b.iter(|| { i += 1; set(&cells[i % FANOUT], FANOUT + i); test::black_box(force(&sum)) })
+}
+
+#[bench]
+fn benchmark_fanout_clean(b: &mut Bencher) {
+    init_dcg();
+    // Each change stops at a thunk over a cell, so the engine cleans
+    // (each edge of) the sum.
+    let (cells, sum) = fanout(true);
+    force(&sum);
+    let mut i = 0;
+    b.iter(|| { i += 1; set(&cells[i % FANOUT], FANOUT + i); test::black_box(force(&sum)) })
+}
@@ -0,0 +1,57 @@
+/*! The stable programming interface, in one place.
+
+This module re-exports the parts of Adapton that downstream code
+should rely on: the core engine interface (arts, names, and the
+functions that introduce and eliminate them), the engine's management
+functions, program points, and preludes for the common collections.
+Importing from here, rather than from the modules that define these
+items, keeps user code working across reorganizations of those
+modules.  The helper macros (e.g., `thunk!`, `cell!` and `prog_pt!`)
+belong to this stable surface, too; as macros, they are exported at
+the root of the crate (via `#[macro_use] extern crate adapton`).
+
+**Stability**: Within a minor version series (e.g., `0.3.x`), items
+are only added to this module; removing or changing an item here is
+a breaking change, and bumps the minor version.  Items elsewhere in
+the crate (e.g., the engine's tuning flags, and the experimental
+parts of `catalog`) carry no such guarantee.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use std::rc::Rc;
+use adapton::api::*;
+manage::init_dcg();
+
+// Cells are the mutable arts, and thunks are the computed ones.
+let c : Art<usize> = cell(name_of_str("c"), 1);
+let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+assert_eq!(force(&t), 2);
+set(&c, 2);
+assert_eq!(force(&t), 3);
+# }
+```
+*/
+
+pub use engine::{
+    // Arts, and their introduction and elimination
+    Art, put, cell, thunk, force, force_map, set, update, demand, read,
+    art_map, art_zip,
+    // Names, and naming choices
+    Name, NameChoice, ns, ns_iter, structural,
+    name_unit, name_pair, name_fork, name_of_str, name_of_string,
+    name_of_usize, name_of_isize, name_of_hash64,
+    // Engines, and their management
+    Engine, manage,
+};
+pub use macros::ProgPt;
+
+/// A prelude for the collections of `catalog::collections`: lists and
+/// trees, and their (generic) introduction and elimination forms.
+pub mod collections {
+    pub use catalog::collections::{
+        List, ListIntro, ListElim, list_of_vec, vec_of_list,
+        Tree, TreeIntro, TreeElim, NameElse,
+        MapIntro, MapElim, SetIntro, SetElim,
+    };
+}
@@ -0,0 +1,395 @@
+/*! Articulate arbitrary `Serialize` data as nominal, incremental structure.
+
+The function `articulate` walks a value through its `serde`
+serialization and builds an `Artic` tree: Scalars are stored
+directly, and each container (sequence, tuple, map, struct and enum
+payload) becomes a nominal `cell`.  The cells are named by their
+_field paths_ from a given root name; for instance, field `b` of
+element `3` of field `a` of the root `doc` is named by the pair of
+names `(((doc, a), 3), b)`.
+
+Because cell names are derived from paths, articulating an updated
+value with the same root name re-allocates the very same cells: only
+the containers whose content actually changed are updated, and only
+their observers are dirtied.  Hence, existing Rust data models can be
+incrementalized without rewriting them around `Art`s by hand.
+
+The function `read_back` forces an `Artic` tree (recording the usual
+observations) and reconstructs the plain value via `Deserialize`.
+
+```
+# #[macro_use] extern crate adapton;
+# extern crate serde;
+# fn main() {
+use adapton::engine::*;
+use adapton::articulate::*;
+manage::init_dcg();
+
+let v : Vec<(String,usize)> = vec![("a".to_string(), 1), ("b".to_string(), 2)];
+let a = articulate(name_of_str("v"), &v).unwrap();
+let w : Vec<(String,usize)> = read_back(&a).unwrap();
+assert_eq!(v, w);
+# }
+```
+
+**Naming caveat:** Sequence elements and map entries are named by
+their position.  Hence, insertions near the front of a sequence
+re-name (and thus, update) every later element; and maps whose
+iteration order is not deterministic (e.g., `HashMap`) should be
+avoided in favor of ordered maps (e.g., `BTreeMap`).
+*/
+
+use std::fmt;
+use std::error;
+
+use serde::ser::{self, Serialize};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+
+use engine::*;
+
+/// Articulated representation of a serialized value.  Containers are
+/// nominal `Art`s, named by their field paths; scalars are stored
+/// directly.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub enum Artic {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Nat(u64),
+    /// Bit pattern of an `f64`, so that `Artic` admits `Eq` and `Hash`.
+    Float(u64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Artic>),
+    /// Sequences, tuples and tuple structs.
+    Seq(Art<Vec<Artic>>),
+    /// Maps, as a sequence of key-value pairs.
+    Map(Art<Vec<(Artic,Artic)>>),
+    /// Structs, as a sequence of field-value pairs.
+    Struct(Art<Vec<(String,Artic)>>),
+    /// An enum variant, and its (possibly unit) payload.
+    Variant(String,Box<Artic>),
+}
+
+/// Errors from articulating or reading back a value.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "articulate: {}", self.0) }
+}
+impl error::Error for Error {
+    fn description(&self) -> &str { &self.0 }
+}
+impl ser::Error for Error {
+    fn custom<T:fmt::Display>(msg:T) -> Self { Error(msg.to_string()) }
+}
+impl de::Error for Error {
+    fn custom<T:fmt::Display>(msg:T) -> Self { Error(msg.to_string()) }
+}
+
+/// Articulate `val`, allocating its containers as cells named by
+/// their field paths, relative to root name `n`.
+pub fn articulate<T:Serialize+?Sized>(n:Name, val:&T) -> Result<Artic, Error> {
+    val.serialize(Articulator{name:n})
+}
+
+/// Force the articulation `a` and reconstruct the plain value that
+/// it represents.
+pub fn read_back<T:DeserializeOwned>(a:&Artic) -> Result<T, Error> {
+    T::deserialize(a)
+}
+
+fn name_of_field(n:&Name, field:&'static str) -> Name {
+    name_pair(n.clone(), name_of_str(field))
+}
+
+fn name_of_index(n:&Name, i:usize) -> Name {
+    name_pair(n.clone(), name_of_usize(i))
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+// Articulation (via `Serializer`)
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+/// A `Serializer` that produces an `Artic`; `name` is the field path
+/// of the value being serialized.
+pub struct Articulator {
+    name:Name,
+}
+
+/// Articulates sequences, tuples, tuple structs and tuple variants.
+pub struct SeqArticulator {
+    name:Name,
+    variant:Option<&'static str>,
+    elms:Vec<Artic>,
+}
+
+/// Articulates maps.
+pub struct MapArticulator {
+    name:Name,
+    key:Option<Artic>,
+    entries:Vec<(Artic,Artic)>,
+}
+
+/// Articulates structs and struct variants.
+pub struct StructArticulator {
+    name:Name,
+    variant:Option<&'static str>,
+    fields:Vec<(String,Artic)>,
+}
+
+fn wrap_variant(variant:Option<&'static str>, a:Artic) -> Artic {
+    match variant {
+        None => a,
+        Some(v) => Artic::Variant(v.to_string(), Box::new(a)),
+    }
+}
+
+impl ser::Serializer for Articulator {
+    type Ok = Artic;
+    type Error = Error;
+    type SerializeSeq = SeqArticulator;
+    type SerializeTuple = SeqArticulator;
+    type SerializeTupleStruct = SeqArticulator;
+    type SerializeTupleVariant = SeqArticulator;
+    type SerializeMap = MapArticulator;
+    type SerializeStruct = StructArticulator;
+    type SerializeStructVariant = StructArticulator;
+
+    fn serialize_bool(self, v:bool) -> Result<Artic, Error> { Ok(Artic::Bool(v)) }
+    fn serialize_i8 (self, v:i8)  -> Result<Artic, Error> { Ok(Artic::Int(v as i64)) }
+    fn serialize_i16(self, v:i16) -> Result<Artic, Error> { Ok(Artic::Int(v as i64)) }
+    fn serialize_i32(self, v:i32) -> Result<Artic, Error> { Ok(Artic::Int(v as i64)) }
+    fn serialize_i64(self, v:i64) -> Result<Artic, Error> { Ok(Artic::Int(v)) }
+    fn serialize_u8 (self, v:u8)  -> Result<Artic, Error> { Ok(Artic::Nat(v as u64)) }
+    fn serialize_u16(self, v:u16) -> Result<Artic, Error> { Ok(Artic::Nat(v as u64)) }
+    fn serialize_u32(self, v:u32) -> Result<Artic, Error> { Ok(Artic::Nat(v as u64)) }
+    fn serialize_u64(self, v:u64) -> Result<Artic, Error> { Ok(Artic::Nat(v)) }
+    fn serialize_f32(self, v:f32) -> Result<Artic, Error> { Ok(Artic::Float((v as f64).to_bits())) }
+    fn serialize_f64(self, v:f64) -> Result<Artic, Error> { Ok(Artic::Float(v.to_bits())) }
+    fn serialize_char(self, v:char) -> Result<Artic, Error> { Ok(Artic::Char(v)) }
+    fn serialize_str(self, v:&str) -> Result<Artic, Error> { Ok(Artic::String(v.to_string())) }
+    fn serialize_bytes(self, v:&[u8]) -> Result<Artic, Error> { Ok(Artic::Bytes(v.to_vec())) }
+    fn serialize_none(self) -> Result<Artic, Error> { Ok(Artic::None) }
+    fn serialize_some<T:Serialize+?Sized>(self, v:&T) -> Result<Artic, Error> {
+        Ok(Artic::Some(Box::new(v.serialize(self)?)))
+    }
+    fn serialize_unit(self) -> Result<Artic, Error> { Ok(Artic::Unit) }
+    fn serialize_unit_struct(self, _name:&'static str) -> Result<Artic, Error> { Ok(Artic::Unit) }
+    fn serialize_unit_variant(self, _name:&'static str, _idx:u32, variant:&'static str) -> Result<Artic, Error> {
+        Ok(Artic::Variant(variant.to_string(), Box::new(Artic::Unit)))
+    }
+    fn serialize_newtype_struct<T:Serialize+?Sized>(self, _name:&'static str, v:&T) -> Result<Artic, Error> {
+        v.serialize(self)
+    }
+    fn serialize_newtype_variant<T:Serialize+?Sized>
+        (self, _name:&'static str, _idx:u32, variant:&'static str, v:&T) -> Result<Artic, Error>
+    {
+        let payload = v.serialize(Articulator{name:name_of_field(&self.name, variant)})?;
+        Ok(Artic::Variant(variant.to_string(), Box::new(payload)))
+    }
+    fn serialize_seq(self, len:Option<usize>) -> Result<SeqArticulator, Error> {
+        Ok(SeqArticulator{name:self.name, variant:None, elms:Vec::with_capacity(len.unwrap_or(0))})
+    }
+    fn serialize_tuple(self, len:usize) -> Result<SeqArticulator, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name:&'static str, len:usize) -> Result<SeqArticulator, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant
+        (self, _name:&'static str, _idx:u32, variant:&'static str, len:usize) -> Result<SeqArticulator, Error>
+    {
+        Ok(SeqArticulator{name:name_of_field(&self.name, variant), variant:Some(variant), elms:Vec::with_capacity(len)})
+    }
+    fn serialize_map(self, len:Option<usize>) -> Result<MapArticulator, Error> {
+        Ok(MapArticulator{name:self.name, key:None, entries:Vec::with_capacity(len.unwrap_or(0))})
+    }
+    fn serialize_struct(self, _name:&'static str, len:usize) -> Result<StructArticulator, Error> {
+        Ok(StructArticulator{name:self.name, variant:None, fields:Vec::with_capacity(len)})
+    }
+    fn serialize_struct_variant
+        (self, _name:&'static str, _idx:u32, variant:&'static str, len:usize) -> Result<StructArticulator, Error>
+    {
+        Ok(StructArticulator{name:name_of_field(&self.name, variant), variant:Some(variant), fields:Vec::with_capacity(len)})
+    }
+}
+
+impl SeqArticulator {
+    fn push<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> {
+        let n = name_of_index(&self.name, self.elms.len());
+        let a = v.serialize(Articulator{name:n})?;
+        self.elms.push(a);
+        Ok(())
+    }
+    fn finish(self) -> Result<Artic, Error> {
+        let variant = self.variant;
+        Ok(wrap_variant(variant, Artic::Seq(cell(self.name, self.elms))))
+    }
+}
+
+impl ser::SerializeSeq for SeqArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_element<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> { self.push(v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+impl ser::SerializeTuple for SeqArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_element<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> { self.push(v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+impl ser::SerializeTupleStruct for SeqArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_field<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> { self.push(v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+impl ser::SerializeTupleVariant for SeqArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_field<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> { self.push(v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+
+impl ser::SerializeMap for MapArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_key<T:Serialize+?Sized>(&mut self, k:&T) -> Result<(), Error> {
+        let (n, _) = name_fork(name_of_index(&self.name, self.entries.len()));
+        self.key = Some(k.serialize(Articulator{name:n})?);
+        Ok(())
+    }
+    fn serialize_value<T:Serialize+?Sized>(&mut self, v:&T) -> Result<(), Error> {
+        let (_, n) = name_fork(name_of_index(&self.name, self.entries.len()));
+        let v = v.serialize(Articulator{name:n})?;
+        match self.key.take() {
+            None => Err(Error("map value without a key".to_string())),
+            Some(k) => { self.entries.push((k, v)); Ok(()) }
+        }
+    }
+    fn end(self) -> Result<Artic, Error> {
+        Ok(Artic::Map(cell(self.name, self.entries)))
+    }
+}
+
+impl StructArticulator {
+    fn push<T:Serialize+?Sized>(&mut self, key:&'static str, v:&T) -> Result<(), Error> {
+        let a = v.serialize(Articulator{name:name_of_field(&self.name, key)})?;
+        self.fields.push((key.to_string(), a));
+        Ok(())
+    }
+    fn finish(self) -> Result<Artic, Error> {
+        let variant = self.variant;
+        Ok(wrap_variant(variant, Artic::Struct(cell(self.name, self.fields))))
+    }
+}
+
+impl ser::SerializeStruct for StructArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_field<T:Serialize+?Sized>(&mut self, key:&'static str, v:&T) -> Result<(), Error> { self.push(key, v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+impl ser::SerializeStructVariant for StructArticulator {
+    type Ok = Artic; type Error = Error;
+    fn serialize_field<T:Serialize+?Sized>(&mut self, key:&'static str, v:&T) -> Result<(), Error> { self.push(key, v) }
+    fn end(self) -> Result<Artic, Error> { self.finish() }
+}
+
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+// Read back (via `Deserializer`)
+// - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+
+struct SeqReader { elms: ::std::vec::IntoIter<Artic> }
+struct MapReader { entries: ::std::vec::IntoIter<(Artic,Artic)>, value:Option<Artic> }
+struct VariantReader<'a> { variant:&'a str, payload:&'a Artic }
+
+impl<'de, 'a> de::Deserializer<'de> for &'a Artic {
+    type Error = Error;
+
+    fn deserialize_any<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        match *self {
+            Artic::Unit => visitor.visit_unit(),
+            Artic::Bool(b) => visitor.visit_bool(b),
+            Artic::Int(i) => visitor.visit_i64(i),
+            Artic::Nat(n) => visitor.visit_u64(n),
+            Artic::Float(bits) => visitor.visit_f64(f64::from_bits(bits)),
+            Artic::Char(c) => visitor.visit_char(c),
+            Artic::String(ref s) => visitor.visit_str(s),
+            Artic::Bytes(ref b) => visitor.visit_bytes(b),
+            Artic::None => visitor.visit_none(),
+            Artic::Some(ref a) => visitor.visit_some(&**a),
+            Artic::Seq(ref art) =>
+                visitor.visit_seq(SeqReader{elms:force(art).into_iter()}),
+            Artic::Map(ref art) =>
+                visitor.visit_map(MapReader{entries:force(art).into_iter(), value:None}),
+            Artic::Struct(ref art) => {
+                let entries = force(art).into_iter()
+                    .map(|(k,v)| (Artic::String(k), v))
+                    .collect::<Vec<_>>();
+                visitor.visit_map(MapReader{entries:entries.into_iter(), value:None})
+            },
+            Artic::Variant(ref variant, ref payload) =>
+                visitor.visit_enum(VariantReader{variant:variant, payload:&**payload}),
+        }
+    }
+
+    fn deserialize_newtype_struct<V:Visitor<'de>>(self, _name:&'static str, visitor:V) -> Result<V::Value, Error> {
+        // Articulation is transparent for newtype structs.
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqReader {
+    type Error = Error;
+    fn next_element_seed<S:DeserializeSeed<'de>>(&mut self, seed:S) -> Result<Option<S::Value>, Error> {
+        match self.elms.next() {
+            None => Ok(None),
+            Some(a) => seed.deserialize(&a).map(Some),
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapReader {
+    type Error = Error;
+    fn next_key_seed<S:DeserializeSeed<'de>>(&mut self, seed:S) -> Result<Option<S::Value>, Error> {
+        match self.entries.next() {
+            None => Ok(None),
+            Some((k, v)) => { self.value = Some(v); seed.deserialize(&k).map(Some) }
+        }
+    }
+    fn next_value_seed<S:DeserializeSeed<'de>>(&mut self, seed:S) -> Result<S::Value, Error> {
+        match self.value.take() {
+            None => Err(Error("map key without a value".to_string())),
+            Some(v) => seed.deserialize(&v),
+        }
+    }
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for VariantReader<'a> {
+    type Error = Error;
+    type Variant = Self;
+    fn variant_seed<S:DeserializeSeed<'de>>(self, seed:S) -> Result<(S::Value, Self), Error> {
+        let v = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((v, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for VariantReader<'a> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> { Ok(()) }
+    fn newtype_variant_seed<S:DeserializeSeed<'de>>(self, seed:S) -> Result<S::Value, Error> {
+        seed.deserialize(self.payload)
+    }
+    fn tuple_variant<V:Visitor<'de>>(self, _len:usize, visitor:V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.payload, visitor)
+    }
+    fn struct_variant<V:Visitor<'de>>(self, _fields:&'static [&'static str], visitor:V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.payload, visitor)
+    }
+}
@@ -0,0 +1,176 @@
+//! An incremental append-only sequence, and folds over it: e.g., for
+//! log-processing or metrics pipelines, whose inputs only grow.
+//!
+//! An `AppendSeq` holds its items in (reference) cells, named by
+//! their positions.  A `SeqFold` reduces the items with an
+//! associative operation, as a forest of (nominal) thunks: one per
+//! leaf, and one for each aligned block of `2^k` items, which combines
+//! its two halves.  The blocks grow as a binary counter does, so that
+//! after an append, the fold creates (amortized) two thunks, and
+//! re-evaluates only these and the thunk that combines the `O(log n)`
+//! complete blocks of the prefix; it never re-evaluates the other
+//! blocks.
+//!
+//! ```
+//! use adapton::engine::*;
+//! use adapton::catalog::appendseq::*;
+//! manage::init_dcg();
+//!
+//! // The slowest request, in milliseconds, of a growing log.
+//! let mut log = AppendSeq::new(name_of_str("log"));
+//! let mut slowest = SeqFold::new(name_of_str("slowest"),
+//!                                |ms:&usize| *ms,
+//!                                |a, b| if a > b { a } else { b });
+//! for ms in vec![12, 40, 7] { log.push(ms); }
+//! assert_eq!(slowest.get(&log), Some(40));
+//! log.push(55);
+//! assert_eq!(slowest.get(&log), Some(55));
+//! ```
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+
+/// A sequence whose items are pushed at its end, only.
+#[derive(Debug,Clone)]
+pub struct AppendSeq<T> {
+  name:Name,
+  items:Vec<Art<T>>,
+}
+
+impl<T:'static+Hash+Eq+Debug+Clone> AppendSeq<T> {
+  /// An empty sequence; its items have names derived from `name`.
+  pub fn new(name:Name) -> Self {
+    AppendSeq{name:name, items:vec![]}
+  }
+
+  /// Pushes `x` at the end of the sequence; returns its cell.
+  pub fn push(&mut self, x:T) -> Art<T> {
+    let i = self.items.len();
+    let a = cell(name_pair(self.name.clone(), name_of_usize(i)), x);
+    self.items.push(a.clone());
+    a
+  }
+
+  /// The number of items.
+  pub fn len(&self) -> usize { self.items.len() }
+
+  /// Tests if the sequence has no items.
+  pub fn is_empty(&self) -> bool { self.items.is_empty() }
+
+  /// The cell of the `i`th item, if any.
+  pub fn get(&self, i:usize) -> Option<&Art<T>> { self.items.get(i) }
+}
+
+/// A fold of an `AppendSeq`, by a function `leaf` of its items, and
+/// an associative operation `op` on their results.
+pub struct SeqFold<T,R> {
+  name:Name,
+  leaf:Rc<Fn(&T) -> R>,
+  op:Rc<Fn(R, R) -> R>,
+  len:usize,
+  blocks:Vec<(usize, Art<R>)>, // the complete blocks, by decreasing size
+}
+
+impl<T,R:Debug> Debug for SeqFold<T,R> {
+  fn fmt(&self, f:&mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    write!(f, "SeqFold({:?}, {:?})", self.name, self.blocks)
+  }
+}
+
+impl<T:'static+Hash+Eq+Debug+Clone,
+     R:'static+Hash+Eq+Debug+Clone> SeqFold<T,R>
+{
+  /// A fold, whose thunks have names derived from `name`.  Use a
+  /// distinct name for each fold (and sequence).
+  pub fn new<L:'static+Fn(&T) -> R, F:'static+Fn(R, R) -> R>
+    (name:Name, leaf:L, op:F) -> Self
+  {
+    SeqFold{name:name, leaf:Rc::new(leaf), op:Rc::new(op), len:0, blocks:vec![]}
+  }
+
+  /// The thunk of the fold of the items of `seq` (or `None`, if it
+  /// has none), creating the thunks of the items pushed since the
+  /// prior call.
+  pub fn art(&mut self, seq:&AppendSeq<T>) -> Option<Art<R>> {
+    assert!(self.len <= seq.len(), "SeqFold: the sequence shrank");
+    while self.len < seq.len() {
+      let i = self.len;
+      let mut block = (1, self.leaf_thunk(i, seq.items[i].clone()));
+      while self.blocks.last().map(|b| b.0) == Some(block.0) {
+        let (size, left) = self.blocks.pop().unwrap();
+        block = (2 * size, self.block_thunk(i + 1 - 2 * size, 2 * size, left, block.1));
+      }
+      self.blocks.push(block);
+      self.len += 1;
+    }
+    if self.blocks.is_empty() { return None };
+    let blocks : Vec<Art<R>> = self.blocks.iter().map(|b| b.1.clone()).collect();
+    Some(thunk(NameChoice::Nominal(name_pair(self.name.clone(), name_of_str("prefix"))),
+               prog_pt!("catalog::appendseq::prefix"),
+               Rc::new(Box::new(|blocks:Vec<Art<R>>, op:Rc<Fn(R, R) -> R>| {
+                 let mut blocks = blocks.iter();
+                 let first = force(blocks.next().unwrap());
+                 blocks.fold(first, |acc, b| op(acc, force(b)))
+               })),
+               blocks, self.op.clone()))
+  }
+
+  /// The fold of the items of `seq` (or `None`, if it has none).
+  pub fn get(&mut self, seq:&AppendSeq<T>) -> Option<R> {
+    self.art(seq).map(|a| force(&a))
+  }
+
+  fn leaf_thunk(&self, i:usize, item:Art<T>) -> Art<R> {
+    thunk(NameChoice::Nominal(self.block_name(i, 1)),
+          prog_pt!("catalog::appendseq::leaf"),
+          Rc::new(Box::new(|item:Art<T>, leaf:Rc<Fn(&T) -> R>| leaf(&force(&item)))),
+          item, self.leaf.clone())
+  }
+
+  fn block_thunk(&self, lo:usize, size:usize, left:Art<R>, right:Art<R>) -> Art<R> {
+    thunk(NameChoice::Nominal(self.block_name(lo, size)),
+          prog_pt!("catalog::appendseq::block"),
+          Rc::new(Box::new(|(left, right):(Art<R>, Art<R>), op:Rc<Fn(R, R) -> R>| {
+            op(force(&left), force(&right))
+          })),
+          (left, right), self.op.clone())
+  }
+
+  fn block_name(&self, lo:usize, size:usize) -> Name {
+    name_pair(self.name.clone(), name_pair(name_of_usize(lo), name_of_usize(size)))
+  }
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_appendseq () {
+  manage::init_dcg();
+  let mut seq = AppendSeq::new(name_of_str("seq"));
+  let mut sum = SeqFold::new(name_of_str("sum"), |x:&usize| *x, |a, b| a + b);
+  let mut cat = SeqFold::new(name_of_str("cat"), |x:&usize| vec![*x], |mut a:Vec<usize>, b| { a.extend(b); a });
+  assert_eq!(sum.get(&seq), None);
+  let n = 1024;
+  let mut evals = 0;
+  for i in 1..n + 1 {
+    seq.push(i);
+    manage::reset_counters();
+    assert_eq!(sum.get(&seq), Some(i * (i + 1) / 2));
+    let eval = manage::counters().unwrap().eval;
+    // The new leaf, the blocks that it completes, and the prefix
+    assert_eq!(eval, 2 + i.trailing_zeros() as usize);
+    evals += eval;
+  }
+  // Amortized, each append re-evaluates (at most) three thunks
+  assert!(evals <= 3 * n, "{} evaluations", evals);
+  assert_eq!(cat.get(&seq), Some((1..n + 1).collect()));
+
+  // Changing an item re-evaluates the blocks that contain it
+  manage::reset_counters();
+  set(seq.get(700).unwrap(), 0);
+  assert_eq!(sum.get(&seq), Some(n * (n + 1) / 2 - 701));
+  assert!(manage::counters().unwrap().eval <= 12, "{:?}", manage::counters());
+}
@@ -0,0 +1,316 @@
+//! Incremental bags (multisets), whose updates are deltas: counting
+//! operators in the style of differential dataflow.
+//!
+//! A `Bag` is mutated from the outer layer, in batches of deltas; each
+//! delta `(x, d)` adds `d` copies of `x` to the bag (or removes them,
+//! when `d` is negative).  A collection (a `Coll`) holds its latest
+//! batch, which points to the prior batches, in cells that never
+//! change.  The operators (`map`, `filter`, `distinct`, and the
+//! `aggregate`s `count` and `sum_by_key`) are (structural) thunks, one
+//! for each batch that they consume: each transforms the deltas of its
+//! batch into the deltas of its output, given its state after the
+//! prior batch (e.g., the multiplicity of each element, for
+//! `distinct`).
+//!
+//! Hence, after a batch, re-forcing an operator re-evaluates a few
+//! thunks, whose work is proportional to the size of the batch (plus a
+//! copy of the operator's state), rather than to the size of the bag,
+//! as in the value-recompute model of `relations::group_by`.
+//!
+//! Each operator takes a name, `op`, that identifies it among the
+//! other operators over the same collection; distinct operators need
+//! distinct names.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+
+/// The batches of deltas of a collection, from the latest one.
+pub type Deltas<T> = Art<Batches<T>>;
+
+/// A node of the batches of a collection.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Batches<T> {
+  /// No (prior) batches.
+  Empty,
+  /// A batch of deltas, and the prior batches.
+  Batch(Vec<(T, isize)>, Deltas<T>),
+}
+
+/// A collection: its batches of deltas, which change (from the outer
+/// layer) with each batch.
+pub type Coll<T> = Art<Deltas<T>>;
+
+/// A bag of elements, mutated from the outer layer: its deltas are
+/// pending until the next `commit`, which adds them to its collection
+/// as one batch.
+#[derive(Debug,Clone)]
+pub struct Bag<T:Ord> {
+  batches_ns:Name,
+  root:Coll<T>,
+  len:usize,
+  pending:BTreeMap<T, isize>,
+}
+
+impl<T:'static+Ord+Hash+Eq+Debug+Clone> Bag<T> {
+  /// An empty bag, whose collection is a cell named `name`.
+  pub fn new(name:Name) -> Self {
+    let batches_ns = name_pair(name.clone(), name_of_str("batches"));
+    let empty = cell(name_pair(batches_ns.clone(), name_of_usize(0)), Batches::Empty);
+    Bag{batches_ns:batches_ns, root:cell(name, empty), len:0, pending:BTreeMap::new()}
+  }
+  /// The collection of the bag's batches; it remains the same art as
+  /// the bag changes.
+  pub fn coll(&self) -> Coll<T> { self.root.clone() }
+  /// The number of batches (committed so far).
+  pub fn len(&self) -> usize { self.len }
+
+  /// Adds `d` copies of `x` (or removes them, when `d` is negative).
+  pub fn update(&mut self, x:T, d:isize) {
+    *self.pending.entry(x).or_insert(0) += d
+  }
+  /// Adds one copy of `x`.
+  pub fn insert(&mut self, x:T) { self.update(x, 1) }
+  /// Removes one copy of `x`.
+  pub fn remove(&mut self, x:T) { self.update(x, -1) }
+
+  /// Adds the pending deltas to the collection, as one batch (without
+  /// the deltas that cancel out); returns false, without a batch, if
+  /// there are no such deltas.
+  pub fn commit(&mut self) -> bool {
+    let batch : Vec<_> = ::std::mem::replace(&mut self.pending, BTreeMap::new())
+      .into_iter().filter(|&(_, d)| d != 0).collect();
+    if batch.is_empty() { return false };
+    self.len += 1;
+    let prior = force(&self.root);
+    let node = cell(name_pair(self.batches_ns.clone(), name_of_usize(self.len)),
+                    Batches::Batch(batch, prior));
+    set(&self.root, node);
+    true
+  }
+}
+
+/// The elements of the collection (forcing it), with their
+/// multiplicities, when not zero.
+pub fn elems<T:'static+Ord+Hash+Eq+Debug+Clone>(coll:&Coll<T>) -> BTreeMap<T, isize> {
+  let mut out = BTreeMap::new();
+  let mut node = force(coll);
+  while let Batches::Batch(batch, prior) = force(&node) {
+    for (x, d) in batch { *out.entry(x).or_insert(0) += d }
+    node = prior
+  }
+  out.into_iter().filter(|&(_, d)| d != 0).collect()
+}
+
+/// The state of an operator after a batch, and the deltas of its output
+/// for that batch.
+type Step<S, U> = (S, Vec<(U, isize)>);
+
+/// The collection of the outputs of `step`, which transforms each batch
+/// of `coll` into a batch of deltas, updating its state (from
+/// `S::default()`); the common core of the operators.
+fn fold<T:'static+Ord+Hash+Eq+Debug+Clone,
+        S:'static+Default+Hash+Eq+Debug+Clone,
+        U:'static+Ord+Hash+Eq+Debug+Clone,
+        F:'static+Fn(&mut S, &[(T, isize)]) -> Vec<(U, isize)>>
+  (op:Name, coll:&Coll<T>, step:F) -> Coll<U>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::bags::fold"),
+        Rc::new(Box::new(|(op, coll):(Name, Coll<T>), step:Rc<F>| {
+          fold_out(op, force(&coll), step)
+        })),
+        (op, coll.clone()), Rc::new(step))
+}
+
+/// The output batches of `step`, from the batch `node`.
+fn fold_out<T:'static+Ord+Hash+Eq+Debug+Clone,
+            S:'static+Default+Hash+Eq+Debug+Clone,
+            U:'static+Ord+Hash+Eq+Debug+Clone,
+            F:'static+Fn(&mut S, &[(T, isize)]) -> Vec<(U, isize)>>
+  (op:Name, node:Deltas<T>, step:Rc<F>) -> Deltas<U>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::bags::fold_out"),
+        Rc::new(Box::new(|(op, node):(Name, Deltas<T>), step:Rc<F>| {
+          match force(&node) {
+            Batches::Empty => Batches::Empty,
+            Batches::Batch(_, prior) => {
+              let (_, out) = force(&fold_step(op.clone(), node, step.clone()));
+              Batches::Batch(out, fold_out(op, prior, step))
+            }
+          }
+        })),
+        (op, node), step)
+}
+
+/// The state and output of `step`, after the batch `node`.
+fn fold_step<T:'static+Ord+Hash+Eq+Debug+Clone,
+             S:'static+Default+Hash+Eq+Debug+Clone,
+             U:'static+Ord+Hash+Eq+Debug+Clone,
+             F:'static+Fn(&mut S, &[(T, isize)]) -> Vec<(U, isize)>>
+  (op:Name, node:Deltas<T>, step:Rc<F>) -> Art<Step<S, U>>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::bags::fold_step"),
+        Rc::new(Box::new(|(op, node):(Name, Deltas<T>), step:Rc<F>| {
+          match force(&node) {
+            Batches::Empty => (S::default(), vec![]),
+            Batches::Batch(batch, prior) => {
+              let (mut state, _) = force(&fold_step(op, prior, step.clone()));
+              let out = step(&mut state, &batch);
+              (state, out)
+            }
+          }
+        })),
+        (op, node), step)
+}
+
+/// The collection of the elements `f(x)`, for the elements `x` of
+/// `coll` (with their multiplicities).
+pub fn map<T:'static+Ord+Hash+Eq+Debug+Clone,
+           U:'static+Ord+Hash+Eq+Debug+Clone,
+           F:'static+Fn(&T) -> U>
+  (op:Name, coll:&Coll<T>, f:F) -> Coll<U>
+{
+  fold(op, coll, move |_:&mut (), batch:&[(T, isize)]| {
+    batch.iter().map(|&(ref x, d)| (f(x), d)).collect()
+  })
+}
+
+/// The collection of the elements of `coll` that satisfy `pred`.
+pub fn filter<T:'static+Ord+Hash+Eq+Debug+Clone,
+              F:'static+Fn(&T) -> bool>
+  (op:Name, coll:&Coll<T>, pred:F) -> Coll<T>
+{
+  fold(op, coll, move |_:&mut (), batch:&[(T, isize)]| {
+    batch.iter().filter(|&&(ref x, _)| pred(x)).cloned().collect()
+  })
+}
+
+/// The collection of the elements of `coll` whose multiplicity is
+/// positive, once each.  Its deltas are the elements whose multiplicity
+/// becomes positive (`+1`), or ceases to be (`-1`).
+pub fn distinct<T:'static+Ord+Hash+Eq+Debug+Clone>
+  (op:Name, coll:&Coll<T>) -> Coll<T>
+{
+  fold(op, coll, |counts:&mut BTreeMap<T, isize>, batch:&[(T, isize)]| {
+    let mut out = vec![];
+    for &(ref x, d) in batch {
+      let (old, new) = bump(counts, x, d);
+      if old <= 0 && new > 0 { out.push((x.clone(), 1)) };
+      if old > 0 && new <= 0 { out.push((x.clone(), -1)) };
+    }
+    out
+  })
+}
+
+/// The collection of the sums of `val` (weighed by multiplicity) for
+/// each key of the elements of `coll`, as pairs of a key and its sum,
+/// when not zero.  Its deltas retract the prior sum of each key of the
+/// batch (`-1`), and assert its new one (`+1`).
+pub fn aggregate<T:'static+Ord+Hash+Eq+Debug+Clone,
+                 K:'static+Ord+Hash+Eq+Debug+Clone,
+                 FK:'static+Fn(&T) -> K,
+                 FV:'static+Fn(&T) -> isize>
+  (op:Name, coll:&Coll<T>, key:FK, val:FV) -> Coll<(K, isize)>
+{
+  fold(op, coll, move |sums:&mut BTreeMap<K, isize>, batch:&[(T, isize)]| {
+    // The prior sums of the keys of the batch.
+    let mut olds = BTreeMap::new();
+    for &(ref x, d) in batch {
+      let k = key(x);
+      let (old, _) = bump(sums, &k, val(x) * d);
+      olds.entry(k).or_insert(old);
+    }
+    let mut out = vec![];
+    for (k, old) in olds {
+      let new = sums.get(&k).cloned().unwrap_or(0);
+      if old == new { continue };
+      if old != 0 { out.push(((k.clone(), old), -1)) };
+      if new != 0 { out.push(((k, new), 1)) };
+    }
+    out
+  })
+}
+
+/// The collection of the elements of `coll`, with their
+/// multiplicities, when not zero (see `aggregate`).
+pub fn count<T:'static+Ord+Hash+Eq+Debug+Clone>
+  (op:Name, coll:&Coll<T>) -> Coll<(T, isize)>
+{
+  aggregate(op, coll, |x| x.clone(), |_| 1)
+}
+
+/// The collection of the sums of the values of each key of the pairs
+/// of `coll`, when not zero (see `aggregate`).
+pub fn sum_by_key<K:'static+Ord+Hash+Eq+Debug+Clone>
+  (op:Name, coll:&Coll<(K, isize)>) -> Coll<(K, isize)>
+{
+  aggregate(op, coll, |&(ref k, _)| k.clone(), |&(_, v)| v)
+}
+
+/// Adds `d` to the entry of `k` (removing it when zero); returns its
+/// prior and new values.
+fn bump<K:Ord+Clone>(m:&mut BTreeMap<K, isize>, k:&K, d:isize) -> (isize, isize) {
+  let old = m.get(k).cloned().unwrap_or(0);
+  let new = old + d;
+  if new == 0 { m.remove(k); } else { m.insert(k.clone(), new); };
+  (old, new)
+}
+
+#[test]
+fn test_bags () {
+  manage::init_dcg();
+  // Page views, as (user, page) pairs.
+  let mut views : Bag<(usize, usize)> = Bag::new(name_of_str("views"));
+  for u in 0..32 { for p in 0..4 { views.insert((u, (u + p) % 8)) } }
+  assert!(views.commit());
+  assert!(! views.commit());
+
+  let pages = map(name_of_str("pages"), &views.coll(), |v| v.1);
+  let distinct_pages = distinct(name_of_str("distinct"), &pages);
+  let hits = count(name_of_str("hits"), &pages);
+  let weights = map(name_of_str("weights"), &views.coll(), |v| (v.1, v.0 as isize));
+  let weight = sum_by_key(name_of_str("weight"), &weights);
+  let low = filter(name_of_str("low"), &pages, |p| *p < 4);
+
+  assert_eq!(elems(&distinct_pages), (0..8).map(|p| (p, 1)).collect());
+  assert_eq!(elems(&hits), (0..8).map(|p| ((p, 16), 1)).collect());
+  let total = |p:usize| (0..32).filter(|u| (0..4).any(|i| (u + i) % 8 == p)).sum::<usize>() as isize;
+  assert_eq!(elems(&weight), (0..8).map(|p| ((p, total(p)), 1)).collect());
+  assert_eq!(elems(&low), (0..4).map(|p| (p, 16)).collect());
+
+  // A batch re-evaluates a few thunks per operator, regardless of the
+  // size of the bag.
+  manage::reset_counters();
+  views.insert((100, 9));
+  views.remove((0, 0));
+  assert!(views.commit());
+  let latest = |coll:&Coll<(usize, isize)>| match force(&force(coll)) {
+    Batches::Batch(batch, _) => batch, Batches::Empty => vec![],
+  };
+  assert_eq!(latest(&hits), vec![((0, 16), -1), ((0, 15), 1), ((9, 1), 1)]);
+  assert_eq!(latest(&weight), vec![((9, 100), 1)]);
+  match force(&force(&distinct_pages)) {
+    Batches::Batch(batch, _) => assert_eq!(batch, vec![(9, 1)]),
+    Batches::Empty => unreachable!(),
+  };
+  // (Three thunks per batch of each of the six operators, at most.)
+  assert!(manage::counters().unwrap().eval <= 3 * 6, "{:?}", manage::counters());
+
+  // Removals that cancel out the element; and batches that cancel out.
+  views.remove((100, 9));
+  assert!(views.commit());
+  assert_eq!(elems(&distinct_pages), (0..8).map(|p| (p, 1)).collect());
+  assert_eq!(elems(&hits)[&(0, 15)], 1);
+  assert!(! elems(&hits).contains_key(&(9, 1)));
+  views.insert((1, 1));
+  views.remove((1, 1));
+  assert!(! views.commit());
+  assert_eq!(views.len(), 3);
+}
@@ -0,0 +1,252 @@
+//! Change sets: The minimal cell edits between an articulated, keyed
+//! list and a new (plain) snapshot of its items.
+//!
+//! Applications that poll an external data source (a database, a
+//! feed, a directory listing) receive each version as a plain
+//! snapshot; re-articulating the entire snapshot would re-write every
+//! cell of the list.  Instead, a _keyed list_ holds each item in a
+//! cell named by the item's key (see `keyed_list`), and `diff`
+//! compares such a list with a snapshot, giving the `ChangeSet` of the
+//! items inserted, updated and removed, and of the cells whose content
+//! changes as a result: the cells of the updated and inserted items,
+//! and the cells that precede an insertion or a removal.
+//! `ChangeSet::apply` writes these cells only, so that the observers
+//! of the other cells stay clean.
+//!
+//! ```
+//! use adapton::engine::*;
+//! use adapton::catalog::collections::List;
+//! use adapton::catalog::patch::elems_of_list;
+//! use adapton::catalog::changeset::*;
+//! manage::init_dcg();
+//!
+//! let key = |x:&(usize, &'static str)| name_of_usize(x.0);
+//! let rows : List<(usize, &'static str)> =
+//!   keyed_list(name_of_str("rows"), &[(1, "a"), (2, "b"), (3, "c")], &key);
+//!
+//! let cs = diff(rows, &[(1, "a"), (2, "B"), (4, "d")], &key);
+//! assert_eq!(cs.changes(), &[Change::Remove(name_of_usize(3)),
+//!                            Change::Update(name_of_usize(2), (2, "B")),
+//!                            Change::Insert(name_of_usize(4), (4, "d"))]);
+//! // The cells of items 2 and 4; item 1's cell keeps its content
+//! assert_eq!(cs.writes(), 2);
+//! let rows = cs.apply();
+//! assert_eq!(elems_of_list(rows), vec![(1, "a"), (2, "B"), (4, "d")]);
+//! ```
+//!
+//! The cells are allocated (and found) in the current namespace (see
+//! `engine::ns`), so `keyed_list`, `diff` and `apply` should run in
+//! the same one.  The cells of removed items remain in the DCG (e.g.,
+//! until `manage::trim_ns` removes them).
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use adapton::engine::* ;
+use catalog::collections::{ListIntro, ListElim, NameElse, list_of_vec, vec_of_list};
+
+/// A change to the items of a keyed list, by key.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub enum Change<X> {
+  /// The item is new.
+  Insert(Name, X),
+  /// The item of the key changed.
+  Update(Name, X),
+  /// The key has no item anymore.
+  Remove(Name),
+}
+
+/// The changes from a keyed list to a snapshot, and the cell writes
+/// that apply them; see `diff`.
+#[derive(Debug,Clone)]
+pub struct ChangeSet<X,L> {
+  root:Name,
+  items:Vec<(Name, X)>,
+  changes:Vec<Change<X>>,
+  writes:Vec<Option<usize>>, // the cells to write, in order: the root (`None`), or the cell of an item
+  list:PhantomData<L>,
+}
+
+/// The keyed list of `items`, named `root`: the cell `root` holds the
+/// list of the items, and each item is in a cell named by its key.
+/// Panics if two items have the same key.
+pub fn keyed_list<X:'static+Clone,
+                  L:ListIntro<X>,
+                  K:Fn(&X) -> Name>
+  (root:Name, items:&[X], key:&K) -> L
+{
+  let keyed = keyed(items, key);
+  let mut v = vec![NameElse::Name(root)];
+  for (k, x) in keyed.into_iter() {
+    v.push(NameElse::Name(k));
+    v.push(NameElse::Else(x));
+  }
+  list_of_vec(&v)
+}
+
+/// The items, with their keys; panics if two have the same key.
+fn keyed<X:Clone,K:Fn(&X) -> Name> (items:&[X], key:&K) -> Vec<(Name, X)> {
+  let mut seen = HashMap::new();
+  items.iter().map(|x| {
+    let k = key(x);
+    if seen.insert(k.clone(), ()).is_some() { panic!("changeset: the key {:?} is not unique", k) };
+    (k, x.clone())
+  }).collect()
+}
+
+/// The root name and the keyed items of a keyed list (see
+/// `keyed_list`); forces the entire list.  Panics if the list is not
+/// keyed.
+fn items_of_list<X:Clone,L:ListElim<X>+'static> (list:L) -> (Name, Vec<(Name, X)>) {
+  let mut v = vec_of_list(list, None).into_iter();
+  let root = match v.next() {
+    Some(NameElse::Name(root)) => root,
+    _ => panic!("changeset: not a keyed list (it has no root name)"),
+  };
+  let mut items = vec![];
+  while let Some(k) = v.next() {
+    match (k, v.next()) {
+      (NameElse::Name(k), Some(NameElse::Else(x))) => items.push((k, x)),
+      _ => panic!("changeset: not a keyed list (an item has no key)"),
+    }
+  }
+  (root, items)
+}
+
+/// The changes from the keyed list `list` (see `keyed_list`) to the
+/// snapshot `items`, whose keys are given by `key`; forces the entire
+/// list.  The changes list the removals, in the order of the list, and
+/// then the insertions and updates, in the order of the snapshot.
+///
+/// Panics if `list` is not keyed, or if two items of the snapshot have
+/// the same key.
+pub fn diff<X:'static+Hash+Eq+Debug+Clone,
+            L:ListIntro<X>+ListElim<X>+'static,
+            K:Fn(&X) -> Name>
+  (list:L, items:&[X], key:&K) -> ChangeSet<X,L>
+{
+  let (root, old) = items_of_list(list);
+  let new = keyed(items, key);
+  let next_key = |items:&Vec<(Name, X)>, i:usize| items.get(i).map(|item| item.0.clone());
+  // The content of each cell (of the old list), by name: the item, if
+  // any (none for the root), and the key of the next item, if any
+  let mut cells : HashMap<Name, (Option<X>, Option<Name>)> = HashMap::new();
+  let mut new_keys : HashMap<Name, ()> = HashMap::new();
+  for k in new.iter() { new_keys.insert(k.0.clone(), ()); }
+  let mut changes = vec![];
+  for (i, &(ref k, ref x)) in old.iter().enumerate() {
+    cells.insert(k.clone(), (Some(x.clone()), next_key(&old, i + 1)));
+    if ! new_keys.contains_key(k) { changes.push(Change::Remove(k.clone())) }
+  }
+  let mut writes = vec![];
+  if next_key(&old, 0) != next_key(&new, 0) { writes.push(None) };
+  for (i, &(ref k, ref x)) in new.iter().enumerate() {
+    let content = (Some(x.clone()), next_key(&new, i + 1));
+    match cells.get(k) {
+      None => changes.push(Change::Insert(k.clone(), x.clone())),
+      Some(&(ref y, _)) if y.as_ref() != Some(x) => changes.push(Change::Update(k.clone(), x.clone())),
+      Some(_) => (),
+    };
+    if cells.get(k) != Some(&content) { writes.push(Some(i)) };
+  }
+  ChangeSet{root:root, items:new, changes:changes, writes:writes, list:PhantomData}
+}
+
+impl<X:'static+Hash+Eq+Debug+Clone,
+     L:ListIntro<X>+ListElim<X>+'static>
+  ChangeSet<X,L>
+{
+  /// The changes to the items, by key.
+  pub fn changes(&self) -> &[Change<X>] { &self.changes }
+
+  /// Tests if the snapshot equals the list.
+  pub fn is_empty(&self) -> bool { self.writes.is_empty() }
+
+  /// The number of cells that `apply` writes.
+  pub fn writes(&self) -> usize { self.writes.len() }
+
+  /// Writes the cells whose content changes, only; returns the keyed
+  /// list of the snapshot (which equals the prior list, since its root
+  /// cell is the same).
+  pub fn apply(self) -> L {
+    let ChangeSet{root, items, writes, ..} = self;
+    let mut written : HashMap<Name, Art<L>> = HashMap::new();
+    let art_of = |written:&HashMap<Name, Art<L>>, i:usize| -> L {
+      match items.get(i) {
+        None => L::nil(),
+        Some(&(ref k, _)) => {
+          let art = match written.get(k) {
+            Some(art) => art.clone(),
+            None => find_cell::<L>(k.clone()).expect("changeset: the cell of an unchanged item is gone"),
+          };
+          L::name(k.clone(), L::art(art))
+        },
+      }
+    };
+    // The next item's cell first, for its art
+    for w in writes.iter().rev() {
+      match *w {
+        Some(i) => {
+          let content = L::cons(items[i].1.clone(), art_of(&written, i + 1));
+          let art = cell(items[i].0.clone(), content);
+          written.insert(items[i].0.clone(), art);
+        },
+        None => { let _ = cell(root.clone(), art_of(&written, 0)); },
+      }
+    }
+    let art = match find_cell::<L>(root.clone()) {
+      Some(art) => art,
+      None => cell(root.clone(), art_of(&written, 0)),
+    };
+    L::name(root, L::art(art))
+  }
+}
+
+#[test]
+fn test_changeset () {
+  use macros::*;
+  use catalog::collections::List;
+  use catalog::patch::elems_of_list;
+  manage::init_dcg();
+  let key = |x:&(usize, usize)| name_of_usize(x.0);
+  let rows : Vec<(usize, usize)> = (0..100).map(|i| (i, i * i)).collect();
+  let list : List<(usize, usize)> = keyed_list(name_of_str("rows"), &rows, &key);
+  let sum = {
+    let list = list.clone();
+    thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+          ::std::rc::Rc::new(Box::new(|list:List<(usize, usize)>, ()| elems_of_list(list).iter().map(|x| x.1).sum::<usize>())),
+          list, ())
+  };
+  assert_eq!(force(&sum), rows.iter().map(|x| x.1).sum());
+
+  // An unchanged snapshot writes nothing
+  let cs = diff(list.clone(), &rows, &key);
+  assert!(cs.is_empty() && cs.changes().is_empty());
+
+  // Updates, an insertion, a removal and a new first item
+  let mut next = rows.clone();
+  next[10].1 = 0;
+  next.insert(50, (1000, 1));
+  next.remove(80);
+  next.insert(0, (2000, 2));
+  let cs = diff(list.clone(), &next, &key);
+  assert_eq!(cs.changes(), &[Change::Remove(name_of_usize(79)),
+                             Change::Insert(name_of_usize(2000), (2000, 2)),
+                             Change::Update(name_of_usize(10), (10, 0)),
+                             Change::Insert(name_of_usize(1000), (1000, 1))]);
+  // The root, the new first item, item 10, items 49 and 1000, and item 78
+  assert_eq!(cs.writes(), 6);
+  let applied = cs.apply();
+  assert_eq!(applied, list);
+  assert_eq!(elems_of_list(applied.clone()), next);
+  assert_eq!(force(&sum), next.iter().map(|x| x.1).sum());
+  assert!(diff(applied, &next, &key).is_empty());
+
+  // Emptying the list
+  let cs = diff(list.clone(), &[], &key);
+  assert_eq!(cs.writes(), 1);
+  assert_eq!(elems_of_list(cs.apply()), vec![]);
+  assert_eq!(force(&sum), 0);
+}
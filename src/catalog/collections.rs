@@ -2,6 +2,7 @@ use std::fmt::Debug;
 use std::hash::{Hash,Hasher};
 use std::collections::hash_map::DefaultHasher;
 use std::rc::Rc;
+use std::marker::PhantomData;
 
 use macros::* ;
 use adapton::engine::* ;
@@ -10,6 +11,18 @@ pub mod trie {
   pub use catalog::trie::*;
 }
 
+pub mod quadtree {
+  pub use catalog::quadtree::*;
+}
+
+pub mod relations {
+  pub use catalog::relations::*;
+}
+
+pub mod bags {
+  pub use catalog::bags::*;
+}
+
 #[derive(Clone,Copy,Hash,Eq,PartialEq,Debug)]
 pub enum Dir2 { Left, Right }
 
@@ -113,6 +126,12 @@ pub trait ListElim<X> : Debug+Clone+Hash+PartialEq+Eq {
                |_,_| true,
                |_,_| false)
   }
+
+  /// Iterates over the elements of the list, using the current
+  /// engine; see `ArtIter`.
+  fn iter (self) -> ArtIter<X,Self> {
+    ArtIter::new(self)
+  }
 }
 
 pub fn list_nil<X:'static, L:ListIntro<X>>()          -> L { L::nil() }
@@ -876,6 +895,50 @@ pub fn vec_of_list<X:Clone,L:ListElim<X>+'static>
   }
 }
 
+/// Iterator over the elements of an articulated list.
+///
+/// Each call to `next` eliminates the list only as far as the next
+/// `cons` cell, forcing any `art` cells along the way (and skipping
+/// `name`s).  Hence, adapters such as `take` and `skip` force only
+/// the prefix that they consume, and when iterating within a thunk,
+/// the thunk observes only this prefix: changes to the rest of the
+/// list do not dirty it.
+#[derive(Debug,Clone)]
+pub struct ArtIter<X,L:ListElim<X>> {
+  list: Option<L>,
+  elem: PhantomData<X>,
+}
+
+impl<X,L:ListElim<X>> ArtIter<X,L> {
+  /// Iterates over the given list, without (yet) forcing any part of it.
+  pub fn new(list:L) -> Self {
+    ArtIter{ list:Some(list), elem:PhantomData }
+  }
+
+  /// The unconsumed rest of the list, if any.
+  pub fn rest(self) -> Option<L> {
+    self.list
+  }
+}
+
+impl<X,L:ListElim<X>> Iterator for ArtIter<X,L> {
+  type Item = X;
+  fn next(&mut self) -> Option<X> {
+    loop {
+      let list = match self.list.take() { Some(l) => l, None => return None };
+      let (hd, rest) =
+        L::elim_arg(
+          list, (),
+          |_, _|       (None, None),
+          |x, rest, _| (Some(x), Some(rest)),
+          |_, rest, _| (None, Some(rest))
+        ) ;
+      self.list = rest ;
+      if hd.is_some() { return hd }
+    }
+  }
+}
+
 /// Constructs a linked list that consists of elements and names, as
 /// given by the input vector (in that order).
 /// Not incremental; used only for setting up inputs for tests.
@@ -1079,6 +1142,44 @@ pub fn test_mergesort2 () {
   assert_eq!(o1, o2);
 }
 
+#[cfg(feature = "counters")]
+#[test]
+fn test_art_iter () {
+  // Each element follows a name and a cell, as in `list_of_vec`.
+  fn input(v:&Vec<usize>) -> (List<usize>, Vec<Art<List<usize>>>) {
+    let mut l = List::nil();
+    let mut cells = vec![];
+    for (i, x) in v.iter().enumerate().rev() {
+      let c = cell(name_of_usize(i), l);
+      cells.push(c.clone());
+      l = List::cons(*x, List::name(name_of_usize(i), List::art(c)));
+    }
+    cells.reverse();
+    (l, cells)
+  }
+  manage::init_dcg();
+  let (l, cells) = input(&vec![1,2,3,4,5,6]);
+  assert_eq!(l.clone().iter().collect::<Vec<_>>(), vec![1,2,3,4,5,6]);
+  assert_eq!(l.clone().iter().skip(2).take(2).collect::<Vec<_>>(), vec![3,4]);
+
+  let t = thunk![[Some(name_of_str("prefix"))]? {
+    let s : usize = l.clone().iter().take(2).sum(); s
+  }];
+  assert_eq!(force(&t), 3);
+
+  // Replace the list's tail after its second element: the prefix is unaffected.
+  let (_, cells2) = input(&vec![1,2,30,40]);
+  set(&cells[1], force(&cells2[1]));
+  manage::reset_counters();
+  assert_eq!(force(&t), 3);
+  assert_eq!(manage::counters().unwrap().eval, 0);
+
+  // Replace the tail after the first element: the prefix changes.
+  set(&cells[0], force(&cells2[1]));
+  assert_eq!(force(&t), 31);
+  assert_eq!(manage::counters().unwrap().eval, 1);
+}
+
 #[derive(Debug,PartialEq,Eq,Hash,Clone)]
 pub enum List<X> {
   Nil,
@@ -1103,6 +1204,32 @@ pub enum NameElse<X> {
   Else(X),
 }
 
+/// Lists differ when their heads differ in kind, name or articulation.
+impl<X:PartialEq> FastEq for List<X> {
+  fn fast_eq(&self, other:&Self) -> Option<bool> {
+    match (self, other) {
+      (&List::Nil, &List::Nil) => Some(true),
+      (&List::Art(ref a), &List::Art(ref b)) => a.fast_eq(b),
+      (&List::Name(ref n, _), &List::Name(ref m, _)) if n != m => Some(false),
+      _ if ::std::mem::discriminant(self) != ::std::mem::discriminant(other) => Some(false),
+      _ => None,
+    }
+  }
+}
+
+/// Trees differ when their roots differ in kind, name or articulation.
+impl<X:PartialEq> FastEq for Tree<X> {
+  fn fast_eq(&self, other:&Self) -> Option<bool> {
+    match (self, other) {
+      (&Tree::Nil, &Tree::Nil) => Some(true),
+      (&Tree::Art(ref a), &Tree::Art(ref b)) => a.fast_eq(b),
+      (&Tree::Name(ref n, l, _, _), &Tree::Name(ref m, k, _, _)) if n != m || l != k => Some(false),
+      _ if ::std::mem::discriminant(self) != ::std::mem::discriminant(other) => Some(false),
+      _ => None,
+    }
+  }
+}
+
 impl<X:'static+Debug+Hash+PartialEq+Eq+Clone> ListIntro<X> for List<X>
 {
   fn nil  ()                 -> Self { List::Nil }
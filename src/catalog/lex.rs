@@ -0,0 +1,155 @@
+//! Incremental lexing of articulated text.
+//!
+//! The input text is a list of string chunks, interposed with names
+//! (e.g., as given by `list_of_vec`); the output is an articulated
+//! list of tokens.  For each name in the input, the lexer:
+//!
+//! - stores the scanner state that flows into the next chunk in a
+//!   cell, and
+//!
+//! - lexes the remaining text in a (nominal) thunk, which forces this
+//!   state cell.
+//!
+//! Hence, after an edit, the engine re-tokenizes a chunk only when its
+//! content or its incoming scanner state has changed: When
+//! re-tokenizing an edited chunk yields the same outgoing state, the
+//! lexer re-allocates the same state cell with the same value, and the
+//! lexing of the later chunks is reused.
+//!
+//! Since they are not named by the input, the chunks before the first
+//! name are lexed eagerly, by the caller of `lex`.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+use catalog::collections::{ListIntro, ListElim};
+
+/// A scanner, whose state flows from chunk to chunk of the input text
+/// (e.g., to hold a lexeme that spans the boundary of two chunks).
+pub trait Scanner : 'static {
+  type State : Hash+Eq+Debug+Clone+'static;
+  type Token : Hash+Eq+Debug+Clone+'static;
+  /// The state at the start of the text.
+  fn init(&self) -> Self::State;
+  /// Scan the chunk, starting in `state`; push the tokens that it
+  /// completes onto `out`, and return the state at its end.
+  fn scan(&self, state:Self::State, chunk:&str, out:&mut Vec<Self::Token>) -> Self::State;
+  /// Push the tokens that are pending in the final `state` onto `out`.
+  fn finish(&self, state:Self::State, out:&mut Vec<Self::Token>);
+}
+
+/// Lex the (articulated) text into an articulated list of tokens,
+/// with one name (and one thunk) for each name in the text.
+///
+/// Like other spurious arguments, the engine does not compare
+/// scanners: changing the scanner for a given text requires a new
+/// namespace (see `engine::ns`).
+pub fn lex<S:Scanner,
+           Le:'static+ListElim<String>,
+           Li:'static+ListIntro<S::Token>>
+  (scanner:Rc<S>, text:Le) -> Li
+{
+  let state = scanner.init();
+  lex_rec(scanner, state, text)
+}
+
+fn lex_rec<S:Scanner,
+           Le:'static+ListElim<String>,
+           Li:'static+ListIntro<S::Token>>
+  (scanner:Rc<S>, state:S::State, text:Le) -> Li
+{
+  Le::elim_arg
+    (text, (scanner, state),
+     |_, (scanner, state)| {
+       let mut toks = vec![];
+       scanner.finish(state, &mut toks);
+       list_of_toks(toks, Li::nil())
+     },
+     |chunk, rest, (scanner, state)| {
+       let mut toks = vec![];
+       let state = scanner.scan(state, &chunk, &mut toks);
+       list_of_toks(toks, lex_rec(scanner, state, rest))
+     },
+     |n, rest, (scanner, state)| {
+       let (n_state, n_lex) = name_fork(n.clone());
+       let state : Art<S::State> = cell(n_state, state);
+       let toks = thunk(NameChoice::Nominal(n_lex),
+                        prog_pt!("catalog::lex::lex"),
+                        Rc::new(Box::new(|(state, rest):(Art<S::State>, Le), scanner:Rc<S>| {
+                          lex_rec(scanner, force(&state), rest)
+                        })),
+                        (state, rest), scanner);
+       Li::name(n, Li::art(toks))
+     })
+}
+
+fn list_of_toks<X:'static, L:ListIntro<X>>(toks:Vec<X>, rest:L) -> L {
+  toks.into_iter().rev().fold(rest, |l, tok| L::cons(tok, l))
+}
+
+/// A scanner for whitespace-separated words; a word may span chunks.
+/// Its state is the (partial) word at the end of the text so far.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct Words;
+
+impl Scanner for Words {
+  type State = String;
+  type Token = String;
+  fn init(&self) -> String { String::new() }
+  fn scan(&self, mut word:String, chunk:&str, out:&mut Vec<String>) -> String {
+    for c in chunk.chars() {
+      if c.is_whitespace() {
+        if !word.is_empty() { out.push(::std::mem::replace(&mut word, String::new())) }
+      } else {
+        word.push(c)
+      }
+    }
+    word
+  }
+  fn finish(&self, word:String, out:&mut Vec<String>) {
+    if !word.is_empty() { out.push(word) }
+  }
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_lex_words () {
+  use catalog::collections::{List, NameElse, list_of_vec};
+  use catalog::patch::elems_of_list;
+  manage::init_dcg();
+  let chunk = |s:&str| NameElse::Else(s.to_string());
+  let input = |c1:&str| -> List<String> {
+    list_of_vec(&vec![chunk("hello wo"),
+                      NameElse::Name(name_of_usize(1)), chunk(c1),
+                      NameElse::Name(name_of_usize(2)), chunk(" baz"),
+                      NameElse::Name(name_of_usize(3)), chunk("  qu"), chunk("ux")])
+  };
+  let text = cell(name_of_str("text"), input("rld foo "));
+  let toks = {
+    let text = text.clone();
+    thunk(NameChoice::Nominal(name_of_str("toks")), prog_pt!("test_lex_words"),
+          Rc::new(Box::new(|text:Art<List<String>>, ()| {
+            let toks : List<String> = lex(Rc::new(Words), force(&text));
+            elems_of_list(toks)
+          })),
+          text, ())
+  };
+  let words = |ws:&[&str]| -> Vec<String> { ws.iter().map(|w| w.to_string()).collect() };
+  assert_eq!(force(&toks), words(&["hello", "world", "foo", "baz", "quux"]));
+
+  // Editing the second chunk (keeping its outgoing state) re-tokenizes only that chunk.
+  let _ = manage::reset_counters();
+  set(&text, input("rld bar "));
+  assert_eq!(force(&toks), words(&["hello", "world", "bar", "baz", "quux"]));
+  // (Re-evaluates the thunk for the edited chunk, and `toks`.)
+  assert_eq!(manage::counters().unwrap().eval, 2);
+
+  // Changing the state that flows into the third chunk re-tokenizes it, too.
+  let _ = manage::reset_counters();
+  set(&text, input("rld b"));
+  assert_eq!(force(&toks), words(&["hello", "world", "b", "baz", "quux"]));
+  assert_eq!(manage::counters().unwrap().eval, 3);
+}
@@ -13,4 +13,14 @@
 
 pub mod collections ;
 pub mod bitstring ;
+pub mod patch ;
+pub mod namemap ;
+pub mod lex ;
+pub mod parse ;
+pub mod spreadsheet ;
+pub mod appendseq ;
+pub mod changeset ;
 mod trie ;
+mod quadtree ;
+mod relations ;
+mod bags ;
@@ -0,0 +1,188 @@
+//! Finite maps from names (qualified by namespace paths) to user data.
+//!
+//! The engine identifies each articulation by a `Name` within a
+//! namespace `Path` (see `engine::ns` and `reflect::Loc`).  A
+//! `NameMap<T>` associates user metadata with such (path, name)
+//! pairs.  Like the engine's paths, it forms a tree of namespaces,
+//! keyed by the (precomputed) hashes of names; hence, it supports
+//! efficient queries over all entries within a namespace prefix.
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+
+use adapton::engine::Name;
+use adapton::reflect::{Loc, Path};
+
+/// A finite map from (path, name) pairs to values of type `T`,
+/// organized as a trie of namespaces.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NameMap<T> {
+  /// Entries whose path is exactly this namespace
+  vals: HashMap<Name, T>,
+  /// Nested namespaces
+  nss: HashMap<Name, NameMap<T>>,
+}
+
+impl<T> NameMap<T> {
+  /// An empty map.
+  pub fn new() -> Self {
+    NameMap{vals:HashMap::new(), nss:HashMap::new()}
+  }
+  fn ns(&self, path:&[Name]) -> Option<&Self> {
+    match path.split_first() {
+      None => Some(self),
+      Some((n, path)) => self.nss.get(n).and_then(|m| m.ns(path)),
+    }
+  }
+  fn ns_mut(&mut self, path:&[Name]) -> &mut Self {
+    match path.split_first() {
+      None => self,
+      Some((n, path)) => self.nss.entry(n.clone()).or_insert_with(NameMap::new).ns_mut(path),
+    }
+  }
+  /// True iff the map has no entries.
+  pub fn is_empty(&self) -> bool {
+    self.vals.is_empty() && self.nss.values().all(|m| m.is_empty())
+  }
+  /// The number of entries.
+  pub fn len(&self) -> usize {
+    self.vals.len() + self.nss.values().map(|m| m.len()).sum::<usize>()
+  }
+  /// Associates `val` with `name` in namespace `path`; returns the
+  /// previously-associated value, if any.
+  pub fn insert(&mut self, path:&[Name], name:Name, val:T) -> Option<T> {
+    self.ns_mut(path).vals.insert(name, val)
+  }
+  /// The value associated with `name` in namespace `path`, if any.
+  pub fn get(&self, path:&[Name], name:&Name) -> Option<&T> {
+    self.ns(path).and_then(|m| m.vals.get(name))
+  }
+  /// Mutable access to the value associated with `name` in namespace `path`.
+  pub fn get_mut(&mut self, path:&[Name], name:&Name) -> Option<&mut T> {
+    match path.split_first() {
+      None => self.vals.get_mut(name),
+      Some((n, path)) => match self.nss.get_mut(n) {
+        None => None,
+        Some(m) => m.get_mut(path, name),
+      }
+    }
+  }
+  /// Removes and returns the value associated with `name` in namespace `path`.
+  pub fn remove(&mut self, path:&[Name], name:&Name) -> Option<T> {
+    match path.split_first() {
+      None => self.vals.remove(name),
+      Some((n, path)) => {
+        let (res, prune) = match self.nss.get_mut(n) {
+          None => return None,
+          Some(m) => { let res = m.remove(path, name); (res, m.is_empty()) }
+        };
+        if prune { self.nss.remove(n); }
+        res
+      }
+    }
+  }
+  /// Associates `val` with the given (reflected) location.
+  pub fn insert_loc(&mut self, loc:&Loc, val:T) -> Option<T> {
+    self.insert(&loc.path, loc.name.clone(), val)
+  }
+  /// The value associated with the given (reflected) location, if any.
+  pub fn get_loc(&self, loc:&Loc) -> Option<&T> {
+    self.get(&loc.path, &loc.name)
+  }
+  /// Removes every entry within namespace `prefix` (including nested
+  /// namespaces); returns the removed sub-map, if any.
+  pub fn remove_prefix(&mut self, prefix:&[Name]) -> Option<NameMap<T>> {
+    match prefix.split_last() {
+      None => Some(::std::mem::replace(self, NameMap::new())),
+      Some((n, prefix)) => {
+        let (ns, prune) = match self.ns_mut_opt(prefix) {
+          None => return None,
+          Some(m) => { let ns = m.nss.remove(n); (ns, m.is_empty()) }
+        };
+        if prune { let _ = self.remove_prefix(prefix); }
+        ns
+      }
+    }
+  }
+  fn ns_mut_opt(&mut self, path:&[Name]) -> Option<&mut Self> {
+    match path.split_first() {
+      None => Some(self),
+      Some((n, path)) => match self.nss.get_mut(n) {
+        None => None,
+        Some(m) => m.ns_mut_opt(path),
+      }
+    }
+  }
+  /// Iterates over every entry within namespace `prefix` (including
+  /// nested namespaces), giving the full path of each entry.  The
+  /// order of iteration is unspecified.
+  pub fn iter_prefix<'a>(&'a self, prefix:&[Name]) -> Iter<'a, T> {
+    let mut stack = vec![];
+    if let Some(m) = self.ns(prefix) {
+      stack.push((prefix.to_vec(), m.vals.iter(), m.nss.iter()))
+    };
+    Iter{stack:stack}
+  }
+  /// Iterates over every entry, giving the full path of each entry.
+  pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+    self.iter_prefix(&[])
+  }
+}
+
+impl<T> Default for NameMap<T> {
+  fn default() -> Self { NameMap::new() }
+}
+
+/// Iterator over the entries of a `NameMap`; see `NameMap::iter_prefix`.
+pub struct Iter<'a, T:'a> {
+  stack: Vec<(Path, hash_map::Iter<'a, Name, T>, hash_map::Iter<'a, Name, NameMap<T>>)>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = (Path, &'a Name, &'a T);
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let next_ns = match self.stack.last_mut() {
+        None => return None,
+        Some(&mut (ref path, ref mut vals, ref mut nss)) => {
+          if let Some((n, v)) = vals.next() { return Some((path.clone(), n, v)) };
+          match nss.next() {
+            None => None,
+            Some((n, m)) => {
+              let mut path = path.clone();
+              path.push(n.clone());
+              Some((path, m))
+            }
+          }
+        }
+      };
+      match next_ns {
+        None => { self.stack.pop(); },
+        Some((path, m)) => self.stack.push((path, m.vals.iter(), m.nss.iter())),
+      }
+    }
+  }
+}
+
+#[test]
+fn test_namemap () {
+  use adapton::engine::*;
+  let (a, b, c) = (name_of_str("a"), name_of_str("b"), name_of_str("c"));
+  let mut m = NameMap::new();
+  assert_eq!(m.insert(&[], a.clone(), 1), None);
+  assert_eq!(m.insert(&[a.clone()], b.clone(), 2), None);
+  assert_eq!(m.insert(&[a.clone(), b.clone()], c.clone(), 3), None);
+  assert_eq!(m.insert(&[b.clone()], c.clone(), 4), None);
+  assert_eq!(m.insert(&[b.clone()], c.clone(), 5), Some(4));
+  assert_eq!(m.len(), 4);
+  assert_eq!(m.get(&[a.clone()], &b), Some(&2));
+  assert_eq!(m.get(&[a.clone()], &c), None);
+  let mut under_a : Vec<_> = m.iter_prefix(&[a.clone()]).map(|(p, n, v)| (p, n.clone(), *v)).collect();
+  under_a.sort_by_key(|e| e.2);
+  assert_eq!(under_a, vec![(vec![a.clone()], b.clone(), 2), (vec![a.clone(), b.clone()], c.clone(), 3)]);
+  assert_eq!(m.iter().count(), 4);
+  assert_eq!(m.remove(&[a.clone(), b.clone()], &c), Some(3));
+  assert_eq!(m.remove_prefix(&[a.clone()]).map(|m| m.len()), Some(1));
+  assert_eq!(m.len(), 2);
+  assert_eq!(m.get(&[], &a), Some(&1));
+}
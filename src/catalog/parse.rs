@@ -0,0 +1,214 @@
+//! Incremental parser combinators over articulated token lists.
+//!
+//! A `Parser` consumes a prefix of a token list (e.g., as produced by
+//! `catalog::lex`), skipping the names within it.  The combinators
+//! below compose parsers as usual (sequencing, choice, repetition);
+//! additionally, `nonterm` articulates the results of a nonterminal:
+//!
+//! - When a nonterminal begins its parse at a name `n` in the input,
+//!   its result is a nominal thunk, named by `n` and the name of the
+//!   nonterminal.  The engine memoizes this result; after an edit to
+//!   the input, it re-parses the nonterminal only when the tokens that
+//!   it consumed (or looked ahead at) have changed.
+//!
+//! - When re-parsing a subtree yields an equal result (including the
+//!   same remaining input), the engine reuses the enclosing parses
+//!   (cutoff).
+//!
+//! Hence, the granularity of re-parsing follows the names of the
+//! input: finer chunking gives finer re-parsing.  Within a given
+//! grammar, distinct nonterminals need distinct names; a nonterminal
+//! that begins its own parse at the same position (left recursion)
+//! is a cycle, which the engine rejects.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+use catalog::collections::{List, ListElim};
+
+/// The result of a parse: the parsed value, and the remaining input.
+pub type ParseResult<Tok,T> = Option<(T, List<Tok>)>;
+
+/// A parser for values of type `T`, from lists of `Tok`s.
+pub struct Parser<Tok,T> {
+  run:Rc<Fn(List<Tok>) -> ParseResult<Tok,T>>,
+}
+
+impl<Tok,T> Clone for Parser<Tok,T> {
+  fn clone(&self) -> Self { Parser{run:self.run.clone()} }
+}
+
+impl<Tok:'static+Hash+Eq+Debug+Clone, T:'static> Parser<Tok,T> {
+  /// A parser, given by its parsing function.
+  pub fn new<F:'static+Fn(List<Tok>) -> ParseResult<Tok,T>>(f:F) -> Self {
+    Parser{run:Rc::new(f)}
+  }
+  /// Parse a prefix of the input.
+  pub fn parse(&self, input:List<Tok>) -> ParseResult<Tok,T> {
+    (self.run)(input)
+  }
+  /// Parse the entire input (up to its trailing names).
+  pub fn parse_all(&self, input:List<Tok>) -> Option<T> {
+    match self.parse(input) {
+      Some((x, ref rest)) if next_tok(rest.clone()).is_none() => Some(x),
+      _ => None,
+    }
+  }
+  /// Map the parsed value.
+  pub fn map<U:'static, F:'static+Fn(T) -> U>(self, f:F) -> Parser<Tok,U> {
+    Parser::new(move |input| match self.parse(input) {
+      None => None,
+      Some((x, rest)) => Some((f(x), rest)),
+    })
+  }
+  /// Parse with `self`, and then with `next`.
+  pub fn then<U:'static>(self, next:Parser<Tok,U>) -> Parser<Tok,(T,U)> {
+    Parser::new(move |input| match self.parse(input) {
+      None => None,
+      Some((x, rest)) => match next.parse(rest) {
+        None => None,
+        Some((y, rest)) => Some(((x, y), rest)),
+      }
+    })
+  }
+  /// Parse with `self`; if that fails, parse with `other` instead.
+  pub fn or(self, other:Self) -> Self {
+    Parser::new(move |input:List<Tok>| match self.parse(input.clone()) {
+      None => other.parse(input),
+      res => res,
+    })
+  }
+  /// Parse with `self` zero or more times (greedily).
+  pub fn many(self) -> Parser<Tok,Vec<T>> {
+    Parser::new(move |input| {
+      let mut xs = vec![];
+      let mut input = input;
+      loop {
+        match self.parse(input.clone()) {
+          None => return Some((xs, input)),
+          Some((x, rest)) => { xs.push(x); input = rest }
+        }
+      }
+    })
+  }
+}
+
+/// The next token of the input, and the rest of the input, skipping names.
+fn next_tok<Tok:'static+Hash+Eq+Debug+Clone>(input:List<Tok>) -> ParseResult<Tok,Tok> {
+  List::elim_arg(input, (),
+                 |_, _| None,
+                 |tok, rest, _| Some((tok, rest)),
+                 |_, rest, _| next_tok(rest))
+}
+
+/// Parse one token, for which `f` gives a value.
+pub fn token<Tok:'static+Hash+Eq+Debug+Clone, T:'static, F:'static+Fn(&Tok) -> Option<T>>
+  (f:F) -> Parser<Tok,T>
+{
+  Parser::new(move |input| match next_tok(input) {
+    None => None,
+    Some((tok, rest)) => f(&tok).map(|x| (x, rest)),
+  })
+}
+
+/// Parse the given token.
+pub fn literal<Tok:'static+Hash+Eq+Debug+Clone>(tok:Tok) -> Parser<Tok,()> {
+  token(move |t| if t == &tok { Some(()) } else { None })
+}
+
+/// Parse nothing, giving the value.
+pub fn succeed<Tok:'static+Hash+Eq+Debug+Clone, T:'static+Clone>(x:T) -> Parser<Tok,T> {
+  Parser::new(move |input| Some((x.clone(), input)))
+}
+
+/// Defer the construction of a parser until it parses; for recursive grammars.
+pub fn lazy<Tok:'static+Hash+Eq+Debug+Clone, T:'static, F:'static+Fn() -> Parser<Tok,T>>
+  (f:F) -> Parser<Tok,T>
+{
+  Parser::new(move |input| f().parse(input))
+}
+
+/// The nonterminal `nt`, given by parser `p`: at each name of the
+/// input, the result of `p` is memoized, as described in the module
+/// documentation.
+///
+/// Like other spurious arguments, the engine does not compare
+/// parsers: changing the grammar for a given input requires a new
+/// namespace (see `engine::ns`).
+pub fn nonterm<Tok:'static+Hash+Eq+Debug+Clone, T:'static+Hash+Eq+Debug+Clone>
+  (nt:&'static str, p:Parser<Tok,T>) -> Parser<Tok,T>
+{
+  Parser::new(move |input:List<Tok>| {
+    let head = List::elim(&input, |_| None, |_, _| None, |n, _| Some(n.clone()));
+    match head {
+      None => p.parse(input),
+      Some(n) => {
+        let res = thunk(NameChoice::Nominal(name_pair(n, name_of_str(nt))),
+                        prog_pt!(nt),
+                        Rc::new(Box::new(|input, p:Parser<Tok,T>| p.parse(input))),
+                        input, p.clone());
+        force(&res)
+      }
+    }
+  })
+}
+
+#[cfg(all(test, feature = "counters"))]
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+enum Sexp {
+  Atom(String),
+  List(Vec<Sexp>),
+}
+
+#[cfg(all(test, feature = "counters"))]
+fn sexp() -> Parser<String,Sexp> {
+  let atom = token(|t:&String| if t != "(" && t != ")" { Some(Sexp::Atom(t.clone())) } else { None });
+  let list = literal("(".to_string())
+    .then(lazy(sexp).many())
+    .then(literal(")".to_string()))
+    .map(|((_, xs), _)| Sexp::List(xs));
+  nonterm("sexp", atom.or(list))
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_parse_sexp () {
+  use catalog::collections::{NameElse, list_of_vec};
+  use catalog::lex::{lex, Words};
+  manage::init_dcg();
+  let chunks = |c2:&str| -> List<String> {
+    let mut v = vec![];
+    for (i, c) in ["( ( a b ) ", c2, "( e f ) )"].iter().enumerate() {
+      v.push(NameElse::Name(name_of_usize(i)));
+      v.push(NameElse::Else(c.to_string()));
+    }
+    list_of_vec(&v)
+  };
+  let text = cell(name_of_str("text"), chunks("( c d ) "));
+  let ast = {
+    let text = text.clone();
+    thunk(NameChoice::Nominal(name_of_str("ast")), prog_pt!("test_parse_sexp"),
+          Rc::new(Box::new(|text:Art<List<String>>, ()| {
+            let toks : List<String> = lex(Rc::new(Words), force(&text));
+            sexp().parse_all(toks)
+          })),
+          text, ())
+  };
+  let atom = |s:&str| Sexp::Atom(s.to_string());
+  let expect = |x:&str| Some(Sexp::List(vec![Sexp::List(vec![atom("a"), atom("b")]),
+                                             Sexp::List(vec![atom("c"), atom(x)]),
+                                             Sexp::List(vec![atom("e"), atom("f")])]));
+  assert_eq!(force(&ast), expect("d"));
+  let _ = manage::reset_counters();
+  set(&text, chunks("( c x ) "));
+  assert_eq!(force(&ast), expect("x"));
+  // Re-lexes the edited chunk, re-parses the sexp that begins at its
+  // name and the enclosing sexp, and reuses the sexp that begins at
+  // the last name.
+  assert_eq!(manage::counters().unwrap().eval, 4);
+  set(&text, chunks("( c "));
+  assert_eq!(force(&ast), None);
+}
@@ -0,0 +1,322 @@
+//! Patch streams: Incremental differences between versions of a list.
+//!
+//! Consumers of a list-producing computation (e.g., the virtual list
+//! of a UI) often want _what changed_, not the list itself.  This
+//! module gives:
+//!
+//! - `diff_vec`, which computes a minimal edit script between two
+//!   sequences of elements, as a vector of `Patch`es.
+//!
+//! - `diff_list`, which computes the patch between two articulated
+//!   lists, following their structure: it stops at the first sublist
+//!   that both share (e.g., the same art, after the same name), so
+//!   that it neither reads this sublist, nor compares its elements.
+//!
+//! - `diff_thunk`, a combinator that articulates the patch between two
+//!   (articulated) lists as a thunk; the engine re-computes this patch
+//!   only when one of the lists changes, before their shared sublist.
+//!
+//! - `PatchObserver`, which polls a list-producing `Art` and delivers
+//!   the patch from the version it last delivered to the current one.
+//!
+//! **Snapshot note:** An articulated list read later reflects the
+//! _current_ content of its arts, not the content at the time it was
+//! first read.  Hence, `PatchObserver` keeps a plain snapshot of the
+//! elements that it last delivered, and differences against that.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+use catalog::collections::{ListElim, NameElse, vec_of_list};
+
+/// An edit to a sequence; positions are relative to the sequence
+/// that results from the preceding patches (applying the patches in
+/// order transforms the old sequence into the new one).
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub enum Patch<X> {
+  /// Insert the element at the position.
+  Insert(usize, X),
+  /// Remove the element at the position.
+  Remove(usize),
+}
+
+/// The elements of an articulated list, in order (forces the entire list).
+pub fn elems_of_list<X:Clone,L:ListElim<X>+'static>(list:L) -> Vec<X> {
+  vec_of_list(list, None).into_iter()
+    .filter_map(|x| match x { NameElse::Else(x) => Some(x), NameElse::Name(_) => None })
+    .collect()
+}
+
+/// Apply the patches, in order, to the given vector.
+pub fn apply_patches<X:Clone>(v:&mut Vec<X>, patches:&[Patch<X>]) {
+  for p in patches.iter() {
+    match *p {
+      Patch::Insert(i, ref x) => v.insert(i, x.clone()),
+      Patch::Remove(i) => { v.remove(i); },
+    }
+  }
+}
+
+/// A minimal edit script (by its number of patches) that transforms
+/// `prev` into `next`; insertions precede removals, where either
+/// order is minimal.  The common prefix and suffix are trimmed first,
+/// and the region that differs takes Myers' algorithm: for `D`
+/// patches, in time `O((N + M) D)`, and space `O(D^2)`.
+pub fn diff_vec<X:Clone+Eq>(prev:&[X], next:&[X]) -> Vec<Patch<X>> {
+  let mut pre = 0;
+  while pre < prev.len() && pre < next.len() && prev[pre] == next[pre] { pre += 1 }
+  let mut suf = 0;
+  while suf < prev.len() - pre && suf < next.len() - pre
+    && prev[prev.len() - 1 - suf] == next[next.len() - 1 - suf] { suf += 1 }
+  let p = &prev[pre .. prev.len() - suf];
+  let n = &next[pre .. next.len() - suf];
+  let (np, nn) = (p.len() as isize, n.len() as isize);
+  // A path of patches is at (x, y) after removing p[..x] and inserting
+  // n[..y], on diagonal k = x - y; furthest[d][k + d] is the furthest x
+  // on diagonal k of the paths of d patches (then skipping common
+  // elements), or -1 if none reaches the diagonal.
+  let mut furthest : Vec<Vec<isize>> = vec![];
+  loop {
+    let d = furthest.len() as isize;
+    let mut row = vec![-1; (2 * d + 1) as usize];
+    let mut done = false;
+    for k in (-d .. d + 1).step_by(2) {
+      let start = if d == 0 { Some(0) } else { myers_step(&furthest[(d - 1) as usize], d, k, np, nn).map(|s| s.1) };
+      if let Some(mut x) = start {
+        let mut y = x - k;
+        while x < np && y < nn && p[x as usize] == n[y as usize] { x += 1; y += 1 }
+        row[(k + d) as usize] = x;
+        if x == np && y == nn { done = true }
+      }
+    }
+    furthest.push(row);
+    if done { break }
+  }
+  // Follow the path back, from (np, nn)
+  let mut patches = vec![];
+  let (mut x, mut y) = (np, nn);
+  for d in (1 .. furthest.len() as isize).rev() {
+    let k = x - y;
+    let (insert, _) = myers_step(&furthest[(d - 1) as usize], d, k, np, nn).unwrap();
+    let k = if insert { k + 1 } else { k - 1 };
+    x = furthest[(d - 1) as usize][(k + d - 1) as usize];
+    y = x - k;
+    patches.push(if insert { Patch::Insert(pre + y as usize, n[y as usize].clone()) }
+                 else { Patch::Remove(pre + y as usize) });
+  }
+  patches.reverse();
+  patches
+}
+
+/// The last patch of the furthest path of `d` patches on diagonal `k`
+/// (see `diff_vec`), from the paths of `d - 1` patches, `prior`: an
+/// insertion, from diagonal `k + 1`, or a removal, from diagonal
+/// `k - 1`, whichever reaches further (the removal, if neither does),
+/// with the `x` that it reaches.  `None` if neither stays within the
+/// sequences' lengths.
+fn myers_step(prior:&[isize], d:isize, k:isize, np:isize, nn:isize) -> Option<(bool, isize)> {
+  let at = |k:isize| if k.abs() <= d - 1 { prior[(k + d - 1) as usize] } else { -1 };
+  let (ins, rem) = (at(k + 1), at(k - 1));
+  let ins_ok = ins >= 0 && ins - (k + 1) < nn;
+  let rem_ok = rem >= 0 && rem < np;
+  if ins_ok && (! rem_ok || ins > rem + 1) { Some((true, ins)) }
+  else if rem_ok { Some((false, rem + 1)) }
+  else { None }
+}
+
+/// One list of `diff_list`, as far as it has been eliminated: its
+/// elements, the sublist after each of its names (with the number of
+/// elements before it), and the rest of the list, if any.
+struct Walk<X,L:Hash+Eq> {
+  elems:Vec<X>,
+  named:HashMap<L, usize>,
+  rest:Option<L>,
+}
+
+impl<X:Clone,L:ListElim<X>> Walk<X,L> {
+  /// Eliminates the next constructor of the list (forcing its arts, if
+  /// any); gives the sublist after it, if it is a name.
+  fn step(&mut self) -> Option<L> {
+    let list = match self.rest.take() { Some(list) => list, None => return None };
+    let (x, rest, named) = L::elim_arg(
+      list, (),
+      |_, _|       (None, None, false),
+      |x, rest, _| (Some(x), Some(rest), false),
+      |_, rest, _| (None, Some(rest), true));
+    if let Some(x) = x { self.elems.push(x) };
+    self.rest = rest;
+    if ! named { return None };
+    let rest = self.rest.clone().unwrap();
+    let count = self.elems.len();
+    self.named.entry(rest.clone()).or_insert(count);
+    Some(rest)
+  }
+}
+
+/// The patch from list `prev` to list `next`, as by `diff_vec`, for
+/// their elements.  Eliminates both lists in lockstep, until a name of
+/// one list precedes the same sublist (e.g., the same art) as a name
+/// of the other: the lists share the rest, which their patch does not
+/// change, so that it need not be read further.  Hence, the work is
+/// proportional to the longer of the lists' prefixes before their
+/// shared sublist, rather than to their lengths.  (Without such names,
+/// the lists are read entirely.)
+pub fn diff_list<X:Clone+Eq,L:ListElim<X>>(prev:L, next:L) -> Vec<Patch<X>> {
+  if prev == next { return vec![] };
+  let mut walks = [Walk{elems:vec![], named:HashMap::new(), rest:Some(prev)},
+                   Walk{elems:vec![], named:HashMap::new(), rest:Some(next)}];
+  // The number of elements of each list before the shared sublist
+  let mut shared = None;
+  while shared.is_none() && (walks[0].rest.is_some() || walks[1].rest.is_some()) {
+    for side in 0..2 {
+      let rest = match walks[side].step() { Some(rest) => rest, None => continue };
+      if let Some(&other) = walks[1 - side].named.get(&rest) {
+        let this = walks[side].elems.len();
+        shared = Some(if side == 0 { (this, other) } else { (other, this) });
+        break
+      }
+    }
+  }
+  let (i, j) = shared.unwrap_or((walks[0].elems.len(), walks[1].elems.len()));
+  diff_vec(&walks[0].elems[..i], &walks[1].elems[..j])
+}
+
+/// Articulate the patch from list `prev` to list `next` (by
+/// `diff_list`) as a (structurally-identified) thunk.  Forcing the
+/// thunk re-computes the patch only when the content of either list
+/// has changed, before their shared sublist.
+pub fn diff_thunk<X:'static+Hash+Eq+Debug+Clone,
+                  L:'static+ListElim<X>>
+  (prev:Art<L>, next:Art<L>) -> Art<Vec<Patch<X>>>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::patch::diff_thunk"),
+        Rc::new(Box::new(|(prev, next):(Art<L>, Art<L>), ()| diff_list(force(&prev), force(&next)))),
+        (prev, next), ())
+}
+
+/// Observes successive versions of a list-producing `Art`, and
+/// delivers the patch between the version that it last delivered and
+/// the current one.
+#[derive(Debug,Clone)]
+pub struct PatchObserver<X,L> {
+  art:Art<L>,
+  snapshot:Vec<X>,
+}
+
+impl<X:'static+Hash+Eq+Debug+Clone,
+     L:'static+ListElim<X>+Hash+Eq+Debug+Clone>
+  PatchObserver<X,L>
+{
+  /// Observe `art`; the first poll delivers the entire list, as insertions.
+  pub fn new(art:Art<L>) -> Self {
+    PatchObserver{art:art, snapshot:vec![]}
+  }
+  /// The elements that were last delivered, in order.
+  pub fn snapshot(&self) -> &Vec<X> { &self.snapshot }
+  /// Force the observed list, and return the patch from the last
+  /// delivered version to the current one (empty if unchanged).
+  pub fn poll(&mut self) -> Vec<Patch<X>> {
+    let next = elems_of_list(force(&self.art));
+    let patches = diff_vec(&self.snapshot, &next);
+    self.snapshot = next;
+    patches
+  }
+  /// Like `poll`, except delivers each patch to the given callback.
+  pub fn observe<F:FnMut(&Patch<X>)>(&mut self, mut f:F) {
+    for p in self.poll().iter() { f(p) }
+  }
+}
+
+#[test]
+fn test_diff_vec () {
+  let cases : Vec<(Vec<usize>, Vec<usize>)> = vec![
+    (vec![], vec![]),
+    (vec![], vec![1,2,3]),
+    (vec![1,2,3], vec![]),
+    (vec![1,2,3,4,5], vec![1,2,9,4,5]),
+    (vec![1,2,3,4,5], vec![0,1,3,5,6]),
+    (vec![5,4,3,2,1], vec![1,2,3,4,5]),
+  ];
+  for (p, n) in cases {
+    let patches = diff_vec(&p, &n);
+    let mut v = p.clone();
+    apply_patches(&mut v, &patches);
+    assert_eq!(v, n);
+  }
+  assert_eq!(diff_vec(&[1,2,3,4,5], &[1,2,9,4,5]), vec![Patch::Insert(2,9), Patch::Remove(3)]);
+}
+
+#[test]
+fn test_diff_vec_is_minimal () {
+  // The length of the longest common subsequence, by the quadratic table
+  fn lcs(p:&[usize], n:&[usize]) -> usize {
+    let mut t = vec![vec![0; n.len() + 1]; p.len() + 1];
+    for i in (0..p.len()).rev() { for j in (0..n.len()).rev() {
+      t[i][j] = if p[i] == n[j] { t[i+1][j+1] + 1 } else { ::std::cmp::max(t[i+1][j], t[i][j+1]) }
+    }}
+    t[0][0]
+  }
+  let mut seed = 7 as usize;
+  let mut next = |m:usize| { seed = seed.wrapping_mul(1103515245).wrapping_add(12345); (seed >> 16) % m };
+  for _ in 0..200 {
+    let p : Vec<usize> = (0..next(12)).map(|_| next(4)).collect();
+    let n : Vec<usize> = (0..next(12)).map(|_| next(4)).collect();
+    let patches = diff_vec(&p, &n);
+    assert_eq!(patches.len(), p.len() + n.len() - 2 * lcs(&p, &n));
+    let mut v = p.clone();
+    apply_patches(&mut v, &patches);
+    assert_eq!(v, n);
+  }
+}
+
+#[test]
+fn test_diff_list_skips_shared_sublist () {
+  use catalog::collections::{List, ListIntro};
+  manage::init_dcg();
+  // A shared sublist, whose rest panics if the diff forces it
+  let rest : Art<List<usize>> = thunk(NameChoice::Nominal(name_of_str("rest")), prog_pt!("rest"),
+                                      Rc::new(Box::new(|(), ()| -> List<usize> { panic!("forced the shared sublist") })),
+                                      (), ());
+  let shared = List::name(name_of_str("shared"), List::art(cell(name_of_str("tail"), List::cons(7, List::art(rest)))));
+  let prev = List::cons(1, List::cons(2, shared.clone()));
+  let next = List::cons(9, shared.clone());
+  let mut v = vec![1,2];
+  apply_patches(&mut v, &diff_list(prev.clone(), next.clone()));
+  assert_eq!(v, vec![9]);
+  assert_eq!(diff_list(prev.clone(), prev.clone()), vec![]);
+
+  let head = cell(name_of_str("head"), prev);
+  let d = diff_thunk(head.clone(), cell(name_of_str("other"), next));
+  assert_eq!(force(&d).len(), 3);
+  set(&head, List::cons(1, List::cons(9, shared)));
+  assert_eq!(force(&d), vec![Patch::Remove(0)]);
+}
+
+#[test]
+fn test_patch_observer () {
+  use catalog::collections::{List, ListIntro, list_of_vec};
+  manage::init_dcg();
+  let input = |v:Vec<usize>| -> List<usize> {
+    list_of_vec(&v.into_iter().map(NameElse::Else).collect())
+  };
+  let inp = cell(name_of_str("inp"), input(vec![1,2,3]));
+  let mut obs = PatchObserver::new(inp.clone());
+  assert_eq!(obs.poll(), vec![Patch::Insert(0,1), Patch::Insert(1,2), Patch::Insert(2,3)]);
+  assert_eq!(obs.poll(), vec![]);
+  set(&inp, List::cons(0, input(vec![1,3])));
+  let mut seen = vec![];
+  obs.observe(|p| seen.push(p.clone()));
+  assert_eq!(seen, vec![Patch::Insert(0,0), Patch::Remove(2)]);
+  assert_eq!(obs.snapshot(), &vec![0,1,3]);
+
+  let other = cell(name_of_str("other"), input(vec![1,2,4]));
+  let d = diff_thunk(inp.clone(), other.clone());
+  let mut v = vec![0,1,3];
+  apply_patches(&mut v, &force(&d));
+  assert_eq!(v, vec![1,2,4]);
+}
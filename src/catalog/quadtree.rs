@@ -0,0 +1,273 @@
+//! Incremental 2D spatial index: quadtrees of named points.
+//!
+//! A `QuadTree` subdivides a square region into quadrants, to a fixed
+//! depth.  Each quadrant is a cell, which the tree names by its path
+//! from the root (see `QuadTree::new`); the tree allocates quadrants
+//! only as points enter them.  Range and nearest-neighbor queries are
+//! (structural) thunks, one for each quadrant that the query visits.
+//!
+//! Hence, after moving one point, re-running a query re-evaluates
+//! only the thunks along the paths from the root to the (one or two)
+//! affected leaf quadrants; the engine reuses the queries of all
+//! other quadrants.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+
+/// A point, in integer coordinates.
+pub type Point = (i64, i64);
+
+/// A point of the tree: its name, its position and its data.
+pub type Entry<X> = (Name, Point, X);
+
+/// The (half-open) rectangle `[x0,x1) x [y0,y1)`.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct Rect { pub x0:i64, pub y0:i64, pub x1:i64, pub y1:i64 }
+
+impl Rect {
+  /// True iff the rectangle contains the point.
+  pub fn contains(&self, p:&Point) -> bool {
+    self.x0 <= p.0 && p.0 < self.x1 && self.y0 <= p.1 && p.1 < self.y1
+  }
+  /// True iff the rectangles overlap.
+  pub fn intersects(&self, other:&Rect) -> bool {
+    self.x0 < other.x1 && other.x0 < self.x1 && self.y0 < other.y1 && other.y0 < self.y1
+  }
+  /// The squared distance from the point to (the nearest point of) the rectangle.
+  pub fn dist2(&self, p:&Point) -> i64 {
+    let d = |v:i64, lo:i64, hi:i64| if v < lo { lo - v } else if v >= hi { v - (hi - 1) } else { 0 };
+    let (dx, dy) = (d(p.0, self.x0, self.x1), d(p.1, self.y0, self.y1));
+    dx * dx + dy * dy
+  }
+  /// Quadrant `i` (in `0..4`): bit 0 selects the upper half of the x
+  /// range, and bit 1 the upper half of the y range.
+  pub fn quadrant(&self, i:usize) -> Rect {
+    let mx = self.x0 + (self.x1 - self.x0) / 2;
+    let my = self.y0 + (self.y1 - self.y0) / 2;
+    let (x0, x1) = if i & 1 == 0 { (self.x0, mx) } else { (mx, self.x1) };
+    let (y0, y1) = if i & 2 == 0 { (self.y0, my) } else { (my, self.y1) };
+    Rect{x0:x0, y0:y0, x1:x1, y1:y1}
+  }
+  /// The quadrant (in `0..4`) that contains the point.
+  pub fn quadrant_of(&self, p:&Point) -> usize {
+    (0..4).find(|i| self.quadrant(*i).contains(p)).unwrap()
+  }
+}
+
+/// The squared distance between two points.
+pub fn dist2(p:&Point, q:&Point) -> i64 {
+  let (dx, dy) = (p.0 - q.0, p.1 - q.1);
+  dx * dx + dy * dy
+}
+
+/// A quadrant of a quadtree.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Quad<X> {
+  /// A quadrant that holds no points.
+  Empty,
+  /// A leaf quadrant (at the depth of the tree), with its points.
+  Leaf(Vec<Entry<X>>),
+  /// An inner quadrant, with its four sub-quadrants (see `Rect::quadrant`).
+  Split(Box<[Art<Quad<X>>; 4]>),
+}
+
+/// A quadtree of named points, with data of type `X`, over a fixed
+/// region.  The tree is mutated from the outer layer; its quadrants
+/// are cells, which thunks query (see `range` and `nearest`).
+#[derive(Debug,Clone)]
+pub struct QuadTree<X> {
+  name:Name,
+  bounds:Rect,
+  depth:usize,
+  root:Art<Quad<X>>,
+  positions:HashMap<Name, Point>,
+}
+
+impl<X:'static+Hash+Eq+Debug+Clone> QuadTree<X> {
+  /// An empty tree over `bounds`, whose leaves are quadrants at the
+  /// given depth.  The root quadrant is a cell named `name`, and the
+  /// sub-quadrant `i` of the quadrant named `n` is a cell named
+  /// `name_pair(n, name_of_usize(i))`.
+  pub fn new(name:Name, bounds:Rect, depth:usize) -> Self {
+    let root = cell(name.clone(), Quad::Empty);
+    QuadTree{name:name, bounds:bounds, depth:depth, root:root, positions:HashMap::new()}
+  }
+  /// The root quadrant.
+  pub fn root(&self) -> Art<Quad<X>> { self.root.clone() }
+  /// The region of the tree.
+  pub fn bounds(&self) -> Rect { self.bounds }
+  /// The position of the named point, if any.
+  pub fn position(&self, id:&Name) -> Option<Point> { self.positions.get(id).cloned() }
+
+  /// The leaf quadrant that contains `p`, allocating quadrants as needed.
+  fn leaf_of(&self, p:&Point) -> Art<Quad<X>> {
+    assert!(self.bounds.contains(p), "quadtree: point {:?} is outside of {:?}", p, self.bounds);
+    let (mut quad, mut name, mut bounds) = (self.root.clone(), self.name.clone(), self.bounds);
+    for _ in 0..self.depth {
+      let i = bounds.quadrant_of(p);
+      let subs = match force(&quad) {
+        Quad::Split(subs) => subs,
+        _ => {
+          let sub = |i:usize| cell(name_pair(name.clone(), name_of_usize(i)), Quad::Empty);
+          let subs = Box::new([sub(0), sub(1), sub(2), sub(3)]);
+          set(&quad, Quad::Split(subs.clone()));
+          subs
+        }
+      };
+      quad = subs[i].clone();
+      name = name_pair(name, name_of_usize(i));
+      bounds = bounds.quadrant(i);
+    }
+    quad
+  }
+
+  /// Inserts the named point, replacing any prior point of that name.
+  pub fn insert(&mut self, id:Name, p:Point, x:X) {
+    let _ = self.remove(&id);
+    let leaf = self.leaf_of(&p);
+    let entry = (id.clone(), p, x);
+    update(&leaf, |quad| match *quad {
+      Quad::Leaf(ref es) => { let mut es = es.clone(); es.push(entry); Quad::Leaf(es) },
+      _ => Quad::Leaf(vec![entry]),
+    });
+    self.positions.insert(id, p);
+  }
+
+  /// Removes the named point; returns its position and data, if any.
+  pub fn remove(&mut self, id:&Name) -> Option<(Point, X)> {
+    let p = match self.positions.remove(id) { None => return None, Some(p) => p };
+    let leaf = self.leaf_of(&p);
+    let mut removed = None;
+    update(&leaf, |quad| match *quad {
+      Quad::Leaf(ref es) => {
+        let es : Vec<Entry<X>> = es.iter().filter(|e| {
+          if &e.0 == id { removed = Some((e.1, e.2.clone())); false } else { true }
+        }).cloned().collect();
+        if es.is_empty() { Quad::Empty } else { Quad::Leaf(es) }
+      },
+      _ => unreachable!(),
+    });
+    removed
+  }
+
+  /// Moves the named point to `p`; returns false if there is no such point.
+  pub fn move_to(&mut self, id:&Name, p:Point) -> bool {
+    match self.remove(id) {
+      None => false,
+      Some((_, x)) => { self.insert(id.clone(), p, x); true }
+    }
+  }
+
+  /// The points within the rectangle; see `range`.
+  pub fn range(&self, query:Rect) -> Art<Vec<Entry<X>>> {
+    range(self.root.clone(), self.bounds, query)
+  }
+
+  /// The point nearest to `p`; see `nearest`.
+  pub fn nearest(&self, p:Point) -> Art<Option<Entry<X>>> {
+    nearest(self.root.clone(), self.bounds, p)
+  }
+}
+
+/// The points of quadrant `quad` (with region `bounds`) that are
+/// within `query`, in the order of their quadrants.  The thunk forces
+/// the range queries of those sub-quadrants that intersect `query`.
+pub fn range<X:'static+Hash+Eq+Debug+Clone>
+  (quad:Art<Quad<X>>, bounds:Rect, query:Rect) -> Art<Vec<Entry<X>>>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::quadtree::range"),
+        Rc::new(Box::new(|(quad, bounds, query):(Art<Quad<X>>, Rect, Rect), ()| {
+          match force(&quad) {
+            Quad::Empty => vec![],
+            Quad::Leaf(es) => es.into_iter().filter(|e| query.contains(&e.1)).collect(),
+            Quad::Split(subs) => {
+              let mut out = vec![];
+              for (i, sub) in subs.iter().enumerate() {
+                let sub_bounds = bounds.quadrant(i);
+                if sub_bounds.intersects(&query) {
+                  out.extend(force(&range(sub.clone(), sub_bounds, query)))
+                }
+              }
+              out
+            }
+          }
+        })),
+        (quad, bounds, query), ())
+}
+
+/// The point of quadrant `quad` (with region `bounds`) that is nearest
+/// to `p`; among equally-near points, the first in the order of their
+/// quadrants.  The thunk forces the nearest-neighbor queries of the
+/// sub-quadrants in the order of their distance to `p`, and prunes
+/// those farther than the nearest point found so far.
+pub fn nearest<X:'static+Hash+Eq+Debug+Clone>
+  (quad:Art<Quad<X>>, bounds:Rect, p:Point) -> Art<Option<Entry<X>>>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::quadtree::nearest"),
+        Rc::new(Box::new(|(quad, bounds, p):(Art<Quad<X>>, Rect, Point), ()| {
+          let mut best : Option<(i64, Entry<X>)> = None;
+          match force(&quad) {
+            Quad::Empty => (),
+            Quad::Leaf(es) => for e in es {
+              let d = dist2(&e.1, &p);
+              if best.as_ref().map_or(true, |b| d < b.0) { best = Some((d, e)) }
+            },
+            Quad::Split(subs) => {
+              let mut order : Vec<(i64, usize)> = (0..4).map(|i| (bounds.quadrant(i).dist2(&p), i)).collect();
+              order.sort();
+              for (d, i) in order {
+                if best.as_ref().map_or(false, |b| d > b.0) { break };
+                if let Some(e) = force(&nearest(subs[i].clone(), bounds.quadrant(i), p)) {
+                  let d = dist2(&e.1, &p);
+                  if best.as_ref().map_or(true, |b| d < b.0) { best = Some((d, e)) }
+                }
+              }
+            }
+          };
+          best.map(|(_, e)| e)
+        })),
+        (quad, bounds, p), ())
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_quadtree () {
+  manage::init_dcg();
+  let bounds = Rect{x0:0, y0:0, x1:64, y1:64};
+  let mut qt : QuadTree<usize> = QuadTree::new(name_of_str("qt"), bounds, 3);
+  let pts : Vec<Point> = vec![(1,1), (3,60), (30,30), (33,33), (40,2), (63,63), (20,45), (50,20)];
+  for (i, p) in pts.iter().enumerate() { qt.insert(name_of_usize(i), *p, i) }
+  let query = Rect{x0:10, y0:10, x1:45, y1:50};
+  let brute = |qt:&QuadTree<usize>| -> Vec<usize> {
+    let mut v : Vec<usize> = (0..pts.len()).filter(|i| query.contains(&qt.position(&name_of_usize(*i)).unwrap())).collect();
+    v.sort(); v
+  };
+  let ids = |es:Vec<Entry<usize>>| -> Vec<usize> { let mut v : Vec<usize> = es.into_iter().map(|e| e.2).collect(); v.sort(); v };
+  let all = qt.range(bounds);
+  let in_query = qt.range(query);
+  assert_eq!(ids(force(&all)), (0..pts.len()).collect::<Vec<_>>());
+  assert_eq!(ids(force(&in_query)), brute(&qt));
+  assert_eq!(force(&qt.nearest((32,31))).map(|e| e.2), Some(2));
+
+  // Moving a point within its leaf re-runs the range queries along its path.
+  let _ = manage::reset_counters();
+  assert!(qt.move_to(&name_of_usize(2), (31,29)));
+  assert_eq!(ids(force(&all)).len(), pts.len());
+  assert_eq!(manage::counters().unwrap().eval, 4);
+
+  // Moving a point across quadrants.
+  assert!(qt.move_to(&name_of_usize(3), (12,12)));
+  assert_eq!(ids(force(&in_query)), brute(&qt));
+  assert_eq!(force(&qt.nearest((11,13))).map(|e| e.2), Some(3));
+  assert_eq!(force(&qt.nearest((60,60))).map(|e| e.2), Some(5));
+  assert_eq!(qt.remove(&name_of_usize(5)), Some(((63,63), 5)));
+  assert_eq!(force(&qt.nearest((60,60))).map(|e| e.2), Some(7));
+  assert!(!qt.move_to(&name_of_usize(5), (0,0)));
+}
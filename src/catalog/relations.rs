@@ -0,0 +1,314 @@
+//! Incremental relational operators: select, project, join and
+//! group-by, over articulated tables of records.
+//!
+//! A `Table` holds its rows in cells, one per row, at the leaves of a
+//! balanced tree of cells (a `Rel`).  The operators are (structural)
+//! thunks, one for each node of the tree that they consume: `select`
+//! and `project` produce relations of the same shape, with one thunk
+//! per row; `group_by` aggregates the rows of each subtree, merging
+//! the aggregates of its two halves; and `index` (the right side of
+//! `join`) puts the rows of each key into a cell of their own.
+//!
+//! Hence, after an edit to one row, re-forcing a view re-evaluates
+//! one thunk per row of the view that depends on that row, plus the
+//! aggregates along the path from that row to the root, as in the
+//! maintenance of database views.
+//!
+//! Each operator takes a name, `op`, that identifies it among the
+//! other operators over the same relation; distinct operators need
+//! distinct names.
+
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+
+/// A relation: a tree whose leaves are rows.
+pub type Rel<R> = Art<Rows<R>>;
+
+/// A node of a relation.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Rows<R> {
+  /// A subtree without rows.
+  Empty,
+  /// A named row, which is absent (`None`) after its removal.
+  Row(Name, Art<Option<R>>),
+  /// A subtree, with its two halves.
+  Bin(Rel<R>, Rel<R>),
+}
+
+/// A table of named rows, mutated from the outer layer.  Its rows
+/// occupy slots, in the order of their insertion; its tree is a
+/// complete binary tree over these slots, whose nodes are cells that
+/// the table allocates as the number of slots grows.
+#[derive(Debug,Clone)]
+pub struct Table<R> {
+  rows_ns:Name,
+  nodes_ns:Name,
+  root:Rel<R>,
+  cap:usize,
+  slots:Vec<(Name, Art<Option<R>>)>,
+  index:HashMap<Name, usize>,
+  nodes:HashMap<(usize, usize), Rel<R>>,
+}
+
+impl<R:'static+Hash+Eq+Debug+Clone> Table<R> {
+  /// An empty table, whose (root) relation is a cell named `name`.
+  pub fn new(name:Name) -> Self {
+    let (rows_ns, nodes_ns) = name_fork(name.clone());
+    Table{rows_ns:rows_ns, nodes_ns:nodes_ns, root:cell(name, Rows::Empty), cap:1,
+          slots:vec![], index:HashMap::new(), nodes:HashMap::new()}
+  }
+  /// The relation of the table's rows; it remains the same art as the
+  /// table changes.
+  pub fn rel(&self) -> Rel<R> { self.root.clone() }
+  /// The number of rows (including the removed ones).
+  pub fn len(&self) -> usize { self.slots.len() }
+
+  /// Inserts the named row, replacing any prior row of that name.
+  pub fn insert(&mut self, id:Name, r:R) {
+    if let Some(&i) = self.index.get(&id) { return set(&self.slots[i].1, Some(r)) };
+    let i = self.slots.len();
+    let row = cell(name_pair(self.rows_ns.clone(), id.clone()), Some(r));
+    self.slots.push((id.clone(), row));
+    self.index.insert(id, i);
+    while self.cap <= i { self.cap *= 2 };
+    let cap = self.cap;
+    let rows = self.build(0, cap, i);
+    set(&self.root, rows)
+  }
+
+  /// Removes the named row; returns false if there is no such row.
+  pub fn remove(&mut self, id:&Name) -> bool {
+    match self.index.get(id) {
+      None => false,
+      Some(&i) => { set(&self.slots[i].1, None); true }
+    }
+  }
+
+  /// The node of the slots `lo..lo+size`, rebuilding the nodes that
+  /// contain (the new) slot `i`.
+  fn build(&mut self, lo:usize, size:usize, i:usize) -> Rows<R> {
+    if size == 1 {
+      return match self.slots.get(lo) {
+        None => Rows::Empty,
+        Some(&(ref id, ref row)) => Rows::Row(id.clone(), row.clone()),
+      }
+    };
+    let half = size / 2;
+    Rows::Bin(self.child(lo, half, i), self.child(lo + half, half, i))
+  }
+
+  fn child(&mut self, lo:usize, size:usize, i:usize) -> Rel<R> {
+    if lo >= self.slots.len() { return put(Rows::Empty) };
+    if lo <= i && i < lo + size || ! self.nodes.contains_key(&(lo, size)) {
+      let rows = self.build(lo, size, i);
+      let name = name_pair(self.nodes_ns.clone(), name_pair(name_of_usize(lo), name_of_usize(size)));
+      let node = cell(name, rows);
+      self.nodes.insert((lo, size), node.clone());
+      node
+    } else {
+      self.nodes[&(lo, size)].clone()
+    }
+  }
+}
+
+/// The rows of the relation (forcing it), in order.
+pub fn rows<R:'static+Hash+Eq+Debug+Clone>(rel:&Rel<R>) -> Vec<(Name, R)> {
+  let mut out = vec![];
+  fn rec<R:'static+Hash+Eq+Debug+Clone>(rel:&Rel<R>, out:&mut Vec<(Name, R)>) {
+    match force(rel) {
+      Rows::Empty => (),
+      Rows::Row(id, row) => if let Some(r) = force(&row) { out.push((id, r)) },
+      Rows::Bin(l, r) => { rec(&l, out); rec(&r, out) },
+    }
+  }
+  rec(rel, &mut out);
+  out
+}
+
+/// The relation of the rows `f(r)` for the rows `r` of `rel`, when not
+/// `None`; the common core of `select`, `project` and `join`.
+pub fn filter_map<R:'static+Hash+Eq+Debug+Clone,
+                  S:'static+Hash+Eq+Debug+Clone,
+                  F:'static+Fn(&R) -> Option<S>>
+  (op:Name, rel:&Rel<R>, f:F) -> Rel<S>
+{
+  filter_map_rc(op, rel.clone(), Rc::new(f))
+}
+
+fn filter_map_rc<R:'static+Hash+Eq+Debug+Clone,
+                 S:'static+Hash+Eq+Debug+Clone,
+                 F:'static+Fn(&R) -> Option<S>>
+  (op:Name, rel:Rel<R>, f:Rc<F>) -> Rel<S>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::relations::filter_map"),
+        Rc::new(Box::new(|(op, rel):(Name, Rel<R>), f:Rc<F>| {
+          match force(&rel) {
+            Rows::Empty => Rows::Empty,
+            Rows::Row(id, row) => Rows::Row(id, thunk(
+              NameChoice::Structural,
+              prog_pt!("catalog::relations::filter_map_row"),
+              Rc::new(Box::new(|(_, row):(Name, Art<Option<R>>), f:Rc<F>| force(&row).and_then(|r| f(&r)))),
+              (op, row), f)),
+            Rows::Bin(l, r) => Rows::Bin(filter_map_rc(op.clone(), l, f.clone()), filter_map_rc(op, r, f)),
+          }
+        })),
+        (op, rel), f)
+}
+
+/// The relation of the rows of `rel` that satisfy `pred`.
+pub fn select<R:'static+Hash+Eq+Debug+Clone,
+              F:'static+Fn(&R) -> bool>
+  (op:Name, rel:&Rel<R>, pred:F) -> Rel<R>
+{
+  filter_map(op, rel, move |r| if pred(r) { Some(r.clone()) } else { None })
+}
+
+/// The relation of the rows `f(r)`, for the rows `r` of `rel`.
+pub fn project<R:'static+Hash+Eq+Debug+Clone,
+               S:'static+Hash+Eq+Debug+Clone,
+               F:'static+Fn(&R) -> S>
+  (op:Name, rel:&Rel<R>, f:F) -> Rel<S>
+{
+  filter_map(op, rel, move |r| Some(f(r)))
+}
+
+/// For each key of the rows of `rel`, the combination (by `combine`,
+/// which must be associative) of the values of its rows, by `val`.
+/// Each node of `rel` has a thunk, which merges the aggregates of the
+/// two halves of the node.
+pub fn group_by<R:'static+Hash+Eq+Debug+Clone,
+                K:'static+Ord+Hash+Eq+Debug+Clone,
+                A:'static+Hash+Eq+Debug+Clone,
+                FK:'static+Fn(&R) -> K,
+                FV:'static+Fn(&R) -> A,
+                FC:'static+Fn(&A, &A) -> A>
+  (op:Name, rel:&Rel<R>, key:FK, val:FV, combine:FC) -> Art<BTreeMap<K, A>>
+{
+  group_by_rc(op, rel.clone(), Rc::new((key, val, combine)))
+}
+
+fn group_by_rc<R:'static+Hash+Eq+Debug+Clone,
+               K:'static+Ord+Hash+Eq+Debug+Clone,
+               A:'static+Hash+Eq+Debug+Clone,
+               FK:'static+Fn(&R) -> K,
+               FV:'static+Fn(&R) -> A,
+               FC:'static+Fn(&A, &A) -> A>
+  (op:Name, rel:Rel<R>, fs:Rc<(FK, FV, FC)>) -> Art<BTreeMap<K, A>>
+{
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::relations::group_by"),
+        Rc::new(Box::new(|(op, rel):(Name, Rel<R>), fs:Rc<(FK, FV, FC)>| {
+          let mut groups = BTreeMap::new();
+          match force(&rel) {
+            Rows::Empty => (),
+            Rows::Row(_, row) => if let Some(r) = force(&row) { groups.insert((fs.0)(&r), (fs.1)(&r)); },
+            Rows::Bin(l, r) => {
+              groups = force(&group_by_rc(op.clone(), l, fs.clone()));
+              for (k, a) in force(&group_by_rc(op, r, fs.clone())) {
+                let a = match groups.get(&k) { None => a, Some(b) => (fs.2)(b, &a) };
+                groups.insert(k, a);
+              }
+            }
+          };
+          groups
+        })),
+        (op, rel), fs)
+}
+
+fn name_of_key<K:Hash>(op:&Name, k:&K) -> Name {
+  let mut hasher = DefaultHasher::new();
+  k.hash(&mut hasher);
+  name_pair(op.clone(), name_of_hash64(hasher.finish()))
+}
+
+/// An index of the rows of `rel`, by `key`: for each key, a cell with
+/// its rows (in order).  The cell of a key changes only when its rows
+/// change, so the observers of one key do not observe the others.
+pub fn index<R:'static+Hash+Eq+Debug+Clone,
+             K:'static+Ord+Hash+Eq+Debug+Clone,
+             FK:'static+Fn(&R) -> K>
+  (op:Name, rel:&Rel<R>, key:FK) -> Art<BTreeMap<K, Art<Vec<R>>>>
+{
+  let groups = group_by(name_pair(op.clone(), name_of_str("groups")), rel, key,
+                        |r| vec![r.clone()],
+                        |a, b| { let mut v = a.clone(); v.extend(b.iter().cloned()); v });
+  thunk(NameChoice::Structural,
+        prog_pt!("catalog::relations::index"),
+        Rc::new(Box::new(|(op, groups):(Name, Art<BTreeMap<K, Vec<R>>>), ()| {
+          force(&groups).into_iter().map(|(k, rs)| {
+            let rs = cell(name_of_key(&op, &k), rs);
+            (k, rs)
+          }).collect()
+        })),
+        (op, groups), ())
+}
+
+/// The (inner) join of `left` and `right`, on equal keys: each row of
+/// `left` whose key (by `left_key`) matches some rows of `right` (by
+/// `right_key`), with those rows.  The rows of `right` are indexed
+/// (see `index`), so each row of the join observes the rows of its
+/// key alone.
+pub fn join<R:'static+Hash+Eq+Debug+Clone,
+            S:'static+Hash+Eq+Debug+Clone,
+            K:'static+Ord+Hash+Eq+Debug+Clone,
+            FL:'static+Fn(&R) -> K,
+            FR:'static+Fn(&S) -> K>
+  (op:Name, left:&Rel<R>, right:&Rel<S>, left_key:FL, right_key:FR) -> Rel<(R, Vec<S>)>
+{
+  let index = index(name_pair(op.clone(), name_of_str("index")), right, right_key);
+  filter_map(op, left, move |r| {
+    match force(&index).get(&left_key(r)) {
+      None => None,
+      Some(ss) => { let ss = force(ss); if ss.is_empty() { None } else { Some((r.clone(), ss)) } }
+    }
+  })
+}
+
+#[test]
+fn test_relations () {
+  manage::init_dcg();
+  // Employees (name, dept, salary), and the floors of the departments.
+  let mut emps : Table<(usize, usize, usize)> = Table::new(name_of_str("emps"));
+  let mut depts : Table<(usize, usize)> = Table::new(name_of_str("depts"));
+  for e in 0..64 { emps.insert(name_of_usize(e), (e, e % 4, 100 + e)) }
+  for d in 0..4 { depts.insert(name_of_usize(d), (d, 10 + d)) }
+
+  let rich = select(name_of_str("rich"), &emps.rel(), |e| e.2 >= 150);
+  let names = project(name_of_str("names"), &rich, |e| e.0);
+  let payroll = group_by(name_of_str("payroll"), &emps.rel(), |e| e.1, |e| e.2, |a, b| a + b);
+  let floors = join(name_of_str("floors"), &emps.rel(), &depts.rel(), |e| e.1, |d| d.0);
+  let vals = |rel:&Rel<usize>| rows(rel).into_iter().map(|(_, e)| e).collect::<Vec<_>>();
+
+  assert_eq!(vals(&names), (50..64).collect::<Vec<_>>());
+  let total = |d:usize| (0..64).filter(|e| e % 4 == d).map(|e| 100 + e).sum::<usize>();
+  assert_eq!(force(&payroll), (0..4).map(|d| (d, total(d))).collect());
+  assert_eq!(rows(&floors).len(), 64);
+  assert_eq!(rows(&floors)[5].1, ((5, 1, 105), vec![(1, 11)]));
+
+  // Editing one row re-evaluates the thunks along its path, only.
+  manage::reset_counters();
+  emps.insert(name_of_usize(49), (49, 1, 1000));
+  assert_eq!(vals(&names), (49..64).collect::<Vec<_>>());
+  assert!(manage::counters().unwrap().eval <= 4, "{:?}", manage::counters());
+  manage::reset_counters();
+  assert_eq!(force(&payroll)[&1], total(1) - 149 + 1000);
+  assert!(manage::counters().unwrap().eval <= 8, "{:?}", manage::counters());
+  manage::reset_counters();
+  depts.insert(name_of_usize(2), (2, 20));
+  assert_eq!(rows(&floors)[6].1, ((6, 2, 106), vec![(2, 20)]));
+  assert!(manage::counters().unwrap().eval <= 16 + 8, "{:?}", manage::counters());
+
+  // Removals, and insertions that grow the table.
+  assert!(emps.remove(&name_of_usize(0)));
+  emps.insert(name_of_usize(64), (64, 0, 500));
+  assert_eq!(force(&payroll)[&0], total(0) - 100 + 500);
+  assert_eq!(rows(&floors).len(), 64);
+  assert_eq!(rows(&floors).last().unwrap().1, ((64, 0, 500), vec![(0, 10)]));
+}
@@ -0,0 +1,297 @@
+//! An incremental spreadsheet: a grid of cells, whose contents are
+//! values or formulas over other cells.
+//!
+//! Each cell of a `Sheet` has its content in a (reference) cell, and
+//! its value in a (nominal) thunk, which evaluates its formula.  The
+//! formulas are closures, which get the values of the cells that they
+//! reference from a `Refs`; the engine records these references as the
+//! dependencies of the cell's thunk.  Hence, after an edit, querying a
+//! cell re-evaluates the formulas that (transitively) depend on the
+//! edit, only, and stops at the formulas whose inputs keep their
+//! values.
+//!
+//! A formula that (transitively) references its own cell has no value:
+//! the engine detects the cycle, and the cells on it give
+//! `Error::Cycle`, until an edit breaks the cycle.
+//!
+//! ```
+//! use adapton::engine::*;
+//! use adapton::catalog::spreadsheet::*;
+//! manage::init_dcg();
+//!
+//! let mut sheet = Sheet::new(name_of_str("sheet"), 4, 4);
+//! let (a1, a2, a3) = (Addr::parse("A1"), Addr::parse("A2"), Addr::parse("A3"));
+//! sheet.set_value(a1, 2);
+//! sheet.set_value(a2, 3);
+//! sheet.set_formula(a3, "=SUM(A1:A2)", move |refs| refs.sum(a1, a2));
+//! assert_eq!(sheet.get(a3), Ok(5));
+//! sheet.set_value(a1, 10);
+//! assert_eq!(sheet.get(a3), Ok(13));
+//!
+//! // A cycle, and its repair.
+//! sheet.set_formula(a1, "=A3", move |refs| refs.get(a3));
+//! assert!(match sheet.get(a3) { Err(Error::Cycle(_)) => true, _ => false });
+//! sheet.set_value(a1, 1);
+//! assert_eq!(sheet.get(a3), Ok(4));
+//! ```
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use macros::* ;
+use adapton::engine::* ;
+
+/// The address of a cell: its column and row (from zero).
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq,PartialOrd,Ord)]
+pub struct Addr {
+  pub col:usize,
+  pub row:usize,
+}
+
+impl Addr {
+  /// The address that `s` gives in "A1" notation (e.g., `"B3"`, or
+  /// `"AA10"`); panics if `s` is not in this notation.
+  pub fn parse(s:&str) -> Addr {
+    let digits = s.find(|c:char| c.is_digit(10)).unwrap_or(s.len());
+    let (letters, digits) = s.split_at(digits);
+    assert!(! letters.is_empty() && letters.chars().all(|c| c.is_ascii_uppercase()),
+            "Addr::parse: no column in {:?}", s);
+    let col = letters.bytes().fold(0, |col, b| col * 26 + (b - b'A') as usize + 1) - 1;
+    let row : usize = digits.parse().ok().filter(|&r| r > 0)
+      .unwrap_or_else(|| panic!("Addr::parse: no row in {:?}", s));
+    Addr{col:col, row:row - 1}
+  }
+}
+
+impl fmt::Display for Addr {
+  fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+    let mut letters = vec![];
+    let mut col = self.col + 1;
+    while col > 0 {
+      letters.push((b'A' + ((col - 1) % 26) as u8) as char);
+      col = (col - 1) / 26;
+    }
+    for c in letters.into_iter().rev() { write!(f, "{}", c)? };
+    write!(f, "{}", self.row + 1)
+  }
+}
+
+/// Why a cell has no value.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Error {
+  /// The formula of the cell (transitively) references this cell,
+  /// which (transitively) references the former; i.e., both are on a
+  /// cycle.
+  Cycle(Addr),
+  /// A formula references this address, which is outside the sheet.
+  Bounds(Addr),
+  /// The formula of the cell at this address fails, with a message
+  /// (see `Refs::fail`).
+  Eval(Addr, String),
+}
+
+/// The value of a cell.
+pub type Value = Result<isize, Error>;
+
+/// A formula: its source text (for display), and its closure, which
+/// computes the cell's value.  Formulas are equal when they come from
+/// the same call to `Sheet::set_formula`.
+#[derive(Clone)]
+pub struct Formula {
+  id:usize,
+  src:String,
+  eval:Rc<Fn(&Refs) -> Value>,
+}
+
+impl Formula {
+  /// The source text of the formula.
+  pub fn src(&self) -> &str { &self.src }
+}
+
+impl fmt::Debug for Formula {
+  fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "Formula({:?})", self.src) }
+}
+impl PartialEq for Formula {
+  fn eq(&self, other:&Formula) -> bool { self.id == other.id }
+}
+impl Eq for Formula { }
+impl Hash for Formula {
+  fn hash<H:Hasher>(&self, state:&mut H) { self.id.hash(state) }
+}
+
+/// The content of a cell.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Content {
+  /// No content; its value is zero.
+  Empty,
+  /// A constant value.
+  Value(isize),
+  /// A formula, over other cells.
+  Formula(Formula),
+}
+
+/// The cells of a sheet, shared by the thunks of its values.
+#[derive(Debug)]
+struct Grid {
+  name:Name,
+  cols:usize,
+  rows:usize,
+  contents:Vec<Art<Content>>,
+}
+
+impl Grid {
+  fn content(&self, a:Addr) -> Option<&Art<Content>> {
+    if a.col < self.cols && a.row < self.rows { Some(&self.contents[a.row * self.cols + a.col]) }
+    else { None }
+  }
+}
+
+/// The value of the cell at `a`, in a nominal thunk (so, the same art
+/// for all the references to `a`).
+fn value(grid:&Rc<Grid>, a:Addr) -> Art<Value> {
+  let content = grid.content(a).unwrap().clone();
+  thunk(NameChoice::Nominal(name_pair(grid.name.clone(), name_pair(name_of_usize(a.col), name_of_usize(a.row)))),
+        prog_pt!("catalog::spreadsheet::value"),
+        Rc::new(Box::new(|(a, content):(Addr, Art<Content>), grid:Rc<Grid>| {
+          match force(&content) {
+            Content::Empty => Ok(0),
+            Content::Value(v) => Ok(v),
+            Content::Formula(f) => (f.eval)(&Refs{grid:grid, at:a}),
+          }
+        })),
+        (a, content), grid.clone())
+}
+
+/// The references of a formula: the values of the other cells.
+pub struct Refs {
+  grid:Rc<Grid>,
+  at:Addr,
+}
+
+impl Refs {
+  /// The address of the formula's own cell.
+  pub fn at(&self) -> Addr { self.at }
+
+  /// The value of the cell at `a`, which becomes a dependency of the
+  /// formula.
+  pub fn get(&self, a:Addr) -> Value {
+    if self.grid.content(a).is_none() { return Err(Error::Bounds(a)) };
+    force_cycle(&value(&self.grid, a), Some(Err(Error::Cycle(a))))
+  }
+
+  /// The sum of the values of the cells of the rectangle from `from`
+  /// to `to` (inclusive); fails with the first error of these cells.
+  pub fn sum(&self, from:Addr, to:Addr) -> Value {
+    let mut sum = 0;
+    for row in from.row..to.row + 1 {
+      for col in from.col..to.col + 1 { sum += self.get(Addr{col:col, row:row})? }
+    }
+    Ok(sum)
+  }
+
+  /// The failure of the formula, with the given message.
+  pub fn fail<S:Into<String>>(&self, msg:S) -> Value {
+    Err(Error::Eval(self.at, msg.into()))
+  }
+}
+
+/// A sheet of cells, edited from the outer layer.
+#[derive(Debug)]
+pub struct Sheet {
+  grid:Rc<Grid>,
+  formulas:usize,
+}
+
+impl Sheet {
+  /// An empty sheet, with the given numbers of columns and rows, whose
+  /// cells are in the namespace `name`.
+  pub fn new(name:Name, cols:usize, rows:usize) -> Sheet {
+    let (contents_ns, values_ns) = name_fork(name);
+    let contents = (0..rows * cols).map(|i| {
+      cell(name_pair(contents_ns.clone(), name_of_usize(i)), Content::Empty)
+    }).collect();
+    Sheet{grid:Rc::new(Grid{name:values_ns, cols:cols, rows:rows, contents:contents}), formulas:0}
+  }
+
+  /// The numbers of columns and rows of the sheet.
+  pub fn size(&self) -> (usize, usize) { (self.grid.cols, self.grid.rows) }
+
+  fn content(&self, a:Addr) -> &Art<Content> {
+    self.grid.content(a).unwrap_or_else(|| panic!("Sheet: {} is outside the sheet", a))
+  }
+
+  /// Sets the content of the cell at `a`.
+  pub fn set(&mut self, a:Addr, c:Content) { set(self.content(a), c) }
+
+  /// Sets the cell at `a` to a constant value.
+  pub fn set_value(&mut self, a:Addr, v:isize) { self.set(a, Content::Value(v)) }
+
+  /// Sets the cell at `a` to a formula, with the given source text.
+  pub fn set_formula<S:Into<String>, F:'static+Fn(&Refs) -> Value>(&mut self, a:Addr, src:S, eval:F) {
+    self.formulas += 1;
+    let f = Formula{id:self.formulas, src:src.into(), eval:Rc::new(eval)};
+    self.set(a, Content::Formula(f))
+  }
+
+  /// Clears the cell at `a`.
+  pub fn clear(&mut self, a:Addr) { self.set(a, Content::Empty) }
+
+  /// The content of the cell at `a` (without observing it).
+  pub fn content_of(&self, a:Addr) -> Content { read(self.content(a)) }
+
+  /// The value of the cell at `a`, re-evaluating the formulas that the
+  /// edits since the last query affect.
+  pub fn get(&self, a:Addr) -> Value {
+    let _ = self.content(a);
+    force_cycle(&value(&self.grid, a), Some(Err(Error::Cycle(a))))
+  }
+}
+
+#[test]
+fn test_spreadsheet () {
+  manage::init_dcg();
+  assert_eq!(Addr::parse("A1"), Addr{col:0, row:0});
+  assert_eq!(Addr::parse("AB12"), Addr{col:27, row:11});
+  assert_eq!(format!("{}", Addr{col:27, row:11}), "AB12");
+
+  // A column of values, their running sums, and their total.
+  let mut sheet = Sheet::new(name_of_str("sheet"), 3, 10);
+  for row in 0..10 {
+    let a = Addr{col:0, row:row};
+    sheet.set_value(a, row as isize);
+    let prior = Addr{col:1, row:row.wrapping_sub(1)};
+    sheet.set_formula(Addr{col:1, row:row}, format!("=A{}+B{}", row + 1, row), move |refs| {
+      Ok(refs.get(a)? + if row == 0 { 0 } else { refs.get(prior)? })
+    });
+  }
+  let (first, last) = (Addr::parse("A1"), Addr::parse("A10"));
+  sheet.set_formula(Addr::parse("C1"), "=SUM(A1:A10)", move |refs| refs.sum(first, last));
+  sheet.set_formula(Addr::parse("C2"), "=B10=C1", |refs| {
+    if refs.get(Addr::parse("B10"))? == refs.get(Addr::parse("C1"))? { Ok(1) } else { refs.fail("unequal") }
+  });
+  assert_eq!(sheet.get(Addr::parse("B10")), Ok(45));
+  assert_eq!(sheet.get(Addr::parse("C2")), Ok(1));
+
+  // An edit re-evaluates its dependents, only.
+  manage::reset_counters();
+  sheet.set_value(Addr::parse("A9"), 100);
+  assert_eq!(sheet.get(Addr::parse("C1")), Ok(45 - 8 + 100));
+  assert!(manage::counters().unwrap().eval <= 2, "{:?}", manage::counters());
+  assert_eq!(sheet.get(Addr::parse("C2")), Ok(1));
+  assert!(manage::counters().unwrap().eval <= 2 + 3, "{:?}", manage::counters());
+
+  // References outside the sheet, and failing formulas.
+  sheet.set_formula(Addr::parse("C3"), "=Z1", |refs| refs.get(Addr::parse("Z1")));
+  assert_eq!(sheet.get(Addr::parse("C3")), Err(Error::Bounds(Addr::parse("Z1"))));
+  sheet.set_formula(Addr::parse("C1"), "=0", |_| Ok(0));
+  assert_eq!(sheet.get(Addr::parse("C2")), Err(Error::Eval(Addr::parse("C2"), "unequal".to_string())));
+
+  // A cycle, through two cells, and its repair.
+  sheet.set_formula(Addr::parse("A1"), "=B2", |refs| refs.get(Addr::parse("B2")));
+  assert!(match sheet.get(Addr::parse("B10")) { Err(Error::Cycle(_)) => true, _ => false });
+  assert!(match sheet.get(Addr::parse("A1")) { Err(Error::Cycle(_)) => true, _ => false });
+  sheet.clear(Addr::parse("A1"));
+  assert_eq!(sheet.get(Addr::parse("B10")), Ok(45 - 8 + 100));
+  assert_eq!(sheet.content_of(Addr::parse("A1")), Content::Empty);
+}
@@ -23,6 +23,18 @@ pub enum Trie<X> {
     Art(Art<Trie<X>>),
 }
 
+/// Tries differ when their roots differ in kind, name or articulation.
+impl<X:PartialEq> FastEq for Trie<X> {
+    fn fast_eq(&self, other:&Self) -> Option<bool> {
+        match (self, other) {
+            (&Trie::Art(ref a), &Trie::Art(ref b)) => a.fast_eq(b),
+            (&Trie::Name(ref n, _), &Trie::Name(ref m, _)) if n != m => Some(false),
+            _ if ::std::mem::discriminant(self) != ::std::mem::discriminant(other) => Some(false),
+            _ => None,
+        }
+    }
+}
+
 pub const PLACEMENT_SEED: u64 = 42;
 
 /// Metadata held by the root node.
@@ -0,0 +1,219 @@
+/*! Differential debugging: two engines, in lockstep.
+
+A `DualEngine` forwards each operation (`cell`, `set`, `thunk` and
+`force`) to two inner engines, and compares the results of each force.
+At the first difference, it panics with a `DualDivergence`, which
+gives the diverging operation, the values of both engines, and the log
+of all the operations that led to it.
+
+Typically, the second engine is the reference: `DualEngine::from_scratch`
+pairs a DCG with another DCG that drops its cached results before each
+force, i.e., that re-computes everything from scratch.  Hence, a
+divergence witnesses an incremental result that is not from-scratch
+consistent (e.g., due to a thunk with an unobserved dependency, or a
+name that two thunks share by mistake).
+
+Each engine gets its own arts: A `DualArt` holds one art for each
+engine, and the arguments of thunks are `DualArg`s, which give the
+arts (and other values) of each side.  Thunk producers run on one
+engine at a time, using the ordinary functions of `engine`.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use std::rc::Rc;
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::dual::*;
+
+let mut dual = DualEngine::from_scratch();
+let c = dual.cell(name_of_str("c"), 2);
+let t = dual.thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                   Rc::new(Box::new(|c:Art<usize>, ()| force(&c) * 10)),
+                   c.clone(), ());
+assert_eq!(dual.force(&t), 20);
+dual.set(&c, 3);
+assert_eq!(dual.force(&t), 30);
+# }
+```
+*/
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::panic::panic_any;
+use std::rc::Rc;
+
+use engine::{Art, Engine, Name, NameChoice, manage};
+use engine;
+use macros::ProgPt;
+
+/// An art of a `DualEngine`: one art for each of its engines.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub struct DualArt<T> {
+    arts : (Art<T>, Art<T>),
+    /// The operation (in `DualEngine::ops`) that created the art.
+    op   : usize,
+}
+
+impl<T> DualArt<T> {
+    /// The art of the given side (`0` or `1`) of the `DualEngine`.
+    pub fn side(&self, side:usize) -> &Art<T> {
+        if side == 0 { &self.arts.0 } else { &self.arts.1 }
+    }
+}
+
+/// The arguments of the thunks of a `DualEngine`, which give one
+/// argument for each of its engines (e.g., the arts of that engine).
+pub trait DualArg : Debug {
+    /// The argument for one engine.
+    type Side : Hash+Eq+Debug+Clone+'static;
+    /// The argument for the given side (`0` or `1`).
+    fn side(&self, side:usize) -> Self::Side;
+}
+
+impl<T:Hash+Eq+Debug+Clone+'static> DualArg for DualArt<T> {
+    type Side = Art<T>;
+    fn side(&self, side:usize) -> Art<T> { DualArt::side(self, side).clone() }
+}
+
+impl DualArg for () {
+    type Side = ();
+    fn side(&self, _side:usize) -> () { () }
+}
+
+impl<X:DualArg, Y:DualArg> DualArg for (X, Y) {
+    type Side = (X::Side, Y::Side);
+    fn side(&self, side:usize) -> Self::Side { (self.0.side(side), self.1.side(side)) }
+}
+
+impl<X:DualArg> DualArg for Vec<X> {
+    type Side = Vec<X::Side>;
+    fn side(&self, side:usize) -> Self::Side { self.iter().map(|x| x.side(side)).collect() }
+}
+
+/// A value that both engines share (it holds no arts).
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub struct Same<T>(pub T);
+
+impl<T:Hash+Eq+Debug+Clone+'static> DualArg for Same<T> {
+    type Side = T;
+    fn side(&self, _side:usize) -> T { self.0.clone() }
+}
+
+/// The (structured) panic payload that a `DualEngine` raises when its
+/// engines force different values.
+#[derive(Debug,Clone)]
+pub struct DualDivergence {
+    /// The index of the diverging force, in `ops`.
+    pub op : usize,
+    /// The log of operations, up to (and including) the diverging force.
+    pub ops : Vec<String>,
+    /// The value forced by the first engine (rendered).
+    pub first : String,
+    /// The value forced by the second engine (rendered).
+    pub second : String,
+}
+
+/// Two engines, in lockstep; see the module documentation.
+pub struct DualEngine {
+    engines : (Option<Engine>, Option<Engine>),
+    from_scratch : bool,
+    ops : Vec<String>,
+}
+
+fn fresh_dcg () -> Engine {
+    let prior = manage::init_dcg();
+    manage::use_engine(prior)
+}
+
+impl DualEngine {
+    /// Runs the given engines in lockstep.  (The `Naive` engine does
+    /// not support `set`.)
+    pub fn new (first:Engine, second:Engine) -> Self {
+        DualEngine{engines:(Some(first), Some(second)), from_scratch:false, ops:vec![]}
+    }
+
+    /// Runs a fresh DCG in lockstep with a reference DCG that drops
+    /// its cached results before each force (see `manage::clear_results`).
+    pub fn from_scratch () -> Self {
+        DualEngine{engines:(Some(fresh_dcg()), Some(fresh_dcg())), from_scratch:true, ops:vec![]}
+    }
+
+    /// The log of operations so far.
+    pub fn ops (&self) -> &[String] { &self.ops }
+
+    /// The two engines, ending the lockstep.
+    pub fn engines (self) -> (Engine, Engine) {
+        (self.engines.0.unwrap(), self.engines.1.unwrap())
+    }
+
+    /// Runs `body` with the engine of the given side installed.
+    fn on<R,F:FnOnce() -> R> (&mut self, side:usize, body:F) -> R {
+        let engine = if side == 0 { self.engines.0.take() } else { self.engines.1.take() };
+        let prior = manage::use_engine(engine.expect("DualEngine: an operation panicked, or is re-entrant"));
+        let res = body();
+        let engine = Some(manage::use_engine(prior));
+        if side == 0 { self.engines.0 = engine } else { self.engines.1 = engine };
+        res
+    }
+
+    fn log (&mut self, op:String) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// `engine::cell`, on both engines.
+    pub fn cell<T:Hash+Eq+Debug+Clone+'static> (&mut self, n:Name, val:T) -> DualArt<T> {
+        let op = self.log(format!("cell({:?}, {:?})", n, val));
+        let a = { let (n, val) = (n.clone(), val.clone()); self.on(0, move || engine::cell(n, val)) };
+        let b = self.on(1, move || engine::cell(n, val));
+        DualArt{arts:(a, b), op:op}
+    }
+
+    /// `engine::set`, on both engines.
+    pub fn set<T:Hash+Eq+Debug+Clone+'static> (&mut self, a:&DualArt<T>, val:T) {
+        let _ = self.log(format!("set(#{}, {:?})", a.op, val));
+        { let val = val.clone(); self.on(0, || engine::set(&a.arts.0, val)) };
+        self.on(1, || engine::set(&a.arts.1, val))
+    }
+
+    /// `engine::thunk`, on both engines; each gets its side of `arg`.
+    pub fn thunk<Arg:DualArg,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+        (&mut self,
+         id:NameChoice,
+         prog_pt:ProgPt,
+         fn_box:Rc<Box<Fn(Arg::Side, Spurious) -> Res>>,
+         arg:Arg, spurious:Spurious)
+         -> DualArt<Res>
+    {
+        let op = self.log(format!("thunk({:?}, {:?}, {:?})", id, prog_pt, arg));
+        let a = {
+            let (id, prog_pt, fn_box, arg, spurious) = (id.clone(), prog_pt.clone(), fn_box.clone(), arg.side(0), spurious.clone());
+            self.on(0, move || engine::thunk(id, prog_pt, fn_box, arg, spurious))
+        };
+        let arg = arg.side(1);
+        let b = self.on(1, move || engine::thunk(id, prog_pt, fn_box, arg, spurious));
+        DualArt{arts:(a, b), op:op}
+    }
+
+    /// `engine::force`, on both engines; panics with a
+    /// `DualDivergence` if they force different values.
+    pub fn force<T:Hash+Eq+Debug+Clone+'static> (&mut self, a:&DualArt<T>) -> T {
+        let op = self.log(format!("force(#{})", a.op));
+        let first = self.on(0, || engine::force(&a.arts.0));
+        let from_scratch = self.from_scratch;
+        let second = self.on(1, || {
+            if from_scratch { manage::clear_results() };
+            engine::force(&a.arts.1)
+        });
+        if first != second {
+            panic_any(DualDivergence{
+                op:op,
+                ops:self.ops.clone(),
+                first:format!("{:?}", first),
+                second:format!("{:?}", second),
+            })
+        };
+        first
+    }
+}
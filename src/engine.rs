@@ -10,32 +10,73 @@ demonstrates the [Adapton programming model](https://docs.rs/adapton/0/adapton/i
  */
 
 use core::any::TypeId;
+use std::any::Any;
+#[cfg(debug_assertions)]
+use std::any::type_name;
 use core::marker::PhantomData;
 
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::fmt::Debug;
 use std::fmt::{Formatter,Result};
 use std::fmt;
-use std::hash::{Hash,Hasher};
+use std::hash::{Hash,Hasher,BuildHasher};
 use std::collections::hash_map::DefaultHasher;
 use std::mem::replace;
+use std::panic::{panic_any, catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::time::Duration;
 use std::mem::transmute;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
+use std::iter::Peekable;
+use std::str::Chars;
 use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
 use macros::{ProgPt};
 use reflect;
-
-thread_local!(static GLOBALS: RefCell<Globals> = RefCell::new(Globals{engine:Engine::Naive}));
-thread_local!(static UNIT_NAME: Name = Name{ hash:0, symbol: Rc::new(NameSym::Unit) });
+use testing;
+
+// TODO-Someday: A thread-safe ("sync") engine, whose state is `Send`
+// and `Sync` (via `Arc` and a lock, rather than `Rc` and `RefCell`).
+// It would permit (1) a background thread that cleans in idle time,
+// handing its pending work over to the foreground's forces (see
+// `IdleCleaner`, which cleans on the same thread, meanwhile); and (2)
+// a lock-free read path for clean nodes, as cached results in
+// atomically-swapped `Arc`s, validated by the epoch (with benchmarks
+// of contention).
+thread_local!(static GLOBALS: RefCell<Globals> = RefCell::new(Globals{engine:Engine::Naive, resources:HashMap::new()}));
+
+// The ids of the DCG engines (unique within the process, e.g., for
+// the names of spill files), and those of the live ones (not yet
+// dropped); see `EngineGone`.
+static ENGINE_COUNT: AtomicUsize = AtomicUsize::new(0);
+thread_local!(static LIVE_ENGINES: RefCell<HashSet<usize>> = RefCell::new(HashSet::new()));
+thread_local!(static DROP_REPORT: RefCell<Option<EngineDropReport>> = RefCell::new(None));
+thread_local!(static UNIT_NAME: Name = Name{ hash:0, symbol: intern_namesym(SymKey::Unit, || NameSym::Unit) });
+
+// Interned name symbols; see `intern_namesym`.
+thread_local!(static NAMES: RefCell<NameTable> = RefCell::new(NameTable{ table:HashMap::new(), strings:HashMap::new(),
+                                                                          next_gc:NAME_GC_MIN, stats:NameInternStats::default() }));
 
 struct TraceSt { stack:Vec<Box<Vec<reflect::trace::Trace>>>, }
 
 /// When this option is set to some, the engine will record a trace of its DCG effects.
 thread_local!(static TRACES: RefCell<Option<TraceSt>> = RefCell::new( None ));
 
+// When this option is set to some, the engine will call this hook at each of its DCG effects.
+thread_local!(static STEP_HOOK: RefCell<Option<Rc<Fn(reflect::trace::StepEvent)>>> = RefCell::new( None ));
+
+// The events of the steps in progress, whose extents have not ended
+// (`None` for the steps without an event, e.g., without a hook).
+thread_local!(static STEP_EVENTS: RefCell<Vec<Option<reflect::trace::StepEvent>>> = RefCell::new( vec![] ));
+
 fn my_hash<T>(obj: T) -> u64
     where T: Hash
 {
@@ -44,6 +85,29 @@ fn my_hash<T>(obj: T) -> u64
     hasher.finish()
 }
 
+/// Builds the hashers of an engine, keyed by its seed (see
+/// `Engine::with_seed`): the seed zero gives those of `my_hash`.  The
+/// DCG's table uses these hashers, rather than randomly-keyed ones, so
+/// that its order (e.g., in `dcg_hash`, and in the DCG's dot files) is
+/// the same across runs.
+#[derive(Debug,Clone,Copy,Default)]
+struct SeededState(u64);
+impl BuildHasher for SeededState {
+    type Hasher = DefaultHasher;
+    fn build_hasher(&self) -> DefaultHasher {
+        let mut hasher = DefaultHasher::new();
+        if self.0 != 0 { self.0.hash(&mut hasher) };
+        hasher
+    }
+}
+
+/// Like `my_hash`, with the hashers of the engine's seed.
+fn seeded_hash<T:Hash>(st:&DCG, obj:T) -> u64 {
+    let mut hasher = st.seed.build_hasher();
+    obj.hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Reflects the DCG engine, including both the effects of the
 /// programs running in it, and the internal effects of the engine
 /// cleaning and dirtying the DCG.  For the latter effects, see the
@@ -69,7 +133,8 @@ pub mod reflect_dcg {
     pub use parse_val;
 
     use std::fmt::{Write};
-    use super::{TraceSt,TRACES,GLOBALS,Engine};
+    use super::{TraceSt,TRACES,STEP_HOOK,GLOBALS,Engine};
+    use std::rc::Rc;
     use adapton::engine::Name;
 
     /// Begin a debugging extent in the trace, with associated name and message.
@@ -137,6 +202,124 @@ pub mod reflect_dcg {
         })
     }
 
+    /// The DCG now, as a property graph (see `graph::Graph`), for
+    /// export in GraphML or Cytoscape's JSON format; as with
+    /// `dcg_reflect_now`, returns None for the `Naive` engine.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use adapton::reflect;
+    /// manage::init_dcg();
+    /// let _ = register_type::<usize>();
+    ///
+    /// let c = cell(name_of_str("c"), 1usize);
+    /// let t = ns(name_of_str("sub"), || thunk!([Some(name_of_str("t"))]? get!(c) + 1));
+    /// assert_eq!(force(&t), 2);
+    /// let g = reflect::dcg_graph_now().unwrap();
+    /// assert_eq!(g.nodes.len(), 2);
+    /// assert_eq!((g.nodes[1].kind, g.nodes[1].namespace.as_str()), (reflect::graph::Kind::Thunk, "sub"));
+    /// assert_eq!(g.nodes[1].value, Some(String::from("2")));
+    /// assert_eq!((g.edges[0].effect.clone(), g.edges[0].dirty), (reflect::Effect::Force, false));
+    /// assert!(g.graphml().contains("<data key=\"kind\">thunk</data>"));
+    /// assert!(g.cytoscape_json().contains("\"namespace\":\"sub\""));
+    /// # }
+    /// ```
+    pub fn dcg_graph_now() -> Option<graph::Graph> {
+        use super::{ArtKey, render_any};
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => {
+                    let st = &*dcg.borrow();
+                    let mut locs : Vec<(String, &Rc<super::Loc>)> =
+                        st.table.keys().map(|loc| (ArtKey{loc:loc.clone()}.to_string(), loc)).collect();
+                    locs.sort_by(|a, b| a.0.cmp(&b.0));
+                    let mut graph = graph::Graph::default();
+                    for (id, loc) in locs {
+                        let node = &st.table[loc];
+                        let kind = if node.is_pure() { graph::Kind::Pure }
+                                   else if node.succs_def() { graph::Kind::Thunk }
+                                   else { graph::Kind::Cell } ;
+                        let succs : Vec<Succ> = if node.succs_def() { node.succs().reflect() } else { vec![] } ;
+                        graph.nodes.push(graph::Node{
+                            id:id.clone(),
+                            kind:kind,
+                            dirty:succs.iter().any(|succ| succ.dirty),
+                            namespace:loc.path.reflect().iter().map(string_of_name).collect::<Vec<_>>().join("/"),
+                            prog_pt:node.prog_pt().map(|p| p.symbol.to_string()),
+                            cost:st.costs.get(loc).cloned(),
+                            value:node.res_any().and_then(render_any),
+                        });
+                        if ! node.succs_def() { continue };
+                        for (succ, rsucc) in node.succs().iter().zip(succs.into_iter()) {
+                            graph.edges.push(graph::Edge{
+                                source:id.clone(),
+                                target:ArtKey{loc:succ.loc.clone()}.to_string(),
+                                effect:rsucc.effect,
+                                dirty:rsucc.dirty,
+                                label:rsucc.label,
+                            })
+                        }
+                    }
+                    Some(graph)
+                },
+                Engine::Naive => None,
+            }
+        })
+    }
+
+    /// Runs `body` in a fresh engine that only records dependencies
+    /// (see `manage::init_recording`), and gives its result, with the
+    /// graph of the cells and thunks that it allocated, and of their
+    /// dependencies; afterwards, restores the prior engine.  Hence,
+    /// ordinary code over `cell`, `thunk` and `force` gives its task
+    /// graph, e.g., for a build system.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use adapton::reflect;
+    ///
+    /// let (out, g) = reflect::record_dependencies(|| {
+    ///     let src = cell(name_of_str("main.c"), "int main;");
+    ///     let obj = thunk!([Some(name_of_str("main.o"))]? get!(src).len());
+    ///     let exe = thunk!([Some(name_of_str("app"))]? get!(obj) + get!(obj));
+    ///     get!(exe)
+    /// });
+    /// assert_eq!(out, 18);
+    /// let ids : Vec<&str> = g.nodes.iter().map(|nd| nd.id.as_str()).collect();
+    /// assert_eq!(ids, vec!["\"app\"", "\"main.c\"", "\"main.o\""]);
+    /// let deps : Vec<(&str, &str)> = g.edges.iter().map(|e| (e.source.as_str(), e.target.as_str())).collect();
+    /// assert_eq!(deps, vec![("\"app\"", "\"main.o\""), ("\"main.o\"", "\"main.c\"")]);
+    /// # }
+    /// ```
+    pub fn record_dependencies<T,F:FnOnce() -> T>(body:F) -> (T, graph::Graph) {
+        let prior = super::manage::init_recording();
+        let res = body();
+        let graph = dcg_graph_now().unwrap();
+        let _ = super::manage::use_engine(prior);
+        (res, graph)
+    }
+
+    /// Set (or with `None`, unset) a hook that the engine calls at each
+    /// of its DCG effects, as the effect ends (after its nested
+    /// effects, if any): each dirtying and cleaning step, each
+    /// re-evaluation, each cache hit (cutoff), and so on (see
+    /// `trace::StepEvent`, with the dirty bit of the step's edge before
+    /// and after it); returns the prior hook, if any.
+    /// Unlike `dcg_reflect_begin`, this permits tools (e.g., debuggers
+    /// and visualizers) to follow these steps live.
+    ///
+    /// The engine calls the hook while it is mid-step, so the hook
+    /// must not call back into the engine (e.g., `force` or `cell`).
+    pub fn set_step_hook(hook:Option<Rc<Fn(trace::StepEvent)>>) -> Option<Rc<Fn(trace::StepEvent)>> {
+        STEP_HOOK.with(|h| ::std::mem::replace(&mut *h.borrow_mut(), hook))
+    }
+
     /// Begin recording (reflections of) DCG effects.  See `dcg_reflect_end()`.
     pub fn dcg_reflect_begin() {
         TRACES.with(|tr| {
@@ -179,32 +362,73 @@ use reflect::Reflect;
 //#[macro_export]
 macro_rules! dcg_effect_begin {
     ( $eff:expr, $loc:expr, $succ:expr, $has_extent:expr ) => {{
-        // The beginning of an effect, with an option extent (nested effects)
-        TRACES.with(|tr| {
+        dcg_effect_begin!(@step $eff, $loc, $succ, $has_extent, None)
+    }}
+    ;
+    ( $eff:expr, $loc:expr, $succ:expr, $has_extent:expr, $dirty_before:expr ) => {{
+        dcg_effect_begin!(@step $eff, $loc, $succ, $has_extent, Some($dirty_before))
+    }}
+    ;
+    ( @step $eff:expr, $loc:expr, $succ:expr, $has_extent:expr, $dirty_before:expr ) => {{
+        // The beginning of an effect, with an option extent (nested
+        // effects).  Its event, if any, has the dirty bits of `$succ`,
+        // or the given dirty bit before the step; with an extent, the
+        // hook receives it at its end (see `dcg_effect_end`).
+        let hook = STEP_HOOK.with(|h| h.borrow().clone());
+        let event = TRACES.with(|tr| {
             match *tr.borrow_mut() {
-                None => (),
+                None => {
+                    match hook {
+                        None => None,
+                        Some(_) => Some(reflect::trace::StepEvent::new(
+                            $eff, reflect::trace::Edge{ loc:$loc.reflect(), succ:$succ.reflect() })),
+                    }
+                },
                 // Some ==> We are building a trace
                 Some(ref mut ts) => {
+                    let edge = reflect::trace::Edge{
+                        loc:  $loc.reflect(),
+                        succ: $succ.reflect(),
+                    };
+                    let effect = $eff;
+                    let event = match hook {
+                        None => None,
+                        Some(_) => Some(reflect::trace::StepEvent::new(effect.clone(), edge.clone())),
+                    };
                     match ts.stack.last_mut() {
                         None => unreachable!(),
                         Some(ref mut ts) => {
                             ts.push( reflect::trace::Trace{
                                 extent: Box::new(vec![]),
-                                effect:$eff,
-                                edge: reflect::trace::EffectEdge::Fwd(
-                                    reflect::trace::Edge{
-                                        loc:  $loc.reflect(),
-                                        succ: $succ.reflect(),
-                                    })})}};
+                                effect:effect,
+                                edge: reflect::trace::EffectEdge::Fwd(edge)})}};
                     if $has_extent {
                         ts.stack.push(Box::new(vec![]))
                     } else { }
+                    event
                 }
-            }})
+            }});
+        let event = match event {
+            None => None,
+            Some(mut event) => {
+                let dirty_before : Option<bool> = $dirty_before;
+                if let Some(dirty_before) = dirty_before { event.dirty_before = dirty_before };
+                Some(event)
+            }
+        };
+        if $has_extent {
+            STEP_EVENTS.with(|evs| evs.borrow_mut().push(event))
+        } else {
+            match (hook, event) {
+                (Some(hook), Some(event)) => hook(event),
+                _ => (),
+            }
+        }
     }}
     ;
     ( $eff:expr, $has_extent:expr ) => {{
         // The beginning of an effect, with an option extent (nested effects)
+        if $has_extent { STEP_EVENTS.with(|evs| evs.borrow_mut().push(None)) };
         TRACES.with(|tr| {
             match *tr.borrow_mut() {
                 None => (),
@@ -233,10 +457,29 @@ macro_rules! dcg_effect_begin {
 //#[macro_export]
 macro_rules! dcg_effect_end {
     () => {{
+        dcg_effect_end!(@step None)
+    }}
+    ;
+    ( $dirty_after:expr ) => {{
+        dcg_effect_end!(@step Some($dirty_after))
+    }}
+    ;
+    ( @step $dirty_after:expr ) => {{
         // The end of an effects' extent. Operationally, the traces at
         // the top of the stack are popped; they become the extent of the
         // trace at the end (top) of the second top-most sequence of
-        // traces.
+        // traces.  The hook receives the event of the effect, if any,
+        // with the given dirty bit after the step (if any).
+        let event = STEP_EVENTS.with(|evs| evs.borrow_mut().pop());
+        match event {
+            Some(Some(mut event)) => {
+                let dirty_after : Option<bool> = $dirty_after;
+                if let Some(dirty_after) = dirty_after { event.dirty_after = dirty_after };
+                let hook = STEP_HOOK.with(|h| h.borrow().clone());
+                if let Some(hook) = hook { hook(event) }
+            },
+            _ => (),
+        };
         TRACES.with(|tr| {
             match *tr.borrow_mut() {
                 None => (),
@@ -263,6 +506,12 @@ macro_rules! dcg_effect {
         /// An effect without an extent (without nested effects)
         dcg_effect_begin!($eff, $loc, $succ, false)
     }}
+    ;
+    ( $eff:expr, $loc:expr, $succ:expr, $dirty_before:expr ) => {{
+        // An effect without an extent, on edge `$succ` (as of after the
+        // effect), which had the given dirty bit before it
+        dcg_effect_begin!($eff, $loc, $succ, false, $dirty_before)
+    }}
 }
 
 macro_rules! current_loc {
@@ -293,11 +542,25 @@ fn debug_effect(n:Option<Name>, msg:Option<String>) {
 /// different content over time, it describes *where* incremental
 /// changing is occurring, relative to other (unaffected) parts of
 /// data structures or computations.
-#[derive(PartialEq,Eq,Clone)]
+///
+/// Name symbols are interned (hash-consed): Equal names share their
+/// symbol, and name equality is a pointer comparison.
+///
+/// As values in application state, names are ordered canonically
+/// (see `Ord`), print in a canonical string form that `Name::parse`
+/// reads back (see `Display`), and with the `serde` feature, serialize
+/// as this string form.
+#[derive(Clone)]
 pub struct Name {
     hash : u64, // hash of symbol
-    symbol : Rc<NameSym>,
+    symbol : Rc<NameSym>, // interned; see `intern_namesym`
+}
+impl PartialEq for Name {
+    fn eq(&self, other:&Name) -> bool {
+        self.hash == other.hash && Rc::ptr_eq(&self.symbol, &other.symbol)
+    }
 }
+impl Eq for Name { }
 impl Debug for Name {
     fn fmt(&self, f:&mut Formatter) -> Result { self.symbol.fmt(f) }
 }
@@ -307,12 +570,196 @@ impl Hash for Name {
     }
 }
 
+/// Names are ordered by their symbols (see `cmp_namesym`), and then
+/// by their hashes, which only distinguish names built from hashes.
+impl Ord for Name {
+    fn cmp(&self, other:&Name) -> Ordering {
+        cmp_namesym(&self.symbol, &other.symbol).then(self.hash.cmp(&other.hash))
+    }
+}
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other:&Name) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+/// The canonical ordering of name symbols: by constructor (in the
+/// order of their declaration), then by their contents.  Unlike the
+/// names' hashes, this order is meaningful (e.g., for sorting names
+/// of strings or numbers), and stable across versions.
+fn cmp_namesym(a:&NameSym, b:&NameSym) -> Ordering {
+    fn rank(s:&NameSym) -> usize {
+        match *s { NameSym::Unit => 0, NameSym::Hash64 => 1, NameSym::String(_) => 2,
+                   NameSym::Usize(_) => 3, NameSym::Isize(_) => 4, NameSym::Pair(_,_) => 5,
+                   NameSym::ForkL(_) => 6, NameSym::ForkR(_) => 7 }
+    }
+    match (a, b) {
+        (&NameSym::String(ref a), &NameSym::String(ref b)) => a.cmp(b),
+        (&NameSym::Usize(ref a), &NameSym::Usize(ref b)) => a.cmp(b),
+        (&NameSym::Isize(ref a), &NameSym::Isize(ref b)) => a.cmp(b),
+        (&NameSym::Pair(ref a1, ref a2), &NameSym::Pair(ref b1, ref b2)) =>
+            cmp_namesym(a1, b1).then_with(|| cmp_namesym(a2, b2)),
+        (&NameSym::ForkL(ref a), &NameSym::ForkL(ref b)) |
+        (&NameSym::ForkR(ref a), &NameSym::ForkR(ref b)) => cmp_namesym(a, b),
+        (a, b) => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// The canonical string form of a name, which `Name::parse` inverts:
+/// `()` for the unit name, `"..."` for strings (escaping `"` and `\`
+/// with `\`), `7` for `usize`s, `-7i` for `isize`s, `(n1, n2)` for
+/// pairs, and `l(n)` and `r(n)` for the names forked from `n`.  A name
+/// built from a hash prints as `#` and the hash (in hexadecimal), or
+/// within another name, as `#_`, since only the outermost name
+/// retains its hash; such inner names do not parse.
+///
+/// The alternate form (`{:#}`) is for people, not for parsing: it
+/// omits the quotes of strings that are identifiers (e.g., `doc1` and
+/// `list_3`, but `"3"` and `"a b"`), so that `name_pair(name_of_str("doc1"),
+/// name_of_usize(3))` prints as `(doc1, 3)`.  With a precision (e.g.,
+/// `{:.12}`), either form is at most that many characters long, and a
+/// longer one ends with an ellipsis (`…`).
+impl fmt::Display for Name {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        if ! f.alternate() && f.precision().is_none() { return write_canonical(f, &self.symbol, Some(self.hash)) };
+        let mut s = String::new();
+        if f.alternate() { write_human(&mut s, &self.symbol, Some(self.hash))? }
+        else { write_canonical(&mut s, &self.symbol, Some(self.hash))? };
+        write_elided(f, &s, false)
+    }
+}
+
+/// The alternate (human-oriented) form of a name; see `Display`.
+fn write_human<W:Write>(w:&mut W, n:&NameSym, hash:Option<u64>) -> Result {
+    fn is_ident(s:&str) -> bool {
+        s.chars().next().map_or(false, |c| c.is_alphabetic() || c == '_') &&
+            s.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+    }
+    match *n {
+        NameSym::String(ref s) if is_ident(s) => write!(w, "{}", s),
+        NameSym::Pair(ref l, ref r) => {
+            write!(w, "(")?; write_human(w, l, None)?; write!(w, ", ")?;
+            write_human(w, r, None)?; write!(w, ")")
+        },
+        NameSym::ForkL(ref s) => { write!(w, "l(")?; write_human(w, s, None)?; write!(w, ")") },
+        NameSym::ForkR(ref s) => { write!(w, "r(")?; write_human(w, s, None)?; write!(w, ")") },
+        _ => write_canonical(w, n, hash),
+    }
+}
+
+/// Writes `s`, in at most `f.precision()` characters (if any): a
+/// longer `s` loses its end (or, with `front`, its start) to an
+/// ellipsis (`…`).
+fn write_elided(f:&mut Formatter, s:&str, front:bool) -> Result {
+    let len = s.chars().count();
+    match f.precision() {
+        Some(width) if len > width => {
+            let keep = width.saturating_sub(1);
+            if front { write!(f, "…{}", s.chars().skip(len - keep).collect::<String>()) }
+            else { write!(f, "{}…", s.chars().take(keep).collect::<String>()) }
+        },
+        _ => f.write_str(s),
+    }
+}
+
+fn write_canonical<W:Write>(w:&mut W, n:&NameSym, hash:Option<u64>) -> Result {
+    match *n {
+        NameSym::Unit => write!(w, "()"),
+        NameSym::Hash64 => match hash { Some(h) => write!(w, "#{:x}", h), None => write!(w, "#_") },
+        NameSym::String(ref s) => {
+            write!(w, "\"")?;
+            for c in s.chars() {
+                if c == '"' || c == '\\' { write!(w, "\\")? };
+                write!(w, "{}", c)?;
+            }
+            write!(w, "\"")
+        },
+        NameSym::Usize(ref n) => write!(w, "{}", n),
+        NameSym::Isize(ref n) => write!(w, "{}i", n),
+        NameSym::Pair(ref l, ref r) => {
+            write!(w, "(")?; write_canonical(w, l, None)?; write!(w, ", ")?;
+            write_canonical(w, r, None)?; write!(w, ")")
+        },
+        NameSym::ForkL(ref s) => { write!(w, "l(")?; write_canonical(w, s, None)?; write!(w, ")") },
+        NameSym::ForkR(ref s) => { write!(w, "r(")?; write_canonical(w, s, None)?; write!(w, ")") },
+    }
+}
+
+impl Name {
+    /// Parses the canonical string form of a name (see its `Display`
+    /// implementation); gives `None` for other strings.
+    pub fn parse (s:&str) -> Option<Name> {
+        let mut cs = s.chars().peekable();
+        let n = parse_canonical(&mut cs, true)?;
+        match cs.next() { None => Some(n), Some(_) => None }
+    }
+}
+
+fn parse_canonical(cs:&mut Peekable<Chars>, outer:bool) -> Option<Name> {
+    fn expect(cs:&mut Peekable<Chars>, c:char) -> Option<()> {
+        if cs.next()? == c { Some(()) } else { None }
+    }
+    match cs.next()? {
+        '(' => {
+            if cs.peek() == Some(&')') { cs.next(); return Some(name_unit()) };
+            let l = parse_canonical(cs, false)?;
+            expect(cs, ',')?;
+            while cs.peek() == Some(&' ') { cs.next(); };
+            let r = parse_canonical(cs, false)?;
+            expect(cs, ')')?;
+            Some(name_pair(l, r))
+        },
+        '"' => {
+            let mut s = String::new();
+            loop { match cs.next()? {
+                '\\' => s.push(cs.next()?),
+                '"' => break,
+                c => s.push(c),
+            }};
+            Some(name_of_string(s))
+        },
+        '#' if outer => {
+            let mut hex = String::new();
+            while cs.peek().map_or(false, |c| c.is_digit(16)) { hex.push(cs.next().unwrap()) };
+            u64::from_str_radix(&hex, 16).ok().map(name_of_hash64)
+        },
+        f @ 'l' | f @ 'r' => {
+            expect(cs, '(')?;
+            let n = parse_canonical(cs, false)?;
+            expect(cs, ')')?;
+            let (l, r) = name_fork(n);
+            Some(if f == 'l' { l } else { r })
+        },
+        c if c == '-' || c.is_digit(10) => {
+            let mut num = c.to_string();
+            while cs.peek().map_or(false, |c| c.is_digit(10)) { num.push(cs.next().unwrap()) };
+            if cs.peek() == Some(&'i') { cs.next(); num.parse().ok().map(name_of_isize) }
+            else { num.parse().ok().map(name_of_usize) }
+        },
+        _ => None,
+    }
+}
+
+/// Names serialize as their canonical string form (see `Name::parse`).
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Name {
+    fn serialize<S: ::serde::Serializer>(&self, s:S) -> ::std::result::Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Name {
+    fn deserialize<D: ::serde::Deserializer<'de>>(d:D) -> ::std::result::Result<Name, D::Error> {
+        let s = String::deserialize(d)?;
+        Name::parse(&s).ok_or_else(|| ::serde::de::Error::custom(format!("invalid name: {}", s)))
+    }
+}
+
 // Each location identifies a node in the DCG.
 #[derive(PartialEq,Eq,Clone)]
 struct Loc {
     hash : u64, // hash of (path,id)
     path : Rc<Path>,
     id   : Rc<ArtId>,
+    engine : usize, // the id of the DCG that interned it
 }
 impl Debug for Loc {
     fn fmt(&self, f:&mut Formatter) -> Result {
@@ -332,11 +779,52 @@ impl reflect::Reflect<reflect::Loc> for Loc {
             name:match *self.id {
                 ArtId::Structural(ref hash) => name_of_hash64(*hash),
                 ArtId::Nominal(ref name) => name.clone(),
-            }
+            },
+            id:match *self.id {
+                ArtId::Structural(ref hash) => reflect::LocId::Structural(*hash),
+                ArtId::Nominal(_) => reflect::LocId::Nominal,
+            },
         }
     }
 }
 
+/// The human-oriented form of a location; see the `Display` of
+/// `reflect::Loc`.
+impl fmt::Display for Loc {
+    fn fmt(&self, f:&mut Formatter) -> Result { fmt::Display::fmt(&self.reflect(), f) }
+}
+
+/// The concise, human-oriented form of a location, for messages and
+/// pictures: the names of its path, and its name, each after a `/`
+/// (e.g., `/doc1/list/3`), in their alternate form (see the `Display`
+/// of `Name`); a structural location's name is `@` and its hash (in
+/// hexadecimal).  Unlike that of `ArtKey`, this form does not parse.
+/// With a precision (e.g., `{:.20}`), it is at most that many
+/// characters long, and a longer one starts with an ellipsis (`…`),
+/// keeping the innermost names.
+impl fmt::Display for reflect::Loc {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        let mut s = String::new();
+        for n in self.path.iter() { write!(&mut s, "/{:#}", n)? };
+        match self.id {
+            reflect::LocId::Structural(hash) => write!(&mut s, "/@{:x}", hash)?,
+            reflect::LocId::Nominal => write!(&mut s, "/{:#}", self.name)?,
+        };
+        write_elided(f, &s, true)
+    }
+}
+
+/// The path as for the `Display` of `reflect::Loc` (e.g.,
+/// `/doc1/list`); the empty path is `/`.
+impl<'a> fmt::Display for reflect::PathDisplay<'a> {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        let mut s = String::new();
+        for n in self.0.iter() { write!(&mut s, "/{:#}", n)? };
+        if s.is_empty() { s.push('/') };
+        write_elided(f, &s, true)
+    }
+}
+
 #[derive(Hash,PartialEq,Eq,Clone)]
 enum ArtId {
     /// Identifies an `Art` structurally, based on hashing content.
@@ -355,7 +843,7 @@ impl Debug for ArtId {
 }
 
 /// Flags control runtime behavior of the DCG.
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Flags {
     pub use_purity_optimization : bool,
     /// Ignore the `Nominal` `NameChoice`, and use `Structural` behavior instead
@@ -367,10 +855,473 @@ pub struct Flags {
     /// Deprecated: At certain points in the Engine's code, write state changes as graph-movie output
     /// TODO: To be replaced with DCG reflection, and reflection-to-filesystem logic.
     pub gmlog_dcg : bool,
+    /// Automatic granularity control: Stop memoizing the thunks whose
+    /// last-measured execution time falls below this threshold (see
+    /// `manage::set_auto_inline`)
+    pub auto_inline_below : Option<Duration>,
+    /// Limits on the work of each force from the outer layer (see
+    /// `manage::set_propagation_budget`)
+    pub propagation_budget : PropagationBudget,
+    /// Producer sharing: Before evaluating a nominal thunk, look for
+    /// another nominal thunk with an equal producer (the same program
+    /// point and argument); when one exists, observe its result instead
+    /// of evaluating (see `Cnt::shared`)
+    pub share_producers : bool,
+    /// Debugging: Log the writes by `cell` from within thunks, and
+    /// panic with a `WriteConflict` when one epoch writes different
+    /// values to the same cell (see `WriteConflict`)
+    pub check_write_conflicts : bool,
+    /// How dirtying treats allocation edges (see `manage::set_alloc_policy`)
+    pub alloc_policy : AllocPolicy,
+    /// Which nodes and edges the dot files of `write_dcg` show (see
+    /// `manage::set_dot_filter`)
+    pub dot_filter : DotFilter,
+    /// Debugging: Record each re-evaluation of change propagation,
+    /// with the change that caused it (see `manage::take_reevaluations`)
+    pub record_reevaluations : bool,
+    /// Convert a panic from within the engine (or a thunk) into a
+    /// poisoned engine, as with a poisoned `Mutex`: the panic unwinds
+    /// as usual, but afterwards, `force`, `set`, `update`, `cell` and
+    /// `thunk` panic with an `EnginePoisoned`, and `try_force` and
+    /// `try_set` return it, until `manage::init_dcg` replaces the
+    /// engine (see `manage::poisoned`)
+    pub poison_on_panic : bool,
+    /// Profiling: Sample every this many steps of the engine (each
+    /// evaluation, force from within a thunk, and examined DCG edge of
+    /// cleaning), recording what it is doing, at a low overhead (see
+    /// `manage::sample_stats`)
+    pub sample_every : Option<usize>,
+    /// Debugging: Re-evaluate every this many cache hits of thunks
+    /// (their memo matches) anyway, and compare the results with the
+    /// cached ones, recording the producers whose results differ, that
+    /// is, those that depend on something other than their arguments
+    /// and observations, e.g., time, randomness or iteration order
+    /// (see `manage::take_nondeterminism`)
+    pub verify_every : Option<usize>,
+    /// Naming discipline: Reject the structural thunks allocated
+    /// within a namespace (see `ns`), except those beneath the
+    /// namespaces of `structural_namespaces`, with a
+    /// `NamingViolation`
+    pub strict_naming : bool,
+    /// The allowlist of `strict_naming`: the namespaces (paths)
+    /// beneath which structural thunks are permitted (see
+    /// `manage::allow_structural_in`)
+    pub structural_namespaces : Vec<reflect::Path>,
+    /// Dependency extraction: Record the DCG's nodes and edges, but
+    /// never reuse the cached result of a thunk: each force (except a
+    /// repeated one, within the same evaluation) re-evaluates it, so
+    /// that no comparison cuts off an evaluation (see
+    /// `manage::init_recording`)
+    pub record_only : bool,
+}
+
+/// How change propagation treats the DCG's allocation edges, from a
+/// thunk to the cells and nominal thunks that it allocates.  By
+/// default, changing a node also dirties (the observers of) its
+/// allocators, and cleaning re-executes them; workloads whose
+/// allocators needn't re-run when the editor changes what they
+/// allocated can disable either step.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct AllocPolicy {
+    /// When a cell (or nominal thunk) changes, dirty the allocation
+    /// edges of the thunks that allocated it, and (transitively) their
+    /// observers.  When false, only the cell's observers are dirtied.
+    pub dirty_allocators : bool,
+    /// When cleaning a dirty allocation edge of a cell or nominal
+    /// thunk, count it as a change, re-executing the allocator.  When
+    /// false, the engine cleans such edges without re-execution
+    /// (as it already does for structural thunks).
+    pub alloc_changes : bool,
+}
+
+impl Default for AllocPolicy {
+    fn default() -> Self { AllocPolicy{dirty_allocators:true, alloc_changes:true} }
+}
+
+/// Filters for the DCG's dot output (see `Flags::write_dcg`, and
+/// `manage::dot_of_dcg`), which becomes unreadable beyond a few
+/// hundred nodes.  By default, the output shows every node and edge.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct DotFilter {
+    /// Only show the nodes within this many edges (in either
+    /// direction) of the nodes that this name identifies.
+    pub around : Option<(Name, usize)>,
+    /// Hide the clean `Observe` edges (showing the dirty ones).
+    pub hide_clean_observes : bool,
+    /// Draw each namespace as a box (a cluster) around its nodes.
+    pub cluster_namespaces : bool,
+    /// Show at most this many edges; when the output omits some, it
+    /// ends with a "truncated" node that counts them.
+    pub max_edges : Option<usize>,
+}
+
+/// What `manage::trim_ns` drops from the nodes beneath a namespace.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub enum TrimPolicy {
+    /// Drop the cached results of the thunks, keeping every node and
+    /// edge (as `manage::clear_results` does, for the entire DCG).
+    DropResults,
+    /// Drop the clean thunks that only nodes beneath the namespace
+    /// use (i.e., that no edge from elsewhere targets), keeping cells.
+    DropCleanNodes,
+    /// Drop every node, including cells; the thunks elsewhere that
+    /// use these nodes lose their results and edges, and re-evaluate
+    /// when next forced.
+    DropAll,
+}
+
+/// One write to a cell from within a thunk, as logged for
+/// `Flags::check_write_conflicts`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct WriteRecord {
+    /// The order of the write within its epoch, from zero (counting
+    /// the writes to all cells).
+    pub order : usize,
+    /// The location of the writing thunk (rendered, since panic
+    /// payloads must be `Send`).
+    pub writer : String,
+    /// The written value (rendered).
+    pub value : String,
+}
+
+/// The (structured) panic payload that the DCG engine raises, with
+/// `Flags::check_write_conflicts`, when two writes within one epoch
+/// write different values (compared by hash) to the same cell.
+///
+/// The writes to a cell are those of `set`, from the outer layer, and
+/// those of `cell`, from the outer layer or from within thunks; within
+/// a thunk, `cell` overwrites the content at its name, dirtying its
+/// observers.  An _epoch_ begins at each write from the outer layer
+/// (an edit), and spans the change propagation that follows it.
+/// Within an epoch, the engine keeps only the last of several
+/// conflicting writes, so the thunks that observed the others are
+/// inconsistent; typically, names that two thunks share by mistake.
+/// Recover the payload with `std::panic::catch_unwind` and `downcast`;
+/// after such a panic, the engine's state is unspecified (re-initialize
+/// it).
+#[derive(Debug,Clone)]
+pub struct WriteConflict {
+    /// The location of the cell (rendered).
+    pub loc : String,
+    /// The epoch's writes to the cell, in order; the last one conflicts
+    /// with at least one of the others.
+    pub writes : Vec<WriteRecord>,
+}
+
+/// The writes of the current epoch; see `Flags::check_write_conflicts`.
+#[derive(Debug,Clone,Default)]
+struct WriteLog {
+    next  : usize,
+    cells : HashMap<Rc<Loc>, Vec<(u64, WriteRecord)>>,
+}
+
+/// A violation of an invariant (see `invariant`), with its provenance.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct InvariantViolation {
+    /// The name of the invariant.
+    pub name : Name,
+    /// The cells that the outer layer changed since the prior check of
+    /// the invariants.
+    pub edits : Vec<reflect::Loc>,
+    /// The dependencies of the invariant's (failing) evaluation: the
+    /// DCG edges of its thunk.
+    pub observed : Vec<reflect::Succ>,
+}
+
+impl InvariantViolation {
+    /// Renders the violation and its provenance, for error messages.
+    pub fn explain (&self) -> String {
+        let mut out = format!("invariant {} failed", self.name);
+        write!(&mut out, "\n\tafter edits to:").unwrap();
+        for loc in self.edits.iter() { write!(&mut out, "\n\t\t{:?}", loc).unwrap() };
+        write!(&mut out, "\n\tobserving:").unwrap();
+        for succ in self.observed.iter() {
+            write!(&mut out, "\n\t\t{:?} {:?}", succ.effect, succ.loc).unwrap();
+            if let Some(label) = succ.label { write!(&mut out, " ({})", label).unwrap() };
+        };
+        out
+    }
+}
+
+/// The samples of the engine's steps, as histograms; see
+/// `Flags::sample_every`.  Unlike a trace (see `reflect_dcg::dcg_reflect_begin`),
+/// sampling costs a countdown per step, so that production deployments
+/// can profile their change propagation.
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct SampleStats {
+    /// Number of samples
+    pub samples : usize,
+    /// The samples taken while a thunk evaluated (or forced), by the
+    /// program point of its producer
+    pub produce : HashMap<ProgPt, usize>,
+    /// The samples taken while cleaning, by the DCG edge examined: its
+    /// source and its target
+    pub clean : HashMap<(reflect::Loc, reflect::Loc), usize>,
+}
+
+/// A re-evaluation of a thunk during change propagation, with its
+/// cause: the dependency whose value changed, and how.  See
+/// `Flags::record_reevaluations`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Reevaluation {
+    /// The re-evaluated thunk.
+    pub loc : reflect::Loc,
+    /// The DCG edge of the thunk whose target changed.
+    pub cause : reflect::Succ,
+    /// The value that the thunk observed before, rendered by its
+    /// registered type (see `register_type`); `None` if its type is
+    /// not registered, or if the edge observes no value (e.g., an
+    /// allocation edge).
+    pub old : Option<String>,
+    /// The current value of the edge's target, rendered likewise.
+    pub new : Option<String>,
+}
+
+impl Reevaluation {
+    /// Renders the re-evaluation and its cause, e.g., to answer why an
+    /// output changed.
+    pub fn explain (&self) -> String {
+        let mut out = format!("{:?} re-evaluated: {:?} {:?}", self.loc, self.cause.effect, self.cause.loc);
+        if let Some(label) = self.cause.label { write!(&mut out, " ({})", label).unwrap() };
+        match (&self.old, &self.new) {
+            (&Some(ref old), &Some(ref new)) => write!(&mut out, " went from {} to {}", old, new).unwrap(),
+            (&None, &Some(ref new)) => write!(&mut out, " is now {}", new).unwrap(),
+            _ => write!(&mut out, " changed").unwrap(),
+        };
+        out
+    }
+}
+
+/// A thunk whose re-evaluation gave a result other than its cached
+/// one, although none of its dependencies changed: its producer is
+/// nondeterministic.  See `Flags::verify_every`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Nondeterminism {
+    /// The thunk.
+    pub loc : reflect::Loc,
+    /// The program point of its producer.
+    pub prog_pt : ProgPt,
+    /// The cached result, rendered by its registered type (see
+    /// `register_type`); `None` if its type is not registered.
+    pub cached : Option<String>,
+    /// The result of the re-evaluation, rendered likewise.
+    pub fresh : Option<String>,
+}
+
+impl Nondeterminism {
+    /// Renders the finding, for error messages.
+    pub fn explain (&self) -> String {
+        let mut out = format!("{:?} ({}) is nondeterministic", self.loc, self.prog_pt.symbol);
+        match (&self.cached, &self.fresh) {
+            (&Some(ref cached), &Some(ref fresh)) => write!(&mut out, ": cached {}, re-evaluated to {}", cached, fresh).unwrap(),
+            _ => write!(&mut out, ": its re-evaluation differs from its cached result").unwrap(),
+        };
+        out
+    }
+}
+
+/// The invariants of the DCG, and their pending check; see `invariant`.
+#[derive(Default,Clone)]
+struct Invariants {
+    locs : Vec<(Rc<Loc>, Name)>,
+    edits : Vec<Rc<Loc>>, // changed cells, since the prior check
+    checking : bool,
+    violations : Vec<InvariantViolation>,
+    hook : Option<Rc<Fn(&InvariantViolation)>>,
+}
+
+impl Debug for Invariants {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        write!(f, "Invariants {{ locs:{:?}, edits:{:?}, violations:{:?} }}", self.locs, self.edits, self.violations)
+    }
+}
+
+/// Limits on the change propagation of each force from the outer
+/// layer (including the forces that it performs transitively); `None`
+/// means unlimited, the default.
+///
+/// Bad naming can make even small edits re-evaluate large parts of a
+/// DCG; interactive applications can bound this work, and recover from
+/// a `PropagationBudgetExceeded` panic instead.
+#[derive(Debug,Clone,Copy,Default,Hash,PartialEq,Eq)]
+pub struct PropagationBudget {
+    /// The maximum depth of propagation: the nesting of thunk
+    /// evaluations and of (recursive) edge cleanings
+    pub max_depth : Option<usize>,
+    /// The maximum number of DCG edges: those that cleaning traverses,
+    /// plus those that evaluation builds
+    pub max_edges : Option<usize>,
+}
+
+/// The (structured) panic payload that the DCG engine raises when a
+/// force exceeds its `PropagationBudget`.  Recover it with
+/// `std::panic::catch_unwind` and `downcast`; after such a panic, the
+/// engine's state is unspecified (re-initialize it).
+#[derive(Debug,Clone)]
+pub struct PropagationBudgetExceeded {
+    /// The budget in effect.
+    pub budget : PropagationBudget,
+    /// The depth of propagation, when the engine stopped.
+    pub depth : usize,
+    /// The number of DCG edges, when the engine stopped.
+    pub edges : usize,
+    /// The location where the engine stopped (rendered, since panic
+    /// payloads must be `Send`).
+    pub loc : String,
+    /// The demand stack where the engine stopped (rendered).
+    pub demand_stack : String,
+}
+
+/// The progress of the current force from the outer layer; see `PropagationBudget`.
+#[derive(Debug,Clone,Default)]
+struct PropWork {
+    depth : usize,
+    edges : usize,
+}
+
+/// Updates the counters of the DCG (`Cnt`); without the `counters`
+/// feature, the update compiles away (so the counters stay zero).
+macro_rules! count {
+    ( $update:expr ) => {{
+        #[cfg(feature = "counters")]
+        { $update }
+    }}
+}
+
+/// Counters for the DCG's work, summed since the engine was created,
+/// or since the last call to `manage::reset_counters`.
+///
+/// The engine only counts with the `counters` feature (on by
+/// default); without it, the counters are always zero, and counting
+/// costs nothing.
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct Cnt {
+    /// Number of thunk nodes created
+    pub create : usize,
+    /// Number of thunk evaluations (first-time and re-evaluations)
+    pub eval   : usize,
+    /// Number of DCG edges dirtied
+    pub dirty  : usize,
+    /// Number of dirty DCG edges cleaned, without re-evaluation
+    pub clean  : usize,
+    /// Number of thunks whose cached results the engine checked, by
+    /// cleaning their edges
+    pub visit  : usize,
+    /// Number of re-evaluations whose result was equal to the result
+    /// observed, and hence, stopped the propagation of a change
+    pub cutoff : usize,
+    /// Maximum depth of the DCG stack
+    pub stack  : usize,
+    /// Maximum depth of propagation of any force (see `PropagationBudget`)
+    pub prop_depth : usize,
+    /// Maximum number of DCG edges of any force (see `PropagationBudget`)
+    pub prop_edges : usize,
+    /// Number of result comparisons decided by digests (see `Cutoff::Digest`)
+    pub digest_cmps : usize,
+    /// Number of thunk evaluations avoided by observing an equal producer (see `Flags::share_producers`)
+    pub shared : usize,
+    /// Number of thunk evaluations whose stack frame reused the edge vector of an earlier one
+    pub pool_hits : usize,
+    /// Number of external effects performed by sinks (see `sink`)
+    pub effects : usize,
+    /// Number of observations that a thunk evaluation had already made
+    /// (of the same art), and hence, that added no DCG edge
+    pub dup_observes : usize,
+    /// Number of allocations of a name that the same thunk evaluation
+    /// had already allocated (counted while `Flags::check_dcg_is_wf`
+    /// holds; see `ns_iter`)
+    pub name_reuse : usize,
+    /// Number of results written to disk (see `Storage::Disk`)
+    pub spills : usize,
+    /// Number of results read back from disk (see `Storage::Disk`)
+    pub reloads : usize,
+    /// Number of thunks whose cleaning `Engine::force_all` skipped,
+    /// having cleaned them for an earlier root
+    pub batch_hits : usize,
+    /// Number of thunk allocations that matched an existing thunk
+    /// whose argument is equal by quantization only (see `Quantized`)
+    pub quantized_hits : usize,
+    /// Number of cache hits whose thunks the engine re-evaluated, to
+    /// verify their results (see `Flags::verify_every`)
+    pub verifications : usize,
+    /// Numbers of allocations of nominal thunks, by their outcomes
+    /// (see also `manage::nominal_matches`)
+    pub nominal : NominalMatches,
+}
+
+/// The outcomes of the allocations of nominal thunks, by kind: when
+/// tuning a nominal incremental program, these numbers say how often
+/// its names match the thunks of earlier runs, and how.
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct NominalMatches {
+    /// Allocations of a fresh name, inserting a thunk
+    pub fresh : usize,
+    /// Matches of an existing thunk, with the same argument (the
+    /// thunk is reused, as is)
+    pub same_arg : usize,
+    /// Matches of an existing thunk, with a different argument (the
+    /// argument is overwritten, and the observers of the thunk are
+    /// dirtied)
+    pub arg_overwrite : usize,
+    /// Matches of an existing thunk of a different producer (the
+    /// engine panics; see `ns`)
+    pub producer_mismatch : usize,
+    /// Matches of an existing cell (the cell becomes a thunk)
+    pub mut_to_comp : usize,
+    /// Matches of an existing node of another type (the thunk replaces
+    /// it, as though fresh; see `cell`)
+    pub retyped : usize,
+}
+
+impl Cnt {
+    /// Adds the work `other` to this work: its sums to the sums, and
+    /// its maxima (e.g., `stack`) to the maxima, by taking the greater.
+    pub fn add (&mut self, other:&Cnt) {
+        self.create += other.create;
+        self.eval += other.eval;
+        self.dirty += other.dirty;
+        self.clean += other.clean;
+        self.visit += other.visit;
+        self.cutoff += other.cutoff;
+        self.stack = self.stack.max(other.stack);
+        self.prop_depth = self.prop_depth.max(other.prop_depth);
+        self.prop_edges = self.prop_edges.max(other.prop_edges);
+        self.digest_cmps += other.digest_cmps;
+        self.shared += other.shared;
+        self.pool_hits += other.pool_hits;
+        self.effects += other.effects;
+        self.dup_observes += other.dup_observes;
+        self.name_reuse += other.name_reuse;
+        self.spills += other.spills;
+        self.reloads += other.reloads;
+        self.batch_hits += other.batch_hits;
+        self.quantized_hits += other.quantized_hits;
+        self.verifications += other.verifications;
+        self.nominal.fresh += other.nominal.fresh;
+        self.nominal.same_arg += other.nominal.same_arg;
+        self.nominal.arg_overwrite += other.nominal.arg_overwrite;
+        self.nominal.producer_mismatch += other.nominal.producer_mismatch;
+        self.nominal.mut_to_comp += other.nominal.mut_to_comp;
+        self.nominal.retyped += other.nominal.retyped;
+    }
+}
+
+impl NominalMatches {
+    fn count (&mut self, case:&reflect::trace::AllocCase) {
+        use reflect::trace::{AllocCase, ChangeFlag};
+        match *case {
+            AllocCase::LocFresh => self.fresh += 1,
+            AllocCase::LocExists(ChangeFlag::ContentSame) => self.same_arg += 1,
+            AllocCase::LocExists(ChangeFlag::ContentDiff) => self.arg_overwrite += 1,
+            AllocCase::LocReplaced => self.mut_to_comp += 1,
+            AllocCase::LocRetyped => self.retyped += 1,
+        }
+    }
 }
 
 struct Globals {
     engine: Engine,
+    resources: HashMap<TypeId, Rc<Any>>,
 }
 
 /// The engine API works in two modes: `Naive` and `DCG`. A `Naive` engine is stateless, whereas the `DCG` is stateful.
@@ -388,10 +1339,34 @@ pub enum Engine {
 #[derive(Debug)]
 pub struct DCG {
     pub flags : Flags, // public because I dont want to write / design abstract accessors
-    table : HashMap<Rc<Loc>, Box<GraphNode>>,
+    table : HashMap<Rc<Loc>, Box<GraphNode>, SeededState>,
     stack : Vec<Frame>,
     path  : Rc<Path>,
-    //cnt   : Cnt,
+    cnt   : Cnt,
+    costs : HashMap<Rc<Loc>, Duration>, // last-measured execution times; see `Flags::auto_inline_below`
+    merged : (HashMap<Rc<Loc>, Rc<Loc>>, CompactionReport), // pass-through thunks, by their observers; see `manage::compact_chains`
+    batch : Option<HashSet<Rc<Loc>>>, // thunks already cleaned by the current `Engine::force_all`
+    prop  : PropWork,
+    producers : HashMap<u64, Rc<Loc>>, // nominal thunks, by the hashes of their producers; see `Flags::share_producers`
+    writes : WriteLog,
+    writer : Option<Name>, // the current writer of the outer layer; see `with_writer`
+    pool  : Vec<(Vec<(Succ, Option<Rc<Box<DCGDep>>>)>, HashMap<Rc<Loc>, usize>, HashMap<Rc<Loc>, usize>)>, // empty edge vectors (and indices), for reuse by stack frames
+    frame_capacity : usize, // initial capacity of the edge vectors of stack frames; see `Engine::with_capacity`
+    sinks : HashMap<Rc<Loc>, Option<u64>>, // sink thunks, with the hashes of the values that they last emitted; see `sink`
+    reevals : Vec<Reevaluation>, // see `Flags::record_reevaluations`
+    annotations : HashMap<Rc<Loc>, Vec<(AnnotationKey, String)>>, // user metadata, by node; see `annotate`
+    invariants : Invariants,
+    groups : HashMap<Rc<Loc>, MemoGroup>, // see `thunk_group`
+    group_work : HashMap<MemoGroup, (usize, Duration)>, // evaluations and their total time, by group
+    specs : Vec<SpecFrame>, // open speculations, innermost last; see `Engine::speculate`
+    locs  : RefCell<LocTable>, // interned paths and locations; see `loc_of_id`
+    epoch : (u64, u64), // numbers of changes and of forces from the outer layer; see `IdleCleaner`
+    nominal : (u64, NominalMatches), // the counts of the latest epoch (of changes) with a nominal thunk allocation
+    id    : usize, // see `EngineGone`
+    poison : Option<EnginePoisoned>, // see `Flags::poison_on_panic`
+    sampler : (usize, SampleStats), // the steps until the next sample, and the samples; see `Flags::sample_every`
+    verifier : (usize, bool, Vec<Nondeterminism>), // the cache hits until the next verification, whether one is running, and its findings; see `Flags::verify_every`
+    seed  : SeededState, // see `Engine::with_seed`
     dcg_count : usize,
     dcg_hash  : u64,
 }
@@ -409,10 +1384,44 @@ impl reflect::Reflect<reflect::DCG> for DCG {
                 .map(|ref frame| frame.reflect() )
                 .collect::<Vec<_>>(),
             path:self.path.reflect(),
+            annotations:self.annotations.iter()
+                .map(|(loc, annots)| (loc.reflect(), annots.clone()))
+                .collect(),
         }
     }
 }
 
+impl Drop for DCG {
+    /// Reports the outstanding arts of the outer layer (see
+    /// `manage::engine_drop_report`), and removes the spill files of
+    /// the thunks (see `Storage::Disk`).
+    fn drop (&mut self) {
+        let _ = LIVE_ENGINES.try_with(|e| e.borrow_mut().remove(&self.id));
+        for node in self.table.values_mut() { node.res_clear() }
+        let speculations = self.specs.len();
+        let outer = replace(&mut self.locs.borrow_mut().outer, HashMap::new());
+        // Drop the DCG's own references to its locations first: the
+        // locations that remain are those of the arts that others hold.
+        self.table.clear();
+        self.stack.clear();
+        self.costs.clear();
+        self.merged.0.clear();
+        self.producers.clear();
+        self.writes = WriteLog::default();
+        self.pool.clear();
+        self.sinks.clear();
+        self.annotations.clear();
+        self.invariants = Invariants::default();
+        self.groups.clear();
+        self.specs.clear();
+        *self.locs.borrow_mut() = LocTable::default();
+        let mut leaked : Vec<reflect::Loc> = outer.values().filter_map(|loc| loc.upgrade()).map(|loc| loc.reflect()).collect();
+        leaked.sort_by_key(|loc| format!("{:?}", loc));
+        let report = EngineDropReport{engine:self.id, leaked:leaked, speculations:speculations};
+        let _ = DROP_REPORT.try_with(|r| *r.borrow_mut() = Some(report));
+    }
+}
+
 impl Hash  for     DCG { fn hash<H>(&self, _state: &mut H) where H: Hasher { unimplemented!() }}
 impl Eq    for     DCG { }
 impl PartialEq for DCG { fn eq(&self, _other:&Self) -> bool { unimplemented!() } }
@@ -438,6 +1447,158 @@ enum NameSym {
     ForkR(Rc<NameSym>), // Right projection of a unique symbol is unique
 }
 
+/// Drops deep chains of symbols (e.g., of a million nested `name_fork`s
+/// or `name_pair`s) iteratively, rather than recursively; otherwise,
+/// dropping them would overflow the stack.
+impl Drop for NameSym {
+    fn drop(&mut self) {
+        // Moves the uniquely-owned sub-symbols of `sym` to `work`,
+        // replacing them with (a shared) `sentinel`.
+        fn unlink (sym:&mut NameSym, sentinel:&mut Option<Rc<NameSym>>, work:&mut Vec<Rc<NameSym>>) {
+            let mut take = |s:&mut Rc<NameSym>| if Rc::strong_count(s) == 1 {
+                let sentinel = sentinel.get_or_insert_with(|| Rc::new(NameSym::Unit)).clone();
+                work.push(replace(s, sentinel))
+            };
+            match *sym {
+                NameSym::Pair(ref mut l, ref mut r) => { take(l); take(r) },
+                NameSym::ForkL(ref mut s) | NameSym::ForkR(ref mut s) => take(s),
+                _ => (),
+            }
+        }
+        let mut sentinel = None;
+        let mut work = vec![];
+        unlink(self, &mut sentinel, &mut work);
+        while let Some(sym) = work.pop() {
+            if let Ok(mut sym) = Rc::try_unwrap(sym) {
+                unlink(&mut sym, &mut sentinel, &mut work)
+            }
+        }
+    }
+}
+
+/// Keys of the name-symbol table, except for strings (see
+/// `NameTable`).  Sub-symbols are identified by address, which is
+/// sound since they are themselves interned, and since the symbols
+/// that contain them keep them alive.
+#[derive(Hash,PartialEq,Eq,Clone,Debug)]
+enum SymKey {
+    Unit,
+    Hash64,
+    Usize(usize),
+    Isize(isize),
+    Pair(usize,usize),
+    ForkL(usize),
+    ForkR(usize),
+}
+
+/// The interned name symbols: those of strings, by their strings (so
+/// that a `&str` looks one up without building a key), and the others.
+struct NameTable {
+    table : HashMap<SymKey, Rc<NameSym>>,
+    strings : HashMap<String, Rc<NameSym>>,
+    next_gc : usize, // the size at which to reclaim the unused symbols
+    stats : NameInternStats,
+}
+
+/// The least size of the table of interned name symbols at which it
+/// reclaims the unused ones (see `name_intern_gc`).
+const NAME_GC_MIN : usize = 1024;
+
+impl NameTable {
+    fn len (&self) -> usize { self.table.len() + self.strings.len() }
+
+    /// Counts a miss; first, reclaims the unused symbols, when the
+    /// table has doubled in size since it last did so.
+    fn miss (&mut self) {
+        self.stats.misses += 1;
+        if self.len() >= self.next_gc { let _ = self.gc(); }
+    }
+
+    /// Reclaims the symbols that no `Name` uses; see `name_intern_gc`.
+    fn gc (&mut self) -> usize {
+        let mut count = 0;
+        loop {
+            // Reclaiming a symbol may release its sub-symbols; repeat until none.
+            let before = self.len();
+            self.table.retain(|_, s| Rc::strong_count(s) > 1);
+            self.strings.retain(|_, s| Rc::strong_count(s) > 1);
+            if self.len() == before { break }
+            count += before - self.len();
+        }
+        self.stats.reclaimed += count;
+        self.next_gc = NAME_GC_MIN.max(2 * self.len());
+        count
+    }
+}
+
+/// Statistics for the (per-thread) table of interned name symbols.
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct NameInternStats {
+    /// Number of name constructions that reused an interned symbol
+    pub hits : usize,
+    /// Number of name constructions that interned a fresh symbol
+    pub misses : usize,
+    /// Number of interned symbols reclaimed, as the table grew, or by
+    /// `name_intern_gc`
+    pub reclaimed : usize,
+}
+
+fn symkey_of_rc (s:&Rc<NameSym>) -> usize {
+    &**s as *const NameSym as usize
+}
+
+/// Returns the interned symbol for `key`, interning the result of
+/// `sym` if there is none yet.
+fn intern_namesym<F:FnOnce() -> NameSym> (key:SymKey, sym:F) -> Rc<NameSym> {
+    NAMES.with(|t| {
+        let t = &mut *t.borrow_mut();
+        if let Some(s) = t.table.get(&key) {
+            t.stats.hits += 1;
+            return s.clone()
+        };
+        t.miss();
+        let s = Rc::new(sym());
+        t.table.insert(key, s.clone());
+        s
+    })
+}
+
+/// Returns the interned symbol for the string `key`, as
+/// `intern_namesym` does; copies the string only to intern it.
+fn intern_strsym (key:&str) -> Rc<NameSym> {
+    NAMES.with(|t| {
+        let t = &mut *t.borrow_mut();
+        if let Some(s) = t.strings.get(key) {
+            t.stats.hits += 1;
+            return s.clone()
+        };
+        t.miss();
+        let s = Rc::new(NameSym::String(key.to_string()));
+        t.strings.insert(key.to_string(), s.clone());
+        s
+    })
+}
+
+/// Statistics for the table of interned name symbols (of this thread).
+pub fn name_intern_stats () -> NameInternStats {
+    NAMES.with(|t| t.borrow().stats.clone())
+}
+
+/// The number of name symbols currently interned (on this thread).
+pub fn name_intern_count () -> usize {
+    NAMES.with(|t| t.borrow().len())
+}
+
+/// Reclaims the interned name symbols that no `Name` uses anymore;
+/// returns the number reclaimed.  The table also does so itself, as it
+/// grows: when interning a symbol finds it twice as large as after its
+/// prior reclamation (and at least `NAME_GC_MIN`), so that names that
+/// a long-running program mints and drops do not accumulate.  Calling
+/// this reclaims them sooner.
+pub fn name_intern_gc () -> usize {
+    NAMES.with(|t| t.borrow_mut().gc())
+}
+
 fn write_namesym<W:Write>(w:&mut W, n:&NameSym) -> Result {
     match *n {
         NameSym::Unit => write!(w, "▲"),
@@ -479,23 +1640,67 @@ impl Debug for Path {
     }
 }
 
+/// The human-oriented form of a path; see `reflect::path_display`.
+impl fmt::Display for Path {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        fmt::Display::fmt(&reflect::path_display(&self.reflect()), f)
+    }
+}
+
+/// Drops deep paths (e.g., of a million nested namespaces)
+/// iteratively, as for `NameSym`.
+impl Drop for Path {
+    fn drop(&mut self) {
+        let mut sentinel : Option<Rc<Path>> = None;
+        let mut unlink = |path:&mut Path| match *path {
+            Path::Child(ref mut p, _) if Rc::strong_count(p) == 1 => {
+                let sentinel = sentinel.get_or_insert_with(|| Rc::new(Path::Empty)).clone();
+                Some(replace(p, sentinel))
+            },
+            _ => None,
+        };
+        let mut next = unlink(self);
+        while let Some(path) = next {
+            next = match Rc::try_unwrap(path) {
+                Ok(mut path) => unlink(&mut path),
+                Err(_) => None,
+            }
+        }
+    }
+}
+
 // The DCG structure consists of `GraphNode`s:
 trait GraphNode : Debug + reflect::Reflect<reflect::Node> {
     fn res_typeid      (self:&Self) -> TypeId ;
+    fn res_any         (self:&Self) -> Option<&Any> ;
+    #[cfg(debug_assertions)]
+    fn res_typename    (self:&Self) -> &'static str ;
     fn preds_alloc<'r> (self:&Self) -> Vec<Rc<Loc>> ;
     fn preds_obs<'r>   (self:&Self) -> Vec<(Rc<Loc>, Option<Rc<Box<DCGDep>>>)> ;
     fn preds_insert<'r>(self:&'r mut Self, Effect, &Rc<Loc>, Option<Rc<Box<DCGDep>>>) -> () ;
     fn preds_remove<'r>(self:&'r mut Self, &Rc<Loc>) -> () ;
     fn succs_def<'r>   (self:&Self) -> bool ;
-    fn succs_mut<'r>   (self:&'r mut Self) -> &'r mut Vec<Succ> ;
+    fn succs_replace<'r>(self:&'r mut Self, Vec<Succ>) -> Vec<Succ> ;
     fn succs<'r>       (self:&'r Self) -> &'r Vec<Succ> ;
+    fn succ_find<'r>   (self:&'r Self, &Effect, &Rc<Loc>) -> Option<&'r Succ> ;
+    fn succ_find_mut<'r>(self:&'r mut Self, &Effect, &Rc<Loc>) -> Option<&'r mut Succ> ;
     fn hash_seeded     (self:&Self, u64) -> u64 ;
+    fn res_clear<'r>   (self:&'r mut Self) -> () ;
+    fn copy            (self:&Self) -> Box<GraphNode> ;
+    fn is_pass_through (self:&Self) -> bool ;
+    fn is_pure         (self:&Self) -> bool ;
+    fn writer          (self:&Self) -> Option<Name> ;
+    fn prog_pt         (self:&Self) -> Option<&ProgPt> ;
 }
 
 #[derive(Debug,Clone)]
 struct Frame {
     loc   : Rc<Loc>,    // The currently-executing node
     succs : Vec<(Succ, Option<Rc<Box<DCGDep>>>)>,  // The currently-executing node's effects (viz., the nodes it demands)
+    observed : HashMap<Rc<Loc>, usize>, // The (indices of the) succs that observe the entire values of their nodes
+    mapped : HashMap<Rc<Loc>, usize>, // The (indices of the) succs that observe projections of the values of their nodes
+    purity : Purity, // The currently-executing node's allocation discipline
+    prog_pt : ProgPt, // The currently-executing node's program point
 }
 
 impl reflect::Reflect<reflect::Frame> for Frame {
@@ -513,6 +1718,7 @@ struct Succ {
     loc    : Rc<Loc>, // Target of the effect, aka, the successor, by this edge
     effect : Effect,
     dep    : Rc<Box<DCGDep>>, // Abstracted dependency information (e.g., for Observe Effect, the prior observed value)
+    label  : Option<EdgeLabel>, // User metadata, for provenance (see `force_labeled`)
 }
 
 #[derive(Debug,Clone)]
@@ -537,6 +1743,7 @@ impl reflect::Reflect<reflect::Succ> for Succ {
             effect:self.effect.reflect(),
             value:reflect::Val::ValTODO,
             is_dup:false, // XXX -- Actually: Not checked here.
+            label:self.label,
         }
     }
 }
@@ -582,6 +1789,11 @@ trait DCGDep : Debug {
     fn is_absmap(self:&Self) -> Option<TypeId> ;
     fn dirty (self:&Self, g:&mut DCG,      loc:&Rc<Loc>) -> DCGRes ;
     fn clean (self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes ;
+    /// The value that the edge observed, if any (for `Reevaluation`).
+    fn observed (self:&Self) -> Option<&Any> { None }
+    /// The type of the node that the edge observed, if the dependency
+    /// casts it to this type (see `retype_node`).
+    fn res_typeid (self:&Self) -> Option<TypeId> { None }
 }
 
 impl Hash for Succ {
@@ -608,9 +1820,9 @@ enum Node<Res> {
     Pure(PureNode<Res>),
     Mut(MutNode<Res>),
 }
-impl<X:Debug> reflect::Reflect<reflect::Node> for Node<X> {
+impl<X:Debug+'static> reflect::Reflect<reflect::Node> for Node<X> {
     fn reflect(&self) -> reflect::Node {
-        use parse_val::parse_val;
+        let parse_val = serialize_val::<X>;
         match *self {
             Node::Comp(ref n) => {
                 reflect::Node::Comp(
@@ -654,8 +1866,10 @@ struct PureNode<T> {
 // They may indirectly mutate these nodes by performing nominal allocation; mutation is limited to "one-shot" changes.
 #[derive(Debug,Hash)]
 struct MutNode<T> {
-    preds : Vec<Pred>,
-    val   : T,
+    preds  : Vec<Pred>,
+    val    : T,
+    change : ChangePolicy<T>,
+    writer : Option<Name>, // The writer of `val`, if any (see `with_writer`)
 }
 
 // CompNode<Res> for a suspended computation whose resulting value of
@@ -668,6 +1882,101 @@ struct CompNode<Res> {
     succs    : Vec<Succ>,
     producer : Box<Producer<Res>>, // Producer can be App<Arg,Res>, where type Arg is hidden.
     res      : Option<Res>,
+    purity   : Purity,
+    cutoff   : Cutoff,
+    digest   : Option<u64>, // digest of `res`, for `Cutoff::Digest` and `Storage::Disk`
+    change   : ChangePolicy<Res>,
+    spill    : Option<Spiller<Res>>, // see `Storage::Disk`
+    succ_index : SuccIndex, // finds the succs of nodes with a high fanout
+}
+
+/// Nodes with fewer succs than this find them by a linear scan; the
+/// others, by their `SuccIndex`.
+const SUCC_INDEX_MIN : usize = 16;
+
+/// The positions of a node's succs, by their effects and targets; for
+/// nodes with a high fanout, so that dirtying and cleaning each of
+/// their edges (by `get_succ`) takes constant time, not linear time.
+/// Empty for the other nodes.
+#[derive(Debug,Clone,Default)]
+struct SuccIndex(HashMap<(Effect, Rc<Loc>), usize>);
+
+impl SuccIndex {
+    fn of_succs(succs:&[Succ]) -> SuccIndex {
+        let mut index = HashMap::new();
+        if succs.len() >= SUCC_INDEX_MIN {
+            index.reserve(succs.len());
+            for (i, succ) in succs.iter().enumerate() {
+                // As with the scan, find the first of duplicate edges
+                index.entry((succ.effect.clone(), succ.loc.clone())).or_insert(i);
+            }
+        }
+        SuccIndex(index)
+    }
+
+    fn find(&self, succs:&[Succ], eff:&Effect, loc:&Rc<Loc>) -> Option<usize> {
+        if succs.len() >= SUCC_INDEX_MIN {
+            self.0.get(&(eff.clone(), loc.clone())).cloned()
+        } else {
+            succs.iter().position(|succ| &succ.effect == eff && &succ.loc == loc)
+        }
+    }
+}
+
+/// The spill file of a thunk with `Storage::Disk`, and the functions
+/// (of `Spill`) that write and read its result.
+#[derive(Clone)]
+struct Spiller<Res> {
+    file    : Rc<SpillFile>, // shared by the copies of the node (e.g., in the journal of a speculation)
+    write   : fn(&Res, &mut io::Write) -> io::Result<()>,
+    read    : fn(&mut io::Read) -> io::Result<Res>,
+    on_disk : bool, // whether the file holds the result (whose digest is the node's)
+}
+
+/// The path of a spill file, which is removed with the last node that
+/// uses it: when the DCG removes or replaces the node (e.g., by
+/// `manage::clear`, by trimming, or when the engine drops).
+struct SpillFile(PathBuf);
+
+impl Drop for SpillFile {
+    fn drop (&mut self) { let _ = fs::remove_file(&self.0); }
+}
+
+impl<Res:Hash+Clone> CompNode<Res> {
+    /// The cached result: in memory, or for a spilled thunk, on disk.
+    /// `None` if there is none, or if the spill file is missing, or
+    /// does not match the result's digest (e.g., after an I/O error).
+    fn cached(&mut self) -> Option<Res> {
+        if self.res.is_some() { return self.res.clone() };
+        let reloaded = match self.spill {
+            Some(ref sp) if sp.on_disk =>
+                fs::File::open(&sp.file.0).and_then(|f| (sp.read)(&mut io::BufReader::new(f))).ok(),
+            _ => None,
+        };
+        match reloaded {
+            Some(res) => if Some(my_hash(&res)) == self.digest { return Some(res) },
+            None => (),
+        };
+        if let Some(ref mut sp) = self.spill { sp.on_disk = false };
+        None
+    }
+
+    /// For a thunk with `Storage::Disk`, drops the result from memory,
+    /// after writing it to the spill file (if not already there);
+    /// keeps it in memory if the write fails.  True if it wrote the file.
+    fn unload(&mut self) -> bool {
+        let mut wrote = false;
+        if let (Some(ref mut sp), Some(ref res)) = (self.spill.as_mut(), self.res.as_ref()) {
+            if ! sp.on_disk {
+                sp.on_disk = fs::File::create(&sp.file.0)
+                    .and_then(|f| { let mut w = io::BufWriter::new(f); (sp.write)(res, &mut w)?; io::Write::flush(&mut w) })
+                    .is_ok();
+                wrote = sp.on_disk;
+            }
+        };
+        if self.spill.as_ref().map(|sp| sp.on_disk) == Some(true) { self.res = None };
+        wrote
+    }
 }
 
 impl reflect::Reflect<Vec<reflect::Pred>> for Vec<Pred> {
@@ -702,6 +2011,208 @@ pub enum NameChoice {
     Nominal(Name),
 }
 
+/// A `Purity` states the allocation discipline of a thunk's producer
+/// (see `thunk_purity`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone)]
+pub enum Purity {
+    /// The producer may allocate cells and thunks (the default).
+    Alloc,
+    /// The producer may not allocate cells or thunks; the DCG engine
+    /// rejects any allocation by it, with a `NoAllocViolation`.
+    NoAlloc,
+    /// The producer may allocate cells and thunks only within its
+    /// _sandbox_: the namespace of its thunk's own name, nested within
+    /// the namespace of its thunk (see `sandbox`).  The DCG engine
+    /// rejects any other allocation by it, with a
+    /// `ConfinementViolation`.
+    Confined,
+}
+
+/// The (structured) panic payload that the DCG engine raises when the
+/// producer of a `Purity::NoAlloc` thunk allocates a cell or thunk.
+/// Recover it with `std::panic::catch_unwind` and `downcast`; after
+/// such a panic, the engine's state is unspecified (re-initialize it).
+#[derive(Debug,Clone)]
+pub struct NoAllocViolation {
+    /// The program point of the offending producer.
+    pub prog_pt : ProgPt,
+    /// The location of the offending producer's thunk (rendered, since
+    /// panic payloads must be `Send`).
+    pub producer : String,
+    /// The location of the rejected allocation (rendered).
+    pub alloc : String,
+    /// The kind of the rejected allocation.
+    pub kind : reflect::trace::AllocKind,
+}
+
+/// The (structured) panic payload that the DCG engine raises when the
+/// producer of a `Purity::Confined` thunk allocates a cell or thunk
+/// outside of its sandbox.  Recover it with `std::panic::catch_unwind`
+/// and `downcast`; after such a panic, the engine's state is
+/// unspecified (re-initialize it).
+#[derive(Debug,Clone)]
+pub struct ConfinementViolation {
+    /// The program point of the offending producer.
+    pub prog_pt : ProgPt,
+    /// The location of the offending producer's thunk (rendered).
+    pub producer : String,
+    /// The producer's sandbox (rendered).
+    pub sandbox : String,
+    /// The location of the rejected allocation (rendered).
+    pub alloc : String,
+    /// The kind of the rejected allocation.
+    pub kind : reflect::trace::AllocKind,
+}
+
+/// The (structured) panic payload that the DCG engine raises, with
+/// `Flags::strict_naming`, for a structural thunk allocated within a
+/// namespace that the allowlist (`Flags::structural_namespaces`) does
+/// not permit.  Recover it with `std::panic::catch_unwind` and
+/// `downcast`; after such a panic, the engine's state is unspecified
+/// (re-initialize it).
+#[derive(Debug,Clone)]
+pub struct NamingViolation {
+    /// The program point of the rejected thunk.
+    pub prog_pt : ProgPt,
+    /// The namespace of the rejected thunk (rendered).
+    pub namespace : String,
+    /// The location of the thunk that allocated it (rendered), if any.
+    pub producer : Option<String>,
+}
+
+/// The (structured) panic payload that the engine raises for a use of
+/// an art whose engine was dropped (e.g., by `manage::init_dcg`),
+/// rather than dereferencing the (now dangling) node; see also
+/// `try_force`, and `manage::engine_drop_report`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct EngineGone {
+    /// The location of the art (rendered), or the speculation (see
+    /// `Speculation::commit`).
+    pub art : String,
+    /// The id of the dropped engine (see `EngineDropReport`).
+    pub engine : usize,
+}
+
+/// What a DCG engine left behind when it was dropped; see
+/// `manage::engine_drop_report`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct EngineDropReport {
+    /// The id of the engine (see `EngineGone`).
+    pub engine : usize,
+    /// The arts of the outer layer that were still outstanding, by
+    /// location; later uses of these raise `EngineGone`.  Tracked in
+    /// debug builds only (empty in release builds).
+    pub leaked : Vec<reflect::Loc>,
+    /// The number of speculations that were still open (see
+    /// `Engine::speculate`).
+    pub speculations : usize,
+}
+
+/// The `EngineGone` error for `loc`, if its engine was dropped.
+fn engine_gone (loc:&Loc) -> Option<EngineGone> {
+    if LIVE_ENGINES.with(|e| e.borrow().contains(&loc.engine)) { None }
+    else { Some(EngineGone{art:format!("{:?}", loc), engine:loc.engine}) }
+}
+
+/// Panics with an `EngineGone` if the engine of `loc` was dropped.
+fn check_engine_live (loc:&Loc) {
+    if let Some(gone) = engine_gone(loc) { panic_any(gone) }
+}
+
+/// The (structured) panic payload that the engine raises for a use of
+/// a poisoned engine: one whose earlier call panicked, while
+/// `Flags::poison_on_panic` was set.  Unlike the other payloads, the
+/// engine's state after this one is specified: it is unchanged.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct EnginePoisoned {
+    /// The message (or the rendered payload) of the panic that
+    /// poisoned the engine.
+    pub panic : String,
+    /// The id of the engine (see `EngineGone`).
+    pub engine : usize,
+}
+
+/// The errors of `try_force` and `try_set`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum EngineError {
+    /// The art's engine was dropped.
+    Gone(EngineGone),
+    /// The current engine is poisoned (see `Flags::poison_on_panic`).
+    Poisoned(EnginePoisoned),
+}
+
+/// Renders a panic payload, for `EnginePoisoned`: its message, or the
+/// engine's structured payloads, by `Debug`.
+fn string_of_panic (payload:&(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() { s.to_string() }
+    else if let Some(s) = payload.downcast_ref::<String>() { s.clone() }
+    else if let Some(p) = payload.downcast_ref::<WriteConflict>() { format!("{:?}", p) }
+    else if let Some(p) = payload.downcast_ref::<PropagationBudgetExceeded>() { format!("{:?}", p) }
+    else if let Some(p) = payload.downcast_ref::<NoAllocViolation>() { format!("{:?}", p) }
+    else if let Some(p) = payload.downcast_ref::<ConfinementViolation>() { format!("{:?}", p) }
+    else if let Some(p) = payload.downcast_ref::<NamingViolation>() { format!("{:?}", p) }
+    else { String::from("(unknown panic payload)") }
+}
+
+/// Performs `body`, a call of the engine's API, with respect to
+/// `Flags::poison_on_panic`: when the engine is poisoned, returns its
+/// `EnginePoisoned` instead; when set (and called from the outer
+/// layer), a panic of `body` poisons the engine, and is returned.
+fn poison_guard<T,F:FnOnce() -> T> (body:F) -> ::std::result::Result<T, Box<Any + Send>> {
+    let guard = GLOBALS.with(|g| match g.borrow().engine {
+        Engine::DCG(ref dcg) => match dcg.try_borrow() {
+            Ok(st) => match st.poison {
+                Some(ref poison) => Err(poison.clone()),
+                None => Ok(st.flags.poison_on_panic && st.stack.is_empty()),
+            },
+            Err(_) => Ok(false),
+        },
+        Engine::Naive => Ok(false),
+    });
+    match guard {
+        Err(poison) => Err(Box::new(poison)),
+        Ok(false) => Ok(body()),
+        Ok(true) => catch_unwind(AssertUnwindSafe(body)).map_err(|payload| {
+            // A use of a dropped engine (e.g., of an outstanding art)
+            // does not involve this one.
+            if ! payload.is::<EngineGone>() {
+                GLOBALS.with(|g| if let Engine::DCG(ref dcg) = g.borrow().engine {
+                    let st = &mut *dcg.borrow_mut();
+                    st.poison = Some(EnginePoisoned{panic:string_of_panic(&*payload), engine:st.id})
+                })
+            };
+            payload
+        }),
+    }
+}
+
+/// Performs `body` with `poison_guard`, panicking as it would have
+/// (or, for a poisoned engine, with an `EnginePoisoned`).
+fn outer_call<T,F:FnOnce() -> T> (body:F) -> T {
+    match poison_guard(body) {
+        Ok(res) => res,
+        Err(payload) => match payload.downcast::<EnginePoisoned>() {
+            Ok(poison) => panic_any(*poison),
+            Err(payload) => resume_unwind(payload),
+        },
+    }
+}
+
+/// The `EngineError` of a panic payload, if any (see `poison_guard`).
+fn engine_error (payload:Box<Any + Send>) -> ::std::result::Result<EngineError, Box<Any + Send>> {
+    let payload = match payload.downcast::<EngineGone>() {
+        Ok(gone) => return Ok(EngineError::Gone(*gone)),
+        Err(payload) => payload,
+    };
+    match payload.downcast::<EnginePoisoned>() {
+        Ok(poison) => Ok(EngineError::Poisoned(*poison)),
+        Err(payload) => match manage::poisoned() {
+            Some(poison) => Ok(EngineError::Poisoned(poison)),
+            None => Err(payload),
+        },
+    }
+}
+
 // Produce a value of type Res.
 trait Producer<Res> : Debug {
     //  fn produce(self:&Self, st:&mut DCG) -> Res;
@@ -709,6 +2220,14 @@ trait Producer<Res> : Debug {
     fn copy(self:&Self) -> Box<Producer<Res>>;
     fn eq(self:&Self, other:&Producer<Res>) -> bool;
     fn prog_pt<'r>(self:&'r Self) -> &'r ProgPt;
+    /// Hashes the program point and argument (and the result type), for
+    /// producer sharing; equal producers have equal keys.
+    fn share_key(self:&Self) -> u64;
+    /// The type of the producer, and its name; in debug builds, the
+    /// engine checks it before casting a producer to a concrete type
+    /// (see `assert_producer_type`).
+    #[cfg(debug_assertions)]
+    fn producer_type(self:&Self) -> (TypeId, &'static str);
 }
 // Consume a value of type Arg.
 trait Consumer<Arg> : Debug {
@@ -740,7 +2259,7 @@ impl<Arg:Hash+Debug,Spurious,Res>
     fn hash<H>(&self, state: &mut H) where H: Hasher { (&self.prog_pt,&self.arg).hash(state) }
 }
 
-impl<Arg:'static+PartialEq+Eq+Clone+Debug,Spurious:'static+Clone,Res:'static+Debug+Hash>
+impl<Arg:'static+Hash+PartialEq+Eq+Clone+Debug,Spurious:'static+Clone,Res:'static+Debug+Hash>
     Producer<Res> for
     App<Arg,Spurious,Res>
 {
@@ -760,11 +2279,20 @@ impl<Arg:'static+PartialEq+Eq+Clone+Debug,Spurious:'static+Clone,Res:'static+Deb
     fn prog_pt<'r>(self:&'r Self) -> &'r ProgPt {
         & self.prog_pt
     }
+    fn share_key(self:&Self) -> u64 {
+        my_hash(&(TypeId::of::<Res>(), self))
+    }
+    #[cfg(debug_assertions)]
+    fn producer_type(self:&Self) -> (TypeId, &'static str) {
+        (TypeId::of::<Self>(), type_name::<Self>())
+    }
     fn eq (&self, other:&Producer<Res>) -> bool {
         if &self.prog_pt == other.prog_pt() {
+            // This is safe if the prog_pt implies unique Arg and Res
+            // types; debug builds check this dynamically.
+            #[cfg(debug_assertions)]
+            assert_producer_type::<Self,Res>(other, &self.prog_pt);
             let other = Box::new(other) ;
-            // This is safe if the prog_pt implies unique Arg and Res types.
-            // TODO-Soon: Program points should store argument + result types; we should check these dynamically here
             let other : &Box<App<Arg,Spurious,Res>> = unsafe { transmute::<_,_>( other ) } ;
             self.arg == other.arg
         } else {
@@ -782,9 +2310,22 @@ impl<Arg:Clone+PartialEq+Eq+Debug,Spurious,Res>
 
 // ----------- Location resolution:
 
+/// Renders the demand stack for the engine's panic messages: the
+/// frames of the currently-executing thunks, innermost first.
+fn string_of_demand_stack(stack:&Vec<Frame>) -> String {
+    let mut out = String::from("Demand stack (innermost first):");
+    if stack.is_empty() { out.push_str(" (empty)") };
+    for (i, frame) in stack.iter().rev().enumerate() {
+        write!(&mut out, "\n\t{}: {}", i, frame.loc).unwrap();
+    }
+    out
+}
+
 fn lookup_abs<'r>(st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Box<GraphNode> {
+    st.journal(loc);
     match st.table.get_mut( loc ) {
-        None => panic!("dangling pointer: {:?}", loc),
+        None => { check_engine_live(loc);
+                  panic!("dangling pointer: {}\n{}", loc, string_of_demand_stack(&st.stack)) },
         Some(node) => node.be_node() // This is a weird workaround; TODO-Later: Investigate.
     }
 }
@@ -797,42 +2338,173 @@ fn get_top_stack_loc(st:&DCG) -> Option<Rc<Loc>> {
     }
 }
 
-fn assert_graphnode_res_type<Res:'static> (loc:&Loc, node:&Box<GraphNode>, top_stack:Option<Rc<Loc>>) {
-    let res_typeid = TypeId::of::<Res>();
-    let node_res_typeid = node.res_typeid();
-    if node_res_typeid != res_typeid {
-        let alloc_preds = node.preds_alloc().reflect();
-        panic!("\
-            Adapton engine: Detected a dynamic type error, possibly due to an ambiguous name:
-\t              at location: {:?}
-\t    existing allocator(s): {:?}
-\tcontext/current allocator: {:?}
-
-\t location has result type: {:?}
-\tbut context expected type: {:?}",
-               loc, alloc_preds, top_stack.reflect(), node_res_typeid, res_typeid
-        );
+/// Rejects an allocation at `loc` when the producer at the top of the
+/// stack is `Purity::NoAlloc`, by panicking with a `NoAllocViolation`,
+/// or when it is `Purity::Confined` and `loc` is outside of its
+/// sandbox, by panicking with a `ConfinementViolation`.
+fn check_purity(st:&mut DCG, loc:&Rc<Loc>, kind:reflect::trace::AllocKind) {
+    let (top_loc, purity) = match st.stack.last() {
+        Some(frame) if frame.purity != Purity::Alloc => (frame.loc.clone(), frame.purity.clone()),
+        _ => return,
+    };
+    let sandbox = if purity == Purity::Confined {
+        let sandbox = sandbox_path(&top_loc);
+        if name::path_is_prefix_of(&sandbox, &loc.path.reflect()) { return };
+        Some(sandbox)
+    } else { None };
+    let prog_pt = match lookup_abs(st, &top_loc).reflect() {
+        reflect::Node::Comp(nd) => nd.prog_pt,
+        _ => unreachable!(),
+    };
+    match sandbox {
+        None => panic_any(NoAllocViolation{
+            prog_pt:prog_pt,
+            producer:format!("{:?}", top_loc),
+            alloc:format!("{:?}", loc),
+            kind:kind,
+        }),
+        Some(sandbox) => panic_any(ConfinementViolation{
+            prog_pt:prog_pt,
+            producer:format!("{:?}", top_loc),
+            sandbox:format!("{:?}", sandbox),
+            alloc:format!("{:?}", loc),
+            kind:kind,
+        }),
     }
 }
 
-// This function uses 'unsafe' to transmute pointer types. Unintended
-// double-uses of names and hashes will cause dynamic type errors, via
-// assert_graphnode_res_type.
-fn res_node_of_loc<'r,Res:'static> (st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Box<Node<Res>> {
-    let top_loc = get_top_stack_loc(st) ;
-    let abs_node = lookup_abs(st, loc) ;
-    assert_graphnode_res_type::<Res>(&*loc, abs_node, top_loc);
+/// With `Flags::strict_naming`, rejects a structural thunk (of
+/// `prog_pt`) in the current namespace, unless it is empty, or
+/// beneath a namespace of the allowlist, by panicking with a
+/// `NamingViolation`.
+fn check_naming(st:&DCG, prog_pt:&ProgPt) {
+    if ! st.flags.strict_naming { return };
+    let path = st.path.reflect();
+    if path.is_empty() || st.flags.structural_namespaces.iter().any(|ns| name::path_is_prefix_of(ns, &path)) {
+        return
+    };
+    panic_any(NamingViolation{
+        prog_pt:prog_pt.clone(),
+        namespace:format!("{:?}", path),
+        producer:st.stack.last().map(|frame| format!("{:?}", frame.loc)),
+    })
+}
+
+/// Counts the allocation of the structural thunk at `loc` (of
+/// `prog_pt` and `arg`) in `Cnt::quantized_hits` if the existing
+/// thunk's argument is equal to `arg` by quantization only (see
+/// `Quantized`).
+fn count_quantized_hit<Arg:'static+Eq+Debug+Clone+Hash,Spurious:'static+Clone,Res:'static+Eq+Debug+Clone+Hash>
+    (st:&mut DCG, loc:&Rc<Loc>, prog_pt:&ProgPt, arg:&Arg)
+{
+    let node : &mut Node<Res> = res_node_of_loc(st, loc);
+    let inexact = match *node {
+        Node::Comp(ref mut nd) if nd.producer.prog_pt() == prog_pt => {
+            #[cfg(debug_assertions)]
+            assert_producer_type::<App<Arg,Spurious,Res>,Res>(&*nd.producer, loc);
+            let app : &mut Box<App<Arg,Spurious,Res>> = unsafe { transmute::<_,_>( &mut nd.producer ) };
+            quantization_reset();
+            app.get_arg() == *arg && quantization_inexact()
+        },
+        _ => false,
+    };
+    if inexact { count!(st.cnt.quantized_hits += 1) }
+}
+
+/// The name of the sandbox of the thunk at `loc`: its own name, or
+/// for a structural thunk, the name of its hash.
+fn sandbox_name(loc:&Loc) -> Name {
+    match *loc.id {
+        ArtId::Nominal(ref n) => n.clone(),
+        ArtId::Structural(h) => name_of_hash64(h),
+    }
+}
+
+/// The sandbox of the thunk at `loc`; see `Purity::Confined`.
+fn sandbox_path(loc:&Loc) -> reflect::Path {
+    let mut path = loc.path.reflect();
+    path.push(sandbox_name(loc));
+    path
+}
+
+/// Checks that a producer has type `P`, before casting it (in debug
+/// builds); `at` identifies the producer in the panic message.
+#[cfg(debug_assertions)]
+fn assert_producer_type<P:'static,Res> (producer:&Producer<Res>, at:&Debug) {
+    let (typeid, typename) = producer.producer_type();
+    if typeid != TypeId::of::<P>() {
+        panic!("\
+            Adapton engine: Detected a dynamic type error, possibly due to an ambiguous program point:
+\texpected {}, found {} at {:?}",
+               type_name::<P>(), typename, at);
+    }
+}
+
+fn assert_graphnode_res_type<Res:'static> (loc:&Loc, node:&Box<GraphNode>, top_stack:Option<Rc<Loc>>, stack:&Vec<Frame>) {
+    let res_typeid = TypeId::of::<Res>();
+    let node_res_typeid = node.res_typeid();
+    if node_res_typeid != res_typeid {
+        let alloc_preds = node.preds_alloc().reflect();
+        // Debug builds know the names of the types; release builds, only their ids
+        #[cfg(debug_assertions)]
+        let (found, expected) = (node.res_typename().to_string(), type_name::<Res>().to_string());
+        #[cfg(not(debug_assertions))]
+        let (found, expected) = (format!("{:?}", node_res_typeid), format!("{:?}", res_typeid));
+        panic!("\
+            Adapton engine: Detected a dynamic type error, possibly due to an ambiguous name:
+\texpected {}, found {} at {:?}
+
+\t              at location: {}
+\t    existing allocator(s): {:?}
+\tcontext/current allocator: {:?}
+
+\t location has result type: {:?}
+\tbut context expected type: {:?}
+
+{}",
+               expected, found, loc.id,
+               loc, alloc_preds, top_stack.reflect(), node_res_typeid, res_typeid,
+               string_of_demand_stack(stack)
+        );
+    }
+}
+
+// This function uses 'unsafe' to transmute pointer types. Unintended
+// double-uses of names and hashes will cause dynamic type errors, via
+// assert_graphnode_res_type.
+fn res_node_of_loc<'r,Res:'static> (st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Box<Node<Res>> {
+    let top_loc = get_top_stack_loc(st) ;
+    st.journal(loc);
+    let abs_node = match st.table.get_mut( loc ) {
+        None => { check_engine_live(loc);
+                  panic!("dangling pointer: {}\n{}", loc, string_of_demand_stack(&st.stack)) },
+        Some(node) => node.be_node(),
+    } ;
+    assert_graphnode_res_type::<Res>(&*loc, abs_node, top_loc, &st.stack);
     unsafe { transmute::<_,_>(abs_node) }
 }
 
 // ---------- Node implementation:
 
-impl <Res:'static+Debug+Hash> GraphNode for Node<Res> {
+impl <Res:'static+Debug+Hash+Clone> GraphNode for Node<Res> {
 
     fn res_typeid(self:&Self) -> TypeId {
         return TypeId::of::<Res>()
     }
 
+    fn res_any(self:&Self) -> Option<&Any> {
+        match *self {
+            Node::Comp(ref nd) => nd.res.as_ref().map(|res| res as &Any),
+            Node::Pure(ref nd) => Some(&nd.val as &Any),
+            Node::Mut(ref nd) => Some(&nd.val as &Any),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn res_typename(self:&Self) -> &'static str {
+        type_name::<Res>()
+    }
+
     fn preds_alloc(self:&Self) -> Vec<Rc<Loc>> {
         match *self { Node::Mut(ref nd) => nd.preds.iter().filter_map(|pred| if pred.effect == Effect::Allocate { Some(pred.loc.clone()) } else { None } ).collect::<Vec<_>>(),
                       Node::Comp(ref nd) => nd.preds.iter().filter_map(|pred| if pred.effect == Effect::Allocate { Some(pred.loc.clone()) } else { None } ).collect::<Vec<_>>(),
@@ -870,8 +2542,11 @@ impl <Res:'static+Debug+Hash> GraphNode for Node<Res> {
     fn succs_def(self:&Self) -> bool {
         match *self { Node::Comp(_) => true, _ => false
         }}
-    fn succs_mut<'r>(self:&'r mut Self) -> &'r mut Vec<Succ> {
-        match *self { Node::Comp(ref mut n) => &mut n.succs,
+    fn succs_replace(self:&mut Self, succs:Vec<Succ>) -> Vec<Succ> {
+        match *self { Node::Comp(ref mut n) => {
+                          n.succ_index = SuccIndex::of_succs(&succs);
+                          replace(&mut n.succs, succs)
+                      },
                       _ => panic!("undefined"),
         }
     }
@@ -880,12 +2555,79 @@ impl <Res:'static+Debug+Hash> GraphNode for Node<Res> {
                       _ => panic!("undefined"),
         }
     }
+    fn succ_find<'r>(self:&'r Self, eff:&Effect, loc:&Rc<Loc>) -> Option<&'r Succ> {
+        match *self { Node::Comp(ref n) => n.succ_index.find(&n.succs, eff, loc).map(move |i| &n.succs[i]),
+                      _ => panic!("undefined"),
+        }
+    }
+    fn succ_find_mut<'r>(self:&'r mut Self, eff:&Effect, loc:&Rc<Loc>) -> Option<&'r mut Succ> {
+        match *self { Node::Comp(ref mut n) => match n.succ_index.find(&n.succs, eff, loc) {
+                          Some(i) => Some(&mut n.succs[i]),
+                          None => None,
+                      },
+                      _ => panic!("undefined"),
+        }
+    }
     fn hash_seeded(self:&Self, seed:u64) -> u64 {
         let mut hasher = DefaultHasher::new();
         seed.hash(&mut hasher);
         self.hash(&mut hasher);
         hasher.finish()
     }
+    fn res_clear(self:&mut Self) -> () {
+        match *self { Node::Comp(ref mut n) => {
+                          n.res = None;
+                          if let Some(ref mut sp) = n.spill {
+                              if sp.on_disk { let _ = fs::remove_file(&sp.file.0); }
+                              sp.on_disk = false
+                          }
+                      },
+                      _ => (),
+        }
+    }
+    fn is_pass_through(self:&Self) -> bool {
+        match *self {
+            Node::Comp(ref n) =>
+                n.succs.iter().any(|succ| succ.effect == Effect::Observe)
+                && n.succs.iter().all(|succ| succ.loc == n.succs[0].loc)
+                && n.cutoff == Cutoff::Eq && n.spill.is_none()
+                && match n.change { ChangePolicy::Eq => true, _ => false }
+                && n.producer.prog_pt().symbol != LAZY_CELL,
+            _ => false,
+        }
+    }
+    fn is_pure(self:&Self) -> bool {
+        match *self { Node::Pure(_) => true, _ => false }
+    }
+    fn writer(self:&Self) -> Option<Name> {
+        match *self { Node::Mut(ref nd) => nd.writer.clone(), _ => None }
+    }
+    fn prog_pt(self:&Self) -> Option<&ProgPt> {
+        match *self { Node::Comp(ref nd) => Some(nd.producer.prog_pt()), _ => None }
+    }
+    fn copy(self:&Self) -> Box<GraphNode> {
+        Box::new(match *self {
+            Node::Comp(ref n) => Node::Comp(CompNode{
+                preds:n.preds.clone(),
+                succs:n.succs.clone(),
+                producer:n.producer.copy(),
+                res:n.res.clone(),
+                purity:n.purity.clone(),
+                cutoff:n.cutoff,
+                digest:n.digest,
+                change:n.change.clone(),
+                spill:n.spill.clone(),
+                succ_index:n.succ_index.clone(),
+            }),
+            Node::Pure(ref n) => Node::Pure(PureNode{val:n.val.clone()}),
+            Node::Mut(ref n) => Node::Mut(MutNode{
+                preds:n.preds.clone(),
+                val:n.val.clone(),
+                change:n.change.clone(),
+                writer:n.writer.clone(),
+            }),
+        })
+    }
 }
 
 trait ShapeShifter {
@@ -932,18 +2674,18 @@ impl<Res:Hash> Hash for CompNode<Res> {
 /// `Node::Comp`.
 fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&Rc<Loc>) -> Res
 {
-    let (producer, prev_path) = {
+    let (producer, prev_path, measure, share_key, shared, group) = {
         let st : &mut DCG = &mut *g.borrow_mut() ;
         let succs : Vec<Succ> = {
             let succs : Vec<Succ> = Vec::new();
             let node : &mut Node<Res> = res_node_of_loc( st, loc ) ;
-            replace(node.succs_mut(), succs)
+            node.succs_replace(succs)
         } ;
         revoke_succs( st, loc, &succs );
-        st.stack.push ( Frame{loc:loc.clone(), succs:Vec::new(), } );
-        //st.cnt.stack = if st.cnt.stack > st.stack.len() { st.cnt.stack } else { st.stack.len() } ;
-        let prev_path = st.path.clone () ;
-        st.path = loc.path.clone() ;
+        let purity = match **res_node_of_loc::<Res>( st, loc ) {
+            Node::Comp(ref nd) => nd.purity.clone(),
+            _ => panic!("internal error"),
+        } ;
         let producer : Box<Producer<Res>> = {
             let node : &mut Node<Res> = res_node_of_loc( st, loc ) ;
             match *node {
@@ -951,9 +2693,30 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
                 _ => panic!("internal error"),
             }
         } ;
-        //st.cnt.eval += 1 ;
+        let (frame_succs, observed, mapped) = match st.pool.pop() {
+            Some(pooled) => { count!(st.cnt.pool_hits += 1); pooled },
+            None => (Vec::with_capacity(st.frame_capacity), HashMap::new(), HashMap::new()),
+        } ;
+        st.stack.push ( Frame{loc:loc.clone(), succs:frame_succs, observed:observed, mapped:mapped, purity:purity,
+                              prog_pt:producer.prog_pt().clone() } );
+        count!(st.cnt.stack = if st.cnt.stack > st.stack.len() { st.cnt.stack } else { st.stack.len() });
+        sample_step(st, None);
+        prop_work(st, loc, 1, 0);
+        let prev_path = st.path.clone () ;
+        st.path = loc.path.clone() ;
+        let share_key = match *loc.id {
+            ArtId::Nominal(_) if st.flags.share_producers => Some(producer.share_key()),
+            _ => None,
+        } ;
+        let shared = match share_key {
+            Some(key) => shared_producer(st, loc, key, &*producer),
+            None => None,
+        } ;
+        count!(if shared.is_some() { st.cnt.shared += 1 } else { st.cnt.eval += 1 });
+        let group = st.groups.get(loc).cloned() ;
+        let measure = st.flags.auto_inline_below.is_some() || group.is_some() ;
         drop(st);  // End mutable borrow of global RefCell
-        (producer, prev_path)
+        (producer, prev_path, measure, share_key, shared, group)
     };
     // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
     // Invoke producer: Run the user's code, and get a result.
@@ -961,15 +2724,39 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
     // engine library.  That's why we end the mutable borrow of `g`
     // above, before making this call.  We re-borrow `g` below, when
     // the call is complete.
-    let res = producer.produce() ;
+    let start = if measure { Some(testing::now()) } else { None } ;
+    let res = match shared {
+        // Producer sharing: observe the equal producer, as if it were our own code.
+        Some(ref other) => <DCG as Adapton>::force(g, &AbsArt::Loc(other.clone()), None),
+        None => producer.produce(),
+    } ;
     // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
     let st = &mut * g.borrow_mut() ;
+    if let Some(start) = start {
+        let cost = testing::now() - start;
+        if st.flags.auto_inline_below.is_some() { st.costs.insert(loc.clone(), cost); }
+        if let Some(group) = group {
+            let work = st.group_work.entry(group).or_insert((0, Duration::new(0, 0)));
+            work.0 += 1;
+            work.1 += cost;
+        }
+    }
+    if let (Some(key), None) = (share_key, shared) { st.producers.insert(key, loc.clone()); }
     st.path = prev_path ;
-    let frame = match st.stack.pop() {
+    let mut frame = match st.stack.pop() {
         None => panic!("expected Some _: stack invariants are broken"),
         Some(frame) => frame
     } ;
     assert!( &frame.loc == loc );
+    if ! frame.observed.is_empty() {
+        // Drop the (mapped) observations that an observation of the
+        // entire value subsumes.
+        let observed = &frame.observed;
+        frame.succs.retain(|&(ref succ, ref dep)|
+                           ! (succ.effect == Effect::Observe && dep.is_some() && observed.contains_key(&succ.loc)));
+    }
+    st.prop.depth -= 1;
+    prop_work(st, loc, 0, frame.succs.len());
     for succ in &frame.succs {
         if succ.0.dirty {
             // This case witnesses an illegal use of nominal side effects
@@ -978,54 +2765,198 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
         let succ_node = lookup_abs( st, &succ.0.loc );
         succ_node.preds_insert( succ.0.effect.clone(), loc, succ.1.clone() );
     } ;
-    {
+    let mut frame_succs = frame.succs ;
+    let old = {
         let node : &mut Node<Res> = res_node_of_loc( st, loc ) ;
         match *node {
             Node::Comp(ref mut node) => {
-                replace(&mut node.succs, frame.succs.into_iter().map(|(succ,_)|succ).collect() ) ;
-                replace(&mut node.res, Some(res.clone()))
+                let mut succs = Vec::with_capacity(frame_succs.len()) ;
+                succs.extend(frame_succs.drain(..).map(|(succ,_)|succ)) ;
+                node.succ_index = SuccIndex::of_succs(&succs) ;
+                replace(&mut node.succs, succs) ;
+                if (node.cutoff == Cutoff::Digest || node.spill.is_some()) && ! is_effect_only::<Res>() { node.digest = Some(my_hash(&res)) } ;
+                if let Some(ref mut sp) = node.spill { sp.on_disk = false } ;
+                let old = replace(&mut node.res, Some(res.clone())) ;
+                if node.unload() { count!(st.cnt.spills += 1) } ;
+                old
             },
             _ => panic!("internal error"),
         }
     } ;
+    if st.pool.len() < FRAME_POOL_SIZE {
+        let (mut observed, mut mapped) = (frame.observed, frame.mapped);
+        observed.clear();
+        mapped.clear();
+        st.pool.push((frame_succs, observed, mapped))
+    } ;
+    call_recompute_hooks(loc, old.as_ref().map(|old| old as &Any), &res);
     res
 }
 
+/// The maximum number of (empty) edge vectors that the DCG keeps for
+/// reuse by the stack frames of later evaluations; each keeps its
+/// capacity, so that deep re-computations do not re-allocate them.
+const FRAME_POOL_SIZE : usize = 64;
+
+/// For producer sharing: the nominal thunk (other than `loc`) that
+/// last evaluated a producer equal to `producer`, if any, and if
+/// observing it does not form a cycle.
+fn shared_producer<Res:'static>(st:&mut DCG, loc:&Rc<Loc>, key:u64, producer:&Producer<Res>) -> Option<Rc<Loc>> {
+    let other = match st.producers.get(&key) {
+        Some(other) if other != loc => other.clone(),
+        _ => return None,
+    } ;
+    if ! st.table.contains_key(&other) || st.stack.iter().any(|frame| frame.loc == other) {
+        return None
+    } ;
+    // The key hashes the result type, so (barring hash collisions)
+    // this lookup is type-safe; the comparison rejects other collisions.
+    let equal = match **res_node_of_loc::<Res>(st, &other) {
+        Node::Comp(ref nd) => nd.producer.eq(producer),
+        _ => false,
+    } ;
+    if equal { Some(other) } else { None }
+}
+
+/// Debugging: Counts a cache hit of the thunk `loc`, and when it is
+/// due, re-evaluates the thunk in a speculation that it then discards
+/// (so that the DCG keeps its edges and cached results), and records
+/// it if the result differs from `cached`; see `Flags::verify_every`.
+fn verify_hit<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&Rc<Loc>, cached:&Res) {
+    let (producer, prev_path) = {
+        let st : &mut DCG = &mut *g.borrow_mut() ;
+        let every = match st.flags.verify_every { Some(every) if ! st.verifier.1 => every, _ => return } ;
+        if st.verifier.0 > 1 { st.verifier.0 -= 1; return } ;
+        st.verifier.0 = every ;
+        let (producer, purity) = match **res_node_of_loc::<Res>( st, loc ) {
+            Node::Comp(ref nd) => (nd.producer.copy(), nd.purity.clone()),
+            _ => return,
+        } ;
+        count!(st.cnt.verifications += 1);
+        let id = SPEC_COUNT.with(|c| { *c.borrow_mut() += 1; *c.borrow() });
+        let spec = SpecFrame::new(id, st);
+        st.specs.push(spec);
+        st.verifier.1 = true ;
+        st.stack.push ( Frame{loc:loc.clone(), succs:Vec::new(), observed:HashMap::new(), mapped:HashMap::new(), purity:purity,
+                              prog_pt:producer.prog_pt().clone() } );
+        let prev_path = replace(&mut st.path, loc.path.clone()) ;
+        (producer, prev_path)
+    };
+    let fresh = producer.produce();
+    let st : &mut DCG = &mut *g.borrow_mut() ;
+    st.path = prev_path ;
+    let frame = st.stack.pop().unwrap() ;
+    assert!( &frame.loc == loc );
+    st.specs.pop().unwrap().restore(st);
+    st.verifier.1 = false ;
+    if fresh != *cached {
+        st.verifier.2.push(Nondeterminism{
+            loc:loc.reflect(),
+            prog_pt:producer.prog_pt().clone(),
+            cached:render_any(cached),
+            fresh:render_any(&fresh),
+        })
+    }
+}
+
+/// Profiling: Counts a step of the engine, and when it is due, samples
+/// it: the cleaning of the DCG edge `edge`, if any, or else, the
+/// evaluation atop the stack; see `Flags::sample_every`.
+fn sample_step(st:&mut DCG, edge:Option<(&Rc<Loc>, &Rc<Loc>)>) {
+    let every = match st.flags.sample_every { Some(every) => every, None => return };
+    if st.sampler.0 > 1 { st.sampler.0 -= 1; return };
+    st.sampler.0 = every;
+    let samples = &mut st.sampler.1;
+    match edge {
+        Some((src, tgt)) => *samples.clean.entry((src.reflect(), tgt.reflect())).or_insert(0) += 1,
+        None => match st.stack.last() {
+            Some(frame) => *samples.produce.entry(frame.prog_pt.clone()).or_insert(0) += 1,
+            None => return,
+        },
+    };
+    samples.samples += 1;
+}
+
+/// Accounts for propagation work (nesting `depth` by one, and
+/// `edges` more DCG edges), at `loc`; enforces the `PropagationBudget`.
+fn prop_work(st:&mut DCG, loc:&Rc<Loc>, depth:usize, edges:usize) {
+    st.prop.depth += depth;
+    st.prop.edges += edges;
+    count!(if st.prop.depth > st.cnt.prop_depth { st.cnt.prop_depth = st.prop.depth });
+    count!(if st.prop.edges > st.cnt.prop_edges { st.cnt.prop_edges = st.prop.edges });
+    let budget = st.flags.propagation_budget;
+    let exceeds = |max:Option<usize>, n:usize| match max { Some(max) => n > max, None => false };
+    if exceeds(budget.max_depth, st.prop.depth) || exceeds(budget.max_edges, st.prop.edges) {
+        panic_any(PropagationBudgetExceeded{
+            budget:budget,
+            depth:st.prop.depth,
+            edges:st.prop.edges,
+            loc:format!("{:?}", loc),
+            demand_stack:string_of_demand_stack(&st.stack),
+        })
+    }
+}
+
 fn clean_comp<Res:'static+Sized+Debug+PartialEq+Clone+Eq+Hash>
     (g:&RefCell<DCG>,
      this_dep:&ForceDep<Res>,
-     loc:&Rc<Loc>, cache:Res, succs:Vec<Succ>) -> DCGRes
+     loc:&Rc<Loc>, cache:Option<Res>, succs:Vec<Succ>) -> DCGRes
 {
+    count!(g.borrow_mut().cnt.visit += 1);
     for succ in succs.iter() {
         let dirty = {
             let mut st = &mut *g.borrow_mut();
+            sample_step(st, Some((loc, &succ.loc)));
+            prop_work(st, loc, 0, 1);
             get_succ_mut(st, loc, succ.effect.clone(), &succ.loc).dirty
         } ;
         if dirty {
-            dcg_effect_begin!(reflect::trace::Effect::CleanRec, Some(loc), succ);
+            dcg_effect_begin!(reflect::trace::Effect::CleanRec, Some(loc), succ, true, dirty);
             let succ_dep = & succ.dep ;
-            let res = succ_dep.clean(g, &succ.loc) ;
+            prop_work(&mut *g.borrow_mut(), &succ.loc, 1, 0);
+            // A merged thunk is not cleaned: its observer re-evaluates,
+            // performing the chain (see `manage::compact_chains`).
+            let merged = g.borrow().merged.0.contains_key(&succ.loc);
+            let retyped = dep_retyped(&mut *g.borrow_mut(), &***succ_dep, &succ.loc);
+            let res = if merged || retyped { DCGRes{changed:true} } else { succ_dep.clean(g, &succ.loc) } ;
+            g.borrow_mut().prop.depth -= 1;
             if res.changed {
-                dcg_effect_begin!(reflect::trace::Effect::CleanEval, Some(loc), succ);
+                {
+                    let st = &mut *g.borrow_mut();
+                    if st.flags.record_reevaluations { record_reevaluation(st, loc, succ) }
+                }
+                dcg_effect_begin!(reflect::trace::Effect::CleanEval, Some(loc), succ, true,
+                                  succ_is_dirty(&*g.borrow(), loc, succ.effect.clone(), &succ.loc));
                 let result : Res = loc_produce( g, loc ) ;
-                dcg_effect_end!();
-                let changed = result != this_dep.res ;
-                dcg_effect_end!();
+                // The re-evaluation replaced the edges of `loc`
+                dcg_effect_end!(succ_is_dirty(&*g.borrow(), loc, succ.effect.clone(), &succ.loc));
+                let changed = force_dep_changed(g, loc, this_dep, Some(&result)) ;
+                count!(if ! changed { g.borrow_mut().cnt.cutoff += 1 });
+                dcg_effect_end!(succ_is_dirty(&*g.borrow(), loc, succ.effect.clone(), &succ.loc));
                 return DCGRes{changed:changed}
             }
             else {
                 let mut st : &mut DCG = &mut *g.borrow_mut();
-                //st.cnt.clean += 1 ;
+                count!(st.cnt.clean += 1);
                 get_succ_mut(st, loc, succ.effect.clone(), &succ.loc).dirty = false ;
-                dcg_effect!(reflect::trace::Effect::CleanEdge, Some(loc), succ);
+                dcg_effect!(reflect::trace::Effect::CleanEdge, Some(loc),
+                            get_succ(st, loc, succ.effect.clone(), &succ.loc), dirty);
             }
-            dcg_effect_end!();
+            dcg_effect_end!(succ_is_dirty(&*g.borrow(), loc, succ.effect.clone(), &succ.loc));
         }
     } ;
-    let changed = this_dep.res != cache ;
+    let changed = force_dep_changed(g, loc, this_dep, cache.as_ref()) ;
     DCGRes{changed:changed}
 }
 
+/// Records the re-evaluation of `loc`, caused by a change to the
+/// target of its edge `succ`; see `Flags::record_reevaluations`.
+fn record_reevaluation(st:&mut DCG, loc:&Rc<Loc>, succ:&Succ) {
+    let old = succ.dep.observed().and_then(render_any);
+    let new = lookup_abs(st, &succ.loc).res_any().and_then(render_any);
+    st.reevals.push(Reevaluation{loc:loc.reflect(), cause:succ.reflect(), old:old, new:new})
+}
+
 #[derive(Debug)]
 struct AllocStructuralThunk;
 impl DCGDep for AllocStructuralThunk {
@@ -1039,7 +2970,7 @@ struct AllocNominalThunk<T> { val:T }
 impl<T:Debug> DCGDep for AllocNominalThunk<T> {
     fn is_absmap (&self) -> Option<TypeId> { None }
     fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
-    fn clean (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} } // TODO-Later: Make this a little better.
+    fn clean (self:&Self, g:&RefCell<DCG>,  _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:g.borrow().flags.alloc_policy.alloc_changes} } // TODO-Later: Make this a little better.
 }
 
 #[derive(Debug)]
@@ -1047,13 +2978,52 @@ struct AllocCell<T> { val:T }
 impl<T:Debug> DCGDep for AllocCell<T> {
     fn is_absmap (&self) -> Option<TypeId> { None }
     fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
-    fn clean (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} } // TODO-Later: Make this a little better.
+    fn clean (self:&Self, g:&RefCell<DCG>,  _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:g.borrow().flags.alloc_policy.alloc_changes} } // TODO-Later: Make this a little better.
 }
 
 /// The structure implements DCGDep, caching a value of type `T` to
-/// compare against future values.
+/// compare against future values (and its digest, for `Cutoff::Digest`).
+/// For a thunk with `Storage::Disk`, it caches the digest only.
 #[derive(Debug)]
-struct ForceDep<T:Debug> { res:T }
+struct ForceDep<T:Debug> { res:Option<T>, digest:Option<u64> }
+
+/// True when the values of type `T` carry no information (e.g., `()`):
+/// the engine treats such results as effect-only, and never compares
+/// (or digests) them, assuming that all values of a zero-sized type
+/// are equal.  Hence, a thunk that returns `()` re-runs when its own
+/// dependencies change, but its observers do not.
+fn is_effect_only<T>() -> bool { ::std::mem::size_of::<T>() == 0 }
+
+/// True if `res`, the current result of the thunk at `loc`, differs
+/// from the result observed by `dep`; with `Cutoff::Digest`, compares
+/// their digests, instead of the results; with a `ChangePolicy` other
+/// than `ChangePolicy::Eq`, asks the policy, instead.  Without `res`
+/// (for a result spilled to disk), compares their digests.
+fn force_dep_changed<Res:'static+Debug+PartialEq+Eq+Clone+Hash>
+    (g:&RefCell<DCG>, loc:&Rc<Loc>, dep:&ForceDep<Res>, res:Option<&Res>) -> bool
+{
+    if is_effect_only::<Res>() { return false };
+    {
+        let st = &mut *g.borrow_mut();
+        if let Node::Comp(ref nd) = **res_node_of_loc::<Res>(st, loc) {
+            if let (&ChangePolicy::Changed(ref changed), Some(prev), Some(res)) = (&nd.change, dep.res.as_ref(), res) {
+                return changed(prev, res)
+            }
+        }
+    };
+    if let Some(prev) = dep.digest {
+        let st = &mut *g.borrow_mut();
+        let digest = match **res_node_of_loc::<Res>(st, loc) {
+            Node::Comp(ref nd) => nd.digest,
+            _ => None,
+        };
+        if let Some(digest) = digest {
+            count!(st.cnt.digest_cmps += 1);
+            return prev != digest
+        }
+    };
+    res.is_none() || dep.res.as_ref() != res
+}
 
 /// The structure implements DCGDep, caching a value of type `S` to
 /// compare against future values (note that values of type `S`
@@ -1192,6 +3162,9 @@ impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
     fn clean(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes {
         check_force_map_dep(&mut *g.borrow_mut(), self, loc)
     }
+    fn res_typeid(self:&Self) -> Option<TypeId> {
+        Some(TypeId::of::<T>())
+    }
 }
 
 impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
@@ -1204,6 +3177,29 @@ impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
     }
 }
 
+/// The structure implements DCGDep, combining several mapped
+/// observations of a node (e.g., of several fields of a cell) by the
+/// same thunk: the engine keeps one dirty bit for the edges between
+/// two nodes, so it keeps one edge.  The edge changed if any of its
+/// observations did.
+#[derive(Debug)]
+struct ForceMapsDep { deps:Vec<Rc<Box<DCGDep>>> }
+
+impl DCGDep for ForceMapsDep {
+    fn is_absmap(self:&Self) -> Option<TypeId> {
+        None
+    }
+    fn dirty(self:&Self, g:&mut DCG, loc:&Rc<Loc>) -> DCGRes {
+        DCGRes{changed:self.deps.iter().any(|dep| dep.dirty(g, loc).changed)}
+    }
+    fn clean(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes {
+        DCGRes{changed:self.deps.iter().any(|dep| dep.clean(g, loc).changed)}
+    }
+    fn res_typeid(self:&Self) -> Option<TypeId> {
+        self.deps.first().and_then(|dep| dep.res_typeid())
+    }
+}
+
 
 
 impl <Res:'static+Sized+Debug+PartialEq+Eq+Clone+Hash>
@@ -1213,6 +3209,14 @@ impl <Res:'static+Sized+Debug+PartialEq+Eq+Clone+Hash>
         None
     }
 
+    fn observed(self:&Self) -> Option<&Any> {
+        self.res.as_ref().map(|res| res as &Any)
+    }
+
+    fn res_typeid(self:&Self) -> Option<TypeId> {
+        Some(TypeId::of::<Res>())
+    }
+
     fn dirty(self:&Self, _g:&mut DCG, _loc:&Rc<Loc>) -> DCGRes {
         DCGRes{changed:true}
     }
@@ -1220,29 +3224,47 @@ impl <Res:'static+Sized+Debug+PartialEq+Eq+Clone+Hash>
     fn clean(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes {
         let res_succs = { // Handle cases where there is no internal computation to re-compute:
             let st = &mut *g.borrow_mut();
+            if st.stack.iter().any(|frame| &frame.loc == loc) {
+                // The edge closes a cycle: the thunk at `loc` is
+                // (re-)evaluating, so its value is not settled; the
+                // observer re-evaluates, and its force sees the cycle.
+                return DCGRes{changed:true}
+            } ;
+            let batched = st.batch.as_ref().map_or(false, |batch| batch.contains(loc)) ;
             let node : &mut Node<Res> = res_node_of_loc(st, loc) ;
-            match *node {
-                Node::Comp(ref nd) => {
-                    match nd.res {
-                        Some(ref res) => Some((res.clone(), nd.succs.clone ())),
-                        None => None
-                    }},
+            let (res_succs, reloaded) = match *node {
+                // Cleaned earlier in this batch (see `Engine::force_all`): its edges are clean
+                Node::Comp(ref nd) if batched && nd.res.is_some() => (Some((nd.res.clone(), vec![])), false),
+                Node::Comp(ref mut nd) => {
+                    let in_memory = nd.res.is_some() ;
+                    let on_disk = nd.spill.as_ref().map(|sp| sp.on_disk) == Some(true) ;
+                    if ! in_memory && on_disk && self.res.is_none() && self.digest.is_some() {
+                        // A spilled result, observed by its digest: no need to reload it
+                        (Some((None, nd.succs.clone ())), false)
+                    } else { match nd.cached() {
+                        Some(res) => (Some((Some(res), nd.succs.clone ())), ! in_memory),
+                        None => (None, false)
+                    }}},
                 Node::Pure(_) => {
                     return DCGRes{changed:false}
                 },
                 Node::Mut(ref nd) => {
-                    return DCGRes{changed:nd.val != self.res}
+                    return DCGRes{changed:! is_effect_only::<Res>() && self.res.as_ref() != Some(&nd.val)}
                 },
-            }
+            } ;
+            if reloaded { count!(st.cnt.reloads += 1) } ;
+            if batched { count!(st.cnt.batch_hits += 1) } ;
+            res_succs
         } ;
         let none : Option<Loc> = None ;
-        match res_succs {
+        let res = match res_succs {
             Some((res,succs)) => clean_comp(g, self, loc, res, succs),
             None => {
                 dcg_effect_begin!(
                     reflect::trace::Effect::CleanEval,
                     none,
                     reflect::Succ{
+                        label:None,
                         loc:loc.reflect(),
                         dirty:true,
                         effect:reflect::Effect::Force,
@@ -1251,12 +3273,15 @@ impl <Res:'static+Sized+Debug+PartialEq+Eq+Clone+Hash>
                     }
                 );
                 let res = loc_produce( g, loc );
-                let changed = self.res != res ;
+                let changed = force_dep_changed(g, loc, self, Some(&res)) ;
+                count!(if ! changed { g.borrow_mut().cnt.cutoff += 1 });
                 // TODO: changed to reflect::trace somehow?
                 dcg_effect_end!();
                 DCGRes{changed:changed}
             }
-        }
+        } ;
+        if let Some(ref mut batch) = g.borrow_mut().batch { batch.insert(loc.clone()); } ;
+        res
     }
 }
 
@@ -1271,9 +3296,79 @@ fn revoke_succs<'x> (st:&mut DCG, src:&Rc<Loc>, succs:&Vec<Succ>) {
     }
 }
 
-fn loc_of_id(path:Rc<Path>,id:Rc<ArtId>) -> Rc<Loc> {
-    let hash = my_hash(&(&path,&id));
-    Rc::new(Loc{path:path,id:id,hash:hash})
+/// Statistics for the (per-DCG) table of interned locations; see
+/// `manage::loc_intern_stats`.
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct LocInternStats {
+    /// Number of allocations that reused an interned location
+    pub hits : usize,
+    /// Number of allocations that interned a fresh location
+    pub misses : usize,
+    /// Number of interned locations (and paths) reclaimed by `manage::loc_intern_gc`
+    pub reclaimed : usize,
+}
+
+/// The size of the current DCG; see `manage::footprint`.
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct Footprint {
+    /// Number of nodes (cells and thunks)
+    pub nodes : usize,
+    /// Number of DCG edges
+    pub edges : usize,
+    /// The (shallow) size of the nodes, their locations and their
+    /// edges, in bytes: it omits the heap data of their values (e.g.,
+    /// the items of a `Vec`), so that it estimates the overhead of
+    /// articulation, rather than of the data.
+    pub bytes : usize,
+}
+
+/// Interned paths and locations, keyed by the addresses of their
+/// (interned) parent paths.  Each entry holds its parent path, so
+/// these addresses remain valid (and unique) while the entry exists.
+#[derive(Default)]
+struct LocTable {
+    paths : HashMap<(usize, Name), Rc<Path>>,
+    locs  : HashMap<(usize, ArtId), Rc<Loc>>,
+    stats : LocInternStats,
+    outer : HashMap<usize, Weak<Loc>>, // the locations allocated by the outer layer, by address (debug builds only); see `EngineDropReport`
+}
+
+impl Debug for LocTable {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        write!(f, "LocTable {{ paths:{}, locs:{}, stats:{:?} }}", self.paths.len(), self.locs.len(), self.stats)
+    }
+}
+
+fn path_key (path:&Rc<Path>) -> usize {
+    &**path as *const Path as usize
+}
+
+/// The (interned) child path of `path`, for namespace `nm`.
+fn path_child(st:&DCG, path:Rc<Path>, nm:Name) -> Rc<Path> {
+    let mut t = st.locs.borrow_mut();
+    t.paths.entry((path_key(&path), nm.clone()))
+        .or_insert_with(|| Rc::new(Path::Child(path, nm))).clone()
+}
+
+/// The (interned) location of `id` in `path`.  Re-allocations of the
+/// same location (e.g., by each re-evaluation of a thunk, after each
+/// edit) reuse its `Rc`, without re-hashing the path.
+fn loc_of_id(st:&DCG, path:Rc<Path>, id:ArtId) -> Rc<Loc> {
+    let t = &mut *st.locs.borrow_mut();
+    let key = (path_key(&path), id);
+    if let Some(loc) = t.locs.get(&key) {
+        t.stats.hits += 1;
+        if cfg!(debug_assertions) && st.stack.is_empty() {
+            t.outer.entry(&**loc as *const Loc as usize).or_insert_with(|| Rc::downgrade(loc));
+        }
+        return loc.clone()
+    };
+    t.stats.misses += 1;
+    let hash = seeded_hash(st, &(&path,&key.1));
+    let loc = Rc::new(Loc{path:path,id:Rc::new(key.1.clone()),hash:hash,engine:st.id});
+    t.locs.insert(key, loc.clone());
+    if cfg!(debug_assertions) && st.stack.is_empty() { t.outer.insert(&*loc as *const Loc as usize, Rc::downgrade(&loc)); }
+    loc
 }
 
 fn get_succ<'r>(st:&'r DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &'r Succ {
@@ -1282,12 +3377,20 @@ fn get_succ<'r>(st:&'r DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &
         None => panic!(""),
         Some(nd) => nd
     } ;
-    for succ in nd.succs() {
-        if (succ.effect == eff) && (&succ.loc == tgt_loc) {
-            return succ
-        } else {}
-    } ;
-    panic!("tgt_loc is dangling in src_node.dem_succs")
+    match nd.succ_find(&eff, tgt_loc) {
+        Some(succ) => succ,
+        None => panic!("tgt_loc is dangling in src_node.dem_succs")
+    }
+}
+
+/// The dirty bit of the edge from `src_loc` to `tgt_loc`, with effect
+/// `eff`; false when there is no such edge (e.g., when the
+/// re-evaluation of `src_loc` did not repeat it).
+fn succ_is_dirty(st:&DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> bool {
+    match st.table.get(src_loc) {
+        None => false,
+        Some(nd) => nd.succ_find(&eff, tgt_loc).map(|succ| succ.dirty).unwrap_or(false),
+    }
 }
 
 // Implement "sharing" of the dirty bit.
@@ -1295,20 +3398,20 @@ fn get_succ<'r>(st:&'r DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &
 // and mutating the dirty bit.
 fn get_succ_mut<'r>(st:&'r mut DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &'r mut Succ {
     let nd = lookup_abs( st, src_loc );
-    for succ in nd.succs_mut().iter_mut() {
-        if (succ.effect == eff) && (&succ.loc == tgt_loc) {
-            return succ
-        } else {}
-    } ;
-    panic!("tgt_loc is dangling in src_node.dem_succs")
+    match nd.succ_find_mut(&eff, tgt_loc) {
+        Some(succ) => succ,
+        None => panic!("tgt_loc is dangling in src_node.dem_succs")
+    }
 }
 
-fn dirty_pred_observers(st:&mut DCG, loc:&Rc<Loc>) {
+/// Returns the number of DCG edges dirtied.
+fn dirty_pred_observers(st:&mut DCG, loc:&Rc<Loc>) -> usize {
+    let mut dirtied = 0;
     let pred_locs : Vec<(Rc<Loc>, Option<Rc<Box<DCGDep>>>)> = lookup_abs( st, loc ).preds_obs() ;
     for (pred_loc, dep) in pred_locs {
         let stop : bool = match dep {
             None => false,
-            Some(dep) => dep.is_absmap() != None || dep.dirty(st, loc).changed == false
+            Some(dep) => dep.is_absmap() != None || ( ! dep_retyped(st, &**dep, loc) && dep.dirty(st, loc).changed == false )
         };
         let stop : bool = if stop { true } else {
             // The stop bit communicates information from st for use below.
@@ -1321,33 +3424,135 @@ fn dirty_pred_observers(st:&mut DCG, loc:&Rc<Loc>) {
             }}
         ;
         if !stop {
-            dirty_pred_observers(st,&pred_loc);
-            dcg_effect_end!();
+            count!(st.cnt.dirty += 1);
+            dirtied += 1;
+            if let Some(ref mut batch) = st.batch { batch.remove(&pred_loc); } ;
+            call_dirty_hooks(&pred_loc);
+            dirtied += dirty_pred_observers(st,&pred_loc);
+            dcg_effect_end!(succ_is_dirty(st, &pred_loc, Effect::Observe, loc));
         } else { }
     }
+    dirtied
 }
 
-fn dirty_alloc(st:&mut DCG, loc:&Rc<Loc>) {
-    dirty_pred_observers(st, loc);
-    let pred_locs : Vec<Rc<Loc>> = lookup_abs(st, loc).preds_alloc() ;
+/// Returns the number of DCG edges dirtied.
+fn dirty_alloc(st:&mut DCG, loc:&Rc<Loc>) -> usize {
+    let mut dirtied = dirty_pred_observers(st, loc);
+    let pred_locs : Vec<Rc<Loc>> =
+        if st.flags.alloc_policy.dirty_allocators { lookup_abs(st, loc).preds_alloc() }
+        else { vec![] } ;
     for pred_loc in pred_locs {
         let stop : bool = {
             // The stop bit communicates information from st for use below.
             let succ = get_succ_mut(st, &pred_loc, Effect::Allocate, &loc) ;
             if succ.dirty { true } else {
-                replace(&mut succ.dirty, true);
                 assert!(&pred_loc != loc);
                 dcg_effect_begin!(reflect::trace::Effect::Dirty, Some(&pred_loc), succ);
+                replace(&mut succ.dirty, true);
                 false
             }} ;
         if !stop {
-            dirty_pred_observers(st,&pred_loc);
-            dcg_effect_end!();
+            count!(st.cnt.dirty += 1);
+            dirtied += 1;
+            if let Some(ref mut batch) = st.batch { batch.remove(&pred_loc); } ;
+            call_dirty_hooks(&pred_loc);
+            dirtied += dirty_pred_observers(st,&pred_loc);
+            dcg_effect_end!(succ_is_dirty(st, &pred_loc, Effect::Allocate, loc));
         } else {  }
     }
     if false /* XXX Check make this better, as a statically/dynamically-set flag? */ {
         wf::check_stack_is_clean(st)
     }
+    dirtied
+}
+
+/// The edges to a node: their sources, effects and dependencies.
+type Preds = Vec<(Rc<Loc>, Effect, Option<Rc<Box<DCGDep>>>)>;
+
+/// True if the node at `loc` has a result type other than the one
+/// that `dep` observed: the node was replaced (see `retype_node`).
+fn dep_retyped(st:&mut DCG, dep:&DCGDep, loc:&Rc<Loc>) -> bool {
+    match dep.res_typeid() {
+        Some(typeid) => lookup_abs(st, loc).res_typeid() != typeid,
+        None => false,
+    }
+}
+
+/// True if the node at `loc` exists, and has a result type other than `T`.
+fn is_retyped<T:'static> (st:&DCG, loc:&Rc<Loc>) -> bool {
+    match st.table.get(loc) {
+        Some(node) => node.res_typeid() != TypeId::of::<T>(),
+        None => false,
+    }
+}
+
+/// Removes the node at `loc`, for an allocation of the same name with
+/// another type: dirties each edge to the node (regardless of its
+/// dependency, and of `AllocPolicy`), and removes the node's own
+/// edges.  Gives the edges to the node, for the node that replaces it
+/// (see `restore_preds`).
+fn retype_node(st:&mut DCG, loc:&Rc<Loc>) -> Preds {
+    let preds : Preds = {
+        let node = lookup_abs(st, loc);
+        if node.is_pure() { vec![] } else {
+            let alloc = node.preds_alloc().into_iter().map(|pred| (pred, Effect::Allocate, None));
+            alloc.chain(node.preds_obs().into_iter().map(|(pred, dep)| (pred, Effect::Observe, dep))).collect()
+        }
+    };
+    for &(ref pred_loc, ref effect, _) in preds.iter() {
+        let stop : bool = {
+            let succ = get_succ_mut(st, pred_loc, effect.clone(), loc) ;
+            if succ.dirty { true } else {
+                assert!(pred_loc != loc);
+                dcg_effect_begin!(reflect::trace::Effect::Dirty, Some(pred_loc), succ);
+                succ.dirty = true;
+                false
+            }} ;
+        if !stop {
+            count!(st.cnt.dirty += 1);
+            if let Some(ref mut batch) = st.batch { batch.remove(pred_loc); } ;
+            call_dirty_hooks(pred_loc);
+            let _ = dirty_pred_observers(st, pred_loc);
+            dcg_effect_end!(succ_is_dirty(st, pred_loc, effect.clone(), loc));
+        }
+    }
+    remove_nodes(st, &Some(loc.clone()).into_iter().collect());
+    preds
+}
+
+/// Removes the nodes at `locs`, with their edges to the other nodes,
+/// and their entries in the other tables of the DCG (those keyed by
+/// location, and those that name one).  Each path that removes nodes
+/// (`retype_node`, `manage::clear` and `manage::trim_ns`) does so here.
+fn remove_nodes(st:&mut DCG, locs:&HashSet<Rc<Loc>>) {
+    for loc in locs.iter() {
+        if lookup_abs(st, loc).succs_def() {
+            let succs = lookup_abs(st, loc).succs_replace(vec![]).into_iter()
+                .filter(|succ| ! locs.contains(&succ.loc)).collect();
+            revoke_succs(st, loc, &succs);
+        }
+    }
+    for loc in locs.iter() {
+        st.journal(loc);
+        st.table.remove(loc);
+        st.costs.remove(loc);
+        st.merged.0.remove(loc);
+        st.writes.cells.remove(loc);
+        st.sinks.remove(loc);
+        st.annotations.remove(loc);
+        st.groups.remove(loc);
+        if let Some(ref mut batch) = st.batch { batch.remove(loc); } ;
+    }
+    st.invariants.locs.retain(|&(ref loc, _)| ! locs.contains(loc));
+    st.producers.retain(|_, loc| ! locs.contains(loc));
+}
+
+/// Gives the node at `loc` the edges `preds` (see `retype_node`).
+fn restore_preds(st:&mut DCG, loc:&Rc<Loc>, preds:Preds) {
+    let node = lookup_abs(st, loc);
+    for (pred_loc, effect, dep) in preds {
+        node.preds_insert(effect, &pred_loc, dep)
+    }
 }
 
 /// Returns true if changed, false if unchanged.
@@ -1355,24 +3560,57 @@ fn check_cell_change<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:&
     if let AbsArt::Loc(ref loc) = cell {
         let node = res_node_of_loc::<T>( st, loc ) ;
         match **node {
-            Node::Mut(ref mut nd) => { &nd.val != val }
+            Node::Mut(ref mut nd) => { nd.change.changed(&nd.val, val) }
             _ => { /* the location was previously _not_ a cell, so yes */ true }
         }
     }
-    else { panic!("{:?} is not a cell", cell) }
+    else { panic!("{:?} is not a cell\n{}", cell, string_of_demand_stack(&st.stack)) }
 }
 
 /// Returns true if changed, false if unchanged.
-fn set_<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:T) {
+/// The program point of the producers of lazy cells; see `Engine::cell_thunked`.
+const LAZY_CELL : &'static str = "engine::cell_thunked";
+
+/// If the node at `loc` is a lazy cell (see `Engine::cell_thunked`),
+/// makes it a cell, with its initial value, and gives true; if its
+/// initial value was never computed, gives false, leaving it for the
+/// caller to write.  Gives `None` for other nodes.
+fn lazy_cell_to_mut<T:'static> (st:&mut DCG, loc:&Rc<Loc>) -> Option<bool> {
+    let (preds, succs, res) = match **res_node_of_loc::<T>(st, loc) {
+        Node::Comp(ref mut nd) if nd.producer.prog_pt().symbol == LAZY_CELL =>
+            (replace(&mut nd.preds, vec![]), replace(&mut nd.succs, vec![]), nd.res.take()),
+        _ => return None,
+    };
+    revoke_succs(st, loc, &succs);
+    match res {
+        Some(res) => {
+            **res_node_of_loc::<T>(st, loc) = Node::Mut(MutNode{preds:preds, val:res, change:ChangePolicy::Eq, writer:None});
+            Some(true)
+        },
+        // Without a value, the cell has no observers
+        None => Some(false),
+    }
+}
+
+fn set_<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:T) -> SetOutcome {
     if let AbsArt::Loc(ref loc) = cell {
+        if st.flags.check_write_conflicts && st.stack.is_empty() { st.writes = WriteLog::default() };
+        let writer = current_writer(st);
+        if lazy_cell_to_mut::<T>(st, loc) == Some(false) {
+            **res_node_of_loc::<T>(st, loc) = Node::Mut(MutNode{preds:vec![], val:val, change:ChangePolicy::Eq, writer:writer});
+            return SetOutcome{changed:true, observers_dirtied:0}
+        };
+        let mut dirtied = 0 ;
         let changed : bool = {
             let node = res_node_of_loc( st, loc ) ;
             match **node {
                 Node::Mut(ref mut nd) => {
-                    if nd.val == val {
+                    if ! nd.change.changed(&nd.val, &val) {
+                        // Latch: keep the value that the observers saw.
                         false
                     } else {
                         replace(&mut nd.val, val) ;
+                        nd.writer = writer ;
                         // know types: T.
                         // Don't know: Arg, Abs, DiffT, S
                         // ==> need a new dep operation
@@ -1393,12 +3631,48 @@ fn set_<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:T) {
             // Only those that allocated a different value than the present
             // one--- we should check this, but we do not (we are *too
             // conservative* at present).
-            dirty_alloc(st, loc);
+            dirtied = dirty_alloc(st, loc);
+            if st.stack.is_empty() { st.epoch.0 += 1 };
+            if st.stack.is_empty() && ! st.invariants.locs.is_empty()
+                && ! st.invariants.edits.contains(loc)
+            {
+                st.invariants.edits.push(loc.clone())
+            };
         }
+        SetOutcome{changed:changed, observers_dirtied:dirtied}
     }
-    else { panic!("{:?} is not a cell", cell) }
+    else { panic!("{:?} is not a cell\n{}", cell, string_of_demand_stack(&st.stack)) }
 }
 
+/// The writer of a write to a cell now: the current writer (see
+/// `with_writer`) for the outer layer, and none for thunks.
+fn current_writer (st:&DCG) -> Option<Name> {
+    if st.stack.is_empty() { st.writer.clone() } else { None }
+}
+
+/// Logs a write by `cell` for `Flags::check_write_conflicts`: from
+/// the outer layer, begins a new epoch; from within a thunk, panics
+/// with a `WriteConflict` if `val` differs from an earlier write to
+/// `loc` within the epoch.
+fn log_write<T:Hash+Debug> (st:&mut DCG, loc:&Rc<Loc>, val:&T) {
+    if ! st.flags.check_write_conflicts { return };
+    let writer = match st.stack.last() {
+        None => { st.writes = WriteLog::default(); return },
+        Some(frame) => format!("{:?}", frame.loc),
+    };
+    let hash = my_hash(val);
+    let record = WriteRecord{order:st.writes.next, writer:writer, value:format!("{:?}", val)};
+    st.writes.next += 1;
+    let writes = st.writes.cells.entry(loc.clone()).or_insert(vec![]);
+    let conflict = writes.iter().any(|&(h, _)| h != hash);
+    writes.push((hash, record));
+    if conflict {
+        panic_any(WriteConflict{
+            loc:format!("{:?}", loc),
+            writes:writes.iter().map(|&(_, ref record)| record.clone()).collect(),
+        })
+    }
+}
 
 fn current_path (st:&DCG) -> Rc<Path> {
     st.path.clone()
@@ -1435,7 +3709,7 @@ trait Adapton : Debug+PartialEq+Eq+Hash+Clone {
     fn cell<T:Eq+Debug+Clone+Hash+'static> (self:&mut Self, Name, T) -> AbsArt<T,Self::Loc> ;
 
     /// Mutates a mutable articulation.
-    fn set<T:'static+Eq+Debug+Clone> (self:&mut Self, AbsArt<T,Self::Loc>, T) ;
+    fn set<T:'static+Eq+Debug+Clone> (self:&mut Self, AbsArt<T,Self::Loc>, T) -> SetOutcome ;
 
     /// Creates an articulated computation.
     fn thunk <Arg:Eq+Hash+Debug+Clone+'static,
@@ -1483,7 +3757,10 @@ impl Adapton for DCG {
     fn new () -> DCG {
         let path = Rc::new(Path::Empty);
         let stack = Vec::new() ;
-        let table = HashMap::new ();
+        let seed = SeededState(env::var("ADAPTON_SEED").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(0));
+        let table = HashMap::with_hasher(seed);
+        let id = ENGINE_COUNT.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+        LIVE_ENGINES.with(|e| e.borrow_mut().insert(id));
         DCG {
             flags : Flags {
                 use_purity_optimization       : { match env::var("ADAPTON_NO_PURITY")  { Ok(_) => false, _ => true } },
@@ -1491,10 +3768,49 @@ impl Adapton for DCG {
                 check_dcg_is_wf               : { match env::var("ADAPTON_CHECK_DCG")  { Ok(_) => true,  _ => false } },
                 write_dcg                     : { match env::var("ADAPTON_WRITE_DCG")  { Ok(_) => true,  _ => false } },
                 gmlog_dcg                     : { match env::var("ADAPTON_GMLOG_DCG")  { Ok(_) => true,  _ => false } },
+                auto_inline_below             : { match env::var("ADAPTON_AUTO_INLINE_NS").ok().and_then(|ns| ns.parse::<u64>().ok()) {
+                    Some(ns) => Some(Duration::from_nanos(ns)), None => None } },
+                propagation_budget            : PropagationBudget::default(),
+                share_producers               : { match env::var("ADAPTON_SHARE_PRODUCERS") { Ok(_) => true, _ => false } },
+                check_write_conflicts         : { match env::var("ADAPTON_CHECK_WRITES") { Ok(_) => true, _ => false } },
+                alloc_policy                  : AllocPolicy::default(),
+                dot_filter                    : DotFilter::default(),
+                record_reevaluations          : { match env::var("ADAPTON_RECORD_REEVALS") { Ok(_) => true, _ => false } },
+                poison_on_panic               : { match env::var("ADAPTON_POISON_ON_PANIC") { Ok(_) => true, _ => false } },
+                sample_every                  : env::var("ADAPTON_SAMPLE_EVERY").ok().and_then(|n| n.parse::<usize>().ok()),
+                verify_every                  : env::var("ADAPTON_VERIFY_EVERY").ok().and_then(|n| n.parse::<usize>().ok()),
+                strict_naming                 : { match env::var("ADAPTON_STRICT_NAMING") { Ok(_) => true, _ => false } },
+                structural_namespaces         : vec![],
+                record_only                   : { match env::var("ADAPTON_RECORD_ONLY") { Ok(_) => true, _ => false } },
             },
+            costs : HashMap::new(),
+            merged : (HashMap::new(), CompactionReport::default()),
+            batch : None,
+            prop  : PropWork::default(),
+            producers : HashMap::new(),
+            writes : WriteLog::default(),
+            writer : None,
+            pool  : Vec::new(),
+            frame_capacity : 0,
+            sinks : HashMap::new(),
+            reevals : Vec::new(),
+            annotations : HashMap::new(),
+            invariants : Invariants::default(),
+            groups : HashMap::new(),
+            group_work : HashMap::new(),
+            specs : Vec::new(),
+            locs : RefCell::new(LocTable::default()),
+            epoch : (0, 0),
+            nominal : (0, NominalMatches::default()),
+            id    : id,
+            poison : None,
+            sampler : (0, SampleStats::default()),
+            verifier : (0, false, Vec::new()),
+            seed  : seed,
             table : table,
             stack : stack,
             path  : path,
+            cnt   : Cnt::default(),
             dcg_count : 0,
             dcg_hash : 0, // XXX This makes assumptions about hashing implementation
         }
@@ -1520,7 +3836,7 @@ impl Adapton for DCG {
         let saved = {
             let st = &mut *g.borrow_mut();
             let saved = st.path.clone();
-            st.path = Rc::new(Path::Child(st.path.clone(), nm)) ; // Todo-Minor: Avoid this clone.
+            st.path = path_child(st, st.path.clone(), nm) ;
             saved
         };
         let x = body() ;
@@ -1538,20 +3854,26 @@ impl Adapton for DCG {
             let path = current_path(self) ;
             let (id, is_pure) = {
                 if ! self.flags.ignore_nominal_use_structural {
-                    (Rc::new(ArtId::Nominal(nm)), false) // Ordinary case: Use provided name.
+                    (ArtId::Nominal(nm), false) // Ordinary case: Use provided name.
                 } else {
-                    let hash = my_hash (&val) ;
-                    (Rc::new(ArtId::Structural(hash)), self.flags.use_purity_optimization) // Ignore the name; do hash-consing instead.
+                    let hash = seeded_hash (self, &val) ;
+                    (ArtId::Structural(hash), self.flags.use_purity_optimization) // Ignore the name; do hash-consing instead.
                 }
             };
-            let hash = my_hash(&(&path,&id));
-            let loc  = Rc::new(Loc{path:path,id:id,hash:hash})
-                ;
+            let loc  = loc_of_id(self, path, id) ;
+            check_purity(self, &loc, reflect::trace::AllocKind::RefCell);
+            log_write(self, &loc, &val);
+            // A node of another type is replaced, as though fresh (see `retype_node`)
+            let retyped = is_retyped::<T>(self, &loc) ;
             let (do_dirty, do_set, succs, do_insert, is_fresh) =
+                if retyped { (false, false, None, true, false) } else
                 if self.table.contains_key(&loc) {
                     let node : &Box<Node<T>> = res_node_of_loc(self, &loc) ;
                     match **node {
                         Node::Mut(_)       => { (false, true,  None, false, false) }
+                        // A lazy cell becomes a cell (see `set_`)
+                        Node::Comp(ref nd) if nd.producer.prog_pt().symbol == LAZY_CELL
+                                           => { (false, true,  None, false, false) }
                         Node::Comp(ref nd) => { (true,  false, Some(nd.succs.clone()),  false, false ) }
                         Node::Pure(_)      => { (false, false, None, false, false) }
                     }} else                 { (false, false, None, true, true ) }
@@ -1570,6 +3892,7 @@ impl Adapton for DCG {
             dcg_effect_begin!(
                 reflect::trace::Effect::Alloc(
                     if is_fresh { reflect::trace::AllocCase::LocFresh }
+                    else if retyped { reflect::trace::AllocCase::LocRetyped }
                     else {
                         let changed =
                             if check_cell_change(self, AbsArt::Loc(loc.clone()), &val) {
@@ -1583,6 +3906,7 @@ impl Adapton for DCG {
                 ),
                 current_loc!(self),
                 reflect::Succ{
+                    label:None,
                     loc:loc.reflect(),
                     effect:reflect::Effect::Alloc,
                     value:reflect::Val::ValTODO,
@@ -1590,9 +3914,10 @@ impl Adapton for DCG {
                     is_dup:false, // XXX -- Actually: Not checked here.
                 }
             );
-            if do_set   { set_(self, AbsArt::Loc(loc.clone()), val.clone()) };
-            if do_dirty { dirty_alloc(self, &loc) } ;
+            if do_set   { let _ = set_(self, AbsArt::Loc(loc.clone()), val.clone()); };
+            if do_dirty { let _ = dirty_alloc(self, &loc); } ;
             match succs { Some(succs) => revoke_succs(self, &loc, &succs), None => () } ;
+            let preds = if retyped { Some(retype_node(self, &loc)) } else { None } ;
             dcg_effect_end!();
 
             if do_insert {
@@ -1600,28 +3925,53 @@ impl Adapton for DCG {
                     Node::Mut(MutNode{
                         preds:Vec::new(),
                         val:val.clone(),
+                        change:ChangePolicy::Eq,
+                        writer:current_writer(self),
                     })} ;
+                self.journal(&loc);
                 self.table.insert(loc.clone(), Box::new(node));
+                if let Some(preds) = preds { restore_preds(self, &loc, preds) } ;
             } ;
             if ! is_pure { match self.stack.last_mut() {
                 None => (),
                 Some(frame) => {
                     let succ =
                         Succ{loc:loc.clone(),
+                             label:None,
                              dep:Rc::new(Box::new(AllocCell{val:val})),
                              effect:Effect::Allocate,
                              dirty:false};
                     frame.succs.push((succ, None))
                 }}} ;
+            if ! is_pure { wf::check_alloc_is_fresh(self, &loc) } ;
             wf::check_dcg(self);
             AbsArt::Loc(loc)
         }
 
-    fn set<T:'static+Eq+Debug> (self:&mut Self, cell:AbsArt<T,Self::Loc>, val:T) {
+    fn set<T:'static+Eq+Debug> (self:&mut Self, cell:AbsArt<T,Self::Loc>, val:T) -> SetOutcome {
         wf::check_dcg(self);
         assert!( self.stack.is_empty() ); // => outer layer has control.
-        set_(self, cell, val);
+        let none : Option<Loc> = None ;
+        if let AbsArt::Loc(ref loc) = cell {
+            // The write contains the dirtying steps, if any
+            dcg_effect_begin!(
+                reflect::trace::Effect::Write(self.writer.clone()),
+                none,
+                reflect::Succ{
+                    label:None,
+                    loc:loc.reflect(),
+                    effect:reflect::Effect::Alloc,
+                    value:reflect::Val::ValTODO,
+                    dirty:false,
+                    is_dup:false,
+                }
+            );
+        };
+        let is_loc = match cell { AbsArt::Loc(_) => true, _ => false };
+        let outcome = set_(self, cell, val);
+        if is_loc { dcg_effect_end!() };
         wf::check_dcg(self);
+        outcome
     }
 
     fn thunk<Arg:Eq+Hash+Debug+Clone+'static,Spurious:'static+Clone,Res:Eq+Debug+Clone+Hash+'static>
@@ -1655,14 +4005,15 @@ impl Adapton for DCG {
             // Name the computation structurally, based on the args and prog point.
             NameChoice::Structural => {
                 wf::check_dcg(self);
-                let hash = my_hash (&(&prog_pt, &arg)) ;
-                let loc = loc_of_id(current_path(self),
-                                    Rc::new(ArtId::Structural(hash)));
-                {   // If the node exists, return early.
-                    let node = self.table.get_mut(&loc);
-                    match node { None    => { },
-                                 Some(_) => { return AbsArt::Loc(loc) }, // Nothing to do; it already exists.
-                    }
+                if ! self.flags.ignore_nominal_use_structural { check_naming(self, &prog_pt) };
+                quantization_reset();
+                let hash = seeded_hash (self, &(&prog_pt, &arg)) ;
+                let loc = loc_of_id(self, current_path(self), ArtId::Structural(hash));
+                check_purity(self, &loc, reflect::trace::AllocKind::Thunk);
+                // If the node exists, return early; nothing to do.
+                if self.table.contains_key(&loc) {
+                    if quantization_hashed() { count_quantized_hit::<Arg,Spurious,Res>(self, &loc, &prog_pt, &arg) };
+                    return AbsArt::Loc(loc)
                 } ;
                 // assert: node does not exist.
                 match self.stack.last_mut() {
@@ -1670,6 +4021,7 @@ impl Adapton for DCG {
                     Some(frame) => {
                         let succ =
                             Succ{loc:loc.clone(),
+                                 label:None,
                                  dep:Rc::new(Box::new(AllocStructuralThunk)),
                                  effect:Effect::Allocate,
                                  dirty:false};
@@ -1686,8 +4038,15 @@ impl Adapton for DCG {
                     succs:Vec::new(),
                     producer:producer,
                     res:None,
+                    purity:Purity::Alloc,
+                    cutoff:Cutoff::Eq,
+                    digest:None,
+                    change:ChangePolicy::Eq,
+                    spill:None,
+                    succ_index:SuccIndex::default(),
                 } ;
-                //self.cnt.create += 1;
+                count!(self.cnt.create += 1);
+                self.journal(&loc);
                 self.table.insert(loc.clone(),
                                   Box::new(Node::Comp(node)));
                 wf::check_dcg(self);
@@ -1697,8 +4056,8 @@ impl Adapton for DCG {
             // Name the thunk explicitly by `nm`
             NameChoice::Nominal(nm) => {
                 wf::check_dcg(self);
-                let loc = loc_of_id(current_path(self),
-                                    Rc::new(ArtId::Nominal(nm)));
+                let loc = loc_of_id(self, current_path(self), ArtId::Nominal(nm));
+                check_purity(self, &loc, reflect::trace::AllocKind::Thunk);
                 let producer : App<Arg,Spurious,Res> =
                     App{prog_pt:prog_pt.clone(),
                         fn_box:fn_box,
@@ -1707,41 +4066,47 @@ impl Adapton for DCG {
                     }
                 ;
                 let top_loc = get_top_stack_loc( self );
-                let (do_dirty, do_insert, is_fresh) = { match self.table.get_mut( &loc ) {
+                self.journal(&loc);
+                // A node of another type is replaced, as though fresh (see `retype_node`)
+                let retyped = is_retyped::<Res>(self, &loc) ;
+                let outcome = if retyped { Ok((false, true, reflect::trace::AllocCase::LocRetyped)) } else { match self.table.get_mut( &loc ) {
                     None => {
                         // do_dirty=false; do_insert=true
-                        (false, true, true)
+                        Ok((false, true, reflect::trace::AllocCase::LocFresh))
                     },
                     Some(node) => {
                         let node: &mut Box<GraphNode> = node ;
-                        assert_graphnode_res_type::<Res>(&loc, node, top_loc);
+                        assert_graphnode_res_type::<Res>(&loc, node, top_loc, &self.stack);
                         let res_nd: &mut Box<Node<Res>> = unsafe { transmute::<_,_>( node ) } ;
                         match ** res_nd {
                             Node::Pure(_)=> unreachable!(),
                             Node::Mut(_) => {
-                                (true, true, false) // Todo: Do we need to preserve preds?
+                                Ok((true, true, reflect::trace::AllocCase::LocReplaced)) // Todo: Do we need to preserve preds?
                             },
                             Node::Comp(ref mut comp_nd) => {
                                 let equal_producer_prog_pts : bool =
                                     comp_nd.producer.prog_pt().eq( producer.prog_pt() ) ;
                                 if equal_producer_prog_pts { // => safe cast to Box<Consumer<Arg>>
+                                    #[cfg(debug_assertions)]
+                                    assert_producer_type::<App<Arg,Spurious,Res>,Res>(&*comp_nd.producer, &loc);
                                     let app: &mut Box<App<Arg,Spurious,Res>> =
-                                    // TODO-Soon: Follow pattern above for assert_graphnode_res_type to dynamically check the safety of this cast
                                         unsafe { transmute::<_,_>( &mut comp_nd.producer ) }
                                     ;
+                                    quantization_reset();
                                     if app.get_arg() == arg {
+                                        if quantization_inexact() { count!(self.cnt.quantized_hits += 1) };
                                         // Case: Same argument; Nothing else to do:
                                         // do_dirty=false; do_insert=false
-                                        (false, false, false)
+                                        Ok((false, false, reflect::trace::AllocCase::LocExists(reflect::trace::ChangeFlag::ContentSame)))
                                     }
                                     else { // Case: Not the same argument:
                                         app.consume(arg.clone()); // overwrite the old argument
                                         comp_nd.res = None ; // clear the cache
                                         // do_dirty=true; do_insert=false
-                                        (true, false, false)
+                                        Ok((true, false, reflect::trace::AllocCase::LocExists(reflect::trace::ChangeFlag::ContentDiff)))
                                     }}
                                 else {
-                                    panic!("Memozied functions not equal!
+                                    Err(format!("Memozied functions not equal!
                             Function was: {:?}
                            with Producer: {:?}
 
@@ -1752,54 +4117,67 @@ impl Adapton for DCG {
 
                         ** Hint: Consider using distinct namespaces, via `Adapton::ns`
                            (See: https://docs.rs/adapton/0/adapton/engine/fn.ns.html)
-                        ",
+
+                        {}",
                                            comp_nd.producer.prog_pt(), &comp_nd.producer,
                                            producer.prog_pt(), &producer,
-                                           &loc,
-                                    )
+                                           &loc, string_of_demand_stack(&self.stack),
+                                    ))
                                 }
                             },
                         }
                     }
                 } } ;
+                let (do_dirty, do_insert, case) = match outcome {
+                    Ok(outcome) => outcome,
+                    Err(msg) => {
+                        count!(self.count_nominal(|m| m.producer_mismatch += 1));
+                        panic!("{}", msg)
+                    }
+                };
+                count!(self.count_nominal(|m| m.count(&case)));
 
                 dcg_effect_begin!(
-                    reflect::trace::Effect::Alloc(
-                        if is_fresh { reflect::trace::AllocCase::LocFresh }
-                        else {
-                            let cf =
-                                if do_dirty { reflect::trace::ChangeFlag::ContentDiff }
-                            else { reflect::trace::ChangeFlag::ContentSame };
-                            reflect::trace::AllocCase::LocExists(cf) },
-                        reflect::trace::AllocKind::Thunk
-                    ),
+                    reflect::trace::Effect::Alloc(case, reflect::trace::AllocKind::Thunk),
                     current_loc!(self),
                     reflect::Succ{
+                        label:None,
                         loc:loc.reflect(),
                         effect:reflect::Effect::Alloc,
                         value:reflect::Val::ValTODO,
                         dirty:false,
                         is_dup:false, // XXX -- Actually: Not checked here.
                     });
-                if do_dirty {dirty_alloc(self, &loc) };
+                if do_dirty { let _ = dirty_alloc(self, &loc); };
+                let preds = if retyped { Some(retype_node(self, &loc)) } else { None } ;
                 dcg_effect_end!();
 
                 match self.stack.last_mut() { None => (), Some(frame) => {
                     let succ =
                         Succ{loc:loc.clone(),
+                             label:None,
                              dep:Rc::new(Box::new(AllocNominalThunk{val:arg.clone()})),
                              effect:Effect::Allocate,
                              dirty:false};
                     frame.succs.push((succ, None))
                 }};
+                wf::check_alloc_is_fresh(self, &loc);
                 if do_insert {
                     let node : CompNode<Res> = CompNode{
                         preds:Vec::new(),
                         succs:Vec::new(),
                         producer:Box::new(producer),
                         res:None,
+                        purity:Purity::Alloc,
+                        cutoff:Cutoff::Eq,
+                        digest:None,
+                        change:ChangePolicy::Eq,
+                        spill:None,
+                        succ_index:SuccIndex::default(),
                     } ;
+                    self.journal(&loc);
                     self.table.insert(loc.clone(), Box::new(Node::Comp(node)));
+                    if let Some(preds) = preds { restore_preds(self, &loc, preds) } ;
                     wf::check_dcg(self);
                     AbsArt::Loc(loc)
                 }
@@ -1845,6 +4223,7 @@ impl Adapton for DCG {
                             reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
                             current_loc!(*g.borrow()),
                             reflect::Succ{
+                                label:None,
                                 loc:loc.reflect(),
                                 value:reflect::Val::ValTODO,
                                 effect:reflect::Effect::Force,
@@ -1853,19 +4232,34 @@ impl Adapton for DCG {
                             });
                         let st : &mut DCG = &mut *g.borrow_mut() ;
                         let res = mapf(&Art{art:EnumArt::Loc(loc.clone())}, val.clone());
-                        match st.stack.last_mut() { None => (), Some(frame) => {
+                        let subsumed = st.stack.last().map_or(false, |frame| frame.observed.contains_key(loc));
+                        if subsumed { count!(st.cnt.dup_observes += 1) };
+                        if !subsumed { match st.stack.last_mut() { None => (), Some(frame) => {
                             // `dep` records the mapping function
                             let dep : Rc<Box<DCGDep>> = Rc::new(Box::new(ForceMapDep{
                                 raw:PhantomData,
                                 mapf:mapf,
                                 res:res.clone()}));
-                            let succ =
-                                Succ{loc:loc.clone(),
-                                     dep:dep.clone(),
-                                     effect:Effect::Observe,
-                                     dirty:false};
-                            frame.succs.push((succ, Some(dep.clone())));
-                        }};
+                            match frame.mapped.get(loc).cloned() {
+                                Some(i) => {
+                                    // Keep one edge, with each of the projections observed
+                                    let deps = vec![frame.succs[i].0.dep.clone(), dep];
+                                    let dep : Rc<Box<DCGDep>> = Rc::new(Box::new(ForceMapsDep{deps:deps}));
+                                    frame.succs[i].0.dep = dep.clone();
+                                    frame.succs[i].1 = Some(dep);
+                                },
+                                None => {
+                                    let succ =
+                                        Succ{loc:loc.clone(),
+                                             label:None,
+                                             dep:dep.clone(),
+                                             effect:Effect::Observe,
+                                             dirty:false};
+                                    frame.mapped.insert(loc.clone(), frame.succs.len());
+                                    frame.succs.push((succ, Some(dep.clone())));
+                                }
+                            }
+                        }}};
                         res
                     }
                 }
@@ -1912,6 +4306,7 @@ impl Adapton for DCG {
                             reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
                             current_loc!(*g.borrow()),
                             reflect::Succ{
+                                label:None,
                                 loc:loc.reflect(),
                                 value:reflect::Val::ValTODO,
                                 effect:reflect::Effect::Force,
@@ -1920,7 +4315,9 @@ impl Adapton for DCG {
                             });
                         let st : &mut DCG = &mut *g.borrow_mut() ;
                         let res = absmapfam.map(arg.clone(),/*&Art{art:EnumArt::Loc(loc.clone())},*/val.clone());
-                        match st.stack.last_mut() { None => (), Some(frame) => {
+                        let subsumed = st.stack.last().map_or(false, |frame| frame.observed.contains_key(loc));
+                        if subsumed { count!(st.cnt.dup_observes += 1) };
+                        if !subsumed { match st.stack.last_mut() { None => (), Some(frame) => {
                             // `dep` records the mapping function
                             let dep : Rc<Box<DCGDep>> = Rc::new(Box::new(ForceAbsDep{
                                 phm:PhantomData,
@@ -1929,11 +4326,12 @@ impl Adapton for DCG {
                             }));
                             let succ =
                                 Succ{loc:loc.clone(),
+                                     label:None,
                                      dep:dep.clone(),
                                      effect:Effect::Observe,
                                      dirty:false};
                             frame.succs.push((succ, Some(dep.clone())));
-                        }};
+                        }}};
                         res
                     }
                 }
@@ -1947,12 +4345,18 @@ impl Adapton for DCG {
         {
             let st : &mut DCG = &mut *g.borrow_mut();
             wf::check_dcg(st);
+            if st.stack.is_empty() {
+                // A force from the outer layer: a fresh propagation budget
+                st.prop = PropWork::default();
+            } else {
+                sample_step(st, None);
+            }
             drop(st)
         }
         match *art {
             AbsArt::Rc(ref v) => (**v).clone(),
             AbsArt::Loc(ref loc) => {
-                let (is_comp, is_dup, is_pure, is_cycle, cached_result) : (bool, bool, bool, bool, Option<T>) = {
+                let (is_comp, is_dup, is_pure, is_cycle, cached_result, reloaded) : (bool, bool, bool, bool, Option<T>, Option<u64>) = {
                     let st : &mut DCG = &mut *g.borrow_mut();
                     let is_pure_opt : bool = st.flags.use_purity_optimization ;
                     let is_cycle = { let mut is_cycle = false;
@@ -1962,18 +4366,18 @@ impl Adapton for DCG {
                                          } else {  } }
                                      is_cycle
                     };
-                    let is_dup : bool = match st.stack.last_mut() { None => false, Some(frame) => {
-                        let mut is_dup = false;
-                        for &(ref succ, ref _pred_dep) in frame.succs.iter() {
-                            if &succ.loc == loc && succ.effect == Effect::Observe
-                            { is_dup = true }
-                        };
-                        is_dup
+                    if is_cycle && cycle_out.is_none() {
+                        panic!("unexpected cycle detected in DCG, at {}\n{}",
+                               loc, string_of_demand_stack(&st.stack))
+                    };
+                    let is_dup : bool = match st.stack.last() { None => false, Some(frame) => {
+                        frame.observed.contains_key(loc)
                     }};
+                    let reuse = ! st.flags.record_only || is_dup ;
                     let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
-                    match *node {
-                        Node::Pure(ref mut nd) => (false, is_dup, true, false, Some(nd.val.clone())),
-                        Node::Mut(ref mut nd)  => (false, is_dup, false, false, Some(nd.val.clone())),
+                    let out = match *node {
+                        Node::Pure(ref mut nd) => (false, is_dup, true, false, Some(nd.val.clone()), None),
+                        Node::Mut(ref mut nd)  => (false, is_dup, false, false, Some(nd.val.clone()), None),
                         Node::Comp(ref mut nd) => {
                             let is_pure = match *loc.id {
                                 ArtId::Structural(_) => nd.succs.len() == 0 && is_pure_opt,
@@ -1982,20 +4386,26 @@ impl Adapton for DCG {
                                 // Cycle detected; check cycle_out to see if the caller expected this
                                 match cycle_out {
                                     // Caller did not expect a cycle; cycle is an error
-                                    None => { panic!("unexpected cycle detected in DCG") }
+                                    None => { unreachable!() /* checked above */ }
                                     // Caller expected that there _could_ be a
                                     // cycle, use this special output value now
                                     // (in particular, in the case of a cycle,
                                     // we do not use the thunk's cached result)
-                                    Some(out) => (true, is_dup, is_pure, true, Some(out)),
+                                    Some(out) => (true, is_dup, is_pure, true, Some(out), None),
                                 }
                             }
                             else {
-                                // "Ordinary case": No cycle, so clone the result we have cached, if any.
-                                (true, is_dup, is_pure, false, nd.res.clone())
+                                // "Ordinary case": No cycle, so clone the result we have cached, if any
+                                // (reloading it from disk, for `Storage::Disk`).
+                                let in_memory = nd.res.is_some() ;
+                                let cached = if reuse { nd.cached() } else { None } ;
+                                let reloaded = if in_memory || cached.is_none() { None } else { nd.digest } ;
+                                (true, is_dup, is_pure, false, cached, reloaded)
                             }
                         }
-                    }
+                    } ;
+                    if out.5.is_some() { count!(st.cnt.reloads += 1) } ;
+                    out
                 };
                 let result = match cached_result {
                     None => {
@@ -2005,6 +4415,7 @@ impl Adapton for DCG {
                             reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheMiss),
                             current_loc!(*g.borrow()),
                             reflect::Succ{
+                                label:None,
                                 loc:loc.reflect(),
                                 value:reflect::Val::ValTODO,
                                 effect:reflect::Effect::Force,
@@ -2024,6 +4435,7 @@ impl Adapton for DCG {
                                 reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheHit),
                                 current_loc!(*g.borrow()),
                                 reflect::Succ{
+                                    label:None,
                                     loc:loc.reflect(),
                                     value:reflect::Val::ValTODO,
                                     effect:reflect::Effect::Force,
@@ -2038,18 +4450,34 @@ impl Adapton for DCG {
                                 res
                             }
                             else {
-                                let _ = ForceDep{res:res.clone()}.clean(g, &loc) ;
+                                let dep = match reloaded {
+                                    Some(_) => ForceDep{res:None, digest:reloaded},
+                                    None => ForceDep{res:Some(res.clone()), digest:None},
+                                } ;
+                                let _ = dep.clean(g, &loc) ;
                                 dcg_effect_end!();
-                                let st : &mut DCG = &mut *g.borrow_mut();
-                                let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
-                                match *node {
-                                    Node::Comp(ref nd) => match nd.res {
-                                        None => unreachable!(),
-                                        Some(ref res) =>
-                                        // Testing: Reached by `pure_caching` tests
-                                            res.clone()
-                                    },
-                                    _ => unreachable!(),
+                                let cached = {
+                                    let st : &mut DCG = &mut *g.borrow_mut();
+                                    let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
+                                    let (cached, reread) = match *node {
+                                        Node::Comp(ref mut nd) => match nd.res {
+                                            Some(ref res) =>
+                                            // Testing: Reached by `pure_caching` tests
+                                                (Some(res.clone()), false),
+                                            // A spilled result, unchanged since we reloaded it
+                                            None if reloaded.is_some() && nd.digest == reloaded => (Some(res), false),
+                                            // A spilled result, changed by cleaning
+                                            None => { let cached = nd.cached(); let reread = cached.is_some(); (cached, reread) },
+                                        },
+                                        _ => unreachable!(),
+                                    } ;
+                                    if reread { count!(st.cnt.reloads += 1) } ;
+                                    cached
+                                } ;
+                                match cached {
+                                    Some(res) => { verify_hit(g, &loc, &res); res },
+                                    // The spill file is gone: re-evaluate
+                                    None => loc_produce(g, &loc),
                                 }}
                         }
                         else {
@@ -2057,6 +4485,7 @@ impl Adapton for DCG {
                                 reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
                                 current_loc!(*g.borrow()),
                                 reflect::Succ{
+                                    label:None,
                                     loc:loc.reflect(),
                                     value:reflect::Val::ValTODO,
                                     effect:reflect::Effect::Force,
@@ -2068,12 +4497,29 @@ impl Adapton for DCG {
                     }
                 } ;
                 let st : &mut DCG = &mut *g.borrow_mut() ;
-                if !is_dup && !is_pure { match st.stack.last_mut() { None => (), Some(frame) => {
+                // A structural thunk is pure when it has no successors
+                // _after_ producing its result (before its first
+                // evaluation, it trivially has none).
+                let (digest, is_pure, spilled) = match **res_node_of_loc::<T>(st, &loc) {
+                    Node::Comp(ref nd) => (nd.digest, is_pure && nd.succs.len() == 0, nd.spill.is_some()),
+                    _ => (None, is_pure, false),
+                } ;
+                // Observers of a spilled thunk keep its result's digest, only.
+                let observed = if spilled && digest.is_some() { None } else { Some(result.clone()) } ;
+                if is_dup {
+                    // Keep one edge, with the last value observed.
+                    count!(st.cnt.dup_observes += 1);
+                    let frame = st.stack.last_mut().unwrap();
+                    let i = frame.observed[loc];
+                    frame.succs[i].0.dep = Rc::new(Box::new(ForceDep{res:observed, digest:digest}));
+                } else if !is_pure { match st.stack.last_mut() { None => (), Some(frame) => {
                     let succ =
                         Succ{loc:loc.clone(),
-                             dep:Rc::new(Box::new(ForceDep{res:result.clone()})),
+                             label:None,
+                             dep:Rc::new(Box::new(ForceDep{res:observed, digest:digest})),
                              effect:Effect::Observe,
                              dirty:false};
+                    frame.observed.insert(loc.clone(), frame.succs.len());
                     frame.succs.push((succ, None));
                 }}} ;
                 wf::check_dcg(st);
@@ -2110,31 +4556,208 @@ pub struct Art<T> {
     art:EnumArt<T>,
 }
 
-#[derive(Clone)]
-enum EnumArt<T> {
-    /// No entry in table. No dependency tracking.
-    Rc(Rc<T>),
-    /// Location in table.
-    Loc(Rc<Loc>),
-    /// A closure that is 'force-able'
-    Force(Rc<Force<T>>),
+/// Quick (partial) equality checks, for values that are expensive to
+/// compare in full, such as big results of thunks; see `Fast`.
+pub trait FastEq {
+    /// Decides whether `self` equals `other` _cheaply_ (e.g., via
+    /// pointers, names or tags), or else, gives `None`.
+    fn fast_eq(&self, other:&Self) -> Option<bool>;
 }
 
-impl<T:Hash> Hash for EnumArt<T> {
-    fn hash<H>(&self, hasher:&mut H) where H:Hasher {
-        match *self {
-            EnumArt::Rc(ref rc)   => rc.hash( hasher ),
-            EnumArt::Loc(ref loc) => loc.hash( hasher ),
-            EnumArt::Force(ref f) => f.hash_u64().hash( hasher ),
+impl<T:PartialEq> FastEq for Art<T> {
+    fn fast_eq(&self, other:&Self) -> Option<bool> {
+        match (&self.art, &other.art) {
+            (&EnumArt::Rc(ref a), &EnumArt::Rc(ref b)) if ! Rc::ptr_eq(a, b) => None,
+            _ => Some(self == other),
         }
     }
 }
 
-impl<T:PartialEq> PartialEq for EnumArt<T> {
+thread_local!(static FAST_REV: RefCell<u64> = RefCell::new(0));
+
+/// A shared value with fast equality, for results (or cell contents)
+/// that are expensive to compare (via `!=`) during change propagation.
+///
+/// Each `Fast::new` gives a fresh revision; clones share it, and its
+/// (cached) hash.  Comparisons first check the revisions, the
+/// pointers and the hashes, then `FastEq::fast_eq`, and only then
+/// compare the values in full.
+pub struct Fast<T> {
+    rev : u64,
+    hash : u64,
+    val : Rc<T>,
+}
+impl<T> Clone for Fast<T> {
+    fn clone(&self) -> Self { Fast{ rev:self.rev, hash:self.hash, val:self.val.clone() } }
+}
+
+impl<T:Hash> Fast<T> {
+    /// Wraps the value, as a fresh revision.
+    pub fn new(val:T) -> Fast<T> {
+        let rev = FAST_REV.with(|r| { *r.borrow_mut() += 1; *r.borrow() });
+        Fast{ rev:rev, hash:my_hash(&val), val:Rc::new(val) }
+    }
+}
+impl<T> Fast<T> {
+    /// The revision of this value (shared by its clones).
+    pub fn rev(&self) -> u64 { self.rev }
+}
+impl<T> ::std::ops::Deref for Fast<T> {
+    type Target = T;
+    fn deref(&self) -> &T { &*self.val }
+}
+impl<T:FastEq+PartialEq> PartialEq for Fast<T> {
     fn eq(&self, other:&Self) -> bool {
-        match *self {
-            EnumArt::Rc(ref rc) =>
-                if let EnumArt::Rc(ref rc2) = *other { rc == rc2 } else { false },
+        if self.rev == other.rev || Rc::ptr_eq(&self.val, &other.val) { return true };
+        if self.hash != other.hash { return false };
+        match self.val.fast_eq(&other.val) {
+            Some(b) => b,
+            None => self.val == other.val,
+        }
+    }
+}
+impl<T:FastEq+Eq> Eq for Fast<T> { }
+impl<T> Hash for Fast<T> {
+    fn hash<H:Hasher>(&self, state:&mut H) { self.hash.hash(state) }
+}
+impl<T:Debug> Debug for Fast<T> {
+    fn fmt(&self, f:&mut Formatter) -> Result { self.val.fmt(f) }
+}
+
+/// A field of a thunk's argument that does not affect its identity
+/// (e.g., a timestamp, or a debugging label): all `MemoIgnore`s hash
+/// alike, and are equal.
+///
+/// Hence, a structural thunk whose arguments differ only in such
+/// fields is the same thunk (with the same DCG node), and
+/// re-allocating a nominal thunk with such a difference does not
+/// dirty it.  In either case, the thunk's producer may see the field
+/// of an earlier allocation.
+#[derive(Clone,Default)]
+pub struct MemoIgnore<T>(pub T);
+
+impl<T> MemoIgnore<T> {
+    /// The wrapped value.
+    pub fn into_inner(self) -> T { self.0 }
+}
+impl<T> ::std::ops::Deref for MemoIgnore<T> {
+    type Target = T;
+    fn deref(&self) -> &T { &self.0 }
+}
+impl<T> ::std::ops::DerefMut for MemoIgnore<T> {
+    fn deref_mut(&mut self) -> &mut T { &mut self.0 }
+}
+impl<T> PartialEq for MemoIgnore<T> {
+    fn eq(&self, _other:&Self) -> bool { true }
+}
+impl<T> Eq for MemoIgnore<T> { }
+impl<T> Hash for MemoIgnore<T> {
+    fn hash<H:Hasher>(&self, _state:&mut H) { }
+}
+impl<T> FastEq for MemoIgnore<T> {
+    fn fast_eq(&self, _other:&Self) -> Option<bool> { Some(true) }
+}
+impl<T:Debug> Debug for MemoIgnore<T> {
+    fn fmt(&self, f:&mut Formatter) -> Result { write!(f, "MemoIgnore({:?})", self.0) }
+}
+
+/// Equality and hashing for the trait objects of type `T`, as
+/// registered by `register_art_obj`.
+struct ArtObjAdapters<T:?Sized> {
+    eq   : Rc<Fn(&T, &T) -> bool>,
+    hash : Rc<Fn(&T) -> u64>,
+}
+
+thread_local!(static ART_OBJ_ADAPTERS: RefCell<HashMap<TypeId, Box<Any>>> = RefCell::new(HashMap::new()));
+
+/// Registers equality and hashing for the `ArtObj`s of the (unsized)
+/// type `T`, typically a trait object type; returns true if this
+/// replaces a prior registration.  Register them before creating any
+/// `ArtObj<T>`, on each thread that uses them.
+pub fn register_art_obj<T:?Sized+'static,
+                        Eq:Fn(&T, &T) -> bool+'static,
+                        H:Fn(&T) -> u64+'static> (eq:Eq, hash:H) -> bool {
+    ART_OBJ_ADAPTERS.with(|a| {
+        a.borrow_mut().insert(TypeId::of::<T>(),
+                              Box::new(ArtObjAdapters::<T>{eq:Rc::new(eq), hash:Rc::new(hash)}))
+            .is_some()
+    })
+}
+
+fn art_obj_adapters<T:?Sized+'static> () -> ArtObjAdapters<T> {
+    ART_OBJ_ADAPTERS.with(|a| {
+        match a.borrow().get(&TypeId::of::<T>()).and_then(|ad| ad.downcast_ref::<ArtObjAdapters<T>>()) {
+            Some(ad) => ArtObjAdapters{eq:ad.eq.clone(), hash:ad.hash.clone()},
+            None => panic!("ArtObj: no equality and hashing registered for {}; see `register_art_obj`",
+                           ::std::any::type_name::<T>()),
+        }
+    })
+}
+
+/// A shared trait object (e.g., `ArtObj<Shape>` for a trait `Shape`),
+/// for the results and cell contents of plugin-style architectures,
+/// which cache heterogeneous values without wrapping each in an enum.
+///
+/// Comparisons first check the pointers, then use the registered
+/// equality (see `register_art_obj`); likewise, hashing uses the
+/// registered hashing.  Using an `ArtObj<T>` before registering them
+/// for `T` panics.
+pub struct ArtObj<T:?Sized> {
+    obj : Rc<T>,
+}
+impl<T:?Sized> ArtObj<T> {
+    /// Shares the trait object (e.g., `ArtObj::new(Rc::new(c) as Rc<Shape>)`).
+    pub fn new(obj:Rc<T>) -> Self { ArtObj{obj:obj} }
+    /// The shared trait object.
+    pub fn rc(&self) -> &Rc<T> { &self.obj }
+}
+impl<T:?Sized> Clone for ArtObj<T> {
+    fn clone(&self) -> Self { ArtObj{obj:self.obj.clone()} }
+}
+impl<T:?Sized> ::std::ops::Deref for ArtObj<T> {
+    type Target = T;
+    fn deref(&self) -> &T { &*self.obj }
+}
+impl<T:?Sized+'static> PartialEq for ArtObj<T> {
+    fn eq(&self, other:&Self) -> bool {
+        Rc::ptr_eq(&self.obj, &other.obj) || (art_obj_adapters::<T>().eq)(&*self.obj, &*other.obj)
+    }
+}
+impl<T:?Sized+'static> Eq for ArtObj<T> { }
+impl<T:?Sized+'static> Hash for ArtObj<T> {
+    fn hash<H:Hasher>(&self, state:&mut H) { (art_obj_adapters::<T>().hash)(&*self.obj).hash(state) }
+}
+impl<T:?Sized+'static> Debug for ArtObj<T> {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        write!(f, "ArtObj<{}>#{}", ::std::any::type_name::<T>(), (art_obj_adapters::<T>().hash)(&*self.obj))
+    }
+}
+
+#[derive(Clone)]
+enum EnumArt<T> {
+    /// No entry in table. No dependency tracking.
+    Rc(Rc<T>),
+    /// Location in table.
+    Loc(Rc<Loc>),
+    /// A closure that is 'force-able'
+    Force(Rc<Force<T>>),
+}
+
+impl<T:Hash> Hash for EnumArt<T> {
+    fn hash<H>(&self, hasher:&mut H) where H:Hasher {
+        match *self {
+            EnumArt::Rc(ref rc)   => rc.hash( hasher ),
+            EnumArt::Loc(ref loc) => loc.hash( hasher ),
+            EnumArt::Force(ref f) => f.hash_u64().hash( hasher ),
+        }
+    }
+}
+
+impl<T:PartialEq> PartialEq for EnumArt<T> {
+    fn eq(&self, other:&Self) -> bool {
+        match *self {
+            EnumArt::Rc(ref rc) =>
+                if let EnumArt::Rc(ref rc2) = *other { rc == rc2 } else { false },
             EnumArt::Loc(ref loc) =>
                 if let EnumArt::Loc(ref loc2) = *other { loc == loc2 } else { false },
             EnumArt::Force(ref f) =>
@@ -2251,8 +4874,9 @@ pub fn name_unit () -> Name {
 /// Create one name from two (binary name composition)
 pub fn name_pair (n1:Name, n2:Name) -> Name {
     let h = my_hash( &(n1.hash,n2.hash) ) ;
-    let p = NameSym::Pair(n1.symbol, n2.symbol) ;
-    Name{ hash:h, symbol:Rc::new(p) }
+    let k = SymKey::Pair(symkey_of_rc(&n1.symbol), symkey_of_rc(&n2.symbol)) ;
+    let p = intern_namesym(k, || NameSym::Pair(n1.symbol, n2.symbol)) ;
+    Name{ hash:h, symbol:p }
 }
 
 /// Create a name from a hash value.
@@ -2263,45 +4887,46 @@ pub fn name_pair (n1:Name, n2:Name) -> Name {
 pub fn name_of_hash64(h:u64) -> Name {
     // TODO: Get rid of need for Rc here;
     // Rc should be optional in names?
-    Name{ hash:h, symbol:Rc::new(NameSym::Hash64) }
+    Name{ hash:h, symbol:intern_namesym(SymKey::Hash64, || NameSym::Hash64) }
 }
 
 /// Create a name from a `usize`
 pub fn name_of_usize (u:usize) -> Name {
     let h = my_hash(&u) ;
-    let s = NameSym::Usize(u) ;
-    Name{ hash:h, symbol:Rc::new(s) }
+    let s = intern_namesym(SymKey::Usize(u), || NameSym::Usize(u)) ;
+    Name{ hash:h, symbol:s }
 }
 
 /// Create a name from a `isize`
 pub fn name_of_isize (i:isize) -> Name {
     let h = my_hash(&i) ;
-    let s = NameSym::Isize(i) ;
-    Name{ hash:h, symbol:Rc::new(s) }
+    let s = intern_namesym(SymKey::Isize(i), || NameSym::Isize(i)) ;
+    Name{ hash:h, symbol:s }
 }
 
 /// Create a name from a `string`
 pub fn name_of_string (s:String) -> Name {
     let h = my_hash(&s);
-    let s = NameSym::String(s) ;
-    Name{ hash:h, symbol:Rc::new(s) }
+    let s = intern_strsym(&s) ;
+    Name{ hash:h, symbol:s }
 }
 
 /// Create a name from a `str`
 pub fn name_of_str (s:&'static str) -> Name {
     let h = my_hash(&s);
-    let s = NameSym::String(s.to_string()) ;
-    Name{ hash:h, symbol:Rc::new(s) }
+    let s = intern_strsym(s) ;
+    Name{ hash:h, symbol:s }
 }
 
 /// Create two names from one
 pub fn name_fork (n:Name) -> (Name, Name) {
     let h1 = my_hash( &(&n, 11111111) ) ; // TODO-Later: make this hashing better.
     let h2 = my_hash( &(&n, 22222222) ) ;
+    let k = symkey_of_rc(&n.symbol) ;
     ( Name{ hash:h1,
-            symbol:Rc::new(NameSym::ForkL(n.symbol.clone())) } ,
+            symbol:intern_namesym(SymKey::ForkL(k), || NameSym::ForkL(n.symbol.clone())) } ,
       Name{ hash:h2,
-            symbol:Rc::new(NameSym::ForkR(n.symbol)) } )
+            symbol:intern_namesym(SymKey::ForkR(k), || NameSym::ForkR(n.symbol)) } )
 }
 
 /// Create three names from one
@@ -2323,6 +4948,207 @@ pub fn name_fork4 (n:Name)
     (n1,n2,n3,n4)
 }
 
+thread_local!(static NAME_SEED: RefCell<u64> = RefCell::new(0));
+
+/// Sets the seed of `name_coin` and `name_level`; returns the prior
+/// seed (initially, zero).  The seed persists when switching engines.
+/// Set it before allocating any articulations whose structure depends
+/// on these outcomes: the engine does not track the seed, so changing
+/// it later invalidates such structures without dirtying anything.
+pub fn set_name_seed (seed:u64) -> u64 {
+    NAME_SEED.with(|s| replace(&mut *s.borrow_mut(), seed))
+}
+
+/// The seed of `name_coin` and `name_level`; see `set_name_seed`.
+pub fn name_seed () -> u64 {
+    NAME_SEED.with(|s| *s.borrow())
+}
+
+/// The pseudo-random bits of a name: the SplitMix64 finalizer applied
+/// to the name's hash, XOR'd with the seed (see `set_name_seed`).
+/// Hence, these bits are stable across runs and edits; they change
+/// only with the name (or the seed).
+fn name_bits (n:&Name) -> u64 {
+    let mut z = (n.hash ^ name_seed()).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// A deterministic, pseudo-random coin flip for the name: the lowest
+/// of its pseudo-random bits (see `name_bits`).  For example,
+/// probabilistic balancing (e.g., of treaps) uses such per-element
+/// coin flips, which must not vary across runs and edits.
+pub fn name_coin (n:&Name) -> bool {
+    name_bits(n) & 1 == 1
+}
+
+/// A deterministic, pseudo-random level for the name, with a geometric
+/// distribution: the number of trailing zeros of its pseudo-random
+/// bits (see `name_bits`), in `0..64`.  Half of all names have level
+/// zero, a quarter have level one, and so on (see Pugh and
+/// Teitelbaum, POPL 1989, for such levels in balanced trees).
+pub fn name_level (n:&Name) -> u32 {
+    ::std::cmp::min(name_bits(n).trailing_zeros(), 63)
+}
+
+/// Operations over the structure of names, and of namespace paths
+/// (see `reflect::Path`), for collections that split and merge their
+/// structure by name (e.g., deterministically, as with `name_coin`).
+///
+/// A name _derives_ from another by `name_fork` (either of its
+/// forks), or by `name_pair`, on the left: `name_pair(n, m)` derives
+/// from `n`, as when a collection names its parts by pairing its own
+/// name with their positions.  A name's _ancestors_ are those that it
+/// derives from, transitively; the roots (unit, strings, numbers and
+/// hashes) have none.  Within a name, hashes are indistinguishable
+/// (see `Display`), so these operations only distinguish two names
+/// built from hashes at their outermost level.
+///
+/// ```
+/// use adapton::engine::*;
+/// let doc = name_of_str("doc");
+/// let (l, r) = name_fork(doc.clone());
+/// let ll = name_fork(l.clone()).0;
+/// let item = name_pair(r.clone(), name_of_usize(3));
+/// assert!(name::is_prefix_of(&doc, &item));
+/// assert!(! name::is_prefix_of(&l, &item));
+/// assert_eq!(name::parent(&item), Some(r));
+/// assert_eq!(name::common_ancestor(&ll, &item), Some(doc.clone()));
+/// assert_eq!(name::common_ancestor(&ll, &name_of_str("other")), None);
+///
+/// // Each name orders before the names that derive from it, and these
+/// // before the next name that does not.
+/// let mut names = vec![item.clone(), doc.clone(), ll.clone(), l.clone()];
+/// names.sort_by(name::cmp);
+/// assert_eq!(names, vec![doc, l, ll, item]);
+/// ```
+pub mod name {
+    use super::*;
+
+    /// The symbol that `s` derives from, if any.
+    fn parent_sym (s:&NameSym) -> Option<&Rc<NameSym>> {
+        match *s {
+            NameSym::ForkL(ref p) | NameSym::ForkR(ref p) | NameSym::Pair(ref p, _) => Some(p),
+            _ => None,
+        }
+    }
+
+    /// The root of the symbol `s`, and the steps from this root to `s`
+    /// (as the symbols along the way), outermost last.
+    fn derivation (s:&Rc<NameSym>) -> (&NameSym, Vec<&NameSym>) {
+        let mut steps = vec![];
+        let mut s : &NameSym = &**s;
+        while let Some(p) = parent_sym(s) { steps.push(s); s = &**p };
+        steps.reverse();
+        (s, steps)
+    }
+
+    /// The name of the (inner) symbol `s`, recomputing its hash as the
+    /// name constructors do; `None` if it contains a hash.
+    fn name_of_sym (s:&Rc<NameSym>) -> Option<Name> {
+        let (root, steps) = derivation(s);
+        let mut hash = match *root {
+            NameSym::Unit => name_unit().hash,
+            NameSym::Hash64 => return None,
+            NameSym::String(ref s) => my_hash(s),
+            NameSym::Usize(ref n) => my_hash(n),
+            NameSym::Isize(ref n) => my_hash(n),
+            _ => unreachable!(),
+        };
+        for step in steps {
+            hash = match *step {
+                NameSym::ForkL(_) => my_hash(&(hash, 11111111)),
+                NameSym::ForkR(_) => my_hash(&(hash, 22222222)),
+                NameSym::Pair(_, ref r) => my_hash(&(hash, name_of_sym(r)?.hash)),
+                _ => unreachable!(),
+            }
+        };
+        Some(Name{hash:hash, symbol:s.clone()})
+    }
+
+    /// The name that `n` derives from directly, if any; `None` for
+    /// roots, and for names whose parents contain a hash.
+    pub fn parent (n:&Name) -> Option<Name> {
+        parent_sym(&n.symbol).and_then(name_of_sym)
+    }
+
+    /// Tests if `n` is `m`, or one of its ancestors.
+    pub fn is_prefix_of (n:&Name, m:&Name) -> bool {
+        if n == m { return true };
+        let mut s = &m.symbol;
+        while let Some(p) = parent_sym(s) {
+            if Rc::ptr_eq(p, &n.symbol) { return true };
+            s = p;
+        };
+        false
+    }
+
+    /// The nearest name that is a prefix of both `n` and `m` (see
+    /// `is_prefix_of`); `None` if there is none, or if it contains a
+    /// hash (and is neither `n` nor `m`).
+    pub fn common_ancestor (n:&Name, m:&Name) -> Option<Name> {
+        if is_prefix_of(n, m) { return Some(n.clone()) };
+        if is_prefix_of(m, n) { return Some(m.clone()) };
+        let mut ancestors = HashSet::new();
+        let mut s = &n.symbol;
+        while let Some(p) = parent_sym(s) { ancestors.insert(symkey_of_rc(p)); s = p };
+        let mut s = &m.symbol;
+        while let Some(p) = parent_sym(s) {
+            if ancestors.contains(&symkey_of_rc(p)) { return name_of_sym(p) };
+            s = p;
+        };
+        None
+    }
+
+    /// The lexicographic order of names, by their derivations: first
+    /// by their roots (as for `Ord`), then by their steps from these
+    /// roots, where the left fork precedes the right one, and both
+    /// precede pairs, which are ordered by their right components (as
+    /// for `Ord`).  Hence, each name precedes the names that derive from
+    /// it, and these names are contiguous.  Like `Ord`, this order is
+    /// stable across runs (and versions).
+    pub fn cmp (n:&Name, m:&Name) -> Ordering {
+        fn rank (s:&NameSym) -> usize {
+            match *s { NameSym::ForkL(_) => 0, NameSym::ForkR(_) => 1, _ => 2 }
+        }
+        let (n_root, n_steps) = derivation(&n.symbol);
+        let (m_root, m_steps) = derivation(&m.symbol);
+        cmp_namesym(n_root, m_root).then_with(|| {
+            for (a, b) in n_steps.iter().zip(m_steps.iter()) {
+                let ord = match (*a, *b) {
+                    (&NameSym::Pair(_, ref a), &NameSym::Pair(_, ref b)) => cmp_namesym(a, b),
+                    (a, b) => rank(a).cmp(&rank(b)),
+                };
+                if ord != Ordering::Equal { return ord }
+            };
+            n_steps.len().cmp(&m_steps.len())
+        }).then(n.hash.cmp(&m.hash))
+    }
+
+    /// Tests if the namespace path `p` is `q`, or encloses it.
+    pub fn path_is_prefix_of (p:&[Name], q:&[Name]) -> bool {
+        q.starts_with(p)
+    }
+
+    /// The innermost namespace path that encloses both `p` and `q`
+    /// (their longest common prefix).
+    pub fn path_common_ancestor (p:&[Name], q:&[Name]) -> reflect::Path {
+        p.iter().zip(q.iter()).take_while(|&(n, m)| n == m).map(|(n, _)| n.clone()).collect()
+    }
+
+    /// The lexicographic order of namespace paths, by the order of
+    /// their names (see `cmp`): each path precedes those that it
+    /// encloses.
+    pub fn path_cmp (p:&[Name], q:&[Name]) -> Ordering {
+        for (n, m) in p.iter().zip(q.iter()) {
+            let ord = cmp(n, m);
+            if ord != Ordering::Equal { return ord }
+        };
+        p.len().cmp(&q.len())
+    }
+}
+
 /// Creates or re-enters a given namespace; performs the given computation there.
 pub fn ns<T,F> (n:Name, body:F) -> T
     where F:FnOnce() -> T {
@@ -2334,6 +5160,40 @@ pub fn ns<T,F> (n:Name, body:F) -> T
     })
 }
 
+/// Performs the given computation in the sandbox of the current thunk:
+/// the namespace of its own name, nested within the namespace of the
+/// thunk.  The producer of a `Purity::Confined` thunk may allocate
+/// only there.  At the outer layer (and for the `Naive` engine),
+/// performs the computation as is.
+pub fn sandbox<T,F> (body:F) -> T
+    where F:FnOnce() -> T {
+    let name = GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => dcg.borrow().stack.last().map(|frame| sandbox_name(&frame.loc)),
+            Engine::Naive => None,
+        }
+    });
+    match name {
+        Some(name) => ns(name, body),
+        None => body(),
+    }
+}
+
+/// Creates or re-enters the namespace of iteration `i` of a loop
+/// whose namespace is named `n`; performs the given computation there.
+///
+/// Within a loop, a name that does not vary with the iteration names
+/// a different allocation in each iteration; each such allocation
+/// overwrites the prior one, and the DCG keeps only the last.  Running
+/// each iteration in its own namespace, as here (or via `for_named!`),
+/// gives their allocations distinct names.  While
+/// `Flags::check_dcg_is_wf` holds, the engine warns about (and counts,
+/// in `Cnt::name_reuse`) such repeated allocations.
+pub fn ns_iter<T,F> (n:Name, i:usize, body:F) -> T
+    where F:FnOnce() -> T {
+    ns(name_pair(n, name_of_usize(i)), body)
+}
+
 /// Enters a special "namespace" where all name uses are ignored; instead, Adapton uses structural identity.
 pub fn structural<T,F> (body:F) -> T
     where F:FnOnce() -> T {
@@ -2345,6 +5205,443 @@ pub fn structural<T,F> (body:F) -> T
     })
 }
 
+/// Provides a resource of type `T` (e.g., a database handle, a cache
+/// or a random number generator) to the producers that run on the
+/// current thread; returns the resource of type `T` previously
+/// provided, if any.
+///
+/// Resources are keyed by type: wrap a value in a newtype to provide
+/// several of the same type, and in a `RefCell` to permit producers to
+/// mutate it.  Resources persist when switching engines (e.g., via
+/// `manage::init_dcg`), so the same code runs under `Naive` and `DCG`
+/// engines alike.
+///
+/// Resources are _not_ part of memo identity: like spurious arguments,
+/// the engine neither compares nor tracks them.  In particular,
+/// re-providing a resource does not dirty the thunks that used it; to
+/// invalidate those thunks, use a cell instead (or `manage::clear_results`).
+///
+/// Panics if called from within a producer.
+pub fn provide<T:'static> (res:T) -> Option<Rc<T>> {
+    GLOBALS.with(|g| {
+        let mut g = match g.try_borrow_mut() {
+            Ok(g) => g,
+            Err(_) => panic!("provide: cannot provide a resource from within a producer"),
+        };
+        g.resources.insert(TypeId::of::<T>(), Rc::new(res))
+            .map(|old| old.downcast::<T>().ok().unwrap())
+    })
+}
+
+/// Withdraws the resource of type `T`; returns it, if any.
+///
+/// Panics if called from within a producer.
+pub fn unprovide<T:'static> () -> Option<Rc<T>> {
+    GLOBALS.with(|g| {
+        let mut g = match g.try_borrow_mut() {
+            Ok(g) => g,
+            Err(_) => panic!("unprovide: cannot withdraw a resource from within a producer"),
+        };
+        g.resources.remove(&TypeId::of::<T>())
+            .map(|old| old.downcast::<T>().ok().unwrap())
+    })
+}
+
+/// The resource of type `T`, if provided (see `provide`); callable
+/// inside producers.
+pub fn resource<T:'static> () -> Option<Rc<T>> {
+    GLOBALS.with(|g| {
+        g.borrow().resources.get(&TypeId::of::<T>())
+            .map(|res| res.clone().downcast::<T>().ok().unwrap())
+    })
+}
+
+/// How tools render the values of one type, without knowing the type
+/// statically; see `register_type`.
+#[derive(Clone)]
+pub struct TypeRenderer {
+    /// The name of the type.
+    pub type_name : &'static str,
+    /// Renders a value of the type as a string.
+    pub debug : Rc<Fn(&Any) -> String>,
+    /// Serializes a value of the type as a reflected value.
+    pub serialize : Rc<Fn(&Any) -> reflect::Val>,
+}
+
+impl Debug for TypeRenderer {
+    fn fmt(&self, f:&mut Formatter) -> Result { write!(f, "TypeRenderer({})", self.type_name) }
+}
+
+thread_local!(static RENDERERS: RefCell<HashMap<TypeId, TypeRenderer>> = RefCell::new(HashMap::new()));
+
+/// Registers the `Debug` rendering of type `T` for tools; returns the
+/// prior renderer of `T`, if any.  See `register_type_with`.
+pub fn register_type<T:Debug+'static> () -> Option<TypeRenderer> {
+    register_type_with::<T,_,_>(|v| format!("{:?}", v), |v| reflect::reflect_val(v))
+}
+
+/// Registers how tools render the values of type `T`: as a string,
+/// and as a reflected value; returns the prior renderer of `T`, if
+/// any.  For registered types, `render_any` and `serialize_any` render
+/// type-erased values (e.g., those of `on_recompute`), the reflected
+/// DCG (see `reflect::dcg_reflect_now`) gives the serialized values,
+/// and the dot output of the DCG labels the nodes with their values.
+///
+/// The registry is per thread, and persists across engines.
+pub fn register_type_with<T,D,S> (debug:D, serialize:S) -> Option<TypeRenderer>
+    where T:'static, D:Fn(&T) -> String+'static, S:Fn(&T) -> reflect::Val+'static
+{
+    let renderer = TypeRenderer{
+        type_name: ::std::any::type_name::<T>(),
+        debug:Rc::new(move |v:&Any| debug(v.downcast_ref::<T>().unwrap())),
+        serialize:Rc::new(move |v:&Any| serialize(v.downcast_ref::<T>().unwrap())),
+    };
+    RENDERERS.with(|r| r.borrow_mut().insert(TypeId::of::<T>(), renderer))
+}
+
+/// The renderer of type `T`, if registered (see `register_type`).
+pub fn type_renderer<T:'static> () -> Option<TypeRenderer> {
+    renderer_of(TypeId::of::<T>())
+}
+
+fn renderer_of (typeid:TypeId) -> Option<TypeRenderer> {
+    RENDERERS.with(|r| r.borrow().get(&typeid).cloned())
+}
+
+/// Renders a value of a registered type as a string (see `register_type`);
+/// `None` if its type is not registered.
+pub fn render_any (v:&Any) -> Option<String> {
+    renderer_of(v.type_id()).map(|r| (r.debug)(v))
+}
+
+/// Serializes a value of a registered type as a reflected value (see
+/// `register_type`); `None` if its type is not registered.
+pub fn serialize_any (v:&Any) -> Option<reflect::Val> {
+    renderer_of(v.type_id()).map(|r| (r.serialize)(v))
+}
+
+/// The reflected value of `v`: its registered serialization, if any,
+/// or else the parse of its `Debug` string.
+fn serialize_val<T:Debug+'static> (v:&T) -> reflect::Val {
+    match serialize_any(v) {
+        Some(val) => val,
+        None => reflect::reflect_val(v),
+    }
+}
+
+/// Identifies a hook registered by `on_dirty` or `on_recompute`; see `remove_hook`.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct HookId(usize);
+
+type DirtyHook = Rc<Fn(&reflect::Loc)>;
+type RecomputeHook = Rc<Fn(&reflect::Loc, Option<&Any>, &Any)>;
+
+#[derive(Default)]
+struct Hooks {
+    next : usize,
+    dirty : Vec<(HookId, reflect::Path, DirtyHook)>,
+    recompute : Vec<(HookId, reflect::Path, RecomputeHook)>,
+}
+
+thread_local!(static HOOKS: RefCell<Hooks> = RefCell::new( Hooks::default() ));
+
+/// Registers a hook that the DCG engine calls when it dirties a node
+/// (a thunk whose result may be stale) in namespace `prefix` (or one
+/// nested within it).  For instance, applications that mirror derived
+/// data in external systems (GPU buffers, HTTP caches) can invalidate
+/// those mirrors precisely, without polling.
+///
+/// The engine calls the hook while it is mid-step, so the hook must
+/// not call back into the engine (e.g., `force` or `cell`).
+pub fn on_dirty<F:Fn(&reflect::Loc)+'static> (prefix:reflect::Path, hook:F) -> HookId {
+    HOOKS.with(|h| {
+        let h = &mut *h.borrow_mut();
+        let id = HookId(h.next);
+        h.next += 1;
+        h.dirty.push((id, prefix, Rc::new(hook)));
+        id
+    })
+}
+
+/// Registers a hook that the DCG engine calls when it (re-)evaluates a
+/// thunk in namespace `prefix` (or one nested within it), with the
+/// thunk's prior result (if any) and its new result; the hook can
+/// `downcast_ref` these results to the thunk's result type.
+///
+/// As with `on_dirty`, the hook must not call back into the engine.
+pub fn on_recompute<F:Fn(&reflect::Loc, Option<&Any>, &Any)+'static> (prefix:reflect::Path, hook:F) -> HookId {
+    HOOKS.with(|h| {
+        let h = &mut *h.borrow_mut();
+        let id = HookId(h.next);
+        h.next += 1;
+        h.recompute.push((id, prefix, Rc::new(hook)));
+        id
+    })
+}
+
+/// Removes a hook registered by `on_dirty` or `on_recompute`; returns
+/// false if it was already removed.
+pub fn remove_hook (id:HookId) -> bool {
+    HOOKS.with(|h| {
+        let h = &mut *h.borrow_mut();
+        let len = h.dirty.len() + h.recompute.len();
+        h.dirty.retain(|&(ref i, _, _)| *i != id);
+        h.recompute.retain(|&(ref i, _, _)| *i != id);
+        h.dirty.len() + h.recompute.len() != len
+    })
+}
+
+fn call_dirty_hooks(loc:&Loc) {
+    let loc = match HOOKS.with(|h| h.borrow().dirty.is_empty()) {
+        true => return,
+        false => loc.reflect(),
+    };
+    let hooks : Vec<DirtyHook> = HOOKS.with(|h| {
+        h.borrow().dirty.iter()
+            .filter(|&&(_, ref prefix, _)| name::path_is_prefix_of(prefix, &loc.path))
+            .map(|&(_, _, ref hook)| hook.clone()).collect()
+    });
+    for hook in hooks { hook(&loc) }
+}
+
+fn call_recompute_hooks(loc:&Loc, old:Option<&Any>, new:&Any) {
+    let loc = match HOOKS.with(|h| h.borrow().recompute.is_empty()) {
+        true => return,
+        false => loc.reflect(),
+    };
+    let hooks : Vec<RecomputeHook> = HOOKS.with(|h| {
+        h.borrow().recompute.iter()
+            .filter(|&&(_, ref prefix, _)| name::path_is_prefix_of(prefix, &loc.path))
+            .map(|&(_, _, ref hook)| hook.clone()).collect()
+    });
+    for hook in hooks { hook(&loc, old, new) }
+}
+
+/// The demand stack: the locations of the thunks currently being
+/// forced, outermost first.  Empty outside of any producer, and for
+/// the `Naive` engine.  Callable inside producers, e.g., for logging
+/// or cost attribution; the engine's own panic messages include it,
+/// too.
+pub fn demand_stack () -> Vec<reflect::Loc> {
+    GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => dcg.borrow().stack.iter().map(|frame| frame.loc.reflect()).collect(),
+            Engine::Naive => vec![],
+        }})
+}
+
+/// The floating-point types of `Quantized` numbers.
+pub trait Float : Copy+Debug+'static {
+    fn to_f64 (self) -> f64 ;
+    fn from_f64 (f:f64) -> Self ;
+}
+impl Float for f64 {
+    fn to_f64 (self) -> f64 { self }
+    fn from_f64 (f:f64) -> Self { f }
+}
+impl Float for f32 {
+    fn to_f64 (self) -> f64 { self as f64 }
+    fn from_f64 (f:f64) -> Self { f as f32 }
+}
+
+/// A floating-point number (its `value`, first), with a `grid`, for
+/// the arguments of structural thunks: it compares and hashes by its
+/// quantization, the multiple of `grid` that is nearest to `value`.
+/// Hence, numbers that differ by less than the grid (e.g., by rounding
+/// errors) often, but not always, match the same thunk; and all NaNs
+/// are equal, as are `0.0` and `-0.0`.  Two numbers with different
+/// grids are never equal.  Whereas `f64` has no `Eq` or `Hash`, and
+/// its bits change with each rounding error, numeric pipelines can
+/// use these numbers in structural (and nominal) memoization.
+///
+/// The counter `Cnt::quantized_hits` counts the allocations that
+/// match an existing thunk whose argument's value differs.
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use adapton::macros::*;
+/// use adapton::engine::*;
+/// use std::rc::Rc;
+/// manage::init_dcg();
+///
+/// fn area (r:Quantized<f64>) -> Art<Quantized<f64>> {
+///     thunk(NameChoice::Structural, prog_pt!("area"),
+///           Rc::new(Box::new(|r:Quantized<f64>, ()| Quantized::new(3.14159 * r.0 * r.0, r.1))), r, ())
+/// }
+/// let a = force(&area(Quantized::new(0.1 + 0.2, 1e-9)));
+/// let b = force(&area(Quantized::new(0.3, 1e-9)));
+/// assert_eq!(a, b);
+/// if cfg!(feature = "counters") {
+///     let cnt = manage::counters().unwrap();
+///     assert_eq!((cnt.eval, cnt.quantized_hits), (1, 1));
+/// }
+/// # }
+/// ```
+#[derive(Clone,Copy,Debug)]
+pub struct Quantized<F:Float> (pub F, pub F);
+
+thread_local!(static QUANTIZATION : ::std::cell::Cell<(bool, bool)> = ::std::cell::Cell::new((false, false)));
+
+/// Clears the record of `Quantized` numbers: whether the engine hashed
+/// one, and whether it found two equal ones of different values.
+fn quantization_reset () { QUANTIZATION.with(|q| q.set((false, false))) }
+fn quantization_hashed () -> bool { QUANTIZATION.with(|q| q.get().0) }
+fn quantization_inexact () -> bool { QUANTIZATION.with(|q| q.get().1) }
+
+impl<F:Float> Quantized<F> {
+    /// The number `value`, quantized by `grid`.  Panics unless `grid`
+    /// is positive and finite.
+    pub fn new (value:F, grid:F) -> Self {
+        let g = grid.to_f64();
+        assert!(g > 0.0 && g.is_finite(), "Quantized: the grid {:?} is not positive and finite", grid);
+        Quantized(value, grid)
+    }
+
+    /// The index of the multiple of the grid nearest to the value
+    /// (saturating for large values); `None` for NaN.
+    pub fn bucket (&self) -> Option<i64> {
+        let v = self.0.to_f64() / self.1.to_f64();
+        if v.is_nan() { None } else { Some(v.round() as i64) }
+    }
+
+    /// The multiple of the grid nearest to the value (NaN for NaN).
+    pub fn quantized (&self) -> F {
+        match self.bucket() {
+            Some(b) => F::from_f64(b as f64 * self.1.to_f64()),
+            None => self.0,
+        }
+    }
+}
+
+impl<F:Float> PartialEq for Quantized<F> {
+    fn eq (&self, other:&Self) -> bool {
+        let eq = self.1.to_f64().to_bits() == other.1.to_f64().to_bits() && self.bucket() == other.bucket();
+        if eq && self.bucket().is_some() && self.0.to_f64().to_bits() != other.0.to_f64().to_bits() {
+            QUANTIZATION.with(|q| { let (hashed, _) = q.get(); q.set((hashed, true)) })
+        };
+        eq
+    }
+}
+
+impl<F:Float> Eq for Quantized<F> { }
+
+impl<F:Float> Hash for Quantized<F> {
+    fn hash<H:Hasher> (&self, state:&mut H) {
+        QUANTIZATION.with(|q| { let (_, inexact) = q.get(); q.set((true, inexact)) });
+        self.1.to_f64().to_bits().hash(state);
+        self.bucket().hash(state)
+    }
+}
+
+/// Identifies the DCG node of an art, regardless of the art's type;
+/// for instance, schedulers can key their outputs by the `ArtKey`s of
+/// the arts that compute them (see `dirty_roots`).
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct ArtKey {
+    loc : Rc<Loc>,
+}
+
+impl ArtKey {
+    /// The (reflected) location of the art.
+    pub fn loc (&self) -> reflect::Loc { self.loc.reflect() }
+
+    /// Parses the canonical string form of a key (see its `Display`
+    /// implementation), as a key of the current DCG; gives `None` for
+    /// other strings, for the `Naive` engine, and for the locations
+    /// that the DCG does not know (e.g., of another engine).
+    pub fn parse (s:&str) -> Option<ArtKey> {
+        let mut cs = s.chars().peekable();
+        let mut names = vec![];
+        let id = loop {
+            if cs.peek() == Some(&'@') {
+                cs.next();
+                let hex : String = cs.by_ref().collect();
+                break ArtId::Structural(u64::from_str_radix(&hex, 16).ok()?)
+            };
+            let n = parse_canonical(&mut cs, true)?;
+            match cs.next() {
+                Some('/') => names.push(n),
+                Some(_) => return None,
+                None => break ArtId::Nominal(n),
+            }
+        };
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                let mut path = st.path.clone();
+                while let Path::Child(ref parent, _) = *path.clone() { path = parent.clone() };
+                let t = st.locs.borrow();
+                for n in names {
+                    path = t.paths.get(&(path_key(&path), n))?.clone()
+                };
+                t.locs.get(&(path_key(&path), id)).map(|loc| ArtKey{loc:loc.clone()})
+            },
+            Engine::Naive => None,
+        })
+    }
+}
+
+/// The canonical string form of a key, which `ArtKey::parse` inverts:
+/// the names of its path (see `Name`), each followed by `/`, and then
+/// its name, or for a structural art, `@` and its hash (in
+/// hexadecimal); e.g., `"ns"/"x"` or `"ns"/@12ab`.  The form is stable
+/// for a given program (and seed; see `Engine::with_seed`), and
+/// within a process, identifies the same art in each DCG.
+impl fmt::Display for ArtKey {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        for n in self.loc.path.reflect() { write!(f, "{}/", n)? };
+        match *self.loc.id {
+            ArtId::Structural(hash) => write!(f, "@{:x}", hash),
+            ArtId::Nominal(ref n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Keys serialize as their canonical string form (see `ArtKey::parse`).
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for ArtKey {
+    fn serialize<S: ::serde::Serializer>(&self, s:S) -> ::std::result::Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for ArtKey {
+    fn deserialize<D: ::serde::Deserializer<'de>>(d:D) -> ::std::result::Result<ArtKey, D::Error> {
+        let s = String::deserialize(d)?;
+        ArtKey::parse(&s).ok_or_else(|| ::serde::de::Error::custom(format!("unknown art key: {}", s)))
+    }
+}
+
+/// The key of the art, if it names a DCG node (arts from `put`, and
+/// those of the `Naive` engine, do not).
+pub fn art_key<T> (a:&Art<T>) -> Option<ArtKey> {
+    match a.art {
+        EnumArt::Loc(ref loc) => Some(ArtKey{loc:loc.clone()}),
+        _ => None,
+    }
+}
+
+/// The roots of the DCG that are stale: the thunks that no thunk
+/// observes, and that have a dirty dependency (hence, a dirty path to
+/// an edited cell), in no particular order.  These are the outputs
+/// that the outer layer forced earlier, and that its edits since (via
+/// `set`) may have changed; a scheduler can re-force exactly these,
+/// e.g., by looking up its arts by `art_key`.  Empty for the `Naive`
+/// engine.
+pub fn dirty_roots () -> Vec<ArtKey> {
+    GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                dcg.borrow().table.iter().filter(|&(_, node)| {
+                    node.succs_def() && node.preds_obs().is_empty() &&
+                        node.succs().iter().any(|succ| succ.dirty)
+                }).map(|(loc, _)| ArtKey{loc:loc.clone()}).collect()
+            },
+            Engine::Naive => vec![],
+        }})
+}
+
 /// Creates an unnamed, immutable reference cell (an eager `Art<_>`)
 /// whose content may not change over time.
 pub fn put<T:Eq+Debug+Clone> (val:T) -> Art<T> {
@@ -2357,8 +5654,37 @@ pub fn put<T:Eq+Debug+Clone> (val:T) -> Art<T> {
 /// From the editor's perspective, this cell is mutable.  From the
 /// archivist's perspective, this cell is a "one-shot" reference cell:
 /// Once allocated, it is immutable.
+///
+/// Re-allocating an existing name overwrites the cell's content (like
+/// `set`, but also from within a thunk); to detect distinct thunks that
+/// overwrite one another's cells, see `Flags::check_write_conflicts`.
+///
+/// Re-allocating a name with another type (as a cell, or as a nominal
+/// `thunk`) replaces its node, as though the allocation were fresh:
+/// the engine dirties each of the node's observers and allocators,
+/// which re-evaluate when demanded.  The arts of the prior type are
+/// stale: forcing one panics, with a dynamic type error.
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use adapton::macros::*;
+/// use adapton::engine::*;
+/// manage::init_dcg();
+///
+/// let n = cell(name_of_str("n"), 21usize);
+/// let t = thunk![ find_cell::<usize>(name_of_str("n")).map(|n| force(&n) * 2) ];
+/// assert_eq!(force(&t), Some(42));
+///
+/// // The thunk observed the cell, so it re-evaluates
+/// let s = cell(name_of_str("n"), String::from("twenty-one"));
+/// assert_eq!(force(&t), None);
+/// assert_eq!(force(&s), "twenty-one");
+/// # drop(n);
+/// # }
+/// ```
 pub fn cell<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T) -> Art<T> {
-    GLOBALS.with(|g| {
+    outer_call(|| GLOBALS.with(|g| {
         match g.borrow().engine {
             Engine::DCG(ref dcg) => {
                 if
@@ -2367,32 +5693,315 @@ pub fn cell<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T) -> Art<T> {
                 else { unreachable!() } }
             Engine::Naive => Art{art:EnumArt::Rc(Rc::new(val))}
         }
-    })
+    }))
 }
 
-/// Mutates a mutable articulation.
-pub fn set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) {
-    match (*a).art {
-        EnumArt::Rc(_)    => { panic!("set: Cannot mutate immutable Rc articulation; use an DCG cell instead") },
-        EnumArt::Force(_) => { panic!("set: Cannot mutate immutable Force articulation; use an DCG cell instead") },
-        EnumArt::Loc(ref l) => {
-            GLOBALS.with(|g| {
-                match g.borrow().engine {
-                    Engine::Naive => unimplemented!(), // TODO: Think more about this case.
-                    Engine::DCG(ref dcg) => {
-                        (dcg.borrow_mut()).set(AbsArt::Loc(l.clone()), val)
-                    }
+/// The cell named `n` in the current namespace, if the DCG has one
+/// with content of type `T` (e.g., allocated earlier by `cell`).  Does
+/// not observe the cell; returns `None` for the `Naive` engine.
+pub fn find_cell<T:Hash+Eq+Debug+Clone+'static> (n:Name) -> Option<Art<T>> {
+    GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                let key = (path_key(&st.path), ArtId::Nominal(n));
+                let loc = match st.locs.borrow().locs.get(&key) { Some(loc) => loc.clone(), None => return None };
+                match st.table.get(&loc) {
+                    Some(node) if ! node.succs_def() && node.res_typeid() == TypeId::of::<T>() =>
+                        Some(Art{art:EnumArt::Loc(loc)}),
+                    _ => None,
                 }
-            })
+            },
+            Engine::Naive => None,
         }
-    }
+    })
 }
 
-/// Allocates a thunk, an `Art<T>` that consists of a suspended
-/// computation that produces a value of type `T`.
-///
-/// Use the macros `thunk!`, `memo!` and `eager!` to create and force
-/// thunks with less typing.
+/// The outcome of `set_report`: Whether the cell's content changed,
+/// and how many DCG edges (transitively) the change dirtied.
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct SetOutcome {
+    /// False when the new value equals the cell's current content; in
+    /// this case, `set` is a no-op and no change propagation occurs.
+    pub changed : bool,
+    /// Number of DCG edges dirtied by the change; when zero, no
+    /// observer will re-evaluate because of it.
+    pub observers_dirtied : usize,
+}
+
+/// An immutable, named articulation, created by `pure_cell`; unlike
+/// an `Art`, there is no way to `set` it.  See `force_pure`.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct PureArt<T> {
+    art:Art<T>,
+}
+
+/// Creates a named, immutable cell, for (large) reference data that
+/// many thunks share; the cell's content never changes.
+///
+/// The DCG stores the cell as a "pure" node: Forcing it creates no
+/// DCG edge, so it needs no bookkeeping of its observers, and change
+/// propagation never visits it.  Re-creating a pure cell (e.g.,
+/// within a re-evaluated thunk) with an equal value is a no-op;
+/// re-creating it with a different value panics, since the engine
+/// could not soundly propagate this change.
+pub fn pure_cell<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T) -> PureArt<T> {
+    GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                wf::check_dcg(st);
+                let loc = loc_of_id(st, current_path(st), ArtId::Nominal(n));
+                check_purity(st, &loc, reflect::trace::AllocKind::RefCell);
+                if st.table.contains_key(&loc) {
+                    match **res_node_of_loc::<T>(st, &loc) {
+                        Node::Pure(ref nd) if nd.val == val => (),
+                        Node::Pure(_) => panic!("pure_cell: cannot change the content of pure cell {}", loc),
+                        _ => panic!("pure_cell: {} is not a pure cell", loc),
+                    }
+                } else {
+                    st.journal(&loc);
+                    st.table.insert(loc.clone(), Box::new(Node::Pure(PureNode{val:val})));
+                };
+                wf::check_dcg(st);
+                PureArt{art:Art{art:EnumArt::Loc(loc)}}
+            },
+            Engine::Naive => PureArt{art:Art{art:EnumArt::Rc(Rc::new(val))}},
+        }
+    })
+}
+
+/// Demands the value of a `PureArt<T>`, returning a (cloned) value of type `T`.
+pub fn force_pure<T:Hash+Eq+Debug+Clone+'static> (a:&PureArt<T>) -> T {
+    force(&a.art)
+}
+
+/// The values of accumulation cells (see `Engine::acc_cell`): an
+/// associative `combine`, with an `identity`.  The integers are
+/// monoids by addition, e.g., for counters.
+pub trait Monoid : Hash+Eq+Debug+Clone+'static {
+    /// The neutral value: combining with it changes nothing.
+    fn identity () -> Self ;
+    /// The combination of `self` and then `other`.
+    fn combine (&self, other:&Self) -> Self ;
+}
+
+macro_rules! monoid_by_addition {
+    ( $( $t:ty ),* ) => { $(
+        impl Monoid for $t {
+            fn identity () -> Self { 0 }
+            fn combine (&self, other:&Self) -> Self { self + other }
+        }
+    )* }
+}
+monoid_by_addition!(usize, u8, u16, u32, u64, isize, i8, i16, i32, i64);
+
+/// A named cell that accumulates the values of a `Monoid`, created by
+/// `Engine::acc_cell`; unlike an `Art`, there is no way to `set` it,
+/// only to add to it, with `acc_add`.  See `force_acc`.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct AccArt<M> {
+    art:Art<M>,
+}
+
+/// Demands the combined value of an `AccArt<M>`, recording the
+/// dependency as `force` does.
+pub fn force_acc<M:Monoid> (a:&AccArt<M>) -> M {
+    force(&a.art)
+}
+
+/// Combines `delta` into the accumulation cell `a`, as an `update` of
+/// its content, and reports the outcome.  When `delta` is the identity,
+/// does nothing: the cell's observers stay clean, and the cell is not
+/// even read.
+///
+/// Panics if called from within a thunk, as `set` does.
+pub fn acc_add<M:Monoid> (a:&AccArt<M>, delta:M) -> SetOutcome {
+    if delta == M::identity() {
+        return SetOutcome{changed:false, observers_dirtied:0}
+    };
+    update(&a.art, |acc| acc.combine(&delta))
+}
+
+/// Creates a named reference cell whose content may refer to the
+/// cell itself (knot-tying), for values with back-references, such as
+/// graphs and ASTs with parent pointers.
+///
+/// Calls `f` with the cell that `cell(n, _)` would return, and then
+/// allocates this cell with the content that `f` produces.  Hence,
+/// outside of `f`, `cell` and `rec_cell` interact exactly as two
+/// allocations at the same name: During change propagation, when a
+/// producer re-defines the cell, its observers are dirtied iff its
+/// content changed (comparing `Art`s, including back-references, by
+/// their names, not their content).
+///
+/// Within `f`, the cell is not yet (re-)defined, so `f` should not
+/// force it: For a fresh name, forcing it panics (a dangling
+/// location); otherwise, forcing it yields the cell's prior content.
+///
+/// Requires the DCG engine, with nominal identities: panics with the
+/// naive engine, whose cells hold their content, so that no cell can
+/// refer to itself; and panics if `ignore_nominal_use_structural` is
+/// set, since then, the cell's identity would depend on its own
+/// content.
+pub fn rec_cell<T:Hash+Eq+Debug+Clone+'static,F:FnOnce(Art<T>) -> T> (n:Name, f:F) -> Art<T> {
+    let this : Art<T> = GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                assert!(!st.flags.ignore_nominal_use_structural,
+                        "rec_cell: cannot tie the knot with structural identities");
+                Art{art:EnumArt::Loc(loc_of_id(st, current_path(st), ArtId::Nominal(n.clone())))}
+            },
+            Engine::Naive => panic!("rec_cell requires the DCG engine"),
+        }
+    });
+    let val = f(this.clone());
+    let art = cell(n, val);
+    assert_eq!(art, this);
+    art
+}
+
+/// Mutates a mutable articulation.
+pub fn set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) {
+    let _ = set_report(a, val);
+}
+
+/// Mutates a mutable articulation, as with `set`, and reports the
+/// outcome, so that calling code can skip scheduling work when nothing
+/// changed.
+pub fn set_report<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) -> SetOutcome {
+    outer_call(|| match (*a).art {
+        EnumArt::Rc(_)    => { panic!("set: Cannot mutate immutable Rc articulation; use an DCG cell instead") },
+        EnumArt::Force(_) => { panic!("set: Cannot mutate immutable Force articulation; use an DCG cell instead") },
+        EnumArt::Loc(ref l) => {
+            GLOBALS.with(|g| {
+                match g.borrow().engine {
+                    Engine::Naive => { check_engine_live(l); unimplemented!() }, // TODO: Think more about this case.
+                    Engine::DCG(ref dcg) => {
+                        (dcg.borrow_mut()).set(AbsArt::Loc(l.clone()), val)
+                    }
+                }
+            })
+        }
+    })
+}
+
+/// Performs `body` as `writer`: the cells that it writes (by `set`,
+/// `update`, `cell`, and so on) record `writer` as their last writer
+/// (see `Engine::last_writer`), and the traces of its writes give
+/// `writer` (see `reflect::trace::Effect::Write`).  When several
+/// subsystems (or collaborators) edit the same inputs, this says which
+/// one wrote each cell's current content.
+///
+/// Only the outer layer has writers: the cells that thunks write
+/// record none.  Nested calls override the writer, until they return.
+///
+/// ```
+/// use adapton::engine::*;
+/// manage::init_dcg();
+///
+/// let c = cell(name_of_str("c"), 1);
+/// assert_eq!(Engine::last_writer(&c), None);
+/// with_writer(name_of_str("alice"), || set(&c, 2));
+/// with_writer(name_of_str("bob"), || {
+///     set(&c, 2); // Unchanged: Alice wrote the content
+///     assert_eq!(Engine::last_writer(&c), Some(name_of_str("alice")));
+///     set(&c, 3)
+/// });
+/// assert_eq!(Engine::last_writer(&c), Some(name_of_str("bob")));
+/// set(&c, 4);
+/// assert_eq!(Engine::last_writer(&c), None);
+/// ```
+pub fn with_writer<T,F:FnOnce() -> T> (writer:Name, body:F) -> T {
+    let swap = |writer:Option<Name>| GLOBALS.with(|g| match g.borrow().engine {
+        Engine::DCG(ref dcg) => replace(&mut dcg.borrow_mut().writer, writer),
+        Engine::Naive => None,
+    });
+    /// Restores the prior writer, even when `body` panics.
+    struct Restore<F:Fn(Option<Name>) -> Option<Name>> { prior:Option<Name>, swap:F }
+    impl<F:Fn(Option<Name>) -> Option<Name>> Drop for Restore<F> {
+        fn drop (&mut self) { (self.swap)(self.prior.take()); }
+    }
+    let _restore = Restore{prior:swap(Some(writer)), swap:swap};
+    body()
+}
+
+/// Mutates a mutable articulation with the result of `f` on its
+/// current content; as with `set_report`, reports the outcome (when
+/// `f` gives an equal value, nothing changes).
+///
+/// Reads the current content without observing it: Unlike `force`,
+/// creates no `Observe` edge.
+pub fn update<T:'static+Eq+Debug+Clone,F:FnOnce(&T) -> T> (a:&Art<T>, f:F) -> SetOutcome {
+    outer_call(|| {
+        let val = f(&peek_cell(a, "update"));
+        set_report(a, val)
+    })
+}
+
+/// Mutates a mutable articulation, as with `set_report`, but only if
+/// its current content equals `expected`; otherwise, gives its current
+/// content as an error.
+///
+/// Reads the current content without observing it: Unlike `force`,
+/// creates no `Observe` edge.
+pub fn compare_and_set<T:'static+Eq+Debug+Clone> (a:&Art<T>, expected:&T, val:T) -> ::std::result::Result<SetOutcome, T> {
+    outer_call(|| {
+        let current = peek_cell(a, "compare_and_set");
+        if &current == expected {
+            Ok(set_report(a, val))
+        } else {
+            Err(current)
+        }
+    })
+}
+
+/// The current content of a mutable articulation, without observing it.
+fn peek_cell<T:'static+Eq+Debug+Clone> (a:&Art<T>, op:&'static str) -> T {
+    match (*a).art {
+        EnumArt::Rc(_)    => { panic!("{}: Cannot mutate immutable Rc articulation; use an DCG cell instead", op) },
+        EnumArt::Force(_) => { panic!("{}: Cannot mutate immutable Force articulation; use an DCG cell instead", op) },
+        EnumArt::Loc(ref l) => {
+            GLOBALS.with(|g| {
+                match g.borrow().engine {
+                    Engine::Naive => unimplemented!(), // TODO: Think more about this case.
+                    Engine::DCG(ref dcg) => {
+                        // An uninitialized lazy cell: initialize it, without observing it
+                        let init = {
+                            let st : &mut DCG = &mut *dcg.borrow_mut();
+                            match lazy_cell_to_mut::<T>(st, l) {
+                                Some(false) => match **res_node_of_loc::<T>(st, l) {
+                                    Node::Comp(ref nd) => Some(nd.producer.copy()),
+                                    _ => unreachable!(),
+                                },
+                                _ => None,
+                            }
+                        };
+                        if let Some(init) = init {
+                            let val = init.produce();
+                            let st : &mut DCG = &mut *dcg.borrow_mut();
+                            **res_node_of_loc::<T>(st, l) = Node::Mut(MutNode{preds:vec![], val:val.clone(), change:ChangePolicy::Eq, writer:None});
+                            return val
+                        };
+                        let st : &mut DCG = &mut *dcg.borrow_mut();
+                        match **res_node_of_loc::<T>(st, l) {
+                            Node::Mut(ref nd) => return nd.val.clone(),
+                            _ => (),
+                        };
+                        panic!("{}: {} is not a cell\n{}", op, l, string_of_demand_stack(&st.stack))
+                    }
+                }
+            })
+        }
+    }
+}
+
+/// Allocates a thunk, an `Art<T>` that consists of a suspended
+/// computation that produces a value of type `T`.
+///
+/// Use the macros `thunk!`, `memo!` and `eager!` to create and force
+/// thunks with less typing.
 ///
 /// A full invocation of `thunk` consists of the following:
 ///
@@ -2431,23 +6040,500 @@ pub fn thunk<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+
      arg:Arg, spurious:Spurious)
      -> Art<Res>
 {
-    GLOBALS.with(|g| {
+    outer_call(|| GLOBALS.with(|g| {
         match g.borrow().engine {
-            Engine::DCG(ref dcg) => {
+            Engine::DCG(ref dcg) if id != NameChoice::Naive => {
+                let inline_loc = auto_inline_loc(&*dcg.borrow(), &id, &prog_pt, &arg);
+                if let Some(loc) = inline_loc {
+                    refresh_inlined_node::<Arg,Spurious,Res>(&mut *dcg.borrow_mut(), &loc, &prog_pt, &arg);
+                    let fn_box : Rc<Box< Fn(Arg, Spurious) -> Res >> =
+                        Rc::new(Box::new(move |arg, spurious| {
+                            let start = testing::now();
+                            let res = fn_box(arg, spurious);
+                            record_cost(&loc, testing::now() - start);
+                            res
+                        }));
+                    return Art{art:EnumArt::Force(
+                        Rc::new(NaiveThunk{
+                            id:NameChoice::Naive,prog_pt:prog_pt,
+                            fn_box:fn_box,arg:arg,
+                            spurious:spurious} ))}
+                };
                 Art{art:EnumArt::Loc({
                     if let AbsArt::Loc(loc) =
                         (dcg.borrow_mut()).thunk(id, prog_pt, fn_box, arg, spurious)
                     { loc } else { unreachable!() }})}
             },
-            Engine::Naive => {
+            // The Naive engine, or a Naive thunk in the DCG engine
+            // (whose effects the DCG attributes to the forcing thunk).
+            _ => {
                 Art{art:EnumArt::Force(
                     Rc::new(NaiveThunk{
                         id:id,prog_pt:prog_pt,
                         fn_box:fn_box,arg:arg,
                         spurious:spurious} ))}}}
+    }))
+}
+
+/// For automatic granularity control: The location of the thunk that
+/// the DCG should inline rather than memoize, if any (see
+/// `manage::set_auto_inline` and `manage::compact_chains`).
+fn auto_inline_loc<Arg:Hash> (st:&DCG, id:&NameChoice, prog_pt:&ProgPt, arg:&Arg) -> Option<Rc<Loc>> {
+    if st.flags.auto_inline_below.is_none() && st.merged.0.is_empty() { return None };
+    let art_id = match *id {
+        NameChoice::Nominal(ref nm) if ! st.flags.ignore_nominal_use_structural
+            => ArtId::Nominal(nm.clone()),
+        NameChoice::Nominal(_) | NameChoice::Structural
+            => ArtId::Structural(seeded_hash(st, &(prog_pt, arg))),
+        NameChoice::Eager | NameChoice::Naive => return None,
+    };
+    let loc = loc_of_id(st, current_path(st), art_id);
+    if st.merged.0.contains_key(&loc) { return Some(loc) };
+    match (st.flags.auto_inline_below, st.costs.get(&loc)) {
+        (Some(threshold), Some(cost)) if *cost < threshold => Some(loc),
+        _ => None,
+    }
+}
+
+/// For automatic granularity control: Brings the node of a thunk that
+/// the DCG inlines (see `auto_inline_loc`) up to date, if it has one
+/// (viz., when it was memoized before): as for a nominal
+/// re-allocation, a new argument replaces the node's argument, clears
+/// its cached result, and dirties the edges to it.  Hence, the arts
+/// that still name the node (e.g., in the results of its observers, or
+/// in the outer layer) do not force stale results.
+fn refresh_inlined_node<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (st:&mut DCG, loc:&Rc<Loc>, prog_pt:&ProgPt, arg:&Arg)
+{
+    if st.merged.0.contains_key(loc) || ! st.table.contains_key(loc) || is_retyped::<Res>(st, loc) { return };
+    let changed = match **res_node_of_loc::<Res>(st, loc) {
+        Node::Comp(ref mut nd) if nd.producer.prog_pt().eq(prog_pt) => {
+            let app : &mut Box<App<Arg,Spurious,Res>> = unsafe { transmute::<_,_>( &mut nd.producer ) };
+            if app.get_arg() == *arg { false } else {
+                app.consume(arg.clone());
+                nd.res = None;
+                true
+            }
+        },
+        _ => false,
+    };
+    if changed { let _ = dirty_alloc(st, loc); }
+}
+
+/// For automatic granularity control: Record the execution time of an
+/// inlined thunk (and count the forces of merged thunks).
+fn record_cost (loc:&Rc<Loc>, cost:Duration) {
+    GLOBALS.with(|g| {
+        if let Engine::DCG(ref dcg) = g.borrow().engine {
+            let st = &mut *dcg.borrow_mut();
+            if st.merged.0.contains_key(loc) { st.merged.1.inlined += 1; unlink_merged(st, loc) };
+            if st.flags.auto_inline_below.is_some() { st.costs.insert(loc.clone(), cost); }
+        }
     })
 }
 
+/// Removes the edges of the DCG node of the merged thunk at `loc`,
+/// once its observer no longer observes it (viz., after the observer
+/// re-evaluated, forcing the thunk within its own evaluation), and
+/// likewise, of the merged thunks beneath it: so that changes no
+/// longer propagate through these nodes.
+fn unlink_merged (st:&mut DCG, loc:&Rc<Loc>) {
+    let mut todo = vec![loc.clone()];
+    while let Some(loc) = todo.pop() {
+        let unobserved = match st.table.get(&loc) {
+            Some(node) => node.succs_def() && node.preds_obs().is_empty() && ! node.succs().is_empty(),
+            None => false,
+        };
+        if unobserved {
+            let succs = lookup_abs(st, &loc).succs_replace(vec![]);
+            revoke_succs(st, &loc, &succs);
+            lookup_abs(st, &loc).res_clear();
+            todo.extend(succs.into_iter().map(|succ| succ.loc).filter(|loc| st.merged.0.contains_key(loc)));
+        }
+    }
+}
+
+/// The chains of pass-through thunks that the DCG merges into the
+/// thunks that observe them (see `manage::compact_chains`).
+#[derive(Debug,Clone,Default,Hash,PartialEq,Eq)]
+pub struct CompactionReport {
+    /// Number of chains: each is a sequence of merged thunks, beneath
+    /// a thunk that is not merged (its head)
+    pub chains : usize,
+    /// Number of merged thunks
+    pub merged : usize,
+    /// Number of DCG edges of the merged thunks, each of which a
+    /// change propagates through, before the merge
+    pub edges : usize,
+    /// Number of forces of merged thunks, each of which the head
+    /// performed within its own evaluation, without a DCG node (and
+    /// without dirtying and cleaning its edges)
+    pub inlined : usize,
+}
+
+/// Allocates a thunk, as with `thunk`, with the given `Purity`.
+///
+/// With `Purity::NoAlloc`, each execution of the thunk's producer by
+/// the DCG engine rejects any cell or thunk allocation that it
+/// performs (directly, not within the thunks that it forces), by
+/// panicking with a `NoAllocViolation` payload.  This helps enforce
+/// that nominal effects stay within designated producers.
+///
+/// With `Purity::Confined`, the engine rejects any allocation that the
+/// producer performs (directly) outside of its sandbox (see `sandbox`),
+/// by panicking with a `ConfinementViolation` payload.  Hence, the
+/// thunks of a library cannot overwrite the cells and thunks that
+/// their callers name.  The `Naive` engine does not check purity.
+pub fn thunk_purity<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (purity:Purity,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    let art = thunk(id, prog_pt, fn_box, arg, spurious);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                match **res_node_of_loc::<Res>(st, loc) {
+                    Node::Comp(ref mut nd) => nd.purity = purity,
+                    _ => unreachable!(),
+                }
+            }
+        })
+    };
+    art
+}
+
+/// A `Cutoff` chooses how the engine compares the results of a thunk
+/// during change propagation (see `thunk_cutoff`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone,Copy)]
+pub enum Cutoff {
+    /// Compare results in full, via `Eq` (the default).
+    Eq,
+    /// Compare the digests (hashes) of results, which the engine
+    /// computes once per evaluation, and stores in the DCG node.  For
+    /// very large results, this is much cheaper than comparing them in
+    /// full (once per observing edge), at the risk of a hash collision
+    /// hiding a change.  See also: `Cnt::digest_cmps`.
+    Digest,
+}
+
+/// Allocates a thunk, as with `thunk`, with the given `Cutoff`.  The
+/// `Naive` engine does not compare results.
+pub fn thunk_cutoff<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (cutoff:Cutoff,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    let art = thunk(id, prog_pt, fn_box, arg, spurious);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                match **res_node_of_loc::<Res>(st, loc) {
+                    Node::Comp(ref mut nd) => {
+                        if nd.cutoff != cutoff { nd.digest = None };
+                        nd.cutoff = cutoff
+                    },
+                    _ => unreachable!(),
+                }
+            }
+        })
+    };
+    art
+}
+
+/// A `ChangePolicy` decides whether a new value of a cell or thunk
+/// changes it, for its observers (see `cell_change` and
+/// `thunk_change`).  Unlike a `Cutoff`, a policy need not agree with
+/// `Eq`: it may treat distinct values as unchanged, e.g., for noisy
+/// numeric inputs, whose small fluctuations should not trigger
+/// re-computation.
+pub enum ChangePolicy<T> {
+    /// Distinct values, via `Eq`, differ (the default).
+    Eq,
+    /// The function, applied to the old and new values, decides
+    /// whether they differ.
+    Changed(Rc<Fn(&T, &T) -> bool>),
+}
+
+impl<T:'static> ChangePolicy<T> {
+    /// Values differ only when their measures (e.g., the numbers
+    /// inside them) differ by more than `epsilon`.
+    pub fn epsilon<F:'static+Fn(&T) -> f64>(epsilon:f64, measure:F) -> ChangePolicy<T> {
+        ChangePolicy::Changed(Rc::new(move |old, new| (measure(old) - measure(new)).abs() > epsilon))
+    }
+}
+
+impl<T:PartialEq> ChangePolicy<T> {
+    /// True if `new` differs from `old`, under this policy.
+    pub fn changed(&self, old:&T, new:&T) -> bool {
+        match *self {
+            ChangePolicy::Eq => old != new,
+            ChangePolicy::Changed(ref changed) => changed(old, new),
+        }
+    }
+}
+
+impl<T> Clone for ChangePolicy<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            ChangePolicy::Eq => ChangePolicy::Eq,
+            ChangePolicy::Changed(ref changed) => ChangePolicy::Changed(changed.clone()),
+        }
+    }
+}
+
+impl<T> Debug for ChangePolicy<T> {
+    fn fmt(&self, f:&mut Formatter) -> Result {
+        match *self {
+            ChangePolicy::Eq => write!(f, "Eq"),
+            ChangePolicy::Changed(_) => write!(f, "Changed(?)"),
+        }
+    }
+}
+
+impl<T> Hash for ChangePolicy<T> {
+    fn hash<H:Hasher>(&self, state:&mut H) {
+        match *self {
+            ChangePolicy::Eq => 0.hash(state),
+            ChangePolicy::Changed(_) => 1.hash(state),
+        }
+    }
+}
+
+/// Allocates a cell, as with `cell`, with the given `ChangePolicy`.
+///
+/// A cell _latches_ its value: `set` (or a re-allocation) with a
+/// value that is unchanged under the policy keeps the old value, and
+/// dirties nothing.  Hence, many small changes, each unchanged on its
+/// own, do not drift from the value that the observers saw; the cell
+/// changes once the new value differs from that (latched) value.  The
+/// `Naive` engine does not compare values.
+pub fn cell_change<T:Hash+Eq+Debug+Clone+'static> (policy:ChangePolicy<T>, n:Name, val:T) -> Art<T> {
+    let art = cell(n, val);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                match **res_node_of_loc::<T>(st, loc) {
+                    Node::Mut(ref mut nd) => nd.change = policy,
+                    _ => (),
+                }
+            }
+        })
+    };
+    art
+}
+
+/// Allocates a thunk, as with `thunk`, with the given `ChangePolicy`.
+///
+/// During change propagation, an observer of the thunk re-evaluates
+/// only if the thunk's new result changed, under the policy, from the
+/// result that this observer saw.  The policy supersedes the thunk's
+/// `Cutoff`.  The `Naive` engine does not compare results.
+pub fn thunk_change<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (policy:ChangePolicy<Res>,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    let art = thunk(id, prog_pt, fn_box, arg, spurious);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                match **res_node_of_loc::<Res>(st, loc) {
+                    Node::Comp(ref mut nd) => nd.change = policy,
+                    _ => unreachable!(),
+                }
+            }
+        })
+    };
+    art
+}
+
+/// The encoding of a thunk result in a spill file (see
+/// `Storage::Disk`).  For other types, encode with a serialization
+/// format of the application's choice (e.g., via `serde`).
+pub trait Spill : Sized {
+    /// Writes the value.
+    fn spill (&self, w:&mut io::Write) -> io::Result<()> ;
+    /// Reads back a value that `spill` wrote.
+    fn reload (r:&mut io::Read) -> io::Result<Self> ;
+}
+
+impl Spill for Vec<u8> {
+    fn spill (&self, w:&mut io::Write) -> io::Result<()> { w.write_all(self) }
+    fn reload (r:&mut io::Read) -> io::Result<Self> {
+        let mut bytes = vec![]; r.read_to_end(&mut bytes)?; Ok(bytes)
+    }
+}
+
+impl Spill for String {
+    fn spill (&self, w:&mut io::Write) -> io::Result<()> { w.write_all(self.as_bytes()) }
+    fn reload (r:&mut io::Read) -> io::Result<Self> {
+        let mut string = String::new(); r.read_to_string(&mut string)?; Ok(string)
+    }
+}
+
+impl<T:Spill> Spill for Rc<T> {
+    fn spill (&self, w:&mut io::Write) -> io::Result<()> { (**self).spill(w) }
+    fn reload (r:&mut io::Read) -> io::Result<Self> { T::reload(r).map(Rc::new) }
+}
+
+/// Where the DCG engine keeps the cached result of a thunk (see
+/// `thunk_storage`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone)]
+pub enum Storage {
+    /// In memory (the default).
+    Memory,
+    /// In a file, in the given directory: After each evaluation, the
+    /// engine writes the result there (see `Spill`), and drops it from
+    /// memory, keeping only its digest (as for `Cutoff::Digest`); its
+    /// observers keep only this digest, too.  Each force reloads the
+    /// result, and drops it again.  When the file is missing, or does
+    /// not match the digest, the thunk re-evaluates.  If writing the
+    /// file fails, the result stays in memory.
+    ///
+    /// Dropping the result (e.g., with `manage::clear_results`, or
+    /// `TrimPolicy::DropResults`) removes the file, as does removing
+    /// the thunk (e.g., with `manage::clear`, or `TrimPolicy::DropAll`),
+    /// or dropping the engine.  The file's name gives the process and
+    /// the engine, so that several may share the directory.  See also
+    /// the counters `Cnt::spills` and `Cnt::reloads`.
+    Disk(PathBuf),
+}
+
+/// Allocates a thunk, as with `thunk`, with the given `Storage` for its
+/// result.  For large results (e.g., rendered assets), `Storage::Disk`
+/// keeps them out of memory between forces.  The `Naive` engine does
+/// not cache results.
+pub fn thunk_storage<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+Spill+'static>
+    (storage:Storage,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    let art = thunk(id, prog_pt, fn_box, arg, spurious);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                let engine = st.id;
+                let spilled = match **res_node_of_loc::<Res>(st, loc) {
+                    Node::Comp(ref mut nd) => match storage {
+                        Storage::Memory => {
+                            if nd.spill.is_some() {
+                                nd.res = nd.cached();
+                                nd.spill = None;
+                                if nd.cutoff != Cutoff::Digest { nd.digest = None }
+                            };
+                            false
+                        },
+                        Storage::Disk(dir) => {
+                            // Distinct engines (and processes) spill to distinct files
+                            let file = dir.join(format!("{}-{}-{:016x}.spill", process::id(), engine, my_hash(&**loc)));
+                            if nd.spill.as_ref().map(|sp| &sp.file.0) != Some(&file) {
+                                nd.res = nd.cached();
+                                if let Some(ref res) = nd.res { nd.digest = Some(my_hash(res)) };
+                                nd.spill = Some(Spiller{file:Rc::new(SpillFile(file)), write:Res::spill, read:Res::reload, on_disk:false});
+                            };
+                            nd.unload()
+                        },
+                    },
+                    _ => unreachable!(),
+                };
+                if spilled { count!(st.cnt.spills += 1) }
+            }
+        })
+    };
+    art
+}
+
+/// A label for a group of thunks that an application measures and
+/// invalidates as a unit, independently of their names and
+/// namespaces; e.g., the thunks of a subsystem (see `thunk_group`,
+/// `manage::group_stats` and `manage::invalidate_group`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone,Copy)]
+pub struct MemoGroup(pub &'static str);
+
+/// The statistics of a `MemoGroup` (see `manage::group_stats`).
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct GroupStats {
+    /// Number of thunks in the group
+    pub thunks : usize,
+    /// Number of these thunks with a cached result
+    pub cached : usize,
+    /// Number of evaluations of the group's thunks (first-time and
+    /// re-evaluations), since the engine was created
+    pub evals  : usize,
+    /// Total time of these evaluations
+    pub time   : Duration,
+}
+
+/// Allocates a thunk, as with `thunk`, in the given `MemoGroup`.  A
+/// thunk belongs to one group, the latest given for its name; the
+/// `Naive` engine ignores groups.
+pub fn thunk_group<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (group:MemoGroup,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    let art = thunk(id, prog_pt, fn_box, arg, spurious);
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let _ = dcg.borrow_mut().groups.insert(loc.clone(), group);
+            }
+        })
+    };
+    art
+}
+
+/// A `MemoPolicy` chooses whether the engine memoizes a thunk (see `thunk_memo`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone)]
+pub enum MemoPolicy {
+    /// The engine memoizes the thunk, as a DCG node (the default).
+    Memo,
+    /// The engine does not memoize the thunk: It creates no DCG node,
+    /// and each force re-runs the thunk's code, in the frame of the
+    /// forcing thunk.  Hence, the forcing (parent) thunk directly
+    /// depends on the dependencies of this thunk (as though "inlined").
+    /// For tiny computations, this is cheaper than tracking them.
+    Transparent,
+}
+
+/// Allocates a thunk, as with `thunk`, with the given `MemoPolicy`.
+/// With `MemoPolicy::Transparent`, this thunk ignores its `id`, as
+/// though it were `NameChoice::Naive`.
+pub fn thunk_memo<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (policy:MemoPolicy,
+     id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box< Fn(Arg, Spurious) -> Res >>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    match policy {
+        MemoPolicy::Memo => thunk(id, prog_pt, fn_box, arg, spurious),
+        MemoPolicy::Transparent => thunk(NameChoice::Naive, prog_pt, fn_box, arg, spurious),
+    }
+}
+
 /// Map a given `thunk` by a mapping function `map_fn`, yielding a new
 /// thunk.
 ///
@@ -2473,30 +6559,651 @@ pub fn thunk_map<Res1:Hash+Eq+Debug+Clone+'static,
     }
     
 }
+
+/// A thunk named `n` that forces `a` and maps its value by `f`; after
+/// a change to `a`, the thunk re-applies `f` only when its value
+/// changed.  Its observers observe the mapped value, so they re-run
+/// only when it changes, too (unlike those of `thunk_map`).
+///
+/// Like other spurious arguments, the engine does not compare `f`:
+/// after re-allocating the name `n` with another function, forcing
+/// the thunk uses the first.
+pub fn art_map<T:Hash+Eq+Debug+Clone+'static,
+               S:Hash+Eq+Debug+Clone+'static,
+               F:Fn(T) -> S+'static>
+    (a:&Art<T>, n:Name, f:F) -> Art<S>
+{
+    thunk(NameChoice::Nominal(n),
+          ProgPt{symbol:"engine::art_map"},
+          Rc::new(Box::new(|a:Art<T>, f:Rc<F>| f(force(&a)))),
+          a.clone(), Rc::new(f))
+}
+
+/// A thunk named `n` that forces `a` and `b`, and combines their
+/// values by `f`; see `art_map`.
+pub fn art_zip<T:Hash+Eq+Debug+Clone+'static,
+               U:Hash+Eq+Debug+Clone+'static,
+               S:Hash+Eq+Debug+Clone+'static,
+               F:Fn(T, U) -> S+'static>
+    (a:&Art<T>, b:&Art<U>, n:Name, f:F) -> Art<S>
+{
+    thunk(NameChoice::Nominal(n),
+          ProgPt{symbol:"engine::art_zip"},
+          Rc::new(Box::new(|(a, b):(Art<T>, Art<U>), f:Rc<F>| f(force(&a), force(&b)))),
+          (a.clone(), b.clone()), Rc::new(f))
+}
     
-/// Demands and observes the value of an `&Art<T>`, returning a (cloned) value of type `T`.
-pub fn force<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> T {
-    match a.art {
-        EnumArt::Force(ref f) => f.force(),
-        EnumArt::Rc(ref rc) => (&**rc).clone(),
-        EnumArt::Loc(ref loc) => {
-            GLOBALS.with(|g| {
-                match g.borrow().engine {
-                    Engine::DCG(ref dcg_refcell) =>
-                        <DCG as Adapton>::force(dcg_refcell, &AbsArt::Loc(loc.clone()), None),
-                    Engine::Naive => panic!("cannot force a non-naive location with the naive engine")
-                }})
-        }
-    }
+/// A sink: a thunk named `n` that observes `a` and performs an
+/// external effect `f` with its value (e.g., writing a file, or
+/// sending a message), at most once per change of that value.
+///
+/// The engine remembers (the hash of) the value that each sink last
+/// emitted, and skips `f` when a re-evaluation of the sink observes the
+/// same value again (e.g., after `manage::clear_results`).  Like other
+/// thunks, sinks run when forced; `manage::flush_sinks` forces all of
+/// them, e.g., after each round of edits.  Like other spurious
+/// arguments, the engine does not compare `f` (see `art_map`).
+pub fn sink<T:Hash+Eq+Debug+Clone+'static,
+            F:Fn(T)+'static>
+    (n:Name, a:&Art<T>, f:F) -> Art<()>
+{
+    let art = thunk(NameChoice::Nominal(n),
+                    ProgPt{symbol:"engine::sink"},
+                    Rc::new(Box::new(|a:Art<T>, f:Rc<F>| {
+                        let val = force(&a);
+                        if sink_should_emit(my_hash(&val)) { f(val) }
+                    })),
+                    a.clone(), Rc::new(f));
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                dcg.borrow_mut().sinks.entry(loc.clone()).or_insert(None);
+            }
+        })
+    };
+    art
 }
 
-/// Demands and observes the value of an `&Art<T>`, returning a
-/// (cloned) value of type `T`, or the value of `cycle_out` if the
-/// force edge forms a cycle in the DCG.
+/// Within the producer of a sink: true (and records the value's
+/// `hash`) if the sink has not yet emitted this value.
+fn sink_should_emit(hash:u64) -> bool {
+    GLOBALS.with(|g| {
+        match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                let loc = get_top_stack_loc(st).unwrap();
+                let last = st.sinks.entry(loc).or_insert(None);
+                if *last == Some(hash) { false } else {
+                    *last = Some(hash);
+                    count!(st.cnt.effects += 1);
+                    true
+                }
+            },
+            Engine::Naive => true,
+        }})
+}
+
+/// A small user label for a DCG edge, for provenance (see `force_labeled`).
+pub type EdgeLabel = &'static str;
+
+/// Labels the latest edge (with the given effect and target) of the
+/// currently-executing thunk, if any.
+fn label_last_succ<T> (a:&Art<T>, eff:Effect, label:EdgeLabel) {
+    if let EnumArt::Loc(ref loc) = a.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                if let Some(frame) = st.stack.last_mut() {
+                    for &mut (ref mut succ, _) in frame.succs.iter_mut().rev() {
+                        if &succ.loc == loc && succ.effect == eff {
+                            succ.label = Some(label);
+                            break
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// A key of a user annotation of a DCG node (see `annotate`).
+pub type AnnotationKey = &'static str;
+
+/// Annotates the DCG node of `a` with the given key and value (e.g.,
+/// the region of a document that a node computes, or the build rule
+/// that it runs), replacing any prior value for this key.
+///
+/// Unlike the node's result, its annotations persist when the node
+/// re-evaluates, or when its thunk is re-allocated; they go away with
+/// the node itself (see `manage::clear` and `manage::trim_ns`).  They
+/// appear in reflected DCGs (see `reflect::DCG::annotations`), in the
+/// DCG's dot output, and, as debugging effects, in reflected traces.
+/// No effect for the `Naive` engine, or for arts without a DCG node.
+pub fn annotate<T> (a:&Art<T>, key:AnnotationKey, value:String) {
+    if let EnumArt::Loc(ref loc) = a.art {
+        let msg = format!("annotate {:?}: {}: {}", loc, key, value);
+        let annotated = GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                let annots = st.annotations.entry(loc.clone()).or_insert(vec![]);
+                match annots.iter().position(|&(k, _)| k == key) {
+                    Some(i) => annots[i].1 = value,
+                    None => annots.push((key, value)),
+                };
+                true
+            },
+            Engine::Naive => false,
+        });
+        if annotated { debug_effect(None, Some(msg)) }
+    }
+}
+
+/// The annotations of the DCG node of `a`, in the order of their
+/// keys' first annotations (see `annotate`).
+pub fn annotations<T> (a:&Art<T>) -> Vec<(AnnotationKey, String)> {
+    match a.art {
+        EnumArt::Loc(ref loc) => GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => dcg.borrow().annotations.get(loc).cloned().unwrap_or(vec![]),
+            Engine::Naive => vec![],
+        }),
+        _ => vec![],
+    }
+}
+
+/// Like `force`, but labels the resulting observation edge of the DCG
+/// with `label`.  Labels permit large applications to attribute
+/// dependencies to features or subsystems; they appear in reflected
+/// traces and DCGs (see `reflect::Succ`), and in the DCG's dot output.
+pub fn force_labeled<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>, label:EdgeLabel) -> T {
+    let res = force(a);
+    label_last_succ(a, Effect::Observe, label);
+    res
+}
+
+/// Like `cell`, but labels the resulting allocation edge of the DCG
+/// with `label` (see `force_labeled`).
+pub fn cell_labeled<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T, label:EdgeLabel) -> Art<T> {
+    let art = cell(n, val);
+    label_last_succ(&art, Effect::Allocate, label);
+    art
+}
+
+/// The work of one force, by `force_with_metrics`: the deltas of
+/// the engine's counters (see `Cnt`) during the force, and its time.
+/// A re-evaluation forces (and checks) its thunks again, so a thunk
+/// may count as visited more than once.
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct PropagationMetrics {
+    /// Number of thunks whose cached results the force checked (see `Cnt::visit`)
+    pub visited : usize,
+    /// Number of thunk evaluations (first-time and re-evaluations; see `Cnt::eval`)
+    pub reruns  : usize,
+    /// Number of dirty DCG edges cleaned, without re-evaluation (see `Cnt::clean`)
+    pub cleaned : usize,
+    /// Number of re-evaluations whose equal result stopped the
+    /// propagation of a change (see `Cnt::cutoff`)
+    pub cutoffs : usize,
+    /// The time of the force, by the engine's clock (see `testing::now`)
+    pub time    : Duration,
+}
+
+/// Forces `a`, as with `force`, and gives the work of this force,
+/// without tracing (see `reflect::dcg_reflect_begin`): e.g., to
+/// assert the cost of a change, or to adapt the granularity of an
+/// incremental program to it.  The counts require the `counters`
+/// feature (for the `Naive` engine, they are zero).
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use adapton::macros::*;
+/// use adapton::engine::*;
+/// manage::init_dcg();
+///
+/// let c = cell(name_of_str("c"), 1usize);
+/// let parity = { let c = c.clone(); thunk![ get!(c) % 2 ] };
+/// let t = { let parity = parity.clone(); thunk![ get!(parity) * 10 ] };
+/// let (res, m) = force_with_metrics(&t);
+/// assert_eq!(res, 10);
+/// if cfg!(feature = "counters") {
+///     assert_eq!(m.reruns, 2);
+/// }
+///
+/// // The parity re-runs, and is unchanged: its observer does not re-run
+/// set(&c, 3);
+/// let (res, m) = force_with_metrics(&t);
+/// assert_eq!(res, 10);
+/// if cfg!(feature = "counters") {
+///     assert_eq!((m.visited, m.reruns, m.cutoffs, m.cleaned), (2, 1, 1, 1));
+/// }
+/// # }
+/// ```
+pub fn force_with_metrics<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> (T, PropagationMetrics) {
+    let start = testing::now();
+    let before = manage::counters().unwrap_or_default();
+    let res = force(a);
+    let after = manage::counters().unwrap_or_default();
+    (res, PropagationMetrics{
+        visited : after.visit.saturating_sub(before.visit),
+        reruns  : after.eval.saturating_sub(before.eval),
+        cleaned : after.clean.saturating_sub(before.clean),
+        cutoffs : after.cutoff.saturating_sub(before.cutoff),
+        time    : testing::now() - start,
+    })
+}
+
+/// Demands and observes the value of an `&Art<T>`, returning a (cloned) value of type `T`.
+pub fn force<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> T {
+    outer_call(|| match a.art {
+        EnumArt::Force(ref f) => f.force(),
+        EnumArt::Rc(ref rc) => (&**rc).clone(),
+        EnumArt::Loc(ref loc) => {
+            let res = GLOBALS.with(|g| {
+                match g.borrow().engine {
+                    Engine::DCG(ref dcg_refcell) =>
+                        <DCG as Adapton>::force(dcg_refcell, &AbsArt::Loc(loc.clone()), None),
+                    Engine::Naive => { check_engine_live(loc);
+                                       panic!("cannot force a non-naive location with the naive engine") }
+                }});
+            let due = GLOBALS.with(|g| match g.borrow().engine {
+                Engine::DCG(ref dcg) => {
+                    let st = &mut *dcg.borrow_mut();
+                    if st.stack.is_empty() { st.epoch.1 += 1 };
+                    st.stack.is_empty() && ! st.invariants.checking && ! st.invariants.edits.is_empty()
+                },
+                Engine::Naive => false,
+            });
+            if due { let _ = manage::check_invariants(); };
+            res
+        }
+    })
+}
+
+/// Forces `a`, as with `force`, except if its engine was dropped, or
+/// is poisoned (including by this force; see `Flags::poison_on_panic`):
+/// then, returns the `EngineError`, rather than panicking.
+pub fn try_force<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> ::std::result::Result<T, EngineError> {
+    if let EnumArt::Loc(ref loc) = a.art {
+        if let Some(gone) = engine_gone(loc) { return Err(EngineError::Gone(gone)) }
+    };
+    poison_guard(|| force(a)).or_else(|payload| match engine_error(payload) {
+        Ok(err) => Err(err),
+        Err(payload) => resume_unwind(payload),
+    })
+}
+
+/// Mutates `a`, as with `set_report`, except if its engine was
+/// dropped, or is poisoned (as with `try_force`).
+pub fn try_set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) -> ::std::result::Result<SetOutcome, EngineError> {
+    if let EnumArt::Loc(ref loc) = a.art {
+        if let Some(gone) = engine_gone(loc) { return Err(EngineError::Gone(gone)) }
+    };
+    poison_guard(|| set_report(a, val)).or_else(|payload| match engine_error(payload) {
+        Ok(err) => Err(err),
+        Err(payload) => resume_unwind(payload),
+    })
+}
+
+/// An invariant: a thunk named `n` that checks a property of the
+/// program's (incremental) state, returning false when it fails.
+///
+/// After the outer layer changes cells, the engine re-forces every
+/// invariant at the end of the next force from the outer layer (or
+/// at `manage::check_invariants`); as thunks, the invariants only
+/// re-evaluate when their dependencies changed.  The engine records
+/// each violation, with its provenance (see `InvariantViolation`), for
+/// `manage::take_invariant_violations`, and calls the hook of
+/// `manage::set_invariant_hook`, if any.  Like other spurious
+/// arguments, the engine does not compare `f` (see `art_map`).
+pub fn invariant<F:Fn() -> bool+'static> (n:Name, f:F) -> Art<bool> {
+    let art = thunk(NameChoice::Nominal(n.clone()),
+                    ProgPt{symbol:"engine::invariant"},
+                    Rc::new(Box::new(|(), f:Rc<F>| f())),
+                    (), Rc::new(f));
+    if let EnumArt::Loc(ref loc) = art.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg) = g.borrow().engine {
+                let st = &mut *dcg.borrow_mut();
+                if ! st.invariants.locs.iter().any(|&(ref l, _)| l == loc) {
+                    st.invariants.locs.push((loc.clone(), n))
+                }
+            }
+        })
+    };
+    art
+}
+
+/// Demands and observes the value of an `&Art<T>`, returning a
+/// (cloned) value of type `T`, or the value of `cycle_out` if the
+/// force edge forms a cycle in the DCG.
 ///
 /// Note that if the articulation `a` is not a thunk, then `cycle_out`
 /// will not be used (cycles in the DCG are formed by thunks forcing
 /// one another, not by reference cells).
+/// Demands the value of an `Art`: Like `force`, cleans (or produces)
+/// it, but records no edge, even from within a producer.  Hence,
+/// schedulers can pre-warm values (e.g., on a background budget),
+/// without making the current thunk depend on them; afterwards,
+/// `read` the values cheaply (e.g., during rendering).
+pub fn demand<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) {
+    match a.art {
+        EnumArt::Force(ref f) => { let _ = f.force(); },
+        EnumArt::Rc(_) => (),
+        EnumArt::Loc(ref loc) => {
+            GLOBALS.with(|g| {
+                match g.borrow().engine {
+                    Engine::DCG(ref dcg_refcell) => {
+                        // Demand from the outer layer, so that its force records
+                        // no edge (and has its own propagation budget)
+                        let (stack, prop) = {
+                            let st = &mut *dcg_refcell.borrow_mut();
+                            if st.stack.iter().any(|frame| &frame.loc == loc) {
+                                panic!("demand: unexpected cycle detected in DCG, at {}\n{}",
+                                       loc, string_of_demand_stack(&st.stack))
+                            };
+                            (replace(&mut st.stack, vec![]), st.prop.clone())
+                        };
+                        let _ : T = <DCG as Adapton>::force(dcg_refcell, &AbsArt::Loc(loc.clone()), None);
+                        let st = &mut *dcg_refcell.borrow_mut();
+                        st.stack = stack;
+                        st.prop = prop;
+                    },
+                    Engine::Naive => { check_engine_live(loc);
+                                       panic!("cannot force a non-naive location with the naive engine") }
+                }})
+        }
+    }
+}
+
+/// Reads the value of an `Art` that is clean (e.g., after `demand`),
+/// recording an observation, as with `force`; panics if the `Art` may
+/// be stale, instead of re-evaluating it.
+pub fn read<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> T {
+    if let EnumArt::Loc(ref loc) = a.art {
+        GLOBALS.with(|g| {
+            if let Engine::DCG(ref dcg_refcell) = g.borrow().engine {
+                let st = &mut *dcg_refcell.borrow_mut();
+                let is_clean = match **res_node_of_loc::<T>(st, loc) {
+                    Node::Comp(ref nd) => nd.res.is_some() && nd.succs.iter().all(|succ| !succ.dirty),
+                    _ => true,
+                };
+                if !is_clean {
+                    panic!("read: {} is not clean; demand it first\n{}", loc, string_of_demand_stack(&st.stack))
+                }
+            }
+        })
+    };
+    force(a)
+}
+
+/// The outcome of one step of a `Propagation`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum StepResult<T> {
+    /// The step examined (or cleaned) one DCG edge, or descended into,
+    /// or returned from, one dirty thunk.
+    Progress,
+    /// One of the thunk's dependencies changed: The next step
+    /// re-evaluates the thunk at this location (running its code).
+    NeedsProduce(reflect::Loc),
+    /// The art is clean, with this value.
+    Done(T),
+}
+
+/// A thunk under cleaning: its edges (as of the descent into it), and
+/// the next one to examine; `dep` is the edge of its observer (`None`
+/// for the art being cleaned).
+struct CleanFrame {
+    loc   : Rc<Loc>,
+    dep   : Option<Rc<Box<DCGDep>>>,
+    succs : Vec<Succ>,
+    next  : usize,
+    produce : bool,
+}
+
+/// The change propagation that cleans an art, as a resumable state
+/// machine (see `begin_clean`).
+///
+/// Each `step` performs a small, bounded amount of cleaning (except
+/// re-evaluations, which run user code; see `StepResult::NeedsProduce`),
+/// so host applications can interleave propagation with their own
+/// event loop, and inspect its progress (see `depth`).  Dropping an
+/// unfinished propagation cancels it, leaving a consistent DCG: the
+/// engine only marks edges clean once it has checked them.
+///
+/// Steps must occur in the outer layer.  Between steps, editing the
+/// DCG (e.g., with `set`, or by forcing) may make the propagation stale:
+/// afterwards, cancel it and begin a new one.
+pub struct Propagation<T> {
+    art    : Art<T>,
+    frames : Vec<CleanFrame>,
+    started : bool,
+}
+
+/// Begins cleaning `a`; see `Propagation`.  Forcing `a` is equivalent
+/// to stepping its propagation until it is `Done`.
+pub fn begin_clean<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> Propagation<T> {
+    Propagation{art:a.clone(), frames:vec![], started:false}
+}
+
+impl<T:Hash+Eq+Debug+Clone+'static> Propagation<T> {
+    /// The number of thunks that the propagation has descended into,
+    /// but not yet cleaned (zero when not started, or when done).
+    pub fn depth (&self) -> usize { self.frames.len() }
+
+    /// Cancels the propagation; equivalent to dropping it.
+    pub fn cancel (self) { }
+
+    /// Performs the next step of the propagation.  Panics if called
+    /// from within a thunk.
+    pub fn step (&mut self) -> StepResult<T> {
+        let loc = match self.art.art {
+            EnumArt::Loc(ref loc) if ! manage::engine_is_naive() => loc.clone(),
+            _ => return StepResult::Done(force(&self.art)),
+        };
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => self.step_dcg(dcg, loc),
+                Engine::Naive => unreachable!(),
+            }})
+    }
+
+    fn step_dcg (&mut self, g:&RefCell<DCG>, loc:Rc<Loc>) -> StepResult<T> {
+        assert!(g.borrow().stack.is_empty(), "Propagation::step: cannot step from within a thunk");
+        if ! self.started {
+            self.started = true;
+            let st = &mut *g.borrow_mut();
+            st.prop = PropWork::default();
+            let has_res = match **res_node_of_loc::<T>(st, &loc) {
+                Node::Comp(ref nd) => nd.res.is_some(),
+                _ => false,
+            };
+            if has_res {
+                let succs = lookup_abs(st, &loc).succs().clone();
+                self.frames.push(CleanFrame{loc:loc, dep:None, succs:succs, next:0, produce:false});
+                return StepResult::Progress
+            }
+        };
+        let (produce, dirty_succ) = match self.frames.last() {
+            // Nothing (more) to clean: `force` produces the result, if necessary
+            None => return StepResult::Done(force(&self.art)),
+            Some(frame) => {
+                let succ = frame.succs.get(frame.next).map(|succ| {
+                    let st = &mut *g.borrow_mut();
+                    sample_step(st, Some((&frame.loc, &succ.loc)));
+                    let dirty = get_succ_mut(st, &frame.loc, succ.effect.clone(), &succ.loc).dirty;
+                    (succ.clone(), dirty)
+                });
+                (frame.produce, succ)
+            }
+        };
+        if produce {
+            // Re-evaluate the frame's thunk, via the (typed) dependency of its observer
+            let frame = self.frames.pop().unwrap();
+            match frame.dep {
+                None => return StepResult::Done(force(&self.art)),
+                Some(dep) => { let res = dep.clean(g, &frame.loc); return self.returned(g, frame.loc, res.changed) }
+            }
+        };
+        match dirty_succ {
+            Some((_, false)) => {
+                self.frames.last_mut().unwrap().next += 1;
+                StepResult::Progress
+            },
+            Some((ref succ, true)) if g.borrow().merged.0.contains_key(&succ.loc) => {
+                // As in `clean_comp`, the observer of a merged thunk re-evaluates
+                self.returned(g, succ.loc.clone(), true)
+            },
+            Some((succ, true)) => {
+                let descend = succ.effect == Effect::Observe && {
+                    let st = &mut *g.borrow_mut();
+                    lookup_abs(st, &succ.loc).succs_def()
+                };
+                if descend {
+                    let succs = { let st = &mut *g.borrow_mut(); lookup_abs(st, &succ.loc).succs().clone() };
+                    self.frames.push(CleanFrame{loc:succ.loc, dep:Some(succ.dep), succs:succs, next:0, produce:false});
+                    StepResult::Progress
+                } else {
+                    let res = succ.dep.clean(g, &succ.loc);
+                    self.returned(g, succ.loc, res.changed)
+                }
+            },
+            None => {
+                // All edges are clean: So is the frame's thunk; compare
+                // its result to the one that its observer observed
+                let frame = self.frames.pop().unwrap();
+                match frame.dep {
+                    None => StepResult::Done(force(&self.art)),
+                    Some(dep) => { let res = dep.clean(g, &frame.loc); self.returned(g, frame.loc, res.changed) }
+                }
+            },
+        }
+    }
+
+    /// Having cleaned `succ`, the next edge of the top frame: marks the
+    /// edge clean, or when `succ` changed, schedules a re-evaluation.
+    fn returned (&mut self, g:&RefCell<DCG>, succ:Rc<Loc>, changed:bool) -> StepResult<T> {
+        let frame = match self.frames.last_mut() {
+            None => return StepResult::Done(force(&self.art)),
+            Some(frame) => frame,
+        };
+        if changed {
+            frame.produce = true;
+            StepResult::NeedsProduce(frame.loc.reflect())
+        } else {
+            let st = &mut *g.borrow_mut();
+            let effect = frame.succs[frame.next].effect.clone();
+            get_succ_mut(st, &frame.loc, effect, &succ).dirty = false;
+            count!(st.cnt.clean += 1);
+            frame.next += 1;
+            StepResult::Progress
+        }
+    }
+}
+
+/// An art that an `IdleCleaner` keeps clean, and its propagation (if any).
+trait IdleArt {
+    /// Performs the next step of cleaning the art; true when it is clean.
+    fn step (&mut self) -> bool;
+    /// Cancels the propagation in progress, if any.
+    fn cancel (&mut self);
+}
+
+impl<T:Hash+Eq+Debug+Clone+'static> IdleArt for (Art<T>, Option<Propagation<T>>) {
+    fn step (&mut self) -> bool {
+        if self.1.is_none() { self.1 = Some(begin_clean(&self.0)) };
+        match self.1.as_mut().unwrap().step() {
+            StepResult::Done(_) => { self.1 = None; true },
+            StepResult::Progress | StepResult::NeedsProduce(_) => false,
+        }
+    }
+    fn cancel (&mut self) { self.1 = None }
+}
+
+fn dcg_epoch () -> Option<(u64, u64)> {
+    GLOBALS.with(|g| match g.borrow().engine {
+        Engine::DCG(ref dcg) => Some(dcg.borrow().epoch),
+        Engine::Naive => None,
+    })
+}
+
+/// Cleans a set of arts (e.g., the outputs of an interactive
+/// application) in idle time: after changes, each call to `run`
+/// performs a bounded amount of their change propagation (see
+/// `Propagation`), so that the host's event loop remains responsive.
+///
+/// The foreground may take over at any time: when it forces an art
+/// (or changes a cell) between two calls to `run`, the cleaner cancels
+/// its propagation in progress, which may be stale, and begins it
+/// again; after changes, it also re-checks the arts that were already
+/// clean.  Since the foreground's work leaves the DCG consistent, the
+/// cleaner only repeats the checks of edges, not re-evaluations.
+///
+/// The cleaner runs on the same thread as the foreground: the engine
+/// is thread-local (and shares its state via `Rc`), so cleaning on a
+/// background thread would require an engine whose state is `Send`
+/// and `Sync`, which does not exist yet (see the note on `GLOBALS`).
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use std::rc::Rc;
+/// use adapton::macros::*;
+/// use adapton::engine::*;
+/// manage::init_dcg();
+///
+/// let c = cell(name_of_str("c"), 1);
+/// let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+///               Rc::new(Box::new(|c:Art<usize>, ()| force(&c) + 1)), c.clone(), ());
+/// let mut cleaner = IdleCleaner::new();
+/// cleaner.watch(&t);
+/// set(&c, 2);
+/// while ! cleaner.run(1) { /* handle other events */ }
+/// assert_eq!(force(&t), 3); // Already clean: no re-evaluation.
+/// # }
+/// ```
+pub struct IdleCleaner {
+    arts : Vec<Box<IdleArt>>,
+    pending : Vec<usize>, // the arts that may be dirty, in reverse order
+    epoch : Option<(u64, u64)>, // the epoch of the latest step
+}
+
+impl IdleCleaner {
+    /// A cleaner without arts.
+    pub fn new () -> Self {
+        IdleCleaner{arts:vec![], pending:vec![], epoch:None}
+    }
+
+    /// Keeps `a` clean, too.
+    pub fn watch<T:Hash+Eq+Debug+Clone+'static> (&mut self, a:&Art<T>) {
+        self.arts.push(Box::new((a.clone(), None)));
+        self.pending.insert(0, self.arts.len() - 1);
+    }
+
+    /// True when (as of the latest call to `run`) every watched art is clean.
+    pub fn is_idle (&self) -> bool { self.pending.is_empty() }
+
+    /// Performs up to `budget` steps of cleaning; returns true when
+    /// every watched art is clean.  Panics if called from within a
+    /// thunk.
+    pub fn run (&mut self, budget:usize) -> bool {
+        let epoch = dcg_epoch();
+        if self.epoch != epoch {
+            // The foreground took over: start over.
+            if let Some(&i) = self.pending.last() { self.arts[i].cancel() };
+            if self.epoch.map(|e| e.0) != epoch.map(|e| e.0) {
+                self.pending = (0..self.arts.len()).rev().collect();
+            }
+        };
+        for _ in 0..budget {
+            let i = match self.pending.last() { Some(i) => *i, None => break };
+            if self.arts[i].step() { self.pending.pop(); };
+        };
+        // Our own final forces count as forces, so note the epoch afterwards.
+        self.epoch = dcg_epoch();
+        self.pending.is_empty()
+    }
+}
+
 pub fn force_cycle<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>, cycle_out:Option<T>) -> T {
     match a.art {
         EnumArt::Force(ref f) => f.force(),
@@ -2506,7 +7213,8 @@ pub fn force_cycle<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>, cycle_out:Option<T
                 match g.borrow().engine {
                     Engine::DCG(ref dcg_refcell) =>
                         <DCG as Adapton>::force(dcg_refcell, &AbsArt::Loc(loc.clone()), cycle_out),
-                    Engine::Naive => panic!("cannot force a non-naive location with the naive engine")
+                    Engine::Naive => { check_engine_live(loc);
+                                       panic!("cannot force a non-naive location with the naive engine") }
                 }})
         }
     }
@@ -2536,36 +7244,864 @@ pub fn force_map<T:Hash+Eq+Debug+Clone+'static,
                 match g.borrow().engine {
                     Engine::DCG(ref dcg_refcell) =>
                         <DCG as Adapton>::force_map(dcg_refcell, &AbsArt::Loc(loc.clone()), mapf),
-                    Engine::Naive => panic!("cannot force a non-naive location with the naive engine")
+                    Engine::Naive => { check_engine_live(loc);
+                                       panic!("cannot force a non-naive location with the naive engine") }
+                }
+            })
+        }
+    }
+}
+
+/// Demands and observes a projection of the value of an `&Art<T>`:
+/// the part of it that `lens` selects, e.g., a field of a struct.
+///
+/// The observer's edge records the projected value, only; hence,
+/// `set` dirties the observer only when its projection changes, and
+/// observers of the other fields of a wide (configuration) struct do
+/// not re-evaluate.  As with `force_map`, `lens` must be pure.
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use adapton::macros::*;
+/// use adapton::engine::*;
+/// manage::init_dcg();
+///
+/// #[derive(Hash,PartialEq,Eq,Clone,Debug)]
+/// struct Config { width:usize, height:usize, title:String }
+///
+/// let c = cell(name_of_str("config"), Config{width:80, height:24, title:"a".to_string()});
+/// let area = { let c = c.clone(); thunk![[area] force_lens(&c, |c| c.width) * force_lens(&c, |c| c.height)] };
+/// assert_eq!(force(&area), 1920);
+///
+/// // Changing the title does not dirty the area
+/// set(&c, Config{width:80, height:24, title:"b".to_string()});
+/// manage::reset_counters();
+/// assert_eq!(force(&area), 1920);
+/// assert_eq!(manage::counters().unwrap().eval, 0);
+///
+/// set(&c, Config{width:80, height:25, title:"b".to_string()});
+/// assert_eq!(force(&area), 2000);
+/// # }
+/// ```
+pub fn force_lens<T:Hash+Eq+Debug+Clone+'static,
+                  S:Hash+Eq+Debug+Clone+'static,
+                  Lens:Fn(&T) -> S+'static>
+    (a:&Art<T>, lens:Lens) -> S
+{
+    force_map(a, move |_, val:T| lens(&val))
+}
+
+/// Demand & observe arts, through an abstracted mapping function (See `AbsMapFam` trait)
+///
+/// (e.g., interval-based projections, using the abstract domain of intervals)
+pub fn force_abs
+    <Arg:'static+Eq+Debug+Clone+Hash,
+     Abs:'static+Eq+Debug+Clone+Hash,
+     T:'static+Eq+Debug+Clone+Hash,
+     DiffT:'static+Eq+Debug+Clone+Hash,
+     S:'static+Eq+Debug+Clone+Hash>
+    (absmapfam:Box<AbsMapFam<Arg,Abs,T,DiffT,S>>, arg:Arg, a:Art<T>) -> S
+{
+    match a.art {
+        EnumArt::Force(ref f) => absmapfam.map(arg, f.force()),
+        EnumArt::Rc(ref rc) => absmapfam.map(arg, (&**rc).clone()),
+        EnumArt::Loc(ref loc) => {
+            GLOBALS.with(|g| {
+                match g.borrow().engine {
+                    Engine::DCG(ref dcg_refcell) =>
+                        <DCG as Adapton>::force_abs(dcg_refcell, absmapfam, arg, &AbsArt::Loc(loc.clone())),
+                    Engine::Naive => { check_engine_live(loc);
+                                       panic!("cannot force a non-naive location with the naive engine") }
                 }
             })
         }
     }
 }
 
-/// Demand & observe arts, through an abstracted mapping function (See `AbsMapFam` trait)
-///
-/// (e.g., interval-based projections, using the abstract domain of intervals)
-pub fn force_abs
-    <Arg:'static+Eq+Debug+Clone+Hash,
-     Abs:'static+Eq+Debug+Clone+Hash,
-     T:'static+Eq+Debug+Clone+Hash,
-     DiffT:'static+Eq+Debug+Clone+Hash,
-     S:'static+Eq+Debug+Clone+Hash>
-    (absmapfam:Box<AbsMapFam<Arg,Abs,T,DiffT,S>>, arg:Arg, a:Art<T>) -> S
-{
-    match a.art {
-        EnumArt::Force(ref f) => absmapfam.map(arg, f.force()),
-        EnumArt::Rc(ref rc) => absmapfam.map(arg, (&**rc).clone()),
-        EnumArt::Loc(ref loc) => {
-            GLOBALS.with(|g| {
-                match g.borrow().engine {
-                    Engine::DCG(ref dcg_refcell) =>
-                        <DCG as Adapton>::force_abs(dcg_refcell, absmapfam, arg, &AbsArt::Loc(loc.clone())),
-                    Engine::Naive => panic!("cannot force a non-naive location with the naive engine")
-                }
-            })
-        }
+/// A `LoadProfile` presets the capacities and flags of a fresh DCG
+/// for a kind of workload (see `Engine::with_profile`).
+#[derive(Hash,Debug,PartialEq,Eq,Clone,Copy)]
+pub enum LoadProfile {
+    /// An interactive application, with a small DCG (about a thousand
+    /// nodes) and frequent, small edits; keeps the flags of the
+    /// environment (e.g., `ADAPTON_CHECK_DCG`).
+    SmallInteractive,
+    /// A batch job, whose initial run builds a large DCG (about a
+    /// million nodes); pre-sizes the tables, so that this run does not
+    /// rehash them repeatedly, and turns off the flags that inspect
+    /// the entire DCG after each operation (`check_dcg_is_wf`,
+    /// `write_dcg` and `gmlog_dcg`).
+    LargeBatch,
+}
+
+impl DCG {
+    /// Counts the outcome of a nominal thunk allocation, in the
+    /// counters, and in those of the current epoch.
+    #[allow(dead_code)] // without the `counters` feature
+    fn count_nominal<F:Fn(&mut NominalMatches)> (&mut self, count:F) {
+        count(&mut self.cnt.nominal);
+        if self.nominal.0 != self.epoch.0 { self.nominal = (self.epoch.0, NominalMatches::default()) };
+        count(&mut self.nominal.1)
+    }
+
+    /// Keys the hashing of the (empty) DCG by `seed`; see `Engine::with_seed`.
+    fn set_seed (&mut self, seed:u64) {
+        assert!(self.table.is_empty() && self.locs.borrow().locs.is_empty(), "set_seed: the DCG is not empty");
+        self.seed = SeededState(seed);
+        self.table = HashMap::with_hasher(self.seed);
+    }
+
+    /// Pre-sizes the tables for (about) `nodes` nodes, with (about)
+    /// `edges` edges in all.
+    fn reserve (&mut self, nodes:usize, edges:usize) {
+        self.table.reserve(nodes);
+        self.locs.borrow_mut().locs.reserve(nodes);
+        if nodes > 0 { self.frame_capacity = edges / nodes };
+    }
+
+    /// Records the content of the node at `loc` (or its absence) before
+    /// the engine changes it, once per open speculation; see
+    /// `Engine::speculate`.
+    fn journal (&mut self, loc:&Rc<Loc>) {
+        match self.specs.last_mut() {
+            None => (),
+            Some(spec) => if ! spec.nodes.contains_key(loc) {
+                let prior = self.table.get(loc).map(|node| node.copy());
+                spec.nodes.insert(loc.clone(), prior);
+            }
+        }
+    }
+
+    /// Records every node; see `journal`.
+    fn journal_all (&mut self) {
+        if self.specs.is_empty() { return };
+        let locs : Vec<Rc<Loc>> = self.table.keys().cloned().collect();
+        for loc in locs.iter() { self.journal(loc) }
+    }
+}
+
+/// An open speculation: the prior content of the nodes that it
+/// changed (`None` for the nodes that it created), and the prior
+/// state of the other tables (but not the counters, which count the
+/// speculative work too); see `Engine::speculate`.
+#[derive(Debug)]
+struct SpecFrame {
+    id    : usize,
+    nodes : HashMap<Rc<Loc>, Option<Box<GraphNode>>>,
+    costs : HashMap<Rc<Loc>, Duration>,
+    producers : HashMap<u64, Rc<Loc>>,
+    writes : WriteLog,
+    sinks : HashMap<Rc<Loc>, Option<u64>>,
+    reevals : Vec<Reevaluation>,
+    annotations : HashMap<Rc<Loc>, Vec<(AnnotationKey, String)>>,
+    invariants : Invariants,
+    groups : HashMap<Rc<Loc>, MemoGroup>,
+    epoch : (u64, u64),
+    dcg_count : usize,
+    dcg_hash  : u64,
+}
+
+impl SpecFrame {
+    fn new (id:usize, st:&DCG) -> SpecFrame {
+        SpecFrame{
+            id:id,
+            nodes:HashMap::new(),
+            costs:st.costs.clone(),
+            producers:st.producers.clone(),
+            writes:st.writes.clone(),
+            sinks:st.sinks.clone(),
+            reevals:st.reevals.clone(),
+            annotations:st.annotations.clone(),
+            invariants:st.invariants.clone(),
+            groups:st.groups.clone(),
+            epoch:st.epoch,
+            dcg_count:st.dcg_count,
+            dcg_hash:st.dcg_hash,
+        }
+    }
+
+    /// Restores the state of the DCG from before the speculation.
+    fn restore (self, st:&mut DCG) {
+        for (loc, prior) in self.nodes.into_iter() {
+            match prior {
+                Some(node) => { st.table.insert(loc, node); },
+                None => { st.table.remove(&loc); },
+            }
+        }
+        st.costs = self.costs;
+        st.producers = self.producers;
+        st.writes = self.writes;
+        st.sinks = self.sinks;
+        st.reevals = self.reevals;
+        st.annotations = self.annotations;
+        st.invariants = self.invariants;
+        st.groups = self.groups;
+        st.epoch = self.epoch;
+        st.dcg_count = self.dcg_count;
+        st.dcg_hash = self.dcg_hash;
+    }
+}
+
+thread_local!(static SPEC_COUNT: RefCell<usize> = RefCell::new(0));
+
+/// The result of a speculative computation, whose changes to the DCG
+/// are pending; see `Engine::speculate`.  Dropping it discards the
+/// changes.
+#[derive(Debug)]
+pub struct Speculation<R> {
+    id  : usize,
+    engine : usize, // the id of the DCG; see `EngineGone`
+    res : Option<R>,
+}
+
+impl<R> Speculation<R> {
+    /// The result of the speculative computation.
+    pub fn result (&self) -> &R {
+        self.res.as_ref().unwrap()
+    }
+
+    /// Keeps the changes of the speculation (in the enclosing
+    /// speculation, if any); returns its result.
+    ///
+    /// Panics if a speculation that began after this one is still open
+    /// (or with an `EngineGone`, if its engine was dropped).
+    pub fn commit (mut self) -> R {
+        self.end(true);
+        self.res.take().unwrap()
+    }
+
+    /// Discards the changes of the speculation, like dropping it;
+    /// returns its result.
+    ///
+    /// Panics if a speculation that began after this one is still open
+    /// (or with an `EngineGone`, if its engine was dropped).
+    pub fn discard (mut self) -> R {
+        self.end(false);
+        self.res.take().unwrap()
+    }
+
+    /// Ends the speculation (if it has not ended), keeping or
+    /// restoring its changes; panics with an `EngineGone` if its
+    /// engine was dropped.
+    fn end (&mut self, keep:bool) {
+        let id = replace(&mut self.id, 0);
+        if id != 0 && ! LIVE_ENGINES.with(|e| e.borrow().contains(&self.engine)) {
+            panic_any(EngineGone{art:format!("Speculation({})", id), engine:self.engine})
+        };
+        Engine::end_speculation(id, keep)
+    }
+}
+
+impl<R> Drop for Speculation<R> {
+    fn drop (&mut self) {
+        if self.id != 0 && ! LIVE_ENGINES.with(|e| e.borrow().contains(&self.engine)) {
+            // The engine was dropped, and its speculations with it
+        } else if self.id != 0 && ! ::std::thread::panicking() {
+            Engine::end_speculation(self.id, false)
+        } else if self.id != 0 {
+            // Unwinding from the speculative computation: discard its
+            // changes, if it is (still) the innermost speculation.
+            GLOBALS.with(|g| match g.borrow().engine {
+                Engine::DCG(ref dcg) => {
+                    let st = &mut *dcg.borrow_mut();
+                    if st.specs.last().map(|spec| spec.id) == Some(self.id) {
+                        st.stack.clear();
+                        st.path = Rc::new(Path::Empty);
+                        st.specs.pop().unwrap().restore(st)
+                    }
+                },
+                Engine::Naive => (),
+            })
+        }
+    }
+}
+
+/// The order in which `Engine::force_all` forces its roots.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum ForceOrder {
+    /// The order of the roots, as given
+    Demand,
+    /// Dependencies first: each root after the roots that it
+    /// (transitively) observes, per the DCG as of the last evaluations,
+    /// so that its force finds them clean; otherwise, as given
+    Topological,
+}
+
+/// Ends the batch of `Engine::force_all`, even when a force panics.
+struct ForceBatch { outer:bool }
+
+impl Drop for ForceBatch {
+    fn drop (&mut self) {
+        if ! self.outer { return };
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => { dcg.borrow_mut().batch = None },
+            Engine::Naive => (),
+        })
+    }
+}
+
+/// The indices of `roots`, dependencies first; see `ForceOrder::Topological`.
+fn topological_roots(st:&DCG, roots:&Vec<Option<Rc<Loc>>>) -> Vec<usize> {
+    let mut index : HashMap<Rc<Loc>, Vec<usize>> = HashMap::new();
+    for (i, root) in roots.iter().enumerate() {
+        if let Some(ref loc) = *root { index.entry(loc.clone()).or_insert(vec![]).push(i) }
+    }
+    let mut order = vec![];
+    let mut visited : HashSet<Rc<Loc>> = HashSet::new();
+    for (i, root) in roots.iter().enumerate() {
+        let root = match *root { Some(ref loc) => loc.clone(), None => { order.push(i); continue } };
+        if ! visited.insert(root.clone()) { continue };
+        // Depth-first, iteratively: each node, with its next successor
+        let mut stack = vec![(root, 0)];
+        while let Some((loc, next)) = stack.pop() {
+            let succ = match st.table.get(&loc) {
+                Some(node) if node.succs_def() => node.succs().get(next).map(|succ| succ.loc.clone()),
+                _ => None,
+            };
+            match succ {
+                Some(succ) => {
+                    stack.push((loc, next + 1));
+                    if visited.insert(succ.clone()) { stack.push((succ, 0)) }
+                },
+                None => if let Some(is) = index.remove(&loc) { order.extend(is) },
+            }
+        }
+    }
+    order
+}
+
+impl Engine {
+    /// A fresh DCG engine, whose tables are pre-sized for (about)
+    /// `nodes` nodes, with (about) `edges` edges in all; the engine is
+    /// not installed (see `manage::use_engine`).
+    pub fn with_capacity (nodes:usize, edges:usize) -> Engine {
+        let mut dcg = DCG::new();
+        dcg.reserve(nodes, edges);
+        Engine::DCG(RefCell::new(dcg))
+    }
+
+    /// A fresh DCG engine, whose internal hashing is keyed by `seed`:
+    /// the structural identities of its cells and thunks, the hashes of
+    /// its locations, and the order of its table (and hence, its
+    /// `dcg_hash`, and its dot files).  For a fixed seed, these are
+    /// the same across runs, e.g., for reproducible benchmarks.  The
+    /// engine is not installed (see `manage::use_engine`).
+    ///
+    /// The environment variable `ADAPTON_SEED` sets the seed of the
+    /// other engines (by default, zero).  The hashes of names do not
+    /// depend on any engine (see `Name`).
+    pub fn with_seed (seed:u64) -> Engine {
+        let mut dcg = DCG::new();
+        dcg.set_seed(seed);
+        Engine::DCG(RefCell::new(dcg))
+    }
+
+    /// A fresh DCG engine, with the capacities and flags of the given
+    /// profile; the engine is not installed (see `manage::use_engine`).
+    pub fn with_profile (profile:LoadProfile) -> Engine {
+        let mut dcg = DCG::new();
+        match profile {
+            LoadProfile::SmallInteractive => dcg.reserve(1 << 10, 1 << 12),
+            LoadProfile::LargeBatch => {
+                dcg.reserve(1 << 20, 1 << 22);
+                dcg.flags.check_dcg_is_wf = false;
+                dcg.flags.write_dcg = false;
+                dcg.flags.gmlog_dcg = false;
+            },
+        };
+        Engine::DCG(RefCell::new(dcg))
+    }
+
+    /// Runs `body` speculatively, in the current engine: Until the
+    /// returned `Speculation` is committed or dropped, the changes to
+    /// the DCG (of `body`, and of the outer layer afterwards) are
+    /// pending.  Committing keeps them; dropping (or discarding) the
+    /// speculation restores the DCG to its prior state, copying only
+    /// the nodes that the speculation changes.  Speculations nest.
+    ///
+    /// **Outstanding `Art`s**: After discarding, the cells and thunks
+    /// that the speculation allocated are invalid (forcing or setting
+    /// one panics); the others revert to their prior content.
+    ///
+    /// For the `Naive` engine, the speculation has no effect.  Panics
+    /// if called from within a thunk.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    ///
+    /// let c = cell!(2);
+    /// let t = { let c = c.clone(); thunk![ get!(c) * 10 ] };
+    /// assert_eq!(get!(t), 20);
+    ///
+    /// // What if `c` were 3?
+    /// let what_if = Engine::speculate(|| { set(&c, 3); get!(t) });
+    /// assert_eq!(*what_if.result(), 30);
+    /// drop(what_if);
+    /// assert_eq!(get!(c), 2);
+    /// assert_eq!(get!(t), 20);
+    ///
+    /// // Keep it.
+    /// let kept = Engine::speculate(|| { set(&c, 4); get!(t) }).commit();
+    /// assert_eq!(kept, 40);
+    /// assert_eq!(get!(t), 40);
+    /// # }
+    /// ```
+    pub fn speculate<R,F:FnOnce() -> R> (body:F) -> Speculation<R> {
+        let id = SPEC_COUNT.with(|c| { *c.borrow_mut() += 1; *c.borrow() });
+        let engine = GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                assert!(st.stack.is_empty(), "speculate: not permitted while the engine is executing a thunk");
+                let spec = SpecFrame::new(id, st);
+                st.specs.push(spec);
+                Some(st.id)
+            },
+            Engine::Naive => None,
+        });
+        let mut spec = Speculation{id:if engine.is_some() { id } else { 0 }, engine:engine.unwrap_or(0), res:None};
+        spec.res = Some(body());
+        spec
+    }
+
+    /// The thunks that (transitively) observe `a`, in the current
+    /// engine: those that an edit of `a` may affect, e.g., to show
+    /// them to the user before committing the edit.  The direct
+    /// observers of `a` are at depth 1, their observers at depth 2,
+    /// and so on; the result lists those up to `depth`, nearest first,
+    /// each once.  It follows the observations of the DCG as of the
+    /// last evaluations, so it omits the thunks that were never forced
+    /// (and those that an edit would newly lead to observe `a`).
+    /// Empty for the `Naive` engine, and for arts from `put`.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    ///
+    /// let c = cell(name_of_str("c"), 2);
+    /// let double = { let c = c.clone(); thunk![ get!(c) * 2 ] };
+    /// let quad = { let double = double.clone(); thunk![ get!(double) * 2 ] };
+    /// assert_eq!(get!(quad), 8);
+    /// assert_eq!(Engine::dependents(&c, 1), vec![art_key(&double).unwrap()]);
+    /// assert_eq!(Engine::dependents(&c, 2), vec![art_key(&double).unwrap(), art_key(&quad).unwrap()]);
+    /// # }
+    /// ```
+    pub fn dependents<T> (a:&Art<T>, depth:usize) -> Vec<ArtKey> {
+        let loc = match a.art { EnumArt::Loc(ref loc) => loc.clone(), _ => return vec![] };
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                let mut visited = HashSet::new();
+                visited.insert(loc.clone());
+                let mut dependents = vec![];
+                let mut frontier = vec![loc];
+                for _ in 0..depth {
+                    let mut next = vec![];
+                    for loc in frontier.iter() {
+                        let preds = match st.table.get(loc) { Some(node) => node.preds_obs(), None => continue };
+                        for (pred, _) in preds {
+                            if visited.insert(pred.clone()) { next.push(pred) }
+                        }
+                    }
+                    if next.is_empty() { break };
+                    dependents.extend(next.iter().map(|loc| ArtKey{loc:loc.clone()}));
+                    frontier = next;
+                }
+                dependents
+            },
+            Engine::Naive => vec![],
+        })
+    }
+
+    /// Forces each of `roots`, in the current engine, in the given
+    /// `order`; gives their values, in the order of `roots`.  Whereas
+    /// forcing each root separately re-checks (the edges of) the
+    /// thunks that they share, this cleans each shared thunk once:
+    /// during the batch, a force of a thunk that the batch already
+    /// cleaned (or evaluated) compares its result, only.  The counter
+    /// `Cnt::batch_hits` counts these forces.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    ///
+    /// let c = cell(name_of_str("c"), 1usize);
+    /// let base = { let c = c.clone(); thunk![ get!(c) * 10 ] };
+    /// let views : Vec<Art<usize>> = (0..3usize).map(|i| {
+    ///     let base = base.clone(); thunk![ get!(base) + i ]
+    /// }).collect();
+    /// assert_eq!(Engine::force_all(&views, ForceOrder::Demand), vec![10, 11, 12]);
+    ///
+    /// set(&c, 2);
+    /// manage::reset_counters();
+    /// assert_eq!(Engine::force_all(&views, ForceOrder::Topological), vec![20, 21, 22]);
+    /// // Once cleaned, `base` was not cleaned again: the re-evaluations
+    /// // of the views, and the cleaning of the last two, forced it
+    /// if cfg!(feature = "counters") {
+    ///     let cnt = manage::counters().unwrap();
+    ///     assert_eq!((cnt.eval, cnt.batch_hits), (4, 5));
+    /// }
+    /// # }
+    /// ```
+    pub fn force_all<T:Hash+Eq+Debug+Clone+'static> (roots:&[Art<T>], order:ForceOrder) -> Vec<T> {
+        let locs : Vec<Option<Rc<Loc>>> = roots.iter().map(|a| match a.art {
+            EnumArt::Loc(ref loc) => Some(loc.clone()),
+            _ => None,
+        }).collect();
+        let (batch, order) = GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                let outer = st.batch.is_none();
+                if outer { st.batch = Some(HashSet::new()) };
+                let order = match order {
+                    ForceOrder::Demand => (0..roots.len()).collect(),
+                    ForceOrder::Topological => topological_roots(st, &locs),
+                };
+                (ForceBatch{outer:outer}, order)
+            },
+            Engine::Naive => (ForceBatch{outer:false}, (0..roots.len()).collect()),
+        });
+        let mut results : Vec<Option<T>> = vec![None; roots.len()];
+        for i in order { results[i] = Some(force(&roots[i])) };
+        drop(batch);
+        results.into_iter().map(|res| res.unwrap()).collect()
+    }
+
+    /// The art of `key`, in the current DCG, if it has a node of type
+    /// `T` there; `None` otherwise (e.g., for a key of another engine,
+    /// or of a node that the DCG dropped, or of another type, and for
+    /// the `Naive` engine).  With `ArtKey::parse`, this reconstructs
+    /// arts from their keys' string forms, e.g., for handles that
+    /// cross an FFI boundary.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    ///
+    /// let c = ns(name_of_str("inputs"), || cell(name_of_str("c"), 2usize));
+    /// let handle : String = art_key(&c).unwrap().to_string();
+    /// assert_eq!(handle, "\"inputs\"/\"c\"");
+    ///
+    /// let key = ArtKey::parse(&handle).unwrap();
+    /// let c2 : Art<usize> = Engine::art_from_key(key.clone()).unwrap();
+    /// assert_eq!(get!(c2), 2);
+    /// assert!(Engine::art_from_key::<String>(key).is_none());
+    /// # }
+    /// ```
+    pub fn art_from_key<T:'static> (key:ArtKey) -> Option<Art<T>> {
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                if key.loc.engine != st.id { return None };
+                match st.table.get(&key.loc) {
+                    Some(node) if node.res_typeid() == TypeId::of::<T>() => Some(Art{art:EnumArt::Loc(key.loc)}),
+                    _ => None,
+                }
+            },
+            Engine::Naive => None,
+        })
+    }
+
+    /// The current DCG, in a compact textual form: a line for each
+    /// node, with its key (see `ArtKey`), kind and value, and for each
+    /// of its edges, with its effect, target and dirty bit (see
+    /// `testing::DcgText`); `None` for the `Naive` engine.  Unlike
+    /// the dot form (see `manage::dot_of_dcg`), this form parses (see
+    /// `testing::parse_dcg`), so that tests can state expected DCGs.
+    /// It renders the values of registered types, only (see
+    /// `register_type`).  Panics if called from within a thunk.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use adapton::testing::*;
+    /// manage::init_dcg();
+    /// register_type::<usize>();
+    ///
+    /// let c = cell(name_of_str("c"), 2usize);
+    /// let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+    ///               std::rc::Rc::new(Box::new(|c:Art<usize>, ()| get!(c) * 10)), c.clone(), ());
+    /// assert_eq!(get!(t), 20);
+    /// set(&c, 3);
+    /// assert_eq!(Engine::dump_text().unwrap(),
+    ///            "cell \"c\" = 3\nthunk \"t\" = 20\n  force \"c\" dirty\n");
+    /// assert_dcg("
+    ///     cell \"c\" = 3
+    ///     thunk \"t\" = _
+    ///       force \"c\" dirty
+    /// ");
+    /// # }
+    /// ```
+    pub fn dump_text () -> Option<String> {
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                assert!(st.stack.is_empty(), "dump_text: not permitted while the engine is executing a thunk");
+                Some(wf::dcg_text(st).to_string())
+            },
+            Engine::Naive => None,
+        })
+    }
+
+    /// The writer of the current content of the cell `a`, if any (see
+    /// `with_writer`): of its last write that changed its content.
+    /// `None` for a content that a thunk (or no writer) wrote, for
+    /// thunks and arts from `put`, and for the `Naive` engine.
+    pub fn last_writer<T> (a:&Art<T>) -> Option<Name> {
+        let loc = match a.art { EnumArt::Loc(ref loc) => loc.clone(), _ => return None };
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                st.table.get(&loc).and_then(|node| node.writer())
+            },
+            Engine::Naive => None,
+        })
+    }
+
+    /// Allocates the cell named `n`, lazily: the engine computes its
+    /// initial value with `init` when it is first forced (or read by
+    /// `update`), rather than now, and not at all if it is set first.
+    /// When constructing large input graphs, most of whose cells are
+    /// never observed, these cells cost neither the computation nor
+    /// the storage of their values.  Until then, the cell is a thunk
+    /// (see `reflect`); `set` and `cell` make it a cell.
+    ///
+    /// If the current namespace already has a node named `n` (e.g., an
+    /// earlier lazy cell), returns it, as is.  For the `Naive` engine,
+    /// or with `Flags::ignore_nominal_use_structural`, allocates the
+    /// cell eagerly, with `cell`.  Panics if called from within a thunk.
+    ///
+    /// ```
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    ///
+    /// let cells : Vec<Art<Vec<usize>>> = (0..1000).map(|i| {
+    ///     Engine::cell_thunked(name_of_usize(i), move || vec![i; 1000])
+    /// }).collect();
+    /// assert_eq!(force(&cells[7])[0], 7);
+    /// set(&cells[8], vec![]);
+    /// assert_eq!(force(&cells[8]), vec![]);
+    /// // Only the forced cell computed its initial value
+    /// if cfg!(feature = "counters") {
+    ///     assert_eq!(manage::counters().unwrap().eval, 1);
+    /// }
+    /// ```
+    pub fn cell_thunked<T:Hash+Eq+Debug+Clone+'static,F:Fn() -> T+'static> (n:Name, init:F) -> Art<T> {
+        let lazy = GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                assert!(st.stack.is_empty(), "cell_thunked: not permitted while the engine is executing a thunk");
+                if st.flags.ignore_nominal_use_structural { return None };
+                let loc = loc_of_id(st, current_path(st), ArtId::Nominal(n.clone()));
+                Some(if st.table.contains_key(&loc) { Some(loc) } else { None })
+            },
+            Engine::Naive => None,
+        });
+        match lazy {
+            None => cell(n, init()),
+            Some(Some(loc)) => Art{art:EnumArt::Loc(loc)},
+            Some(None) => thunk(NameChoice::Nominal(n), ProgPt{symbol:LAZY_CELL},
+                                Rc::new(Box::new(|(), init:Rc<F>| init())), (), Rc::new(init)),
+        }
+    }
+
+    /// Allocates (or overwrites, as `cell` does) the accumulation cell
+    /// named `n`, with the content `init`: e.g., a counter or a metric,
+    /// to which the outer layer adds deltas with `acc_add`, rather than
+    /// rebuilding (and re-combining) a list of its contributions.  Its
+    /// observers (see `force_acc`) see the combined value; a delta
+    /// that is the identity dirties none of them.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    ///
+    /// let hits : AccArt<usize> = Engine::acc_cell(name_of_str("hits"), 0);
+    /// let report = { let hits = hits.clone(); thunk![ [Some(name_of_str("report"))]? format!("{} hits", force_acc(&hits)) ] };
+    /// acc_add(&hits, 3);
+    /// acc_add(&hits, 4);
+    /// assert_eq!(get!(report), "7 hits");
+    /// assert_eq!(acc_add(&hits, 0).observers_dirtied, 0);
+    /// assert_eq!(acc_add(&hits, 1).observers_dirtied, 1);
+    /// assert_eq!(get!(report), "8 hits");
+    /// # }
+    /// ```
+    pub fn acc_cell<M:Monoid> (n:Name, init:M) -> AccArt<M> {
+        AccArt{art:cell(n, init)}
+    }
+
+    /// Ends the speculation `id`, keeping or restoring its changes.
+    fn end_speculation (id:usize, keep:bool) {
+        if id == 0 { return };
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &mut *dcg.borrow_mut();
+                assert!(st.stack.is_empty(), "speculate: not permitted while the engine is executing a thunk");
+                match st.specs.last() {
+                    Some(spec) if spec.id == id => (),
+                    Some(_) => panic!("speculate: a later speculation is still open"),
+                    None => panic!("speculate: the current engine is not speculating"),
+                };
+                let spec = st.specs.pop().unwrap();
+                if ! keep { spec.restore(st) }
+                else if let Some(outer) = st.specs.last_mut() {
+                    // The outer speculation keeps its own (earlier) records.
+                    for (loc, prior) in spec.nodes.into_iter() {
+                        outer.nodes.entry(loc).or_insert(prior);
+                    }
+                }
+            },
+            Engine::Naive => panic!("speculate: the current engine is not speculating"),
+        })
+    }
+}
+
+thread_local!(static BUILD_COUNT: RefCell<usize> = RefCell::new(0));
+
+/// Builds a DCG engine with a fixed set of declared inputs (e.g.,
+/// configuration fields, or sensor channels): each `declare_cell`
+/// gives a typed handle (an `Input`) for one input cell, and `build`
+/// creates the engine, its input cells and the `Inputs` that holds
+/// them.  The declarations also document the application's inputs
+/// (see `Inputs::declared`), and permit the engine to pre-size its
+/// tables.
+///
+/// ```
+/// # #[macro_use] extern crate adapton;
+/// # fn main() {
+/// use adapton::engine::*;
+///
+/// let mut b = EngineBuilder::new();
+/// let rate : Input<usize> = b.declare_cell(name_of_str("rate"), 10);
+/// let label : Input<String> = b.declare_cell(name_of_str("label"), "hz".to_string());
+/// let (engine, inputs) = b.build();
+/// manage::use_engine(engine);
+/// assert_eq!(force(&inputs.get(&rate)), 10);
+/// inputs.set(&label, "khz".to_string());
+/// assert_eq!(force(&inputs.get(&label)), "khz");
+/// # }
+/// ```
+pub struct EngineBuilder {
+    build : usize,
+    decls : Vec<(Name, &'static str, Box<Fn() -> Rc<Any>>)>,
+    capacity : usize,
+    seed : Option<u64>,
+}
+
+/// A typed handle for an input cell of an `EngineBuilder`; see `Inputs::get`.
+pub struct Input<T> {
+    build : usize,
+    index : usize,
+    name  : Name,
+    typ   : PhantomData<T>,
+}
+
+impl<T> Input<T> {
+    /// The name of the input cell.
+    pub fn name (&self) -> &Name { &self.name }
+}
+
+impl<T> Clone for Input<T> {
+    fn clone (&self) -> Self { Input{build:self.build, index:self.index, name:self.name.clone(), typ:PhantomData} }
+}
+
+impl<T> Debug for Input<T> {
+    fn fmt(&self, f:&mut Formatter) -> Result { write!(f, "Input({:?})", self.name) }
+}
+
+/// The input cells of an engine built by an `EngineBuilder`.
+pub struct Inputs {
+    build : usize,
+    cells : Vec<(Name, &'static str, Rc<Any>)>,
+}
+
+impl Debug for Inputs {
+    fn fmt(&self, f:&mut Formatter) -> Result { write!(f, "Inputs({:?})", self.declared()) }
+}
+
+impl EngineBuilder {
+    /// A builder without declarations.
+    pub fn new () -> Self {
+        let build = BUILD_COUNT.with(|c| { *c.borrow_mut() += 1; *c.borrow() });
+        EngineBuilder{build:build, decls:vec![], capacity:0, seed:None}
+    }
+
+    /// Declares an input cell, named `n`, with initial content
+    /// `default`; returns its typed handle.
+    ///
+    /// Panics if another input already has name `n`.
+    pub fn declare_cell<T:Hash+Eq+Debug+Clone+'static> (&mut self, n:Name, default:T) -> Input<T> {
+        if self.decls.iter().any(|d| d.0 == n) {
+            panic!("EngineBuilder: input {} is already declared", n)
+        };
+        let index = self.decls.len();
+        let nm = n.clone();
+        self.decls.push((n.clone(), ::std::any::type_name::<T>(),
+                         Box::new(move || Rc::new(cell(nm.clone(), default.clone())) as Rc<Any>)));
+        Input{build:self.build, index:index, name:n, typ:PhantomData}
+    }
+
+    /// Pre-sizes the engine's tables for (about) `nodes` nodes, in
+    /// addition to its inputs.
+    pub fn with_capacity (mut self, nodes:usize) -> Self {
+        self.capacity = nodes; self
+    }
+
+    /// Keys the engine's hashing by `seed`; see `Engine::with_seed`.
+    pub fn with_seed (mut self, seed:u64) -> Self {
+        self.seed = Some(seed); self
+    }
+
+    /// Builds a fresh DCG engine, with its input cells; the engine is
+    /// not installed (see `manage::use_engine`).
+    pub fn build (self) -> (Engine, Inputs) {
+        let mut dcg = DCG::new();
+        if let Some(seed) = self.seed { dcg.set_seed(seed) };
+        dcg.reserve(self.capacity + self.decls.len(), 0);
+        let prior = manage::use_engine(Engine::DCG(RefCell::new(dcg)));
+        let cells = self.decls.into_iter().map(|(n, typ, alloc)| (n, typ, alloc())).collect();
+        let engine = manage::use_engine(prior);
+        (engine, Inputs{build:self.build, cells:cells})
+    }
+}
+
+impl Inputs {
+    /// The input cell of the given handle.
+    ///
+    /// Panics if the handle is from another builder.
+    pub fn get<T:Hash+Eq+Debug+Clone+'static> (&self, input:&Input<T>) -> Art<T> {
+        assert!(input.build == self.build, "Inputs: {:?} is from another EngineBuilder", input);
+        self.cells[input.index].2.downcast_ref::<Art<T>>().unwrap().clone()
+    }
+
+    /// Sets the input cell of the given handle to `val` (see `set`).
+    pub fn set<T:Hash+Eq+Debug+Clone+'static> (&self, input:&Input<T>, val:T) {
+        set(&self.get(input), val)
+    }
+
+    /// The declared inputs, in order: their names, and the names of
+    /// their types.
+    pub fn declared (&self) -> Vec<(Name, &'static str)> {
+        self.cells.iter().map(|c| (c.0.clone(), c.1)).collect()
     }
 }
 
@@ -2580,6 +8116,18 @@ pub mod manage {
     /// At a high level, it consists of a data dependence graph (the "demanded computation graph"), and an associated memoization table.
     pub fn init_dcg () -> Engine { init_engine(Engine::DCG(RefCell::new(DCG::new()))) }
 
+    /// Initializes global state with a fresh DCG-based engine that only
+    /// records the dependencies of the thunks that it evaluates (see
+    /// `Flags::record_only`), without the purity optimization, which
+    /// omits edges; returns the old engine.  Its DCG gives the
+    /// dependency graph of the program, e.g., the task graph of a build
+    /// (see `reflect::record_dependencies`).
+    pub fn init_recording () -> Engine {
+        let prior = init_dcg();
+        let _ = set_flags(|f| { f.record_only = true; f.use_purity_optimization = false });
+        prior
+    }
+
     /// Initializes global state with a ("fresh") Naive engine; returns the old engine.
     /// The naive engine is stateless, and performs no memoization and builds no dependence graphs.
     /// (Since the naive engine is stateless, every instance of the naive engine is equivalent to a "fresh" one).
@@ -2600,6 +8148,60 @@ pub mod manage {
         use_engine(engine)
     }
 
+    /// The report of the DCG engine dropped last on this thread, if
+    /// any: the arts of its outer layer that were still outstanding
+    /// (in debug builds), and its open speculations.  A use of such
+    /// an art raises `EngineGone` (or, from `try_force`, returns it).
+    ///
+    /// ```
+    /// use adapton::engine::*;
+    /// manage::init_dcg();
+    /// let c = cell(name_of_str("config"), 1);
+    /// drop(manage::init_dcg());
+    /// let report = manage::engine_drop_report().unwrap();
+    /// if cfg!(debug_assertions) { assert_eq!(report.leaked.len(), 1) };
+    /// match try_force(&c) {
+    ///     Err(EngineError::Gone(gone)) => assert_eq!(gone.engine, report.engine),
+    ///     res => panic!("{:?}", res),
+    /// };
+    /// ```
+    pub fn engine_drop_report () -> Option<EngineDropReport> {
+        DROP_REPORT.with(|r| r.borrow().clone())
+    }
+
+    /// The poisoning of the current engine, if any; see
+    /// `Flags::poison_on_panic`.  A host that serves several documents
+    /// can fail the one whose engine this is, and replace its engine
+    /// (with `init_dcg`), rather than aborting.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::panic;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    /// manage::set_flags(|f| f.poison_on_panic = true);
+    /// let c = cell(name_of_str("den"), 0);
+    /// let t = { let c = c.clone(); thunk!(100 / force(&c)) };
+    /// assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| force(&t))).is_err());
+    /// assert!(manage::poisoned().unwrap().panic.contains("divide by zero"));
+    /// match try_set(&c, 1) {
+    ///     Err(EngineError::Poisoned(_)) => (),
+    ///     res => panic!("{:?}", res),
+    /// };
+    /// drop(manage::init_dcg());
+    /// assert_eq!(manage::poisoned(), None);
+    /// # }
+    /// ```
+    pub fn poisoned () -> Option<EnginePoisoned> {
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => dcg.borrow().poison.clone(),
+            Engine::Naive => None,
+        })
+    }
+
     /// True iff the current engine is `Naive`
     pub fn engine_is_naive () -> bool {
         GLOBALS.with(|g| {
@@ -2617,6 +8219,644 @@ pub mod manage {
                 Engine::Naive  => false
             }})
     }
+
+    fn with_dcg<T,F:FnOnce(&mut DCG) -> T> (op:&str, f:F) -> Option<T> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => {
+                    let st = &mut *dcg.borrow_mut();
+                    assert!(st.stack.is_empty(), "{}: not permitted while the engine is executing a thunk", op);
+                    Some(f(st))
+                },
+                Engine::Naive => None
+            }})
+    }
+
+    /// Drops every node of the current DCG, keeping its flags and counters.
+    ///
+    /// **Outstanding `Art`s**: Every cell and thunk allocated before
+    /// the call is invalid afterwards; forcing or setting one panics
+    /// (with a "dangling pointer" error).  Arts that do not name a DCG
+    /// node (e.g., those produced by `put`) remain valid.  To keep
+    /// cells valid, use `clear_results` instead.
+    ///
+    /// No effect for the `Naive` engine; panics if called from within a thunk.
+    pub fn clear () {
+        with_dcg("clear", |st| {
+            let all : HashSet<Rc<Loc>> = st.table.keys().cloned().collect();
+            remove_nodes(st, &all);
+            st.writes = WriteLog::default();
+            st.invariants.edits.clear();
+            st.path = Rc::new(Path::Empty);
+            let (stats, outer) = { let t = &mut *st.locs.borrow_mut(); (t.stats.clone(), replace(&mut t.outer, HashMap::new())) };
+            *st.locs.borrow_mut() = LocTable{stats:stats, outer:outer, ..LocTable::default()};
+        });
+    }
+
+    /// The statistics of the given group, in the current DCG (or `None`
+    /// for the `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn group_stats (group:MemoGroup) -> Option<GroupStats> {
+        with_dcg("group_stats", |st| {
+            let (evals, time) = st.group_work.get(&group).cloned().unwrap_or((0, Duration::new(0, 0)));
+            let mut stats = GroupStats{evals:evals, time:time, ..GroupStats::default()};
+            for (loc, _) in st.groups.iter().filter(|&(_, g)| *g == group) {
+                stats.thunks += 1;
+                if st.table[loc].res_any().is_some() { stats.cached += 1 }
+            }
+            stats
+        })
+    }
+
+    /// Drops the cached results of the thunks of the given group, and
+    /// dirties their observers; returns the number of these thunks.
+    /// Afterwards, demanding an observer re-evaluates the group's
+    /// thunks that it uses, and then the observer itself only if their
+    /// results changed.
+    ///
+    /// **Outstanding `Art`s**: All remain valid.
+    ///
+    /// No effect for the `Naive` engine; panics if called from within a thunk.
+    pub fn invalidate_group (group:MemoGroup) -> Option<usize> {
+        with_dcg("invalidate_group", |st| {
+            let locs : Vec<Rc<Loc>> = st.groups.iter()
+                .filter(|&(_, g)| *g == group).map(|(loc, _)| loc.clone()).collect();
+            for loc in locs.iter() {
+                let _ = dirty_pred_observers(st, loc);
+                lookup_abs(st, loc).res_clear();
+            }
+            locs.len()
+        })
+    }
+
+    /// Drops the cached results of every thunk in the current DCG,
+    /// keeping all nodes, edges, flags and counters.  Afterwards,
+    /// forcing a thunk re-evaluates it (a full re-computation).
+    ///
+    /// **Outstanding `Art`s**: All remain valid; cells keep their values.
+    ///
+    /// No effect for the `Naive` engine; panics if called from within a thunk.
+    pub fn clear_results () {
+        with_dcg("clear_results", |st| {
+            st.journal_all();
+            for (_, node) in st.table.iter_mut() {
+                node.res_clear()
+            }
+        });
+    }
+
+    /// Sets (or with `None`, unsets) the threshold for automatic
+    /// granularity control; returns the prior threshold.
+    ///
+    /// When set, the DCG engine measures the execution time of each
+    /// thunk, and when a thunk is (re-)allocated, it stops memoizing it
+    /// if its last-measured time falls below the threshold: Instead,
+    /// the thunk behaves as one with `MemoPolicy::Transparent`.  The
+    /// engine continues to measure such inlined thunks, and memoizes
+    /// them again when they become expensive.  (The measured time of a
+    /// thunk includes that of the thunks that it forces.)  When a
+    /// memoized thunk becomes inlined, its DCG node gets the argument of
+    /// each later allocation, so that the arts of the node stay
+    /// consistent.
+    ///
+    /// The environment variable `ADAPTON_AUTO_INLINE_NS` sets an initial
+    /// threshold, in nanoseconds.  No effect for the `Naive` engine.
+    pub fn set_auto_inline (threshold:Option<Duration>) -> Option<Duration> {
+        with_dcg("set_auto_inline", |st| replace(&mut st.flags.auto_inline_below, threshold)).unwrap_or(None)
+    }
+
+    /// Graph compaction: Merges the chains of pass-through thunks of
+    /// the current DCG into the thunks that observe them; returns the
+    /// chains of this pass (or `None` for the `Naive` engine).
+    ///
+    /// A pass-through thunk observes a single node (and allocates no
+    /// other), and a single thunk allocates and observes it: e.g.,
+    /// the intermediate thunks of a pipeline of combinators.  The
+    /// merge takes effect after a change beneath a chain: rather than
+    /// cleaning the chain, the engine re-evaluates its head, where the
+    /// allocation of each merged thunk gives a thunk that the head
+    /// forces within its own evaluation, as with `set_auto_inline`.
+    /// Thereafter, the head's DCG node observes the bottom of the
+    /// chain, directly, and the nodes of the merged thunks have no
+    /// edges.  Thunks with a `Cutoff`, `ChangePolicy` or `Storage`
+    /// other than the default are not merged, since they cut off
+    /// changes.
+    ///
+    /// The merge is reversible: see `uncompact_chains`.  The report of
+    /// `compaction_report` measures its savings.
+    ///
+    /// Panics if called from within a thunk.
+    pub fn compact_chains () -> Option<CompactionReport> {
+        with_dcg("compact_chains", |st| {
+            let mut merged : HashMap<Rc<Loc>, Rc<Loc>> = HashMap::new();
+            let mut edges = 0;
+            for (loc, node) in st.table.iter() {
+                if ! node.is_pass_through() || st.sinks.contains_key(loc) || st.merged.0.contains_key(loc) { continue };
+                let (obs, alloc) = (node.preds_obs(), node.preds_alloc());
+                let observer = match obs.first() { Some(pred) => pred.0.clone(), None => continue };
+                if obs.len() != 1 || alloc.is_empty() || alloc.iter().any(|pred| pred != &observer) { continue };
+                edges += 1 + obs.len() + alloc.len();
+                merged.insert(loc.clone(), observer);
+            }
+            let chains = merged.values().filter(|observer| ! merged.contains_key(*observer)).count();
+            let report = CompactionReport{chains:chains, merged:merged.len(), edges:edges, inlined:0};
+            st.merged.1.chains += report.chains;
+            st.merged.1.merged += report.merged;
+            st.merged.1.edges  += report.edges;
+            st.merged.0.extend(merged);
+            report
+        })
+    }
+
+    /// Reverses `compact_chains`: the DCG memoizes the merged thunks
+    /// again, as their observers re-evaluate; returns the number of
+    /// thunks that were merged (or `None` for the `Naive` engine), and
+    /// resets the `compaction_report`.
+    ///
+    /// Panics if called from within a thunk.
+    pub fn uncompact_chains () -> Option<usize> {
+        with_dcg("uncompact_chains", |st| {
+            let merged = st.merged.0.len();
+            st.merged = (HashMap::new(), CompactionReport::default());
+            merged
+        })
+    }
+
+    /// The chains merged by `compact_chains` since the DCG was created
+    /// (or since `uncompact_chains`), and the forces of merged thunks
+    /// that their observers performed since then (or `None` for the
+    /// `Naive` engine).
+    pub fn compaction_report () -> Option<CompactionReport> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => Some(dcg.borrow().merged.1.clone()),
+                Engine::Naive => None
+            }})
+    }
+
+    /// Changes the flags of the current DCG via `f`; returns the prior
+    /// flags (or `None` for the `Naive` engine).
+    ///
+    /// The initial flags come from environment variables (e.g.,
+    /// `ADAPTON_CHECK_DCG`, `ADAPTON_WRITE_DCG`); this function permits
+    /// changing them mid-run, while keeping their checks sound:
+    ///
+    /// - Enabling `write_dcg` writes the current DCG immediately, as
+    ///   the first of its numbered snapshots.
+    ///
+    /// - Enabling `check_dcg_is_wf` checks the entire DCG immediately,
+    ///   so that later checks do not assume well-formedness of a DCG
+    ///   that was never checked.
+    ///
+    /// - Changing `ignore_nominal_use_structural` changes how the
+    ///   engine identifies allocations, so it panics unless the DCG is
+    ///   empty (see `clear`).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn set_flags<F:FnOnce(&mut Flags)> (f:F) -> Option<Flags> {
+        with_dcg("set_flags", |st| {
+            let prior = st.flags.clone();
+            f(&mut st.flags);
+            if st.flags.ignore_nominal_use_structural != prior.ignore_nominal_use_structural
+                && ! st.table.is_empty()
+            {
+                st.flags = prior;
+                panic!("set_flags: cannot change `ignore_nominal_use_structural` of a non-empty DCG")
+            };
+            if st.flags.write_dcg && ! prior.write_dcg {
+                st.dcg_hash = seeded_hash(st, format!("{:?}",st.table));
+                let dcg_count = st.dcg_count;
+                st.dcg_count += 1;
+                wf::write_next_dcg(st, Some(dcg_count));
+            };
+            if st.flags.check_dcg_is_wf && ! prior.check_dcg_is_wf {
+                wf::check_dcg(st)
+            };
+            prior
+        })
+    }
+
+    /// Adds the namespace `prefix` to the allowlist of
+    /// `Flags::strict_naming`: structural thunks are permitted beneath
+    /// it (e.g., within a library whose combinators allocate
+    /// structurally).  No effect for the `Naive` engine.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    /// manage::set_flags(|f| f.strict_naming = true);
+    ///
+    /// let double = |n:usize| thunk(NameChoice::Structural, prog_pt!("double"),
+    ///                              Rc::new(Box::new(|n:usize, ()| n * 2)), n, ());
+    /// manage::allow_structural_in(vec![name_of_str("lib")]);
+    /// assert_eq!(ns(name_of_str("lib"), || force(&double(2))), 4);
+    ///
+    /// let res = std::panic::catch_unwind(|| ns(name_of_str("app"), || double(1)));
+    /// assert!(res.unwrap_err().downcast_ref::<NamingViolation>().is_some());
+    /// # }
+    /// ```
+    pub fn allow_structural_in (prefix:reflect::Path) {
+        set_flags(|f| if ! f.structural_namespaces.contains(&prefix) { f.structural_namespaces.push(prefix) });
+    }
+
+    /// Checks that the current DCG is well-formed, once, as
+    /// `Flags::check_dcg_is_wf` does after each operation; panics if it
+    /// is not.  Returns `None` for the `Naive` engine.
+    ///
+    /// Panics if called from within a thunk.
+    pub fn check_dcg_is_wf () -> Option<()> {
+        with_dcg("check_dcg_is_wf", |st| wf::check_wf(st))
+    }
+
+    /// Sets the limits on the change propagation of each force from the
+    /// outer layer (see `PropagationBudget`); returns the prior limits
+    /// (or `None` for the `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn set_propagation_budget (budget:PropagationBudget) -> Option<PropagationBudget> {
+        with_dcg("set_propagation_budget", |st| replace(&mut st.flags.propagation_budget, budget))
+    }
+
+    /// Sets how change propagation treats allocation edges (see
+    /// `AllocPolicy`); returns the prior policy (or `None` for the
+    /// `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn set_alloc_policy (policy:AllocPolicy) -> Option<AllocPolicy> {
+        with_dcg("set_alloc_policy", |st| replace(&mut st.flags.alloc_policy, policy))
+    }
+
+    /// Trims the nodes of the current DCG in namespace `prefix` (or one
+    /// nested within it; see `ns`), according to
+    /// `policy`; returns the number of nodes trimmed (or `None` for the
+    /// `Naive` engine).  For instance, an application can bound the
+    /// cache footprint of a closed document by trimming the namespace
+    /// where it computes over that document, without a full `clear`.
+    ///
+    /// **Outstanding `Art`s**: With `DropResults`, all remain valid.
+    /// With the other policies, the arts of the dropped nodes are
+    /// invalid (forcing or setting one panics) until a thunk allocates
+    /// them anew; with `DropAll`, this includes the cells beneath the
+    /// namespace.
+    ///
+    /// Panics if called from within a thunk.
+    pub fn trim_ns (prefix:reflect::Path, policy:TrimPolicy) -> Option<usize> {
+        with_dcg("trim_ns", |st| {
+            let beneath : HashSet<Rc<Loc>> = st.table.keys()
+                .filter(|loc| name::path_is_prefix_of(&prefix, &loc.path.reflect())).cloned().collect();
+            let drop : HashSet<Rc<Loc>> = match policy {
+                TrimPolicy::DropResults => {
+                    let thunks = beneath.iter().filter(|loc| st.table[*loc].succs_def()).cloned().collect::<Vec<_>>();
+                    for loc in thunks.iter() {
+                        lookup_abs(st, loc).res_clear()
+                    }
+                    return thunks.len()
+                },
+                TrimPolicy::DropAll => beneath,
+                TrimPolicy::DropCleanNodes => {
+                    let mut drop : HashSet<Rc<Loc>> = beneath.into_iter().filter(|loc| {
+                        let node = &st.table[loc];
+                        node.succs_def() && node.succs().iter().all(|succ| ! succ.dirty)
+                    }).collect();
+                    // Keep the nodes that a kept node uses, until none remain
+                    loop {
+                        let used : Vec<Rc<Loc>> = drop.iter().filter(|loc| {
+                            let node = &st.table[*loc];
+                            node.preds_alloc().into_iter().chain(node.preds_obs().into_iter().map(|(pred, _)| pred))
+                                .any(|pred| ! drop.contains(&pred))
+                        }).cloned().collect();
+                        if used.is_empty() { break drop };
+                        for loc in used.iter() { drop.remove(loc); }
+                    }
+                },
+            };
+            // Invalidate the users of the dropped nodes (for `DropAll`)
+            let mut users = HashSet::new();
+            for loc in drop.iter() {
+                let node = &st.table[loc];
+                for pred in node.preds_alloc().into_iter().chain(node.preds_obs().into_iter().map(|(pred, _)| pred)) {
+                    if ! drop.contains(&pred) { users.insert(pred); }
+                }
+            }
+            for user in users.iter() {
+                // The observers of a user observed its cached result
+                let _ = dirty_alloc(st, user);
+                let succs = lookup_abs(st, user).succs_replace(vec![]);
+                lookup_abs(st, user).res_clear();
+                let succs = succs.into_iter().filter(|succ| ! drop.contains(&succ.loc)).collect();
+                revoke_succs(st, user, &succs);
+            }
+            remove_nodes(st, &drop);
+            drop.len()
+        })
+    }
+
+    /// Sets the filter for the dot files that the engine writes when
+    /// `Flags::write_dcg` holds (see `DotFilter`); returns the prior
+    /// filter (or `None` for the `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn set_dot_filter (filter:DotFilter) -> Option<DotFilter> {
+        with_dcg("set_dot_filter", |st| replace(&mut st.flags.dot_filter, filter))
+    }
+
+    /// The current DCG in dot format, filtered by `filter` (or `None`
+    /// for the `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn dot_of_dcg (filter:&DotFilter) -> Option<String> {
+        with_dcg("dot_of_dcg", |st| {
+            let mut out = vec![];
+            wf::write_dcg_dot(st, filter, &mut out);
+            String::from_utf8(out).unwrap()
+        })
+    }
+
+    /// Forces every invariant of the current DCG (see `invariant`);
+    /// returns the number of violations (or `None` for the `Naive`
+    /// engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn check_invariants () -> Option<usize> {
+        let invs = with_dcg("check_invariants", |st| {
+            st.invariants.checking = true;
+            let edits = replace(&mut st.invariants.edits, vec![]);
+            (st.invariants.locs.clone(), edits.reflect(), st.invariants.hook.clone())
+        });
+        invs.map(|(invs, edits, hook)| {
+            let mut count = 0;
+            for (loc, name) in invs {
+                let inv : Art<bool> = Art{art:EnumArt::Loc(loc.clone())};
+                if force(&inv) { continue };
+                let violation = GLOBALS.with(|g| match g.borrow().engine {
+                    Engine::DCG(ref dcg) => {
+                        let st = &mut *dcg.borrow_mut();
+                        let observed = lookup_abs(st, &loc).succs().reflect();
+                        InvariantViolation{name:name, edits:edits.clone(), observed:observed}
+                    },
+                    Engine::Naive => unreachable!(),
+                });
+                if let Some(ref hook) = hook { hook(&violation) };
+                let _ = with_dcg("check_invariants", |st| st.invariants.violations.push(violation));
+                count += 1;
+            }
+            let _ = with_dcg("check_invariants", |st| st.invariants.checking = false);
+            count
+        })
+    }
+
+    /// Returns (and forgets) the violations of the invariants so far,
+    /// in order (see `invariant`); empty for the `Naive` engine.
+    pub fn take_invariant_violations () -> Vec<InvariantViolation> {
+        with_dcg("take_invariant_violations", |st| replace(&mut st.invariants.violations, vec![]))
+            .unwrap_or(vec![])
+    }
+
+    /// Returns (and forgets) the re-evaluations of change propagation
+    /// so far, in order, with their causes (see
+    /// `Flags::record_reevaluations`, which is off by default); empty
+    /// for the `Naive` engine.
+    pub fn take_reevaluations () -> Vec<Reevaluation> {
+        with_dcg("take_reevaluations", |st| replace(&mut st.reevals, vec![]))
+            .unwrap_or(vec![])
+    }
+
+    /// Returns (and forgets) the nondeterministic producers found so
+    /// far, in order (see `Flags::verify_every`, which is off by
+    /// default); empty for the `Naive` engine.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    /// manage::set_flags(|f| f.verify_every = Some(1));
+    ///
+    /// thread_local!(static TICKS: Cell<usize> = Cell::new(0));
+    /// let c = cell!(2);
+    /// let stable = { let c = c.clone(); thunk![ [Some(name_of_str("stable"))]? get!(c) * 10 ] };
+    /// let clock = thunk![ [Some(name_of_str("clock"))]? TICKS.with(|t| { t.set(t.get() + 1); t.get() }) ];
+    /// assert_eq!(get!(stable), 20);
+    /// assert_eq!(get!(clock), 1);
+    /// assert!(manage::take_nondeterminism().is_empty());
+    ///
+    /// // The cache hits re-evaluate; the clock's result differs.
+    /// assert_eq!(get!(stable), 20);
+    /// assert_eq!(get!(clock), 1);
+    /// let found = manage::take_nondeterminism();
+    /// assert_eq!(found.len(), 1);
+    /// assert!(found[0].prog_pt.symbol.contains("TICKS"));
+    /// # }
+    /// ```
+    pub fn take_nondeterminism () -> Vec<Nondeterminism> {
+        with_dcg("take_nondeterminism", |st| replace(&mut st.verifier.2, vec![]))
+            .unwrap_or(vec![])
+    }
+
+    /// Sets (or with `None`, unsets) the hook that the engine calls with
+    /// each violation of an invariant, as it finds it; returns the prior
+    /// hook (or `None` for the `Naive` engine).  Unlike the hooks of
+    /// `on_dirty`, this hook runs in the outer layer, so it may call the
+    /// engine.
+    pub fn set_invariant_hook (hook:Option<Rc<Fn(&InvariantViolation)>>) -> Option<Rc<Fn(&InvariantViolation)>> {
+        with_dcg("set_invariant_hook", |st| replace(&mut st.invariants.hook, hook)).unwrap_or(None)
+    }
+
+    /// Forces every sink of the current DCG (see `sink`), performing
+    /// the effects of those whose values changed; returns the number
+    /// of effects performed (or `None` for the `Naive` engine).
+    ///
+    /// Panics if called from within a thunk.
+    pub fn flush_sinks () -> Option<usize> {
+        let sinks = with_dcg("flush_sinks", |st| st.sinks.clone());
+        sinks.map(|sinks| {
+            let mut effects = 0;
+            for (loc, last) in sinks {
+                let sink : Art<()> = Art{art:EnumArt::Loc(loc.clone())};
+                demand(&sink);
+                let now = with_dcg("flush_sinks", |st| st.sinks.get(&loc).cloned()).unwrap();
+                if now != Some(last) { effects += 1 };
+            }
+            effects
+        })
+    }
+
+    /// Statistics for the table of interned locations of the current
+    /// DCG (or `None` for the `Naive` engine).  Each allocation of a
+    /// cell or thunk is either a hit (reusing the location of a prior
+    /// allocation in the same namespace) or a miss.
+    pub fn loc_intern_stats () -> Option<LocInternStats> {
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => Some(dcg.borrow().locs.borrow().stats.clone()),
+            Engine::Naive => None,
+        })
+    }
+
+    /// The size of the current DCG (or `None` for the `Naive` engine):
+    /// e.g., to compare the memory that different naming strategies
+    /// cost (see `granularity`).
+    pub fn footprint () -> Option<Footprint> {
+        use std::mem::{size_of, size_of_val};
+        GLOBALS.with(|g| match g.borrow().engine {
+            Engine::DCG(ref dcg) => {
+                let st = &*dcg.borrow();
+                let mut fp = Footprint::default();
+                for node in st.table.values() {
+                    let edges = if node.succs_def() { node.succs().len() } else { 0 };
+                    fp.nodes += 1;
+                    fp.edges += edges;
+                    // Each edge is a succ of its source, and a pred of its target.
+                    fp.bytes += size_of::<Rc<Loc>>() + size_of::<Loc>() + size_of::<Box<GraphNode>>() + size_of_val(&**node)
+                        + edges * (size_of::<Succ>() + size_of::<Pred>());
+                }
+                Some(fp)
+            },
+            Engine::Naive => None,
+        })
+    }
+
+    /// Reclaims the interned locations and paths that neither the DCG
+    /// nor any `Art` uses anymore (e.g., after `trim_ns`, or for the
+    /// structural thunks of replaced inputs); returns the number
+    /// reclaimed (or `None` for the `Naive` engine).  Without this
+    /// call, the table of interned locations only grows.
+    ///
+    /// Panics if called from within a thunk.
+    pub fn loc_intern_gc () -> Option<usize> {
+        with_dcg("loc_intern_gc", |st| {
+            let t = &mut *st.locs.borrow_mut();
+            let before = t.locs.len() + t.paths.len();
+            t.locs.retain(|_, loc| Rc::strong_count(loc) > 1);
+            t.outer.retain(|_, loc| loc.upgrade().is_some());
+            // Child paths hold their parents, so reclaim from the leaves up.
+            loop {
+                let len = t.paths.len();
+                t.paths.retain(|_, path| Rc::strong_count(path) > 1);
+                if t.paths.len() == len { break }
+            }
+            let reclaimed = before - t.locs.len() - t.paths.len();
+            t.stats.reclaimed += reclaimed;
+            reclaimed
+        })
+    }
+
+    /// Resets the counters of the current DCG to zero; returns their
+    /// prior values (or `None` for the `Naive` engine).
+    pub fn reset_counters () -> Option<Cnt> {
+        with_dcg("reset_counters", |st| replace(&mut st.cnt, Cnt::default()))
+    }
+
+    /// Sets the counters of the current DCG to `cnt`; returns their
+    /// prior values (or `None` for the `Naive` engine).
+    pub fn set_counters (cnt:Cnt) -> Option<Cnt> {
+        with_dcg("set_counters", |st| replace(&mut st.cnt, cnt))
+    }
+
+    /// The current counters of the current DCG (or `None` for the `Naive` engine).
+    pub fn counters () -> Option<Cnt> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => Some(dcg.borrow().cnt.clone()),
+                Engine::Naive => None
+            }})
+    }
+
+    /// The seed of the current DCG (or `None` for the `Naive` engine);
+    /// see `Engine::with_seed`.
+    pub fn seed () -> Option<u64> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => Some(dcg.borrow().seed.0),
+                Engine::Naive => None
+            }})
+    }
+
+    /// The samples of the current DCG so far (or `None` for the
+    /// `Naive` engine); empty unless `Flags::sample_every` is set.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    /// manage::set_flags(|f| f.sample_every = Some(1));
+    ///
+    /// let c = cell(name_of_str("c"), 1);
+    /// let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("double"),
+    ///               Rc::new(Box::new(|c:Art<usize>, ()| force(&c) * 2)), c.clone(), ());
+    /// assert_eq!(force(&t), 2);
+    /// // Once as it began, and once as it forced `c`
+    /// let stats = manage::sample_stats().unwrap();
+    /// assert_eq!(stats.produce.get(&prog_pt!("double")), Some(&2));
+    /// set(&c, 2);
+    /// assert_eq!(force(&t), 4);
+    /// assert_eq!(manage::sample_stats().unwrap().clean.len(), 1);
+    /// # }
+    /// ```
+    pub fn sample_stats () -> Option<SampleStats> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => Some(dcg.borrow().sampler.1.clone()),
+                Engine::Naive => None
+            }})
+    }
+
+    /// Resets the samples of the current DCG; returns the prior ones
+    /// (or `None` for the `Naive` engine).  Panics if called from
+    /// within a thunk.
+    pub fn reset_sample_stats () -> Option<SampleStats> {
+        with_dcg("reset_sample_stats", |st| replace(&mut st.sampler.1, SampleStats::default()))
+    }
+
+    /// The outcomes of the allocations of nominal thunks in the
+    /// current epoch, with its number (or `None` for the `Naive`
+    /// engine).  An epoch begins at each change of a cell by the outer
+    /// layer, so these are the matches of the change propagation (and
+    /// of the allocations from the outer layer) since the last change.
+    /// For the matches summed over all epochs, see `Cnt::nominal`.
+    /// Always zero without the `counters` feature.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate adapton;
+    /// # fn main() {
+    /// use adapton::macros::*;
+    /// use adapton::engine::*;
+    /// use std::rc::Rc;
+    /// manage::init_dcg();
+    ///
+    /// let succ = |c:Art<usize>| thunk(NameChoice::Nominal(name_of_str("succ")), prog_pt!("succ"),
+    ///                                  Rc::new(Box::new(|c:Art<usize>, ()| get!(c) + 1)), c, ());
+    /// let c = cell(name_of_str("c"), 1);
+    /// assert_eq!(get!(succ(c.clone())), 2);
+    /// set(&c, 2);
+    /// // The same name, and the same argument (the cell `c`)
+    /// assert_eq!(get!(succ(c.clone())), 3);
+    /// if cfg!(feature = "counters") {
+    ///     assert_eq!(manage::nominal_matches().unwrap().1.same_arg, 1);
+    /// }
+    /// # }
+    /// ```
+    pub fn nominal_matches () -> Option<(u64, NominalMatches)> {
+        GLOBALS.with(|g| {
+            match g.borrow().engine {
+                Engine::DCG(ref dcg) => {
+                    let st = dcg.borrow();
+                    Some((st.epoch.0, if st.nominal.0 == st.epoch.0 { st.nominal.1.clone() }
+                                      else { NominalMatches::default() }))
+                },
+                Engine::Naive => None
+            }})
+    }
 }
 
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
@@ -2678,7 +8918,7 @@ mod wf {
         add_constraint(cs, loc, NodeStatus::Clean) ;
         let node = match st.table.get(loc) {
             Some(x) => x,
-            None => { panic!("dangling: {:?}", loc) }
+            None => { panic!("dangling: {}", loc) }
         } ;
         if ! node.succs_def () { return } ;
         for succ in node.succs () {
@@ -2690,7 +8930,7 @@ mod wf {
 
     pub fn check_dcg (st:&mut DCG) {
         if st.flags.write_dcg {
-            let dcg_hash = my_hash(format!("{:?}",st.table)); // XXX: This assumes that the table's debugging string identifies it uniquely
+            let dcg_hash = seeded_hash(st, format!("{:?}",st.table)); // XXX: This assumes that the table's debugging string identifies it uniquely
             if dcg_hash != st.dcg_hash {
                 println!("adapton: dcg #{} hash: {:?}", st.dcg_count, dcg_hash);
                 st.dcg_hash = dcg_hash;
@@ -2700,19 +8940,44 @@ mod wf {
             }
         } ;
         if st.flags.check_dcg_is_wf {
-            let mut cs = HashMap::new() ;
-            for frame in st.stack.iter() {
-                clean(st, &mut cs, &frame.loc)
-            }
-            for (loc, node) in &st.table {
-                if ! node.succs_def () { continue } ;
-                for succ in node.succs () {
-                    if succ.dirty {
-                        dirty(st, &mut cs, loc)
-                    }
+            check_wf(st)
+        }}
+
+    /// Checks that the DCG is well-formed (regardless of
+    /// `Flags::check_dcg_is_wf`); panics if it is not.
+    pub fn check_wf (st:&DCG) {
+        let mut cs = HashMap::new() ;
+        for frame in st.stack.iter() {
+            clean(st, &mut cs, &frame.loc)
+        }
+        for (loc, node) in &st.table {
+            if ! node.succs_def () { continue } ;
+            for succ in node.succs () {
+                if succ.dirty {
+                    dirty(st, &mut cs, loc)
                 }
             }
-        }}
+        }
+    }
+
+    /// Warns when the current thunk has already allocated `loc` (e.g.,
+    /// when a loop reuses one name across its iterations; see
+    /// `ns_iter`).  Call after recording the allocation.
+    pub fn check_alloc_is_fresh (st:&mut DCG, loc:&Rc<Loc>) {
+        if ! st.flags.check_dcg_is_wf { return } ;
+        let reused = match st.stack.last() {
+            None => false,
+            Some(frame) => frame.succs.iter().filter(
+                |&&(ref succ, _)| succ.effect == super::Effect::Allocate && &succ.loc == loc
+            ).count() > 1,
+        } ;
+        if reused {
+            count!(st.cnt.name_reuse += 1);
+            println!("adapton: warning: {} allocates {} more than once; \
+                      to name the allocations of each loop iteration, use `ns_iter` or `for_named!`.\n{}",
+                     st.stack.last().unwrap().loc, loc, string_of_demand_stack(&st.stack));
+        }
+    }
 
     pub fn write_next_dcg (st:&DCG, num:Option<usize>) {
         let name = match num {
@@ -2725,33 +8990,138 @@ mod wf {
 
     pub fn write_dcg_file (st:&DCG, file:&mut File) {
         let mut writer = BufWriter::new(file);
-        writeln!(&mut writer, "digraph {{\n").unwrap();
-        writeln!(&mut writer, "ordering=out;").unwrap();
-        for frame in st.stack.iter() {
-            writeln!(&mut writer, "\"{:?}\" [color=blue,penwidth=10];", frame.loc).unwrap();
-            for succ in frame.succs.iter() {
-                writeln!(&mut writer, "\"{:?}\" -> \"{:?}\" [color=blue,weight=10,penwidth=10];", &frame.loc, &succ.0.loc).unwrap();
+        write_dcg_dot(st, &st.flags.dot_filter, &mut writer)
+    }
+
+    /// The dot identifier of a location (its `Debug` string, quoted).
+    fn dot_id (loc:&Loc) -> String {
+        format!("\"{}\"", format!("{:?}", loc).replace('"', "\\\""))
+    }
+
+    /// Escapes a string for a (quoted) dot label.
+    fn dot_escape (s:&str) -> String {
+        s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+    }
+
+    /// The nodes within `k` edges (in either direction) of the nodes
+    /// that `n` identifies.
+    fn dot_neighborhood (st:&DCG, n:&Name, k:usize) -> HashSet<Rc<Loc>> {
+        let mut shown : HashSet<Rc<Loc>> = st.table.keys()
+            .filter(|loc| *loc.id == super::ArtId::Nominal(n.clone())).cloned().collect();
+        let mut frontier : Vec<Rc<Loc>> = shown.iter().cloned().collect();
+        for _ in 0..k {
+            let mut next = vec![];
+            for loc in frontier.iter() {
+                let node = &st.table[loc];
+                let mut adjacent = node.preds_alloc();
+                adjacent.extend(node.preds_obs().into_iter().map(|(pred, _)| pred));
+                if node.succs_def() { adjacent.extend(node.succs().iter().map(|succ| succ.loc.clone())) };
+                for adj in adjacent {
+                    if shown.insert(adj.clone()) { next.push(adj) }
+                }
             }
+            frontier = next;
+        }
+        shown
+    }
+
+    /// The DCG in textual form; see `Engine::dump_text`.
+    pub fn dcg_text (st:&DCG) -> testing::DcgText {
+        let mut dcg = testing::DcgText::default();
+        for (loc, node) in st.table.iter() {
+            let kind = if node.succs_def() { testing::NodeKind::Thunk }
+                else if node.is_pure() { testing::NodeKind::Pure }
+                else { testing::NodeKind::Cell };
+            let value = node.res_any().map(|val| super::render_any(val).unwrap_or("_".to_string()));
+            let edges = if ! node.succs_def() { vec![] } else {
+                node.succs().iter().map(|succ| testing::EdgeText{
+                    effect:succ.effect.reflect(),
+                    target:ArtKey{loc:succ.loc.clone()}.to_string(),
+                    dirty:succ.dirty,
+                    label:succ.label.map(|l| l.to_string()),
+                }).collect()
+            };
+            dcg.nodes.insert(ArtKey{loc:loc.clone()}.to_string(), testing::NodeText{kind:kind, value:value, edges:edges});
         };
-        for (loc, node) in &st.table {
+        dcg
+    }
+
+    /// Writes the DCG in dot format, showing the nodes and edges that
+    /// pass the filter; the output order is deterministic.  Each node's
+    /// label gives its location (as by its `Display`), and its value
+    /// and annotations, if any.
+    pub fn write_dcg_dot<W:Write> (st:&DCG, filter:&DotFilter, w:&mut W) {
+        let shown = filter.around.as_ref().map(|&(ref n, k)| dot_neighborhood(st, n, k));
+        let is_shown = |loc:&Rc<Loc>| shown.as_ref().map_or(true, |shown| shown.contains(loc));
+        let mut locs : Vec<(String, &Rc<Loc>)> = st.table.keys()
+            .filter(|loc| is_shown(loc)).map(|loc| (dot_id(loc), loc)).collect();
+        locs.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut edges = 0;
+        let mut omitted = 0;
+        let mut edge = |w:&mut W, line:String| {
+            if filter.max_edges.map_or(false, |max| edges >= max) { omitted += 1 }
+            else { edges += 1; writeln!(w, "{}", line).unwrap() }
+        };
+        writeln!(w, "digraph {{\n").unwrap();
+        writeln!(w, "ordering=out;").unwrap();
+        for frame in st.stack.iter().filter(|frame| is_shown(&frame.loc)) {
+            writeln!(w, "{} [color=blue,penwidth=10];", dot_id(&frame.loc)).unwrap();
+            for succ in frame.succs.iter().filter(|succ| is_shown(&succ.0.loc)) {
+                edge(w, format!("{} -> {} [color=blue,weight=10,penwidth=10];", dot_id(&frame.loc), dot_id(&succ.0.loc)));
+            }
+        };
+        if filter.cluster_namespaces {
+            let mut clusters : Vec<(String, Vec<&(String, &Rc<Loc>)>)> = vec![];
+            for entry in locs.iter() {
+                let path = format!("{}", entry.1.path);
+                match clusters.iter().position(|c| c.0 == path) {
+                    Some(i) => clusters[i].1.push(entry),
+                    None => clusters.push((path, vec![entry])),
+                }
+            }
+            clusters.sort_by(|a, b| a.0.cmp(&b.0));
+            for (i, &(ref path, ref entries)) in clusters.iter().enumerate() {
+                writeln!(w, "subgraph cluster_{} {{\nlabel={:?};", i, path).unwrap();
+                for &&(ref id, loc) in entries.iter() {
+                    let shape = if st.table[loc].succs_def() { "ellipse" } else { "box" };
+                    writeln!(w, "{} [shape={}];", id, shape).unwrap();
+                }
+                writeln!(w, "}}").unwrap();
+            }
+        };
+        for &(ref id, loc) in locs.iter() {
+            let node = &st.table[loc];
+            let val = node.res_any().and_then(super::render_any);
+            let annots = st.annotations.get(loc);
+            let mut label = dot_escape(&format!("{}", loc));
+            if let Some(val) = val { label.push_str(&format!("\\n{}", dot_escape(&val))) };
+            for &(key, ref value) in annots.into_iter().flat_map(|annots| annots.iter()) {
+                label.push_str(&format!("\\n{}: {}", dot_escape(key), dot_escape(value)))
+            };
+            writeln!(w, "{} [label=\"{}\"];", id, label).unwrap();
             if ! node.succs_def () {
-                writeln!(&mut writer, "\"{:?}\" [shape=box];", loc).unwrap();
+                if ! filter.cluster_namespaces { writeln!(w, "{} [shape=box];", id).unwrap() };
                 continue;
             } ;
-            for succ in node.succs () {
+            for succ in node.succs ().iter().filter(|succ| is_shown(&succ.loc)) {
+                let label = match succ.label { Some(l) => format!(",label=\"{}\"", l), None => String::new() } ;
                 if succ.dirty {
-                    writeln!(&mut writer, "\"{:?}\" -> \"{:?}\" [color=red,weight=5,penwidth=5];", &loc, &succ.loc).unwrap();
+                    edge(w, format!("{} -> {} [color=red,weight=5,penwidth=5{}];", id, dot_id(&succ.loc), label));
                 } else {
+                    if filter.hide_clean_observes && succ.effect == super::Effect::Observe { continue } ;
                     let (weight, penwidth, color) =
                         match succ.effect {
                             super::Effect::Observe => (0.1, 1, "grey"),
                             super::Effect::Allocate => (2.0, 3, "darkgreen") } ;
-                    writeln!(&mut writer, "\"{:?}\" -> \"{:?}\" [weight={},penwidth={},color={}];",
-                             &loc, &succ.loc, weight, penwidth, color).unwrap();
+                    edge(w, format!("{} -> {} [weight={},penwidth={},color={}{}];",
+                                    id, dot_id(&succ.loc), weight, penwidth, color, label));
                 }
             }
         }
-        writeln!(&mut writer, "}}\n").unwrap();
+        if omitted > 0 {
+            writeln!(w, "\"truncated\" [shape=note,color=red,label=\"truncated: {} more edges\"];", omitted).unwrap();
+        };
+        writeln!(w, "}}\n").unwrap();
     }
 
     pub fn debug_dcg (st:&DCG) {
@@ -2781,7 +9151,7 @@ mod wf {
         for frame in stack.iter() {
             let node = match st.table.get(&frame.loc) {
                 Some(x) => x,
-                None => { panic!("dangling: {:?}", &frame.loc) }
+                None => { panic!("dangling: {}", &frame.loc) }
             } ;
             if ! node.succs_def () { return } ;
             for succ in node.succs () {
@@ -2793,6 +9163,19 @@ mod wf {
 }
 
 
+#[test]
+fn test_deep_drop () {
+    let mut path = Rc::new(Path::Empty);
+    let mut sym = Rc::new(NameSym::Unit);
+    for i in 0..1000000 {
+        path = Rc::new(Path::Child(path, name_of_usize(i)));
+        sym = if i % 2 == 0 { Rc::new(NameSym::ForkL(sym)) }
+              else { Rc::new(NameSym::Pair(Rc::new(NameSym::Usize(i)), sym)) };
+    }
+    drop(path);
+    drop(sym);
+}
+
 #[test]
 fn test_cycles () -> () {
     fn adjs (n:usize) -> (usize, usize) {
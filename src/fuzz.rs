@@ -0,0 +1,380 @@
+/*! Fuzzing the engine: random programs, checked after each step.
+
+A program is a sequence of `Op`s, from the outer layer: allocating
+cells and thunks (nominal or structural, within namespaces or not),
+setting cells and forcing arts.  `run` executes a program on a fresh
+DCG; after each operation, it checks that the DCG is well-formed (see
+`manage::check_dcg_is_wf`), and each force compares the forced value
+with its from-scratch value, which `run` computes from a model of the
+program.  Each thunk sums the values of the arts that it depends on,
+so the model is simple, and the programs never form cycles.
+
+`fuzz` runs random programs (see `program`); at the first failure, it
+shrinks the program to a (locally) minimal one that still fails, and
+returns it as a `Repro`, which renders it as a runnable test.
+
+```
+use adapton::fuzz::*;
+
+assert_eq!(run(&[
+    Op::Cell{ns:None, name:0, val:1},
+    Op::Thunk{ns:Some(0), name:Some(0), deps:vec![0, 0], alloc:true},
+    Op::Force{art:1},
+    Op::Set{art:0, val:2},
+    Op::Force{art:1},
+]), Ok(()));
+
+if let Some(repro) = fuzz(0, 10, 30) {
+    panic!("{}", repro.test_source())
+}
+```
+*/
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use engine::*;
+use macros::*;
+
+/// An operation of a random program, from the outer layer.  An
+/// operation refers to the arts of earlier operations by their index,
+/// modulo the number of these arts, so that every index is valid.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub enum Op {
+    /// Allocates (or re-allocates) the cell `name`, in the namespace
+    /// `ns`, if any, with the value `val`.
+    Cell{ns:Option<usize>, name:usize, val:usize},
+    /// Allocates a thunk that sums the values of the arts `deps`, in
+    /// the namespace `ns`, if any; the thunk is nominal (named `name`),
+    /// or structural (without a name).  With `alloc`, a nominal thunk
+    /// also allocates a cell, whose value is its sum.  (The deps that
+    /// would form a cycle are dropped.)
+    Thunk{ns:Option<usize>, name:Option<usize>, deps:Vec<usize>, alloc:bool},
+    /// Sets the art `art` to `val`, if it is a cell.
+    Set{art:usize, val:usize},
+    /// Forces the art `art`, and compares its value with its
+    /// from-scratch value.
+    Force{art:usize},
+}
+
+impl Op {
+    /// The operation, as Rust source.
+    pub fn source(&self) -> String {
+        match *self {
+            Op::Cell{ns, name, val} => format!("Op::Cell{{ns:{:?}, name:{}, val:{}}}", ns, name, val),
+            Op::Thunk{ns, name, ref deps, alloc} =>
+                format!("Op::Thunk{{ns:{:?}, name:{:?}, deps:vec!{:?}, alloc:{}}}", ns, name, deps, alloc),
+            Op::Set{art, val} => format!("Op::Set{{art:{}, val:{}}}", art, val),
+            Op::Force{art} => format!("Op::Force{{art:{}}}", art),
+        }
+    }
+
+    /// Simpler variants of the operation, for shrinking.
+    fn simpler(&self) -> Vec<Op> {
+        let mut ops = vec![];
+        match *self {
+            Op::Cell{ns, name, val} => {
+                if ns.is_some() { ops.push(Op::Cell{ns:None, name:name, val:val}) };
+                if name > 0 { ops.push(Op::Cell{ns:ns, name:0, val:val}) };
+                if val > 0 { ops.push(Op::Cell{ns:ns, name:name, val:0}) };
+            },
+            Op::Thunk{ns, name, ref deps, alloc} => {
+                if ns.is_some() { ops.push(Op::Thunk{ns:None, name:name, deps:deps.clone(), alloc:alloc}) };
+                if alloc { ops.push(Op::Thunk{ns:ns, name:name, deps:deps.clone(), alloc:false}) };
+                if name.map_or(false, |n| n > 0) { ops.push(Op::Thunk{ns:ns, name:Some(0), deps:deps.clone(), alloc:alloc}) };
+                for i in 0..deps.len() {
+                    let mut fewer = deps.clone();
+                    let dep = fewer.remove(i);
+                    ops.push(Op::Thunk{ns:ns, name:name, deps:fewer.clone(), alloc:alloc});
+                    for smaller in smaller(dep) {
+                        let mut deps = deps.clone();
+                        deps[i] = smaller;
+                        ops.push(Op::Thunk{ns:ns, name:name, deps:deps, alloc:alloc})
+                    }
+                }
+            },
+            Op::Set{art, val} => {
+                for art in smaller(art) { ops.push(Op::Set{art:art, val:val}) };
+                if val > 0 { ops.push(Op::Set{art:art, val:0}) };
+            },
+            Op::Force{art} => {
+                for art in smaller(art) { ops.push(Op::Force{art:art}) };
+            },
+        };
+        ops
+    }
+}
+
+/// Smaller indices than `i`, for shrinking: zero, and the one before
+/// `i`.  (Dropping an operation shifts the indices of the later arts,
+/// so an index may need to shrink one step at a time.)
+fn smaller (i:usize) -> Vec<usize> {
+    match i { 0 => vec![], 1 => vec![0], _ => vec![0, i - 1] }
+}
+
+/// The failure of a program: the index of the failing operation, and
+/// the message of its panic.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+pub struct Failure {
+    pub step : usize,
+    pub msg  : String,
+}
+
+/// The identity of an art of a program: equal keys give the same DCG
+/// location.
+#[derive(Debug,Clone,Hash,PartialEq,Eq)]
+enum Key {
+    Cell(Option<usize>, usize),
+    Nominal(Option<usize>, usize),
+    Structural(Option<usize>, Vec<Key>),
+}
+
+/// The definition of an art, in the model.
+#[derive(Debug,Clone)]
+enum Def {
+    Cell(usize),
+    Thunk(Vec<Key>),
+}
+
+/// The model of a program, for from-scratch values; and its arts.
+#[derive(Default)]
+struct Model {
+    defs : HashMap<Key, Def>,
+    arts : Vec<(Key, Art<usize>)>,
+}
+
+impl Model {
+    fn art(&self, i:usize) -> Option<&(Key, Art<usize>)> {
+        if self.arts.is_empty() { None } else { Some(&self.arts[i % self.arts.len()]) }
+    }
+
+    /// True if the art `from` (transitively) depends on the art `to`.
+    fn reaches(&self, from:&Key, to:&Key) -> bool {
+        from == to || match self.defs.get(from) {
+            Some(&Def::Thunk(ref deps)) => deps.iter().any(|dep| self.reaches(dep, to)),
+            _ => false,
+        }
+    }
+
+    fn value(&self, key:&Key, memo:&mut HashMap<Key, usize>) -> usize {
+        if let Some(v) = memo.get(key) { return *v };
+        let v = match self.defs[key] {
+            Def::Cell(v) => v,
+            Def::Thunk(ref deps) => deps.iter().fold(0, |sum:usize, dep| sum.wrapping_add(self.value(dep, memo))),
+        };
+        memo.insert(key.clone(), v);
+        v
+    }
+
+    fn step(&mut self, op:&Op) {
+        match *op {
+            Op::Cell{ns, name, val} => {
+                let art = in_ns(ns, || cell(name_pair(name_of_str("cell"), name_of_usize(name)), val));
+                let key = Key::Cell(ns, name);
+                self.defs.insert(key.clone(), Def::Cell(val));
+                self.arts.push((key, art))
+            },
+            Op::Thunk{ns, name, ref deps, alloc} => {
+                let mut dep_keys = vec![];
+                let mut dep_arts = vec![];
+                for &dep in deps {
+                    let (key, art) = match self.art(dep) { Some(ka) => ka.clone(), None => break };
+                    if let Some(name) = name {
+                        if self.reaches(&key, &Key::Nominal(ns, name)) { continue }
+                    };
+                    dep_keys.push(key);
+                    dep_arts.push(art);
+                };
+                let (key, id, out) = match name {
+                    Some(name) => {
+                        let nm = name_pair(name_of_str("thunk"), name_of_usize(name));
+                        let out = if alloc { Some(name_pair(name_of_str("out"), name_of_usize(name))) } else { None };
+                        (Key::Nominal(ns, name), NameChoice::Nominal(nm), out)
+                    },
+                    None => (Key::Structural(ns, dep_keys.clone()), NameChoice::Structural, None),
+                };
+                let art = in_ns(ns, || sum(id, dep_arts, out));
+                self.defs.insert(key.clone(), Def::Thunk(dep_keys));
+                self.arts.push((key, art))
+            },
+            Op::Set{art, val} => {
+                let (key, art) = match self.art(art) { Some(ka) => ka.clone(), None => return };
+                if let Key::Cell(_, _) = key {
+                    set(&art, val);
+                    self.defs.insert(key, Def::Cell(val));
+                }
+            },
+            Op::Force{art} => {
+                let (key, art) = match self.art(art) { Some(ka) => ka.clone(), None => return };
+                let forced = force(&art);
+                let expected = self.value(&key, &mut HashMap::new());
+                assert!(forced == expected, "force of {:?} gives {}, but from scratch, {}", key, forced, expected);
+            },
+        }
+    }
+}
+
+fn in_ns<T, F:FnOnce() -> T> (ns_opt:Option<usize>, body:F) -> T {
+    match ns_opt {
+        None => body(),
+        Some(n) => ns(name_pair(name_of_str("ns"), name_of_usize(n)), body),
+    }
+}
+
+/// A thunk that sums the values of `deps`; with `Some(out)`, it
+/// allocates a cell named `out`, with its sum.
+fn sum (id:NameChoice, deps:Vec<Art<usize>>, out:Option<Name>) -> Art<usize> {
+    thunk(id, prog_pt!("fuzz::sum"),
+          Rc::new(Box::new(|deps:Vec<Art<usize>>, out:Option<Name>| {
+              let sum = deps.iter().fold(0, |sum:usize, dep| sum.wrapping_add(force(dep)));
+              if let Some(out) = out { let _ = cell(out, sum); };
+              sum
+          })),
+          deps, out)
+}
+
+/// The message of a panic payload.
+fn panic_msg (payload:Box<::std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "(a panic payload that is not a string)".to_string(),
+        }
+    }
+}
+
+/// Runs the program on a fresh DCG, checking it after each operation
+/// (see the module documentation); afterwards, restores the prior
+/// engine.
+pub fn run (ops:&[Op]) -> Result<(), Failure> {
+    let prior = manage::init_dcg();
+    let _ = manage::set_flags(|flags| flags.check_dcg_is_wf = true);
+    let mut model = Model::default();
+    let mut res = Ok(());
+    for (step, op) in ops.iter().enumerate() {
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            model.step(op);
+            let _ = manage::check_dcg_is_wf();
+        }));
+        if let Err(payload) = outcome {
+            res = Err(Failure{step:step, msg:panic_msg(payload)});
+            break
+        }
+    };
+    drop(model);
+    let _ = manage::use_engine(prior);
+    res
+}
+
+/// A random program of `len` operations; equal seeds give equal
+/// programs.
+pub fn program (seed:u64, len:usize) -> Vec<Op> {
+    let mut state = seed;
+    // SplitMix64 (as in `engine::name_coin`).
+    let mut below = |n:usize| {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) % n as u64) as usize
+    };
+    (0..len).map(|_| {
+        let ns = if below(2) == 0 { None } else { Some(below(3)) };
+        match below(4) {
+            0 => Op::Cell{ns:ns, name:below(4), val:below(10)},
+            1 => {
+                let name = if below(3) == 0 { None } else { Some(below(4)) };
+                let deps = (0..below(4)).map(|_| below(16)).collect();
+                Op::Thunk{ns:ns, name:name, deps:deps, alloc:below(2) == 0}
+            },
+            2 => Op::Set{art:below(16), val:below(10)},
+            _ => Op::Force{art:below(16)},
+        }
+    }).collect()
+}
+
+/// Shrinks a program for which `fails` holds to a (locally) minimal
+/// one for which it still holds: by dropping chunks of operations
+/// (halving their size), and by simplifying operations, until neither
+/// makes progress.
+pub fn shrink (ops:Vec<Op>, fails:&Fn(&[Op]) -> bool) -> Vec<Op> {
+    let mut ops = ops;
+    loop {
+        let shrunk = simplify(drop_chunks(ops.clone(), fails), fails);
+        if shrunk == ops { return ops };
+        ops = shrunk
+    }
+}
+
+fn drop_chunks (ops:Vec<Op>, fails:&Fn(&[Op]) -> bool) -> Vec<Op> {
+    let mut ops = ops;
+    let mut chunk = ops.len() / 2;
+    while chunk > 0 {
+        let mut i = 0;
+        let mut dropped = false;
+        while i + chunk <= ops.len() {
+            let mut fewer = ops.clone();
+            fewer.drain(i..i + chunk);
+            if fails(&fewer) { ops = fewer; dropped = true } else { i += chunk }
+        };
+        if ! dropped || chunk > 1 { chunk /= 2 }
+    };
+    ops
+}
+
+fn simplify (ops:Vec<Op>, fails:&Fn(&[Op]) -> bool) -> Vec<Op> {
+    let mut ops = ops;
+    let mut i = 0;
+    while i < ops.len() {
+        let simpler = ops[i].simpler().into_iter().find(|op| {
+            let mut simpler = ops.clone();
+            simpler[i] = op.clone();
+            fails(&simpler)
+        });
+        match simpler { Some(op) => ops[i] = op, None => i += 1 }
+    };
+    ops
+}
+
+/// A (shrunk) failing program, and the seed of the random program
+/// from which it comes.
+#[derive(Debug,Clone)]
+pub struct Repro {
+    pub seed    : u64,
+    pub ops     : Vec<Op>,
+    pub failure : Failure,
+}
+
+impl Repro {
+    /// The program, as a runnable test.
+    pub fn test_source(&self) -> String {
+        let mut src = format!("#[test]\nfn fuzz_seed_{} () {{\n    use adapton::fuzz::*;\n", self.seed);
+        for line in self.failure.msg.lines() { src.push_str(&format!("    // {}\n", line)) };
+        src.push_str("    assert_eq!(run(&[\n");
+        for op in self.ops.iter() { src.push_str(&format!("        {},\n", op.source())) };
+        src.push_str("    ]), Ok(()));\n}\n");
+        src
+    }
+}
+
+/// Runs `programs` random programs of `len` operations each, from the
+/// seeds `seed`, `seed + 1`, and so on; returns the first failure, if
+/// any, shrunk (see `shrink`).
+///
+/// While shrinking, the failing programs panic often; hence, `fuzz`
+/// silences the panic hook (of all threads) until it returns.
+pub fn fuzz (seed:u64, programs:u64, len:usize) -> Option<Repro> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| ()));
+    let repro = (seed..seed + programs).filter_map(|seed| {
+        let ops = program(seed, len);
+        if run(&ops).is_ok() { return None };
+        let ops = shrink(ops, &|ops| run(ops).is_err());
+        let failure = run(&ops).unwrap_err();
+        Some(Repro{seed:seed, ops:ops, failure:failure})
+    }).next();
+    panic::set_hook(hook);
+    repro
+}
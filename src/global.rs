@@ -0,0 +1,100 @@
+/*! A thread-local default engine, for programs that only want one.
+
+The functions of `engine` use whichever engine is current for the
+thread (see `engine::manage`), which starts as the `Naive` engine.
+Instead, the functions below use a _default engine_ for the thread: a
+DCG, created on first use, and kept across calls.  Each call installs
+the default engine for its duration (via `with_engine`), and then
+restores the prior engine.  Hence, small programs and examples can
+use these functions without initializing an engine, while library
+code keeps using the engine that its caller chooses.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use std::rc::Rc;
+use adapton::macros::*;
+use adapton::engine::{Art, NameChoice, name_of_str};
+use adapton::global;
+
+let c : Art<usize> = global::cell(name_of_str("c"), 1);
+let t : Art<usize> = global::thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                                   Rc::new(Box::new(|c:Art<usize>, ()| global::force(&c) + 1)),
+                                   c.clone(), ());
+assert_eq!(global::force(&t), 2);
+global::set(&c, 2);
+assert_eq!(global::force(&t), 3);
+# }
+```
+
+Within a thunk, the default engine is already installed, so the
+functions of `engine` and of this module are interchangeable there.
+*/
+
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use engine;
+use engine::{Art, Engine, Name, NameChoice, manage};
+use macros::ProgPt;
+
+// The default engine, while it is not installed (`None` while it is,
+// or before its first use).
+thread_local!(static DEFAULT: RefCell<Option<Engine>> = RefCell::new(None));
+
+// True while `with_engine` has installed the default engine.
+thread_local!(static INSTALLED: RefCell<bool> = RefCell::new(false));
+
+/// Restores the prior engine (and stores the default one), even when
+/// the body unwinds.
+struct Restore { prior:Option<Engine> }
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let default = manage::use_engine(self.prior.take().unwrap());
+        DEFAULT.with(|d| *d.borrow_mut() = Some(default));
+        INSTALLED.with(|i| *i.borrow_mut() = false);
+    }
+}
+
+/// Runs `body` using the thread's default engine (creating it first,
+/// if needed); afterwards, restores the engine that was in use.
+/// Calls may nest; the inner calls simply run their bodies.
+pub fn with_engine<R,F:FnOnce() -> R> (body:F) -> R {
+    if INSTALLED.with(|i| *i.borrow()) { return body() };
+    let default = match DEFAULT.with(|d| d.borrow_mut().take()) {
+        Some(default) => default,
+        None => { let prior = manage::init_dcg(); manage::use_engine(prior) },
+    };
+    INSTALLED.with(|i| *i.borrow_mut() = true);
+    let _restore = Restore{ prior:Some(manage::use_engine(default)) };
+    body()
+}
+
+/// `engine::cell`, using the default engine.
+pub fn cell<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T) -> Art<T> {
+    with_engine(|| engine::cell(n, val))
+}
+
+/// `engine::thunk`, using the default engine.
+pub fn thunk<Arg:Hash+Eq+Debug+Clone+'static,Spurious:Clone+'static,Res:Hash+Eq+Debug+Clone+'static>
+    (id:NameChoice,
+     prog_pt:ProgPt,
+     fn_box:Rc<Box<Fn(Arg, Spurious) -> Res>>,
+     arg:Arg, spurious:Spurious)
+     -> Art<Res>
+{
+    with_engine(|| engine::thunk(id, prog_pt, fn_box, arg, spurious))
+}
+
+/// `engine::force`, using the default engine.
+pub fn force<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> T {
+    with_engine(|| engine::force(a))
+}
+
+/// `engine::set`, using the default engine.
+pub fn set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) {
+    with_engine(|| engine::set(a, val))
+}
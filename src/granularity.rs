@@ -0,0 +1,182 @@
+/*! Exploring naming strategies: the granularity of articulation, empirically.
+
+How finely to articulate a structure (how many items per thunk or
+cell), and how many of its articulations to name, is a trade-off:
+fine articulations and many names re-evaluate little after an edit,
+but cost nodes, edges and memory; coarse ones cost little, but
+re-evaluate much.  The best choice depends on the workload, and is
+easier to measure than to predict.
+
+A `Strategy` gives both: its `chunk` size, and a `density`, which
+places the names of chunks pseudo-randomly (and stably, see
+`engine::name_level`), such that about one chunk in `2^density` is
+named (nominal), and the others are structural.  A workload is a
+closure of a strategy and of a step: step zero runs the program from
+scratch, and each later step runs it again after an edit (e.g., with
+the input cells of the next version), so that the names of the prior
+steps match.  `explore` runs the workload on a fresh DCG for each
+strategy, and reports the engine's work (see `Cnt`) and the size of
+its DCG (see `Footprint`); it also checks that all strategies give
+the same results, since naming should only change the engine's work.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::granularity::*;
+use std::rc::Rc;
+
+// The sum of 1000 items, in chunks; each step changes one item.
+let sum = |s:&Strategy, step:usize| -> usize {
+    let items : Vec<usize> = (0..1000).map(|i| if step > 0 && i == step * 37 % 1000 { 0 } else { i }).collect();
+    let sums : Vec<Art<usize>> = s.chunks(items.len()).into_iter().enumerate().map(|(i, r)| {
+        thunk(s.choice(&name_of_str("sum"), i), prog_pt!("sum"),
+              Rc::new(Box::new(|xs:Vec<usize>, ()| xs.iter().sum())), items[r].to_vec(), ())
+    }).collect();
+    sums.iter().map(|t| force(t)).sum()
+};
+let reports = explore(&grid(&[10, 100], &[0, 2]), 5, sum);
+assert_eq!(reports.len(), 4);
+// The finer chunks re-evaluate fewer items, but use more nodes.
+assert!(reports[0].footprint.nodes > reports[2].footprint.nodes);
+println!("{}", render(&reports));
+# }
+```
+*/
+
+use std::fmt::Debug;
+use std::fmt::Write;
+use std::ops::Range;
+use std::time::Duration;
+
+use engine::*;
+use testing;
+
+/// A naming strategy: the number of items of each chunk (articulation),
+/// and the density of the names of the chunks (see the module
+/// documentation).
+#[derive(Debug,Clone,Copy,Hash,PartialEq,Eq)]
+pub struct Strategy {
+    /// The number of items of each chunk (at least one)
+    pub chunk : usize,
+    /// The level (see `engine::name_level`) that the name of a chunk
+    /// needs, for the chunk to be named: zero names every chunk, one
+    /// names about half of them, and so on
+    pub density : u32,
+}
+
+impl Strategy {
+    /// The chunks of `len` items, as ranges of their indices.
+    pub fn chunks (&self, len:usize) -> Vec<Range<usize>> {
+        assert!(self.chunk > 0, "Strategy: the chunk size is zero");
+        (0..len).step_by(self.chunk).map(|lo| lo..::std::cmp::min(lo + self.chunk, len)).collect()
+    }
+
+    /// The name of the `i`th chunk of the structure named `base`, if
+    /// the strategy names it.
+    pub fn name (&self, base:&Name, i:usize) -> Option<Name> {
+        let n = name_pair(base.clone(), name_of_usize(i));
+        if name_level(&n) >= self.density { Some(n) } else { None }
+    }
+
+    /// The identity of the `i`th chunk of the structure named `base`:
+    /// nominal, by its name, if the strategy names it, and otherwise,
+    /// structural.
+    pub fn choice (&self, base:&Name, i:usize) -> NameChoice {
+        match self.name(base, i) {
+            Some(n) => NameChoice::Nominal(n),
+            None => NameChoice::Structural,
+        }
+    }
+}
+
+/// The strategies of every chunk size of `chunks` with every density of
+/// `densities`, in this order.
+pub fn grid (chunks:&[usize], densities:&[u32]) -> Vec<Strategy> {
+    chunks.iter().flat_map(|&chunk| densities.iter().map(move |&density| Strategy{chunk:chunk, density:density})).collect()
+}
+
+/// `count` random strategies, with chunk sizes in `1..max_chunk + 1`,
+/// and densities in `0..max_density + 1`; equal seeds give equal
+/// strategies.
+pub fn random (seed:u64, count:usize, max_chunk:usize, max_density:u32) -> Vec<Strategy> {
+    let mut state = seed;
+    // SplitMix64 (as in `fuzz::program`).
+    let mut below = |n:usize| {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) % n as u64) as usize
+    };
+    (0..count).map(|_| {
+        let chunk = 1 + below(max_chunk);
+        Strategy{chunk:chunk, density:below(max_density as usize + 1) as u32}
+    }).collect()
+}
+
+/// The measurements of a strategy: the engine's work of step zero
+/// (from scratch), and of the later steps (summed), the time of the
+/// later steps (by `testing::now`), and the size of the DCG after the
+/// last step.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Report {
+    pub strategy  : Strategy,
+    pub initial   : Cnt,
+    pub edits     : Cnt,
+    pub edit_time : Duration,
+    pub footprint : Footprint,
+}
+
+/// Runs `workload` for each strategy, on a fresh DCG, for the steps
+/// `0..steps + 1`; reports the measurements of each strategy, in order
+/// (see the module documentation).  Afterwards, restores the prior
+/// engine.
+///
+/// Panics if two strategies give different results for a step: the
+/// workload's naming is then unsound (e.g., it names two chunks
+/// alike).
+pub fn explore<T:PartialEq+Debug, F:Fn(&Strategy, usize) -> T> (strategies:&[Strategy], steps:usize, workload:F) -> Vec<Report> {
+    let mut expected : Vec<(Strategy, T)> = vec![];
+    strategies.iter().map(|strategy| {
+        let prior = manage::init_dcg();
+        let mut report = Report{strategy:*strategy, initial:Cnt::default(), edits:Cnt::default(),
+                                edit_time:Duration::new(0, 0), footprint:Footprint::default()};
+        for step in 0..steps + 1 {
+            let start = testing::now();
+            let (res, cnt) = testing::counts(|| workload(strategy, step));
+            if step == 0 { report.initial = cnt } else {
+                report.edits.add(&cnt);
+                report.edit_time += testing::now() - start;
+            };
+            if expected.len() <= step {
+                expected.push((*strategy, res))
+            } else if expected[step].1 != res {
+                panic!("granularity: at step {}, {:?} gives {:?}, whereas {:?} gives {:?}",
+                       step, strategy, res, expected[step].0, expected[step].1)
+            }
+        };
+        report.footprint = manage::footprint().unwrap();
+        let _ = manage::use_engine(prior);
+        report
+    }).collect()
+}
+
+/// The report whose later steps (edits) evaluated the fewest thunks,
+/// and among these, whose DCG is the smallest (in bytes).
+pub fn best (reports:&[Report]) -> Option<&Report> {
+    reports.iter().min_by_key(|r| (r.edits.eval, r.footprint.bytes))
+}
+
+/// The reports, as a table, one strategy per line.
+pub fn render (reports:&[Report]) -> String {
+    let mut out = format!("{:>8} {:>8} {:>10} {:>10} {:>12} {:>8} {:>8} {:>10}\n",
+                          "chunk", "density", "evals", "edit-evals", "edit-time", "nodes", "edges", "bytes");
+    for r in reports.iter() {
+        writeln!(out, "{:>8} {:>8} {:>10} {:>10} {:>12} {:>8} {:>8} {:>10}",
+                 r.strategy.chunk, r.strategy.density, r.initial.eval, r.edits.eval, format!("{:?}", r.edit_time),
+                 r.footprint.nodes, r.footprint.edges, r.footprint.bytes).unwrap();
+    };
+    out
+}
@@ -0,0 +1,156 @@
+/*! The layered interface: the editor and archivist roles, as types.
+
+The functions of `engine` serve both roles (see the crate's
+documentation), and reject the operations that a role may not perform
+at run time: e.g., `set` panics when called from within a thunk.  The
+handles of this module give each role its operations, only:
+
+ - An `Editor` exists at the outer layer; it allocates and mutates
+   input cells, allocates thunks, and demands their results.
+
+ - An `Archivist` exists within a thunk's computation; it allocates
+   (nominal) cells and thunks, and forces other thunks, but it has no
+   `set`.
+
+A thunk's computation gets an `Archivist`, rather than an `Editor`: an
+`Editor` is only ever lent (by `editor`), and thunk computations are
+`'static`, so they cannot capture one.  Hence, a computation that
+mutates input does not compile.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::layers::*;
+manage::init_dcg();
+
+editor(|ed| {
+    let num = ed.cell(name_of_str("num"), 42);
+    let den = ed.cell(name_of_str("den"), 2);
+    let div = ed.thunk(NameChoice::Nominal(name_of_str("div")), prog_pt!("div"),
+                       |ar, (num, den):(Art<usize>, Art<usize>)| ar.force(&num) / ar.force(&den),
+                       (num, den.clone()));
+    assert_eq!(ed.force(&div), 21);
+    ed.set(&den, 7);
+    assert_eq!(ed.force(&div), 6);
+});
+# }
+```
+
+Whereas the following computation does not compile, since it captures
+the editor:
+
+```compile_fail
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::layers::*;
+manage::init_dcg();
+
+editor(|ed| {
+    let c = ed.cell(name_of_str("c"), 1);
+    let t = ed.thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                     |ar, c:Art<usize>| { ed.set(&c, 2); ar.force(&c) },
+                     c);
+    ed.force(&t)
+});
+# }
+```
+*/
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use engine::{Art, Name, NameChoice};
+use engine;
+use macros::ProgPt;
+
+/// The editor role: the outer layer.  See `editor`.
+#[derive(Debug)]
+pub struct Editor {
+    _role : (),
+}
+
+/// The archivist role: within a thunk's computation.  See
+/// `Editor::thunk`.
+#[derive(Debug)]
+pub struct Archivist {
+    _role : (),
+}
+
+/// Lends the editor to `body`, in the current engine.
+///
+/// Panics if called from within a thunk (this is the only run-time
+/// check of the roles).
+pub fn editor<T,F:FnOnce(&Editor) -> T> (body:F) -> T {
+    assert!(engine::demand_stack().is_empty(), "editor: not permitted while the engine is executing a thunk");
+    body(&Editor{_role:()})
+}
+
+/// The thunk of `f`, which computes as an archivist.
+fn thunk<Arg:Hash+Eq+Debug+Clone+'static,
+         Res:Hash+Eq+Debug+Clone+'static,
+         F:Fn(&Archivist, Arg) -> Res+'static>
+    (id:NameChoice, prog_pt:ProgPt, f:F, arg:Arg) -> Art<Res>
+{
+    engine::thunk(id, prog_pt,
+                  Rc::new(Box::new(|arg:Arg, f:Rc<F>| f(&Archivist{_role:()}, arg))),
+                  arg, Rc::new(f))
+}
+
+impl Editor {
+    /// Allocates (or overwrites) the input cell named `n`; see `engine::cell`.
+    pub fn cell<T:Hash+Eq+Debug+Clone+'static> (&self, n:Name, val:T) -> Art<T> {
+        engine::cell(n, val)
+    }
+
+    /// Mutates the input cell `a`; see `engine::set`.
+    pub fn set<T:Hash+Eq+Debug+Clone+'static> (&self, a:&Art<T>, val:T) {
+        engine::set(a, val)
+    }
+
+    /// Allocates a thunk, whose computation `f` gets an archivist and
+    /// the argument `arg`; see `engine::thunk`.
+    pub fn thunk<Arg:Hash+Eq+Debug+Clone+'static,
+                 Res:Hash+Eq+Debug+Clone+'static,
+                 F:Fn(&Archivist, Arg) -> Res+'static>
+        (&self, id:NameChoice, prog_pt:ProgPt, f:F, arg:Arg) -> Art<Res>
+    {
+        thunk(id, prog_pt, f, arg)
+    }
+
+    /// Demands the value of `a`; see `engine::force`.
+    pub fn force<T:Hash+Eq+Debug+Clone+'static> (&self, a:&Art<T>) -> T {
+        engine::force(a)
+    }
+}
+
+impl Archivist {
+    /// Allocates the cell named `n` (a "one-shot" cell, for the
+    /// archivist); see `engine::cell`.
+    pub fn cell<T:Hash+Eq+Debug+Clone+'static> (&self, n:Name, val:T) -> Art<T> {
+        engine::cell(n, val)
+    }
+
+    /// Allocates a thunk, as with `Editor::thunk`.
+    pub fn thunk<Arg:Hash+Eq+Debug+Clone+'static,
+                 Res:Hash+Eq+Debug+Clone+'static,
+                 F:Fn(&Archivist, Arg) -> Res+'static>
+        (&self, id:NameChoice, prog_pt:ProgPt, f:F, arg:Arg) -> Art<Res>
+    {
+        thunk(id, prog_pt, f, arg)
+    }
+
+    /// Observes the value of `a`, recording the dependency; see `engine::force`.
+    pub fn force<T:Hash+Eq+Debug+Clone+'static> (&self, a:&Art<T>) -> T {
+        engine::force(a)
+    }
+
+    /// Performs `body` in the namespace `n`; see `engine::ns`.
+    pub fn ns<T,F:FnOnce(&Archivist) -> T> (&self, n:Name, body:F) -> T {
+        engine::ns(n, || body(self))
+    }
+}
@@ -1403,6 +1403,9 @@ list.
 #![crate_type = "lib"]
 
 extern crate core;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 #[macro_use]
 pub mod macros ;
@@ -1410,6 +1413,15 @@ pub mod engine ;
 pub mod catalog ;
 pub mod parse_val;
 pub mod reflect;
+pub mod global;
+pub mod api;
+pub mod dual;
+pub mod fuzz;
+pub mod granularity;
+pub mod layers;
+pub mod testing;
+#[cfg(feature = "serde")]
+pub mod articulate;
 
 
 mod adapton {
@@ -18,6 +18,10 @@
 
 use std::cell::RefCell;
 use std::fmt::{Formatter,Result,Debug};
+use std::hash::{Hash,Hasher};
+use std::collections::hash_map::DefaultHasher;
+
+use engine::{Name, name_pair, name_of_str, name_of_hash64, Art, cell, force};
 
 #[doc(hidden)]
 pub use std::rc::Rc;
@@ -934,3 +938,342 @@ fn test_memo_macros() {
     let _ = demand_graph(cell(name_of_str("a"), 3));
 }
 
+
+/**
+Loop over an iterator, running each iteration in its own namespace
+(via `ns_iter`), derived from a base name and the iteration's index.
+Hence, the names that the loop body allocates vary with the iteration,
+even when the body reuses one name (e.g., `name_of_str("x")`).
+
+The body runs within a closure: `break`, `continue` and `return` do
+not apply to the loop.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+manage::init_dcg();
+
+let mut cells = vec![];
+for_named!(name_of_str("row"), x in vec![1, 2, 3] => {
+  cells.push(cell(name_of_str("x"), x))
+});
+assert_eq!(cells.iter().map(|c| force(c)).collect::<Vec<_>>(), vec![1, 2, 3]);
+# }
+```
+*/
+#[macro_export]
+macro_rules! for_named {
+  ( $nm:expr , $x:pat in $iter:expr => $body:expr ) => {{
+    let nm = $nm;
+    for (i, $x) in ($iter).into_iter().enumerate() {
+      ns_iter(nm.clone(), i, || { $body })
+    }
+  }};
+}
+
+/// The name of the instance of query `query` for the given key; see `queries!`.
+pub fn query_name<K:Hash>(query:&'static str, key:&K) -> Name {
+  let mut hasher = DefaultHasher::new();
+  key.hash(&mut hasher);
+  name_pair(name_of_str(query), name_of_hash64(hasher.finish()))
+}
+
+/**
+Define _queries_, in the style of query databases (e.g., `salsa`):
+functions of keys, whose results the engine memoizes and keeps up to
+date.  There are two kinds of queries:
+
+- An **input** query, declared with `#[input(setter)]` and without a
+  body, reads a cell for each key; the `setter` function (with the
+  key, then the value) allocates or changes that cell.  Reading the
+  input of a key that was never set panics.
+
+- A **derived** query, with a body, is a nominal thunk for each key,
+  named by its query and key (see `query_name`).  Calling it forces
+  that thunk; hence, after changes to inputs, only the queries whose
+  inputs changed re-evaluate.
+
+The keys (the query arguments) and the results must be
+`Hash+Eq+Debug+Clone`, as for `thunk`.  Queries require the DCG
+engine; call them in the outer layer (outside of `ns`), or from other
+queries.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+
+queries! {
+  /// The text of a file (an input).
+  #[input(set_text)]
+  fn text(file:String) -> String;
+
+  /// The number of lines of a file.
+  fn lines(file:String) -> usize { text(file).lines().count() }
+
+  /// The number of lines of two files.
+  fn total(a:String, b:String) -> usize { lines(a) + lines(b) }
+}
+
+manage::init_dcg();
+set_text("a".to_string(), "1\n2".to_string());
+set_text("b".to_string(), "3".to_string());
+assert_eq!(total("a".to_string(), "b".to_string()), 3);
+set_text("b".to_string(), "3\n4\n5".to_string());
+assert_eq!(total("a".to_string(), "b".to_string()), 5);
+# }
+```
+*/
+#[macro_export]
+macro_rules! queries {
+  () => { };
+  ( $(#[doc = $doc:expr])* #[input($set:ident)]
+    $vis:vis fn $q:ident ( $($k:ident : $kt:ty),* ) -> $vt:ty ; $($rest:tt)* ) => {
+    $(#[doc = $doc])*
+    $vis fn $q ( $($k : $kt),* ) -> $vt {
+      let key = ( $($k ,)* );
+      match find_cell::<$vt>(query_name(stringify!($q), &key)) {
+        Some(c) => force(&c),
+        None => panic!("query input {}{:?} is not set", stringify!($q), key),
+      }
+    }
+    /// Sets an input of a query; see `queries!`.
+    $vis fn $set ( $($k : $kt ,)* val:$vt ) {
+      let key = ( $($k ,)* );
+      let _ = cell(query_name(stringify!($q), &key), val);
+    }
+    queries!{ $($rest)* }
+  };
+  ( $(#[doc = $doc:expr])*
+    $vis:vis fn $q:ident ( $($k:ident : $kt:ty),* ) -> $rt:ty $body:block $($rest:tt)* ) => {
+    $(#[doc = $doc])*
+    $vis fn $q ( $($k : $kt),* ) -> $rt {
+      let key = ( $($k ,)* );
+      force(&thunk(NameChoice::Nominal(query_name(stringify!($q), &key)),
+                   prog_pt!(stringify!($q)),
+                   Rc::new(Box::new(|( $($k ,)* ):( $($kt ,)* ), ()| -> $rt { $body })),
+                   key, ()))
+    }
+    queries!{ $($rest)* }
+  };
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_queries_macro() {
+  use adapton::macros::*;
+  use adapton::engine::*;
+
+  queries! {
+    #[input(set_weight)]
+    fn weight(item:usize) -> usize;
+    fn double(item:usize) -> usize { weight(item) * 2 }
+    fn sum(n:usize) -> usize { (0..n).map(double).sum() }
+  }
+
+  manage::init_dcg();
+  for i in 0..4 { set_weight(i, i) }
+  assert_eq!(sum(4), 12);
+  let _ = manage::reset_counters();
+  set_weight(2, 10);
+  assert_eq!(sum(4), 28);
+  // Only the changed input's query, and the sum, re-evaluate.
+  assert_eq!(manage::counters().unwrap().eval, 2);
+  assert_eq!(sum(3), 22);
+}
+
+#[test]
+fn test_for_named_macro() {
+  use std::rc::Rc;
+  use adapton::macros::*;
+  use adapton::engine::*;
+
+  manage::init_dcg();
+  let c : Art<usize> = cell(name_of_str("c"), 3);
+  let t = { let c = c.clone(); thunk![[t] {
+    let mut cells = vec![];
+    for_named!(name_of_str("i"), i in 0..force(&c) => {
+      cells.push(cell(name_of_str("x"), i * 10))
+    });
+    cells.iter().map(|c| force(c)).sum::<usize>()
+  }] };
+  assert_eq!(force(&t), 30);
+  set(&c, 4);
+  assert_eq!(force(&t), 60);
+}
+
+/// A type whose values have an _articulated_ form, with `Art`s at
+/// its articulation points; see `articulated!`, which implements it
+/// for recursive enums.
+pub trait Articulate {
+  /// The articulated form of the type.
+  type Articulated : Hash+Eq+Debug+Clone+'static;
+  /// Articulates the value: allocates a cell for each articulation
+  /// point, named by its field path from `n`.  Hence, articulating an
+  /// updated value with the same name re-allocates the same cells, and
+  /// changes only those whose content changed.
+  fn articulate(&self, n:Name) -> Self::Articulated;
+  /// Forces the articulation points of `a`, and rebuilds its value.
+  fn read_back(a:&Self::Articulated) -> Self;
+}
+
+/// An articulation point of a `T`: a cell that holds the articulated
+/// form of a `T`.
+pub struct ArtPt<T:Articulate>(Art<T::Articulated>);
+
+impl<T:Articulate> ArtPt<T> {
+  /// Articulates `val` in a cell named `n`; its own articulation
+  /// points are named by their field paths from `n`.
+  pub fn new(n:Name, val:&T) -> ArtPt<T> { ArtPt(cell(n.clone(), val.articulate(n))) }
+  /// Forces the articulation point, giving the articulated form that
+  /// it holds.
+  pub fn force(&self) -> T::Articulated { force(&self.0) }
+  /// The cell of the articulation point.
+  pub fn art(&self) -> &Art<T::Articulated> { &self.0 }
+}
+
+impl<T:Articulate> Clone for ArtPt<T> {
+  fn clone(&self) -> Self { ArtPt(self.0.clone()) }
+}
+impl<T:Articulate> PartialEq for ArtPt<T> {
+  fn eq(&self, other:&Self) -> bool { self.0 == other.0 }
+}
+impl<T:Articulate> Eq for ArtPt<T> { }
+impl<T:Articulate> Hash for ArtPt<T> {
+  fn hash<H:Hasher>(&self, state:&mut H) { self.0.hash(state) }
+}
+impl<T:Articulate> Debug for ArtPt<T> {
+  fn fmt(&self, f:&mut Formatter) -> Result { self.0.fmt(f) }
+}
+
+/**
+Define a recursive enum, and its _articulated_ form, with `Art`s at
+the fields marked `#[art]`: the macro defines both enums, with the
+same variants, and implements `Articulate` for the first.  In the
+first enum, a field `#[art] f:T` has type `Box<T>`; in the articulated
+enum, it has type `ArtPt<T>` (whose `force` gives the articulated
+form of its `T`), and the other fields keep their types.  Hence,
+existing tree types (e.g., ASTs) become incremental mechanically,
+without rewriting them around `Art`s by hand.
+
+The variants are unit or struct variants; the attributes of the
+enum apply to the first enum, only, while the articulated enum
+derives `Debug`, `Clone`, `PartialEq`, `Eq` and `Hash` (so, its
+fields must implement them).
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+
+articulated! {
+  #[derive(Debug,PartialEq)]
+  pub enum Expr / ArtExpr {
+    Num{ n:isize },
+    Add{ #[art] l:Expr, #[art] r:Expr },
+  }
+}
+
+fn eval (e:ArtExpr) -> isize {
+  match e {
+    ArtExpr::Num{n} => n,
+    ArtExpr::Add{l, r} => eval(l.force()) + eval(r.force()),
+  }
+}
+
+manage::init_dcg();
+let e = Expr::Add{ l:Box::new(Expr::Num{n:1}), r:Box::new(Expr::Num{n:2}) };
+let a = e.articulate(name_of_str("e"));
+assert_eq!(eval(a.clone()), 3);
+assert_eq!(Expr::read_back(&a), e);
+# }
+```
+*/
+#[macro_export]
+macro_rules! articulated {
+  (@plain [art] $t:ty) => { Box<$t> };
+  (@plain [] $t:ty) => { $t };
+  (@artic [art] $t:ty) => { ArtPt<$t> };
+  (@artic [] $t:ty) => { $t };
+  (@articulate [art] $n:ident, $f:ident) => { ArtPt::new(name_pair($n.clone(), name_of_str(stringify!($f))), &**$f) };
+  (@articulate [] $n:ident, $f:ident) => { $f.clone() };
+  (@read_back [art] $t:ty, $f:ident) => { Box::new(<$t as Articulate>::read_back(&$f.force())) };
+  (@read_back [] $t:ty, $f:ident) => { $f.clone() };
+  ( $(#[$attr:meta])*
+    $vis:vis enum $plain:ident / $artic:ident {
+      $( $(#[$vattr:meta])* $v:ident $({ $( $(#[$fa:ident])* $f:ident : $ft:ty ),* $(,)* })? ),* $(,)*
+    } ) => {
+    $(#[$attr])*
+    $vis enum $plain {
+      $( $(#[$vattr])* $v $({ $( $f : articulated!(@plain [$($fa)*] $ft) ),* })? ),*
+    }
+    #[derive(Debug,Clone,PartialEq,Eq,Hash)]
+    $vis enum $artic {
+      $( $(#[$vattr])* $v $({ $( $f : articulated!(@artic [$($fa)*] $ft) ),* })? ),*
+    }
+    impl Articulate for $plain {
+      type Articulated = $artic;
+      fn articulate(&self, name:Name) -> $artic {
+        let _ = &name;
+        match *self {
+          $( $plain::$v $({ $( ref $f ),* })? =>
+             $artic::$v $({ $( $f : articulated!(@articulate [$($fa)*] name, $f) ),* })? ),*
+        }
+      }
+      fn read_back(a:&$artic) -> $plain {
+        match *a {
+          $( $artic::$v $({ $( ref $f ),* })? =>
+             $plain::$v $({ $( $f : articulated!(@read_back [$($fa)*] $ft, $f) ),* })? ),*
+        }
+      }
+    }
+  };
+}
+
+#[cfg(feature = "counters")]
+#[test]
+fn test_articulated_macro() {
+  use adapton::macros::*;
+  use adapton::engine::*;
+
+  articulated! {
+    /// A list, with a label (which is not articulated) at its end.
+    #[derive(Debug,Clone,PartialEq)]
+    enum List / ArtList {
+      Cons{ hd:usize, #[art] tl:List },
+      Nil{ label:String },
+      Empty,
+    }
+  }
+
+  fn sum (l:&ArtList) -> usize {
+    match *l {
+      ArtList::Cons{hd, ref tl} => {
+        hd + force(&thunk(NameChoice::Structural, prog_pt!("sum"),
+                          Rc::new(Box::new(|tl:ArtPt<List>, ()| sum(&tl.force()))),
+                          tl.clone(), ()))
+      },
+      _ => 0,
+    }
+  }
+
+  manage::init_dcg();
+  let list = |xs:&[usize]| xs.iter().rev().fold(List::Nil{label:"end".to_string()}, |tl, &hd| {
+    List::Cons{hd:hd, tl:Box::new(tl)}
+  });
+  let l = list(&[1, 2, 3, 4]);
+  let a = l.articulate(name_of_str("l"));
+  assert_eq!(sum(&a), 10);
+  assert_eq!(List::read_back(&a), l);
+  // Re-articulating with the same name updates the changed cells, only.
+  let _ = manage::reset_counters();
+  let b = list(&[1, 2, 30, 4]).articulate(name_of_str("l"));
+  assert_eq!(a, b);
+  assert_eq!(sum(&b), 37);
+  assert_eq!(manage::counters().unwrap().eval, 2);
+  assert_eq!(List::read_back(&ArtList::Empty), List::Empty);
+}
@@ -8,8 +8,8 @@
 //! traversal.
 
 use std::fmt::Debug;
-use adapton::reflect::{Loc,Path,Val,ArtContent,Const};
-use adapton::engine::{Name, name_of_str, name_of_string};
+use adapton::reflect::{Loc,LocId,Path,Val,ArtContent,Const};
+use adapton::engine::{Name, name_of_str, name_of_string, name_of_hash64};
 
 /// _Balanced tokens_: Tokens that must be balanced with a left and
 /// right instance and well-nested balanced tokens between them.
@@ -368,8 +368,13 @@ fn parse_art_val ( i:&String, fields:&Vec<(Name, Val)> ) -> Option<Val> {
                             // Now we are confident that the rest ought to parse.
                             // Any further parse errors are panics.
                             let path = path_of_val( & ws[0].1 );
-                            let name = name_of_val( & ws[1].1 );
-                            Some( Val::Art(Loc{path:path, name:name}, ArtContent::Unknown) )
+                            let (name, id) = match ws[1].1 {
+                                // Structural ids print as their hashes.
+                                Val::Const( Const::Nat( hash ) ) =>
+                                    (name_of_hash64( hash as u64 ), LocId::Structural( hash as u64 )),
+                                ref n => (name_of_val( n ), LocId::Nominal),
+                            };
+                            Some( Val::Art(Loc{path:path, name:name, id:id}, ArtContent::Unknown) )
                         } 
 
 
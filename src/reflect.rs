@@ -21,10 +21,12 @@ walking their values, recursively.
 */
 
 use engine::Name;
+use engine;
 use macros::ProgPt;
 use std::fmt::Debug;
 use std::rc::Rc;
 use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 pub use engine::reflect_dcg::*;
 
@@ -135,11 +137,57 @@ pub struct Loc {
   pub path: Path,
   /// The distinguished `Name` of the `Loc` (must be unique in the path).
   pub name: Name,
+  /// How the engine identifies the `Loc` within its path.
+  pub id: LocId,
+}
+
+/// How the engine identifies a `Loc` within its path; see `engine::NameChoice`.
+#[derive(PartialEq,Eq,Debug,Hash,Clone)]
+pub enum LocId {
+  /// Nominally, by the `Loc`'s `name`, which the program chose.
+  Nominal,
+  /// Structurally, by this hash (of the thunk's program point and
+  /// argument, or of the cell's content); the `Loc`'s `name` is the
+  /// name of this hash.
+  Structural(u64),
+}
+
+impl Loc {
+  /// True if the `Loc` is in namespace `prefix`, or in one nested
+  /// within it (see `engine::ns`).
+  pub fn in_namespace(&self, prefix:&[Name]) -> bool {
+    self.path.len() >= prefix.len() && &self.path[..prefix.len()] == prefix
+  }
+
+  /// The namespace of the `Loc`, up to nesting depth `depth`: the first
+  /// `depth` names of its path (or its entire path, if shorter).
+  pub fn namespace(&self, depth:usize) -> Path {
+    self.path.iter().take(depth).cloned().collect()
+  }
 }
 
 /// A `Path` here is just a `Vec` of `Name`s
 pub type Path = Vec<Name>;
 
+/// A path, formatted for people (as by its `Display`); see `path_display`.
+#[derive(Clone,Copy,Debug)]
+pub struct PathDisplay<'a>(pub &'a [Name]);
+
+/// The concise, human-oriented form of a path, for messages: the
+/// names after a `/` each (e.g., `/doc1/list`), as for a `Loc`.  With
+/// a precision (e.g., `{:.20}`), a longer path loses its outermost
+/// names to an ellipsis (`…`).
+///
+/// ```
+/// use adapton::engine::*;
+/// use adapton::reflect::path_display;
+/// let p = vec![name_of_str("doc1"), name_of_str("list")];
+/// assert_eq!(format!("{}", path_display(&p)), "/doc1/list");
+/// assert_eq!(format!("{:.7}", path_display(&p)), "…1/list");
+/// assert_eq!(format!("{}", path_display(&[])), "/");
+/// ```
+pub fn path_display<'a>(path:&'a [Name]) -> PathDisplay<'a> { PathDisplay(path) }
+
 /// Reflected version of `engine::Effect`
 #[derive(Debug,Clone,Eq,PartialEq,Hash)]
 pub enum Effect {
@@ -178,6 +226,8 @@ pub struct Succ {
   /// the engine does not store these duplicate edges: They are
   /// completely redundant.
   pub is_dup: bool,
+  /// The user label of this edge, if any (see `engine::force_labeled`).
+  pub label: Option<&'static str>,
 }
 
 // Note: Can't implement Reflect<T> for T, since that conflicts with
@@ -269,9 +319,66 @@ pub struct DCG {
   /// by the `ns` (namespace) combinator.  This path variable
   /// determines the path for each allocated `Loc`.
   pub path:  Vec<Name>,
+  /// The user annotations of the nodes, by their `Loc`s (see `engine::annotate`).
+  pub annotations: HashMap<Loc, Vec<(engine::AnnotationKey, String)>>,
 }
 
 
+/// Groups the nodes of a reflected DCG by their namespaces, up to
+/// nesting depth `depth` (see `Loc::namespace`); e.g., with depth `1`,
+/// by the outermost namespace of each node, which often identifies a
+/// subsystem of the program.  The groups, and the locations within
+/// each, are in order.
+pub fn group_by_namespace(dcg:&DCG, depth:usize) -> BTreeMap<Path, Vec<&Loc>> {
+  let mut groups : BTreeMap<Path, Vec<&Loc>> = BTreeMap::new();
+  for loc in dcg.table.keys() {
+    groups.entry(loc.namespace(depth)).or_insert_with(Vec::new).push(loc)
+  }
+  for locs in groups.values_mut() {
+    locs.sort_by(|a, b| (&a.path, &a.name).cmp(&(&b.path, &b.name)))
+  }
+  groups
+}
+
+/// A summary of the nodes of one namespace; see `summarize_namespaces`.
+#[derive(Debug,Clone,Default,PartialEq,Eq,Hash)]
+pub struct NamespaceSummary {
+  /// Number of thunk nodes
+  pub comp_nodes: usize,
+  /// Number of ref cell nodes
+  pub ref_nodes: usize,
+  /// Number of pure nodes
+  pub pure_nodes: usize,
+  /// Number of nodes identified structurally (see `LocId`)
+  pub structural: usize,
+  /// Number of the nodes' outgoing edges
+  pub edges: usize,
+  /// Number of the nodes' outgoing edges that are dirty
+  pub dirty_edges: usize,
+}
+
+/// Summarizes the nodes of a reflected DCG by namespace, up to
+/// nesting depth `depth` (see `group_by_namespace`).
+pub fn summarize_namespaces(dcg:&DCG, depth:usize) -> BTreeMap<Path, NamespaceSummary> {
+  group_by_namespace(dcg, depth).into_iter().map(|(path, locs)| {
+    let mut sum = NamespaceSummary::default();
+    for loc in locs {
+      let node = &dcg.table[loc];
+      match *node {
+        Node::Comp(_) => sum.comp_nodes += 1,
+        Node::Ref(_) => sum.ref_nodes += 1,
+        Node::Pure(_) => sum.pure_nodes += 1,
+      };
+      if let LocId::Structural(_) = loc.id { sum.structural += 1 };
+      if let Some(succs) = succs_of_node(node) {
+        sum.edges += succs.len();
+        sum.dirty_edges += succs.iter().filter(|succ| succ.dirty).count();
+      }
+    }
+    (path, sum)
+  }).collect()
+}
+
 /// Wrapper for `parse_val::parse_val`. Transforms most Rust data
 /// that derives `Debug` into a reflected `Val`.
 pub fn reflect_val <V:Debug> (v:&V) -> Val { 
@@ -296,6 +403,13 @@ pub mod trace {
     /// content may or may not also match.  The `bool` indicates the two
     /// cases: `true` means same content, `false` means changed content.
     LocExists(ChangeFlag),      
+    /// The allocation of a (nominal) thunk matched the location of a
+    /// prior cell, which the thunk replaces.
+    LocReplaced,
+    /// The allocation matched the location of a prior allocation of
+    /// another type, which it replaces, as though fresh; its extent
+    /// dirties the edges to the location.
+    LocRetyped,
   }
 
   #[derive(Clone,Debug)]
@@ -402,6 +516,12 @@ pub mod trace {
     /// Transition to the DCG without this edge.  Perhaps it will be
     /// replaced via re-execution, sometime later.
     Remove,
+
+    /// The editor writes the cell that is the target of this edge
+    /// (see `engine::set`), as the given writer, if any (see
+    /// `engine::with_writer`).  The extent of the write consists of
+    /// the dirtying steps that it causes, if any.
+    Write(Option<engine::Name>),
   }
   
   /// An edge in the DCG, representing an effect of the incremental program.
@@ -417,6 +537,35 @@ pub mod trace {
     pub succ: super::Succ,
   }
   
+  /// A single step of the engine, as given to the hook of
+  /// `set_step_hook`: The effect, the edge on which it occurs, and the
+  /// dirty bit of this edge before and after the step, as the engine
+  /// observes it.  (The edges of the editor, and those that a step
+  /// creates, e.g., by an allocation, are not in the DCG; their bits
+  /// are those of the step's edge.)
+  #[derive(Clone,Debug)]
+  pub struct StepEvent {
+    /// The DCG effect of this step
+    pub effect: Effect,
+    /// The DCG edge of this step (the source, and the target and effect, by location)
+    pub edge: Edge,
+    /// True iff the edge is dirty before this step
+    pub dirty_before: bool,
+    /// True iff the edge is dirty after this step, including its
+    /// nested steps (an edge that the step removed, e.g., by
+    /// re-evaluating its source, is not dirty)
+    pub dirty_after: bool,
+  }
+
+  impl StepEvent {
+    /// The step with the given effect, on the given edge, whose dirty
+    /// bit (before and after the step) is that of `edge.succ`.
+    pub fn new(effect:Effect, edge:Edge) -> StepEvent {
+      let dirty = edge.succ.dirty;
+      StepEvent{ effect:effect, edge:edge, dirty_before:dirty, dirty_after:dirty }
+    }
+  }
+
   /// `DCGTrace`: A Rose-tree of DCG edge-effects.  This tree structure
   /// allows the effects to have a a "time interval" that nests around
   /// and within the time intervals of other effects.
@@ -552,6 +701,7 @@ archivist:
                     //     }                        
                     // },
                     Effect::Dirty => trace_count_dirty(role, tr, c),
+                    Effect::Write(_) => for sub_tr in tr.extent.iter() { trace_count_dirty(role, sub_tr, c) },
                     Effect::Remove => unreachable!(),
                     Effect::CleanEdge => unreachable!(),
                     Effect::CleanEval => unreachable!(),
@@ -564,7 +714,9 @@ archivist:
                         c.alloc_nochange.0 += 1;
                         for sub_tr in tr.extent.iter() { trace_count_dirty(role, sub_tr, c) }
                     }
-                    Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentDiff), _) => {
+                    Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentDiff), _) |
+                    Effect::Alloc(AllocCase::LocReplaced, _) |
+                    Effect::Alloc(AllocCase::LocRetyped, _) => {
                         c.alloc_change.0 += 1;
                         for sub_tr in tr.extent.iter() { trace_count_dirty(role, sub_tr, c) }
                     }
@@ -586,6 +738,7 @@ archivist:
                     //     }                        
                     // },
                     Effect::Dirty => trace_count_dirty(role, tr, c),
+                    Effect::Write(_) => unreachable!(),
                     Effect::Remove => assert!(tr.extent.len() == 0),
                     Effect::CleanEdge => assert!(tr.extent.len() == 0),
                     Effect::CleanEval => {
@@ -601,10 +754,13 @@ archivist:
                                 Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentSame), _) => (),
                                 Effect::Alloc(AllocCase::LocFresh, _)                           => change = true,
                                 Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentDiff), _) => change = true,
+                                Effect::Alloc(AllocCase::LocReplaced, _)                        => change = true,
+                                Effect::Alloc(AllocCase::LocRetyped, _)                         => change = true,
                                 Effect::Force(ForceCase::RefGet)        => (), /* XXX/TODO: This is undercounting?; do we need to know if gotten value was different? */
                                 Effect::Force(ForceCase::CompCacheHit)  => (),
                                 Effect::Force(ForceCase::CompCacheMiss) => change = true,
                                 Effect::Dirty => change = true,
+                                Effect::Write(_) => unreachable!(),
                             }
                         }
                         if change { c.reeval_change   += 1 }
@@ -629,7 +785,9 @@ archivist:
                             trace_count_rec(Role::Archivist, sub_tr, c);
                         }
                     }
-                    Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentDiff), _) => {
+                    Effect::Alloc(AllocCase::LocExists(ChangeFlag::ContentDiff), _) |
+                    Effect::Alloc(AllocCase::LocReplaced, _) |
+                    Effect::Alloc(AllocCase::LocRetyped, _) => {
                         c.alloc_change.1 += 1;
                         for sub_tr in tr.extent.iter() { 
                             trace_count_rec(Role::Archivist, sub_tr, c);
@@ -652,3 +810,173 @@ archivist:
         }
     }
 }
+
+/// The DCG as a property graph, for standard graph-analysis tools:
+/// its nodes and edges, with typed attributes, in GraphML or in
+/// Cytoscape's JSON format (see `dcg_graph_now`).  Unlike the dot
+/// output of the engine, these formats preserve the attributes as
+/// data (e.g., for filtering by namespace, or sorting by cost).
+pub mod graph {
+  use std::fmt::Write;
+  use std::time::Duration;
+  use super::Effect;
+
+  /// The kind of a node.
+  #[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+  pub enum Kind {
+    /// A (mutable) reference cell
+    Cell,
+    /// A thunk
+    Thunk,
+    /// A pure cell (see `engine::pure_cell`)
+    Pure,
+  }
+
+  impl Kind {
+    /// The name of the kind, as an attribute value.
+    pub fn as_str(&self) -> &'static str {
+      match *self { Kind::Cell => "cell", Kind::Thunk => "thunk", Kind::Pure => "pure" }
+    }
+  }
+
+  /// A node of the graph.
+  #[derive(Debug,Clone,PartialEq,Eq)]
+  pub struct Node {
+    /// The node's identity: the string form of its `engine::ArtKey`
+    pub id: String,
+    pub kind: Kind,
+    /// Whether any edge from the node is dirty (for a thunk, whether
+    /// its cached result may be stale)
+    pub dirty: bool,
+    /// The node's namespace: the names of its path, separated by `/`
+    pub namespace: String,
+    /// The program point of a thunk
+    pub prog_pt: Option<String>,
+    /// The last-measured execution time of a thunk, if measured (see
+    /// `engine::Flags::auto_inline_below`)
+    pub cost: Option<Duration>,
+    /// The node's value, if its type is registered (see `engine::register_type`)
+    pub value: Option<String>,
+  }
+
+  /// An edge of the graph.
+  #[derive(Debug,Clone,PartialEq,Eq)]
+  pub struct Edge {
+    /// The `id` of the source node
+    pub source: String,
+    /// The `id` of the target node
+    pub target: String,
+    pub effect: Effect,
+    pub dirty: bool,
+    /// The user label of the edge, if any (see `engine::force_labeled`)
+    pub label: Option<&'static str>,
+  }
+
+  /// A DCG, as a property graph; its nodes are in order of their
+  /// `id`s, and the edges of each node in the order of the DCG.
+  #[derive(Debug,Clone,Default,PartialEq,Eq)]
+  pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+  }
+
+  fn effect_str(effect:&Effect) -> &'static str {
+    match *effect { Effect::Force => "force", Effect::Alloc => "alloc" }
+  }
+
+  fn nanos(d:&Duration) -> u64 {
+    d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64
+  }
+
+  fn xml_escape(s:&str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+  }
+
+  fn json_escape(s:&str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+      match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+        c => out.push(c),
+      }
+    }
+    out.push('"');
+    out
+  }
+
+  impl Graph {
+    /// The graph in GraphML.  The attributes of nodes are `kind`,
+    /// `dirty`, `namespace`, `prog_pt`, `cost` (in nanoseconds) and
+    /// `value`; those of edges, `effect`, `dirty` and `label`.
+    /// Absent attributes (e.g., the cost of a cell) are omitted.
+    pub fn graphml(&self) -> String {
+      let mut w = String::new();
+      writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+      writeln!(w, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">").unwrap();
+      for &(id, on, name, ty) in [("kind", "node", "kind", "string"),
+                                  ("dirty", "node", "dirty", "boolean"),
+                                  ("namespace", "node", "namespace", "string"),
+                                  ("prog_pt", "node", "prog_pt", "string"),
+                                  ("cost", "node", "cost", "long"),
+                                  ("value", "node", "value", "string"),
+                                  ("effect", "edge", "effect", "string"),
+                                  ("edge_dirty", "edge", "dirty", "boolean"),
+                                  ("label", "edge", "label", "string")].iter() {
+        writeln!(w, "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>", id, on, name, ty).unwrap();
+      }
+      writeln!(w, "  <graph id=\"dcg\" edgedefault=\"directed\">").unwrap();
+      for nd in self.nodes.iter() {
+        writeln!(w, "    <node id=\"{}\">", xml_escape(&nd.id)).unwrap();
+        writeln!(w, "      <data key=\"kind\">{}</data>", nd.kind.as_str()).unwrap();
+        writeln!(w, "      <data key=\"dirty\">{}</data>", nd.dirty).unwrap();
+        writeln!(w, "      <data key=\"namespace\">{}</data>", xml_escape(&nd.namespace)).unwrap();
+        if let Some(ref p) = nd.prog_pt { writeln!(w, "      <data key=\"prog_pt\">{}</data>", xml_escape(p)).unwrap() };
+        if let Some(ref c) = nd.cost { writeln!(w, "      <data key=\"cost\">{}</data>", nanos(c)).unwrap() };
+        if let Some(ref v) = nd.value { writeln!(w, "      <data key=\"value\">{}</data>", xml_escape(v)).unwrap() };
+        writeln!(w, "    </node>").unwrap();
+      }
+      for e in self.edges.iter() {
+        writeln!(w, "    <edge source=\"{}\" target=\"{}\">", xml_escape(&e.source), xml_escape(&e.target)).unwrap();
+        writeln!(w, "      <data key=\"effect\">{}</data>", effect_str(&e.effect)).unwrap();
+        writeln!(w, "      <data key=\"edge_dirty\">{}</data>", e.dirty).unwrap();
+        if let Some(l) = e.label { writeln!(w, "      <data key=\"label\">{}</data>", xml_escape(l)).unwrap() };
+        writeln!(w, "    </edge>").unwrap();
+      }
+      writeln!(w, "  </graph>").unwrap();
+      writeln!(w, "</graphml>").unwrap();
+      w
+    }
+
+    /// The graph in Cytoscape's JSON format (its `elements`, as for
+    /// `cytoscape.js`, or for import by Cytoscape), with the
+    /// attributes of `graphml` as `data` fields; the `id` of the `i`th
+    /// edge is `e<i>`.
+    pub fn cytoscape_json(&self) -> String {
+      let mut w = String::new();
+      writeln!(w, "{{\"elements\":{{\"nodes\":[").unwrap();
+      for (i, nd) in self.nodes.iter().enumerate() {
+        write!(w, "{{\"data\":{{\"id\":{},\"kind\":\"{}\",\"dirty\":{},\"namespace\":{}",
+               json_escape(&nd.id), nd.kind.as_str(), nd.dirty, json_escape(&nd.namespace)).unwrap();
+        if let Some(ref p) = nd.prog_pt { write!(w, ",\"prog_pt\":{}", json_escape(p)).unwrap() };
+        if let Some(ref c) = nd.cost { write!(w, ",\"cost\":{}", nanos(c)).unwrap() };
+        if let Some(ref v) = nd.value { write!(w, ",\"value\":{}", json_escape(v)).unwrap() };
+        writeln!(w, "}}}}{}", if i + 1 < self.nodes.len() { "," } else { "" }).unwrap();
+      }
+      writeln!(w, "],\"edges\":[").unwrap();
+      for (i, e) in self.edges.iter().enumerate() {
+        write!(w, "{{\"data\":{{\"id\":\"e{}\",\"source\":{},\"target\":{},\"effect\":\"{}\",\"dirty\":{}",
+               i, json_escape(&e.source), json_escape(&e.target), effect_str(&e.effect), e.dirty).unwrap();
+        if let Some(l) = e.label { write!(w, ",\"label\":{}", json_escape(l)).unwrap() };
+        writeln!(w, "}}}}{}", if i + 1 < self.edges.len() { "," } else { "" }).unwrap();
+      }
+      writeln!(w, "]}}}}").unwrap();
+      w
+    }
+  }
+}
@@ -0,0 +1,477 @@
+/*! Testing incremental programs: assertions about the engine's work.
+
+The main property that a test of an incremental program checks,
+beyond its results, is its incremental complexity: e.g., that a
+change re-evaluates one thunk, not all of them.  This module asserts
+the work of the engine, as the deltas of its counters (see `Cnt`):
+
+ - `assert_counts!` runs an expression, and asserts exact or bounded
+   deltas of the named counters, with a table of the expected and
+   actual deltas when an assertion fails.
+
+ - `counts` runs a closure, and gives the deltas; `render_counts` and
+   `assert_golden` compare them (or any rendering) with a golden
+   file, which the environment variable `ADAPTON_BLESS` (re)writes.
+
+ - `use_step_clock` makes the clock of the engine deterministic, so
+   that measured execution times (e.g., for `manage::set_auto_inline`
+   and `MemoGroup`s) do not vary from run to run.
+
+ - `parse_dcg` parses the textual form of a DCG (see
+   `Engine::dump_text`), and `assert_dcg` compares the current DCG
+   with one, node by node; hence, fixtures can state the expected
+   graph, with its edges and dirty bits.
+
+ - `for_each_mode` runs a closure under each identity mode of the
+   engine (see `Mode`), nominal and structural, each on a fresh
+   engine, and asserts that the results agree; hence, a library can
+   check that its naming only changes the engine's work, not its
+   results.
+
+```
+# #[macro_use] extern crate adapton;
+# fn main() {
+use adapton::macros::*;
+use adapton::engine::*;
+manage::init_dcg();
+
+let c = cell(name_of_str("c"), 1);
+let t = { let c = c.clone(); thunk![[t] get!(c) + 1] };
+if cfg!(feature = "counters") {
+    assert_counts!(force(&t); eval == 1, dirty == 0);
+    set(&c, 2);
+    let res = assert_counts!(force(&t); eval == 1, clean <= 1, nominal.same_arg == 0);
+    assert_eq!(res, 3);
+}
+# }
+```
+*/
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use engine::{Cnt, Engine, Name, manage};
+use reflect::Effect;
+
+thread_local!(static CLOCK : Cell<Option<(Instant, Duration, u32)>> = Cell::new(None));
+
+/// The current time of the engine's clock.  Without a step clock (see
+/// `use_step_clock`), this is `Instant::now()`.
+pub fn now () -> Instant {
+    CLOCK.with(|c| match c.get() {
+        None => Instant::now(),
+        Some((start, step, ticks)) => {
+            c.set(Some((start, step, ticks + 1)));
+            start + step * ticks
+        }
+    })
+}
+
+/// With `Some(step)`, makes the engine's clock (of the current thread)
+/// deterministic: each reading of it advances it by `step`, so that
+/// each measured execution time is a multiple of `step`, which depends
+/// only on the program.  With `None`, restores the system clock.
+pub fn use_step_clock (step:Option<Duration>) {
+    CLOCK.with(|c| c.set(step.map(|step| (Instant::now(), step, 0))))
+}
+
+/// Runs `body`, and gives its result, with the work of the current
+/// engine during `body` (or `Cnt::default()` for the `Naive` engine):
+/// the deltas of its sums, and its maxima (e.g., `Cnt::stack`) during
+/// `body`.  The engine's counters are as they were, plus this work.
+pub fn counts<T,F:FnOnce() -> T> (body:F) -> (T, Cnt) {
+    let before = manage::reset_counters();
+    let res = body();
+    let cnt = match (before, manage::counters()) {
+        (Some(mut before), Some(cnt)) => { before.add(&cnt); manage::set_counters(before); cnt },
+        _ => Cnt::default(),
+    };
+    (res, cnt)
+}
+
+/// One assertion of `assert_counts!`: of the delta of the counter
+/// `field`, compared by `op` with `expected`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Expectation {
+    /// The (path of the) field of `Cnt`, e.g., `nominal.same_arg`
+    pub field : &'static str,
+    /// The comparison, e.g., `<=`
+    pub op : &'static str,
+    pub expected : usize,
+    pub actual : usize,
+    /// Whether `actual op expected`
+    pub holds : bool,
+}
+
+/// Panics with a table of the `expectations`, when any fails, and
+/// with all of the deltas `cnt`.  Used by `assert_counts!`.
+pub fn check_counts (cnt:&Cnt, expectations:Vec<Expectation>) {
+    if expectations.iter().all(|e| e.holds) { return };
+    let mut msg = String::from("assert_counts!: unexpected work\n");
+    for e in expectations.iter() {
+        writeln!(msg, "  {} {:<20} {} {:<8} actual: {}",
+                 if e.holds { " " } else { "✗" }, e.field, e.op, e.expected, e.actual).unwrap();
+    }
+    write!(msg, "all counts:\n{}", render_counts(cnt)).unwrap();
+    panic!("{}", msg)
+}
+
+/// Renders the counters `cnt`, one per line (e.g., for golden files).
+pub fn render_counts (cnt:&Cnt) -> String {
+    format!("{:#?}\n", cnt)
+}
+
+/// Asserts that the file at `path` holds `actual`; panics with the
+/// (first) lines that differ, otherwise.  When the environment
+/// variable `ADAPTON_BLESS` is set, writes `actual` to the file,
+/// instead, creating it (e.g., to record a golden file, or to accept
+/// a change).
+pub fn assert_golden<P:AsRef<Path>> (path:P, actual:&str) {
+    let path = path.as_ref();
+    if env::var("ADAPTON_BLESS").is_ok() {
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir).unwrap() };
+        fs::write(path, actual).unwrap_or_else(|e| panic!("assert_golden: cannot write {:?}: {}", path, e));
+        return
+    };
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!("assert_golden: cannot read {:?}: {} (set ADAPTON_BLESS to write it)", path, e)
+    });
+    if expected == actual { return };
+    let mut msg = format!("assert_golden: {:?} differs (set ADAPTON_BLESS to accept the change)\n", path);
+    let (exp, act) : (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    for i in 0..exp.len().max(act.len()) {
+        let (e, a) = (exp.get(i), act.get(i));
+        if e != a {
+            if let Some(e) = e { writeln!(msg, "{:>4} - {}", i + 1, e).unwrap() };
+            if let Some(a) = a { writeln!(msg, "{:>4} + {}", i + 1, a).unwrap() };
+        }
+    }
+    panic!("{}", msg)
+}
+
+/// A DCG, in textual form (see `Engine::dump_text`): its nodes, by
+/// their keys (see `ArtKey`).
+///
+/// The form has a line for each node, in the order of the keys: its
+/// kind (`cell`, `thunk` or `pure`), its key, and when it has a value,
+/// `=` and the value, rendered as in `render_any` (or `_`, for an
+/// unregistered type).  After each thunk, an indented line for each of
+/// its edges, in order: its effect (`force` or `alloc`), the key of
+/// its target, `dirty` when it is dirty, and `:` and its label, if
+/// any (see `force_labeled`).  For example:
+///
+/// ```text
+/// cell "c" = 2
+/// thunk "t" = 21
+///   force "c" dirty
+///   alloc "ns"/@8c1f3a2e
+/// ```
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct DcgText {
+    pub nodes : BTreeMap<String, NodeText>,
+}
+
+/// A node of a `DcgText`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NodeText {
+    pub kind : NodeKind,
+    /// The rendered value; `_` when its type is not registered
+    pub value : Option<String>,
+    /// The edges of a thunk, in order
+    pub edges : Vec<EdgeText>,
+}
+
+/// The kind of a node of a `DcgText`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum NodeKind { Cell, Thunk, Pure }
+
+/// An edge of a `DcgText`.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub struct EdgeText {
+    pub effect : Effect,
+    /// The key of the target
+    pub target : String,
+    pub dirty : bool,
+    pub label : Option<String>,
+}
+
+impl fmt::Display for DcgText {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        for (key, node) in self.nodes.iter() {
+            write!(f, "{} {}", match node.kind { NodeKind::Cell => "cell", NodeKind::Thunk => "thunk", NodeKind::Pure => "pure" }, key)?;
+            if let Some(ref val) = node.value { write!(f, " = {}", val.replace('\n', "\\n"))? };
+            writeln!(f)?;
+            for edge in node.edges.iter() { writeln!(f, "  {}", edge)? };
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EdgeText {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", match self.effect { Effect::Force => "force", Effect::Alloc => "alloc" }, self.target)?;
+        if self.dirty { write!(f, " dirty")? };
+        match self.label { Some(ref label) => write!(f, " : {}", label), None => Ok(()) }
+    }
+}
+
+/// Splits the key at the start of `s` (whose names may contain spaces,
+/// within quotes or pairs) from the rest of `s`; checks its syntax.
+fn split_key (s:&str) -> Result<(&str, &str), String> {
+    let (mut depth, mut quoted, mut escaped) = (0, false, false);
+    let end = s.char_indices().find(|&(_, c)| {
+        if escaped { escaped = false }
+        else if quoted { match c { '\\' => escaped = true, '"' => quoted = false, _ => () } }
+        else { match c { '"' => quoted = true, '(' => depth += 1, ')' => depth -= 1, _ => () } };
+        ! quoted && depth == 0 && c == ' '
+    }).map_or(s.len(), |(i, _)| i);
+    let key = &s[..end];
+    let mut names = vec![];
+    let (mut start, mut depth, mut quoted, mut escaped) = (0, 0, false, false);
+    for (i, c) in key.char_indices() {
+        if escaped { escaped = false }
+        else if quoted { match c { '\\' => escaped = true, '"' => quoted = false, _ => () } }
+        else { match c {
+            '"' => quoted = true, '(' => depth += 1, ')' => depth -= 1,
+            '/' if depth == 0 => { names.push(&key[start..i]); start = i + 1 },
+            _ => (),
+        }}
+    };
+    let last = &key[start..];
+    let structural = last.starts_with('@') && last.len() > 1 && u64::from_str_radix(&last[1..], 16).is_ok();
+    if key.is_empty() || ! names.iter().all(|n| Name::parse(n).is_some()) || ! (structural || Name::parse(last).is_some()) {
+        return Err(format!("invalid key: {}", key))
+    };
+    Ok((key, &s[end..]))
+}
+
+/// Parses the textual form of a DCG (see `DcgText`); ignores blank
+/// lines, and those that start with `#`, and the indentation of the
+/// first node (e.g., of a string literal in a test).  The error gives
+/// the line (numbered from 1) that does not parse.
+///
+/// ```
+/// use adapton::reflect::Effect;
+/// use adapton::testing::*;
+///
+/// let dcg = parse_dcg("cell \"c\" = 2\nthunk \"t\"\n  force \"c\" dirty\n").unwrap();
+/// assert_eq!(dcg.nodes["\"t\""].kind, NodeKind::Thunk);
+/// assert_eq!(dcg.nodes["\"t\""].edges[0],
+///            EdgeText{effect:Effect::Force, target:"\"c\"".to_string(), dirty:true, label:None});
+/// assert_eq!(parse_dcg(&dcg.to_string()), Ok(dcg));
+/// ```
+pub fn parse_dcg (text:&str) -> Result<DcgText, String> {
+    let mut dcg = DcgText::default();
+    let mut last : Option<String> = None;
+    let indent = text.lines().find(|line| ! line.trim().is_empty() && ! line.trim_start().starts_with('#'))
+        .map_or(0, |line| line.len() - line.trim_start().len());
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg:String| format!("parse_dcg: line {}: {}", i + 1, msg);
+        if line.trim().is_empty() || line.trim_start().starts_with('#') { continue };
+        let line = if line.is_char_boundary(indent) && line[..indent].trim().is_empty() { &line[indent..] } else { line };
+        if line.starts_with(' ') {
+            let line = line.trim_start();
+            let (effect, rest) = if line.starts_with("force ") { (Effect::Force, &line[6..]) }
+                else if line.starts_with("alloc ") { (Effect::Alloc, &line[6..]) }
+                else { return Err(err(format!("expected an edge: {}", line))) };
+            let (target, rest) = split_key(rest).map_err(&err)?;
+            let (dirty, rest) = if rest.starts_with(" dirty") { (true, &rest[6..]) } else { (false, rest) };
+            let label = if rest.starts_with(" : ") { Some(rest[3..].to_string()) }
+                else if rest.is_empty() { None }
+                else { return Err(err(format!("unexpected: {}", rest))) };
+            let node = match last { Some(ref key) => dcg.nodes.get_mut(key).unwrap(), None => return Err(err("an edge without a node".to_string())) };
+            if node.kind != NodeKind::Thunk { return Err(err("an edge of a node other than a thunk".to_string())) };
+            node.edges.push(EdgeText{effect:effect, target:target.to_string(), dirty:dirty, label:label});
+        } else {
+            let mut words = line.splitn(2, ' ');
+            let kind = match words.next() {
+                Some("cell") => NodeKind::Cell,
+                Some("thunk") => NodeKind::Thunk,
+                Some("pure") => NodeKind::Pure,
+                _ => return Err(err(format!("expected a node: {}", line))),
+            };
+            let (key, rest) = split_key(words.next().unwrap_or("")).map_err(&err)?;
+            let value = if rest.starts_with(" = ") { Some(rest[3..].replace("\\n", "\n")) }
+                else if rest.is_empty() { None }
+                else { return Err(err(format!("unexpected: {}", rest))) };
+            if dcg.nodes.insert(key.to_string(), NodeText{kind:kind, value:value, edges:vec![]}).is_some() {
+                return Err(err(format!("a second node {}", key)))
+            };
+            last = Some(key.to_string());
+        }
+    };
+    Ok(dcg)
+}
+
+impl DcgText {
+    /// The differences of `actual` from this (expected) DCG, one per
+    /// line: its missing and extra nodes, and those whose kinds, values
+    /// or edges differ.  An expected value `_` matches any value.
+    pub fn diff (&self, actual:&DcgText) -> Vec<String> {
+        let mut diffs = vec![];
+        for (key, exp) in self.nodes.iter() {
+            let act = match actual.nodes.get(key) {
+                None => { diffs.push(format!("missing node {}", key)); continue },
+                Some(act) => act,
+            };
+            if exp.kind != act.kind { diffs.push(format!("{}: kind {:?}, expected {:?}", key, act.kind, exp.kind)) };
+            let values_match = match (&exp.value, &act.value) {
+                (&Some(ref e), &Some(_)) if e == "_" => true,
+                (e, a) => e == a,
+            };
+            if ! values_match { diffs.push(format!("{}: value {:?}, expected {:?}", key, act.value, exp.value)) };
+            if exp.edges != act.edges {
+                let show = |edges:&Vec<EdgeText>| edges.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ");
+                diffs.push(format!("{}: edges [{}], expected [{}]", key, show(&act.edges), show(&exp.edges)))
+            };
+        };
+        for key in actual.nodes.keys().filter(|key| ! self.nodes.contains_key(*key)) {
+            diffs.push(format!("extra node {}", key))
+        };
+        diffs
+    }
+}
+
+/// Asserts that the current DCG is `expected`, in textual form (see
+/// `DcgText` and `DcgText::diff`); panics with the differences,
+/// otherwise.  Panics if called from within a thunk, and for the
+/// `Naive` engine.
+pub fn assert_dcg (expected:&str) {
+    let expected = parse_dcg(expected).unwrap_or_else(|e| panic!("assert_dcg: {}", e));
+    let actual = Engine::dump_text().expect("assert_dcg: the engine is not a DCG");
+    let diffs = expected.diff(&parse_dcg(&actual).unwrap());
+    if ! diffs.is_empty() {
+        panic!("assert_dcg: unexpected DCG\n  {}\nactual DCG:\n{}", diffs.join("\n  "), actual)
+    }
+}
+
+/// A mode of the engine, by how it identifies allocations; see
+/// `for_each_mode`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum Mode {
+    /// A DCG engine that identifies nominal allocations by their names
+    /// (the default)
+    Nominal,
+    /// A DCG engine that ignores names, and identifies each allocation
+    /// structurally (see `Flags::ignore_nominal_use_structural`), without
+    /// the purity optimization, so that its cells remain writable.  The
+    /// cells of equal content (in a namespace) are then one cell.
+    Structural,
+    /// The naive engine, which memoizes nothing (see `manage::init_naive`);
+    /// its cells are immutable (`set` panics)
+    Naive,
+}
+
+/// Every mode.
+pub const MODES : [Mode; 3] = [Mode::Nominal, Mode::Structural, Mode::Naive];
+
+/// The modes whose cells are writable, in the order of `for_each_mode`.
+pub const EDIT_MODES : [Mode; 2] = [Mode::Nominal, Mode::Structural];
+
+impl Mode {
+    /// Initializes a fresh engine of this mode; returns the prior engine.
+    pub fn init (&self) -> Engine {
+        match *self {
+            Mode::Naive => manage::init_naive(),
+            Mode::Nominal | Mode::Structural => {
+                let prior = manage::init_dcg();
+                let structural = *self == Mode::Structural;
+                let _ = manage::set_flags(|f| {
+                    f.ignore_nominal_use_structural = structural;
+                    if structural { f.use_purity_optimization = false };
+                });
+                prior
+            }
+        }
+    }
+}
+
+/// Runs `body` under each mode whose cells are writable (see
+/// `EDIT_MODES`), and asserts that the results agree; gives the
+/// result.  See `for_modes`, e.g., to include the `Naive` mode, for
+/// programs that do not write cells.
+///
+/// ```
+/// use adapton::engine::*;
+/// use adapton::testing::*;
+///
+/// let sum = for_each_mode(|_mode| {
+///     let cells : Vec<Art<usize>> = (0..10).map(|i| cell(name_of_usize(i), i)).collect();
+///     let before : usize = cells.iter().map(|c| force(c)).sum();
+///     set(&cells[3], 30);
+///     before + cells.iter().map(|c| force(c)).sum::<usize>()
+/// });
+/// assert_eq!(sum, 45 + 72);
+/// ```
+pub fn for_each_mode<T:PartialEq+fmt::Debug, F:FnMut(Mode) -> T> (body:F) -> T {
+    for_modes(&EDIT_MODES, body)
+}
+
+/// Runs `body` under each of the `modes`, in order, on a fresh engine
+/// of the mode, with the mode as its argument (e.g., to assert the
+/// engine's work in some modes only); afterwards, restores the prior
+/// engine, and gives the result.
+///
+/// Panics if the results of two modes differ, naming both, or if
+/// `modes` is empty.
+pub fn for_modes<T:PartialEq+fmt::Debug, F:FnMut(Mode) -> T> (modes:&[Mode], mut body:F) -> T {
+    let mut expected : Option<(Mode, T)> = None;
+    let mut prior = None;
+    for mode in modes.iter() {
+        let engine = mode.init();
+        if prior.is_none() { prior = Some(engine) };
+        let res = body(*mode);
+        match expected {
+            None => expected = Some((*mode, res)),
+            Some((ref first, ref first_res)) => if *first_res != res {
+                panic!("for_modes: the {:?} mode gives {:?}, whereas the {:?} mode gives {:?}",
+                       mode, res, first, first_res)
+            },
+        }
+    };
+    if let Some(prior) = prior { let _ = manage::use_engine(prior); };
+    expected.expect("for_modes: no modes").1
+}
+
+/// Evaluates an expression, and asserts the deltas of the engine's
+/// counters during its evaluation; gives its value.
+///
+/// Each assertion compares the delta of a field of `Cnt` (e.g.,
+/// `eval`, `dirty`, `clean`, or a nested field, such as
+/// `nominal.same_arg`) with an expression, by `==`, `<=`, `>=`, `<`,
+/// `>` or `!=`.  When any assertion fails, the panic lists them all,
+/// marking those that fail, and all of the deltas.  See `counts`.
+#[macro_export]
+macro_rules! assert_counts {
+    ( $body:expr ; $( $spec:tt )+ ) => {{
+        let (res, cnt) = $crate::testing::counts(|| $body);
+        let mut expectations = vec![];
+        assert_counts!(@ cnt expectations [] $( $spec )+);
+        $crate::testing::check_counts(&cnt, expectations);
+        res
+    }};
+    // The (nested) field of an expectation, e.g., `nominal.same_arg`
+    (@ $cnt:ident $es:ident [ $( $path:tt )* ] $field:ident . $( $rest:tt )+ ) => {
+        assert_counts!(@ $cnt $es [ $( $path )* $field . ] $( $rest )+)
+    };
+    (@ $cnt:ident $es:ident [ $( $path:tt )* ] $field:ident $op:tt $expected:expr , $( $rest:tt )+ ) => {
+        assert_counts!(@ $cnt $es [ $( $path )* $field ] $op $expected);
+        assert_counts!(@ $cnt $es [] $( $rest )+)
+    };
+    (@ $cnt:ident $es:ident [ $( $path:tt )* ] $field:ident $op:tt $expected:expr $(,)* ) => {
+        assert_counts!(@ $cnt $es [ $( $path )* $field ] $op $expected)
+    };
+    (@ $cnt:ident $es:ident [ $( $path:tt )+ ] $op:tt $expected:expr ) => {
+        let (actual, expected) : (usize, usize) = ($cnt.$( $path )+, $expected);
+        $es.push($crate::testing::Expectation{
+            field:stringify!($( $path )+), op:stringify!($op),
+            expected:expected, actual:actual, holds:actual $op expected,
+        });
+    };
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "serde")]
+
+#[macro_use]
+extern crate adapton;
+#[macro_use]
+extern crate serde;
+
+use std::collections::BTreeMap;
+use adapton::macros::*;
+use adapton::engine::*;
+use adapton::articulate::*;
+use adapton::reflect;
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+enum Shape {
+    Dot,
+    Circle(f64),
+    Rect { w:u32, h:u32 },
+    Poly(Vec<(i32,i32)>),
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+struct Doc {
+    title:String,
+    tags:BTreeMap<String,bool>,
+    shapes:Vec<Shape>,
+    parent:Option<Box<Doc>>,
+}
+
+fn doc(title:&str, w:u32) -> Doc {
+    let mut tags = BTreeMap::new();
+    tags.insert("draft".to_string(), true);
+    Doc{ title:title.to_string(),
+         tags:tags,
+         shapes:vec![Shape::Dot, Shape::Circle(1.5), Shape::Rect{w:w, h:2}, Shape::Poly(vec![(0,0),(1,-1)])],
+         parent:Some(Box::new(Doc{ title:"root".to_string(), tags:BTreeMap::new(), shapes:vec![], parent:None })),
+    }
+}
+
+#[test]
+fn articulate_read_back_roundtrip () {
+    manage::init_dcg();
+    let d = doc("hello", 1);
+    let a = articulate(name_of_str("doc"), &d).unwrap();
+    let d2 : Doc = read_back(&a).unwrap();
+    assert_eq!(d, d2);
+}
+
+#[test]
+fn articulate_reuses_unchanged_containers () {
+    manage::init_dcg();
+    let a1 = articulate(name_of_str("doc"), &doc("hello", 1)).unwrap();
+    let fields = match a1 { Artic::Struct(ref fields) => fields.clone(), _ => unreachable!() };
+    let shapes = match force(&fields)[2].1 { Artic::Seq(ref shapes) => shapes.clone(), _ => unreachable!() };
+
+    // The title lives in the top-level struct cell; the shapes in their own sequence cell.
+    let t1 = {
+        let fields = fields.clone();
+        thunk![[t1] { let _ = force(&fields); () }]
+    };
+    let t2 = {
+        let shapes = shapes.clone();
+        thunk![[t2] { let _ = force(&shapes); () }]
+    };
+    force(&t1); force(&t2);
+
+    // Changing only the rectangle re-allocates the same names, and
+    // leaves the top-level struct and shape sequence unchanged.
+    reflect::dcg_reflect_begin();
+    let a2 = articulate(name_of_str("doc"), &doc("hello", 5)).unwrap();
+    assert_eq!(a1, a2);
+    force(&t1); force(&t2);
+    let counts = reflect::trace::trace_count(&reflect::dcg_reflect_end(), None);
+    assert_eq!(counts.alloc_change.0, 1);
+    assert_eq!(counts.reeval_change + counts.reeval_nochange, 0);
+    let d : Doc = read_back(&a2).unwrap();
+    assert_eq!(d, doc("hello", 5));
+}
+
+#[test]
+fn names_round_trip() {
+    manage::init_dcg();
+    let names : Vec<Name> = vec![name_of_str("a"), name_pair(name_of_usize(1), name_of_isize(-2)),
+                                 name_fork(name_unit()).1];
+    let a = articulate(name_of_str("names"), &names).unwrap();
+    let back : Vec<Name> = read_back(&a).unwrap();
+    assert_eq!(back, names);
+}
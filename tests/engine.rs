@@ -11,8 +11,10 @@ mod engine_is_typesafe {
         use adapton::engine::*;
         manage::init_dcg();
         let n = name_of_str("cell");
-        let _x : Art<usize> = cell(n.clone(), 1);
+        let x : Art<usize> = cell(n.clone(), 1);
         let _y : Art<(usize,usize)> = cell(n, (2,3));
+        // The second cell replaces the first, whose art is stale
+        let _ = force(&x);
     }
 
     #[test]
@@ -24,14 +26,16 @@ mod engine_is_typesafe {
         let t = thunk!([t]{
             let t1 = thunk!([t1]{
                 let n = name_of_str("cell");
-                let _x : Art<usize> = cell(n, 1);
+                let x : Art<usize> = cell(n, 1);
+                x
             });
             let t2 = thunk!([t2]{
                 let n = name_of_str("cell");
                 let _y : Art<(usize,usize)> = cell(n, (2,3));
             });
-            get!(t1);
+            let x = get!(t1);
             get!(t2);
+            get!(x);
         });
         let _ = get!(t);
     }
@@ -184,4 +188,3929 @@ mod engine_api {
         let c : u64      = force_map(&b, |_,x| x as u64);    
         assert_eq!(a as u64, c);
     }
+
+    #[test]
+    fn force_nested_structural_thunk () {
+        // A structural thunk is pure only if it has no successors
+        // after its first evaluation; here, the inner thunk observes a
+        // cell, so the outer thunk must observe the inner one.
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        fn depth (c:Art<usize>, d:usize) -> Art<usize> {
+            thunk(NameChoice::Structural, prog_pt!("depth"),
+                  Rc::new(Box::new(|(c,d):(Art<usize>,usize), ()| {
+                      if d == 0 { force(&c) } else { force(&depth(c, d - 1)) }
+                  })),
+                  (c, d), ())
+        }
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t = depth(c.clone(), 2);
+        assert_eq!(force(&t), 1);
+        set(&c, 2);
+        assert_eq!(force(&t), 2);
+    }
+}
+
+mod engine_manage {
+    #[cfg(feature = "counters")]
+    #[test]
+    fn clear_results_forces_recompute () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) * 10 }] };
+        assert_eq!(force(&t), 20);
+        let before = manage::reset_counters().unwrap();
+        assert_eq!(before.eval, 1);
+        assert_eq!(force(&t), 20);
+        assert_eq!(manage::counters().unwrap().eval, 0);
+        manage::clear_results();
+        assert_eq!(force(&t), 20);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        set(&c, 3);
+        assert_eq!(force(&t), 30);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn clear_keeps_counters_and_drops_nodes () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = thunk![[t] { force(&c) + 1 }];
+        assert_eq!(force(&t), 3);
+        manage::clear();
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        // Re-allocating the same names yields fresh, valid Arts.
+        let c : Art<usize> = cell(name_of_str("c"), 5);
+        let t2 : Art<usize> = thunk![[t] { force(&c) + 1 }];
+        assert_eq!(force(&t2), 6);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_invalidates_outstanding_arts () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        manage::clear();
+        let _ = force(&c);
+    }
+}
+
+mod engine_purity {
+    use std::rc::Rc;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect::trace::AllocKind;
+
+    fn sum_thunk (c:Art<usize>, d:Art<usize>, alloc:bool) -> Art<usize> {
+        thunk_purity(Purity::NoAlloc, NameChoice::Nominal(name_of_str("sum")),
+                     prog_pt!("sum"),
+                     Rc::new(Box::new(move |(c,d):(Art<usize>,Art<usize>), alloc:bool| {
+                         if alloc { let _ = cell(name_of_str("scratch"), 0); }
+                         force(&c) + force(&d)
+                     })),
+                     (c, d), alloc)
+    }
+
+    #[test]
+    fn no_alloc_permits_forcing () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        // The inner thunk allocates, but it is not itself `NoAlloc`.
+        let d = thunk![[d] { let e = cell(name_of_str("e"), 2); force(&e) }];
+        let t = sum_thunk(c.clone(), d, false);
+        assert_eq!(force(&t), 3);
+        set(&c, 10);
+        assert_eq!(force(&t), 12);
+    }
+
+    #[test]
+    fn no_alloc_rejects_allocation () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let t = sum_thunk(c.clone(), c, true);
+        let err = catch_unwind(AssertUnwindSafe(|| force(&t))).unwrap_err();
+        let err = err.downcast::<NoAllocViolation>().unwrap();
+        assert_eq!(err.prog_pt, prog_pt!("sum"));
+        match err.kind { AllocKind::RefCell => (), _ => panic!("expected cell allocation") };
+        manage::init_dcg();
+    }
+
+    fn confined_thunk (c:Art<usize>, escape:bool) -> Art<usize> {
+        thunk_purity(Purity::Confined, NameChoice::Nominal(name_of_str("lib")),
+                     prog_pt!("lib"),
+                     Rc::new(Box::new(move |c:Art<usize>, escape:bool| {
+                         let scratch = if escape { cell(name_of_str("c"), 0) }
+                                       else { sandbox(|| cell(name_of_str("c"), 0)) };
+                         force(&c) + force(&scratch)
+                     })),
+                     c, escape)
+    }
+
+    #[test]
+    fn confined_permits_sandbox_allocation () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let t = confined_thunk(c.clone(), false);
+        assert_eq!(force(&t), 1);
+        // The caller's cell, of the same name, is intact
+        assert_eq!(force(&c), 1);
+        set(&c, 5);
+        assert_eq!(force(&t), 5);
+    }
+
+    #[test]
+    fn confined_rejects_escaping_allocation () {
+        manage::init_dcg();
+        let c = ns(name_of_str("app"), || cell(name_of_str("c"), 1));
+        let t = ns(name_of_str("app"), || confined_thunk(c.clone(), true));
+        let err = catch_unwind(AssertUnwindSafe(|| force(&t))).unwrap_err();
+        let err = err.downcast::<ConfinementViolation>().unwrap();
+        assert_eq!(err.prog_pt, prog_pt!("lib"));
+        assert_eq!(err.sandbox, format!("{:?}", vec![name_of_str("app"), name_of_str("lib")]));
+        match err.kind { AllocKind::RefCell => (), _ => panic!("expected cell allocation") };
+        manage::init_dcg();
+    }
+}
+
+mod engine_set_report {
+    #[test]
+    fn set_report_outcomes () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let u : Art<usize> = cell(name_of_str("u"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        let s : Art<usize> = { let t = t.clone(); thunk![[s] { force(&t) * 2 }] };
+        assert_eq!(force(&s), 4);
+        assert_eq!(set_report(&c, 1), SetOutcome{changed:false, observers_dirtied:0});
+        assert_eq!(set_report(&c, 2), SetOutcome{changed:true, observers_dirtied:2});
+        // Already dirty: no further edges to dirty.
+        assert_eq!(set_report(&c, 3), SetOutcome{changed:true, observers_dirtied:0});
+        assert_eq!(set_report(&u, 5), SetOutcome{changed:true, observers_dirtied:0});
+        assert_eq!(force(&s), 8);
+    }
+}
+
+mod engine_resources {
+    use std::cell::RefCell;
+
+    #[derive(Debug)]
+    struct Rng(RefCell<usize>);
+
+    #[test]
+    fn producers_use_resources () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        for &is_dcg in [true, false].iter() {
+            if is_dcg { manage::init_dcg(); } else { manage::init_naive(); }
+            let _ = provide(Rng(RefCell::new(7)));
+            let c : Art<usize> = cell(name_of_str("c"), 1);
+            let t : Art<usize> = { let c = c.clone(); thunk![[t] {
+                let rng = resource::<Rng>().unwrap();
+                *rng.0.borrow_mut() += 1;
+                let r = *rng.0.borrow();
+                force(&c) + r
+            }] };
+            assert_eq!(force(&t), 9);
+            // Re-providing does not dirty the thunk; only the cell does.
+            let old = provide(Rng(RefCell::new(100))).unwrap();
+            assert_eq!(*old.0.borrow(), 8);
+            if is_dcg {
+                assert_eq!(force(&t), 9);
+                set(&c, 2);
+                assert_eq!(force(&t), 103);
+            }
+            assert!(unprovide::<Rng>().is_some());
+            assert!(resource::<Rng>().is_none());
+        }
+    }
+}
+
+mod engine_names {
+    #[test]
+    fn names_are_interned () {
+        use adapton::engine::*;
+        let _ = name_intern_gc();
+        let count = name_intern_count();
+        let mk = || name_fork(name_pair(name_of_str("a"), name_of_usize(12345678))).1;
+        let n1 = mk();
+        let misses = name_intern_stats().misses;
+        let n2 = mk();
+        assert_eq!(n1, n2);
+        assert!(n1 != name_fork(name_pair(name_of_str("a"), name_of_usize(12345678))).0);
+        // Re-creating the name interns nothing new.
+        assert_eq!(name_intern_stats().misses, misses);
+        assert!(name_intern_count() > count);
+        drop(n1); drop(n2);
+        assert!(name_intern_gc() >= 3);
+        assert_eq!(name_intern_count(), count);
+    }
+
+    #[test]
+    fn unused_names_are_reclaimed_as_the_table_grows () {
+        use adapton::engine::*;
+        let reclaimed = name_intern_stats().reclaimed;
+        let x = name_of_string("x".to_string());
+        // Minting and dropping 200,000 symbols, without calling `name_intern_gc`
+        for i in 0..100000 {
+            let _ = name_pair(name_of_usize(i), x.clone());
+        }
+        assert!(name_intern_count() < 10000, "{}", name_intern_count());
+        assert!(name_intern_stats().reclaimed > reclaimed);
+        // Looking up a string finds its interned symbol
+        let hits = name_intern_stats().hits;
+        assert_eq!(name_of_str("x"), x);
+        assert_eq!(name_intern_stats().hits, hits + 1);
+    }
+}
+
+mod engine_step_hook {
+    #[test]
+    fn step_hook_sees_propagation () {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        use adapton::reflect::trace::{Effect, StepEvent};
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        assert_eq!(force(&t), 2);
+        let events : Rc<RefCell<Vec<StepEvent>>> = Rc::new(RefCell::new(vec![]));
+        let _ = { let events = events.clone();
+                  reflect::set_step_hook(Some(Rc::new(move |ev| events.borrow_mut().push(ev)))) };
+        set(&c, 2);
+        assert_eq!(force(&t), 3);
+        assert!(reflect::set_step_hook(None).is_some());
+        let events = events.borrow();
+        let dirty : Vec<_> = events.iter().filter(|ev| match ev.effect { Effect::Dirty => true, _ => false }).collect();
+        assert_eq!(dirty.len(), 1);
+        assert!(!dirty[0].dirty_before && dirty[0].dirty_after);
+        assert_eq!(dirty[0].edge.succ.loc.name, name_of_str("c"));
+        assert!(events.iter().any(|ev| match ev.effect { Effect::CleanEval => true, _ => false }));
+        // With the hook unset, no more events are recorded.
+        let n = events.len();
+        set(&c, 3);
+        assert_eq!(force(&t), 4);
+        assert_eq!(events.len(), n);
+    }
+
+    #[test]
+    fn step_events_observe_the_dirty_bits () {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        use adapton::reflect::trace::{Effect, StepEvent};
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let parity : Art<usize> = { let c = c.clone(); thunk![[parity] force(&c) % 2] };
+        let t : Art<usize> = { let parity = parity.clone(); thunk![[t] force(&parity) * 10] };
+        assert_eq!(force(&t), 10);
+        set(&c, 3);
+        let events : Rc<RefCell<Vec<StepEvent>>> = Rc::new(RefCell::new(vec![]));
+        let _ = { let events = events.clone();
+                  reflect::set_step_hook(Some(Rc::new(move |ev| events.borrow_mut().push(ev)))) };
+        // The parity re-evaluates, and is unchanged: the edge from `t`
+        // is cleaned, without re-evaluating `t`
+        assert_eq!(force(&t), 10);
+        let _ = reflect::set_step_hook(None);
+        let events = events.borrow();
+        // The dirty bits of the steps of the given kind, on the edges from `src`
+        let of = |kind:&str, src:&'static str| -> Vec<(bool, bool)> {
+            events.iter().filter(|ev| {
+                let is_kind = match ev.effect {
+                    Effect::CleanRec => kind == "rec", Effect::CleanEval => kind == "eval",
+                    Effect::CleanEdge => kind == "edge", _ => false };
+                is_kind && ev.edge.loc.as_ref().map(|l| l.name.clone()) == Some(name_of_str(src))
+            }).map(|ev| (ev.dirty_before, ev.dirty_after)).collect()
+        };
+        assert_eq!(of("edge", "t"), vec![(true, false)]);
+        assert_eq!(of("rec", "t"), vec![(true, false)]);
+        assert_eq!(of("rec", "parity"), vec![(true, false)]);
+        assert_eq!(of("eval", "parity"), vec![(true, false)]);
+        assert_eq!(of("eval", "t"), vec![]);
+    }
+}
+
+mod engine_rec_cell {
+    use adapton::engine::*;
+
+    #[cfg(feature = "counters")]
+    #[derive(Clone,PartialEq,Eq,Hash,Debug)]
+    struct Cyc { v:usize, next:Art<Cyc> }
+
+    #[cfg(feature = "counters")]
+    fn ring (v1:usize, v2:usize) -> Art<Cyc> {
+        rec_cell(name_of_str("a"), |a| Cyc{v:v1, next:cell(name_of_str("b"), Cyc{v:v2, next:a})})
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn rec_cell_ties_the_knot () {
+        use adapton::macros::*;
+        manage::init_dcg();
+        let a = ring(1, 2);
+        let b = force(&a).next;
+        assert_eq!(force(&b).v, 2);
+        assert_eq!(force(&force(&b).next), force(&a));
+        let t : Art<usize> = { let a = a.clone(); thunk![[t] {
+            let x = force(&a);
+            let y = force(&x.next);
+            x.v + y.v + force(&y.next).v
+        }] };
+        assert_eq!(force(&t), 4);
+        // Re-defining the ring with the same content changes nothing.
+        assert_eq!(ring(1, 2), a);
+        let _ = manage::reset_counters();
+        assert_eq!(force(&t), 4);
+        assert_eq!(manage::counters().unwrap().eval, 0);
+        // Changing part of it re-evaluates its observers.
+        assert_eq!(ring(1, 5), a);
+        assert_eq!(force(&t), 7);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "rec_cell requires the DCG engine")]
+    fn rec_cell_requires_the_dcg () {
+        let _ = manage::init_naive();
+        let _ : Art<usize> = rec_cell(name_of_str("a"), |_| 1);
+    }
+}
+
+mod engine_memo_policy {
+    #[test]
+    fn transparent_thunks_inline_their_dependencies () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let inc = thunk_memo(MemoPolicy::Transparent, NameChoice::Structural,
+                             prog_pt!("inc"),
+                             Rc::new(Box::new(|c:Art<usize>, ()| force(&c) + 1)),
+                             c.clone(), ());
+        let t : Art<usize> = { let inc = inc.clone(); thunk![[t] { force(&inc) * 2 }] };
+        let nodes = || reflect::dcg_reflect_now().unwrap().table.len();
+        assert_eq!(force(&t), 4);
+        // Only the cell and the memoized thunk have DCG nodes.
+        assert_eq!(nodes(), 2);
+        set(&c, 2);
+        assert_eq!(force(&t), 6);
+        assert_eq!(force(&inc), 3);
+        assert_eq!(nodes(), 2);
+    }
+}
+
+mod engine_auto_inline {
+    #[test]
+    fn auto_inline_cheap_thunks_and_repromote () {
+        use std::time::Duration;
+        use std::thread::sleep;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        manage::init_dcg();
+        assert_eq!(manage::set_auto_inline(Some(Duration::from_millis(50))), None);
+        let slow : Art<bool> = cell(name_of_str("slow"), false);
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let (slow, c) = (slow.clone(), c.clone()); thunk![[t] {
+            let (slow, c) = (slow.clone(), c.clone());
+            let inner = thunk![[inner] {
+                if force(&slow) { sleep(Duration::from_millis(100)) };
+                force(&c) + 1
+            }];
+            force(&inner) * 2
+        }] };
+        // Whether `t` depends on a DCG node for `inner` (or else, inlines it).
+        let is_inner_memo = || {
+            let dcg = reflect::dcg_reflect_now().unwrap();
+            let (_, t) = dcg.table.iter().find(|&(l, _)| l.name == name_of_str("t")).unwrap();
+            reflect::succs_of_node(t).unwrap().iter().any(|s| s.loc.name == name_of_str("inner"))
+        };
+        // First run: no cost is known yet, so `inner` is memoized (and measured).
+        assert_eq!(force(&t), 4);
+        assert!(is_inner_memo());
+        // Re-run: `inner` is cheap, so it is inlined.
+        set(&c, 2);
+        assert_eq!(force(&t), 6);
+        assert!(!is_inner_memo());
+        // The inlined `inner` becomes slow; on the next re-run, it is memoized again.
+        set(&slow, true);
+        assert_eq!(force(&t), 6);
+        set(&c, 3);
+        assert_eq!(force(&t), 8);
+        assert!(is_inner_memo());
+        assert_eq!(manage::set_auto_inline(None), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn inlining_a_memoized_thunk_refreshes_its_node () {
+        use std::rc::Rc;
+        use std::time::Duration;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        manage::set_auto_inline(Some(Duration::from_secs(10)));
+        let times_ten = |k:usize| thunk(NameChoice::Nominal(name_of_str("inner")), prog_pt!("times_ten"),
+                                        Rc::new(Box::new(|k:usize, ()| k * 10)), k, ());
+        // No cost is known yet, so `inner` is memoized, and measured
+        let inner = times_ten(1);
+        let obs = { let inner = inner.clone(); thunk![[obs] force(&inner) + 1] };
+        assert_eq!(force(&obs), 11);
+        // `inner` is cheap, so its re-allocation is inlined; yet, its
+        // node gets the new argument, and its observer re-evaluates
+        assert_eq!(force(&times_ten(2)), 20);
+        assert_eq!(force(&inner), 20);
+        assert_eq!(force(&obs), 21);
+    }
+}
+
+mod engine_edge_labels {
+    #[test]
+    fn labels_appear_on_edges_and_in_traces () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        use adapton::reflect::trace::{Effect, EffectEdge};
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] {
+            let d = cell_labeled(name_of_str("d"), 10, "scratch");
+            force_labeled(&c, "input") + force(&d)
+        }] };
+        assert_eq!(force(&t), 11);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let (_, t_nd) = dcg.table.iter().find(|&(l, _)| l.name == name_of_str("t")).unwrap();
+        let labels : Vec<_> = reflect::succs_of_node(t_nd).unwrap().iter()
+            .map(|s| (s.loc.name.clone(), s.label)).collect();
+        assert_eq!(labels, vec![(name_of_str("d"), Some("scratch")),
+                                (name_of_str("c"), Some("input")),
+                                (name_of_str("d"), None)]);
+        reflect::dcg_reflect_begin();
+        set(&c, 2);
+        let traces = reflect::dcg_reflect_end();
+        let dirty_labels : Vec<_> = traces.iter().flat_map(|tr| tr.extent.iter().chain(Some(tr))).filter_map(|tr| match (&tr.effect, &tr.edge) {
+            (&Effect::Dirty, &EffectEdge::Fwd(ref e)) => Some(e.succ.label),
+            _ => None }).collect();
+        assert_eq!(dirty_labels, vec![Some("input")]);
+    }
+}
+
+mod engine_fast_eq {
+    use std::cell::Cell;
+    use std::hash::{Hash, Hasher};
+    use adapton::engine::*;
+
+    thread_local!(static DEEP_EQS: Cell<usize> = Cell::new(0));
+
+    #[derive(Debug,Eq)]
+    struct Big(Vec<usize>);
+    impl PartialEq for Big {
+        fn eq(&self, other:&Big) -> bool { DEEP_EQS.with(|c| c.set(c.get() + 1)); self.0 == other.0 }
+    }
+    impl Hash for Big {
+        fn hash<H:Hasher>(&self, state:&mut H) { self.0.hash(state) }
+    }
+    impl FastEq for Big {
+        fn fast_eq(&self, _other:&Big) -> Option<bool> { None }
+    }
+    fn deep_eqs () -> usize { DEEP_EQS.with(|c| c.get()) }
+
+    #[test]
+    fn fast_shortcuts_comparisons () {
+        let a = Fast::new(Big(vec![1,2,3]));
+        assert!(a == a.clone());
+        assert!(a != Fast::new(Big(vec![1,2,4])));
+        assert_eq!(deep_eqs(), 0);
+        assert!(a == Fast::new(Big(vec![1,2,3])));
+        assert_eq!(deep_eqs(), 1);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn fast_results_cut_off_propagation () {
+        use adapton::macros::*;
+        use adapton::catalog::collections::{List, ListIntro};
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<Fast<List<usize>>> = { let c = c.clone(); thunk![[t] {
+            let _ = force(&c);
+            Fast::new(List::cons(1, List::art(cell(name_of_str("rest"), List::nil()))))
+        }] };
+        let u : Art<usize> = { let t = t.clone(); thunk![[u] { let _ = force(&t); 0 }] };
+        assert_eq!(force(&u), 0);
+        set(&c, 2);
+        let _ = manage::reset_counters();
+        assert_eq!(force(&u), 0);
+        // `t` re-evaluates to an equal list; `u` does not re-evaluate.
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        assert_eq!(List::nil().fast_eq(&List::cons(1, List::<usize>::nil())), Some(false));
+    }
+}
+
+mod engine_pure_cell {
+    #[test]
+    fn pure_cells_have_no_observer_edges () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        manage::init_dcg();
+        let table : PureArt<Vec<usize>> = pure_cell(name_of_str("table"), vec![1,2,3]);
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let (table, c) = (table.clone(), c.clone()); thunk![[t] {
+            force_pure(&table)[force(&c)]
+        }] };
+        assert_eq!(force(&t), 2);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let (_, t_nd) = dcg.table.iter().find(|&(l, _)| l.name == name_of_str("t")).unwrap();
+        assert_eq!(reflect::succs_of_node(t_nd).unwrap().len(), 1);
+        assert_eq!(pure_cell(name_of_str("table"), vec![1,2,3]), table);
+        set(&c, 2);
+        assert_eq!(force(&t), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn pure_cells_cannot_change () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let _ = pure_cell(name_of_str("table"), vec![1,2,3]);
+        let _ = pure_cell(name_of_str("table"), vec![4]);
+    }
+}
+
+mod engine_demand_stack {
+    #[test]
+    fn demand_stack_lists_forced_thunks () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        assert_eq!(demand_stack(), vec![]);
+        let u : Art<Vec<Name>> = thunk![[u] {
+            demand_stack().into_iter().map(|loc| loc.name).collect()
+        }];
+        let t : Art<Vec<Name>> = { let u = u.clone(); thunk![[t] { force(&u) }] };
+        assert_eq!(force(&t), vec![name_of_str("t"), name_of_str("u")]);
+        assert_eq!(demand_stack(), vec![]);
+    }
+
+    #[test]
+    fn engine_panics_include_demand_stack () {
+        use std::panic;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let x = cell(name_of_str("x"), "ambiguous");
+        let t : Art<usize> = thunk![[t] { let _ = cell(name_of_str("x"), 1); force(&x).len() }];
+        let err = panic::catch_unwind(panic::AssertUnwindSafe(|| force(&t))).unwrap_err();
+        let msg = err.downcast::<String>().unwrap();
+        assert!(msg.contains("dynamic type error"));
+        assert!(msg.contains("Demand stack (innermost first):\n\t0: "));
+        assert!(msg.contains("\"t\""));
+    }
+}
+
+mod engine_update {
+    #[test]
+    fn update_and_compare_and_set () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) * 10 }] };
+        assert_eq!(force(&t), 10);
+        assert_eq!(update(&c, |x| x + 1), SetOutcome{changed:true, observers_dirtied:1});
+        assert_eq!(force(&t), 20);
+        assert_eq!(update(&c, |x| *x), SetOutcome{changed:false, observers_dirtied:0});
+        assert_eq!(compare_and_set(&c, &5, 7), Err(2));
+        assert_eq!(compare_and_set(&c, &2, 2), Ok(SetOutcome{changed:false, observers_dirtied:0}));
+        assert_eq!(compare_and_set(&c, &2, 3).map(|o| o.changed), Ok(true));
+        assert_eq!(force(&t), 30);
+    }
+}
+
+mod engine_name_coins {
+    #[test]
+    fn name_coins_and_levels_are_deterministic () {
+        use adapton::engine::*;
+        let names : Vec<Name> = (0..1000).map(name_of_usize).collect();
+        let coins : Vec<bool> = names.iter().map(name_coin).collect();
+        let levels : Vec<u32> = names.iter().map(name_level).collect();
+        manage::init_dcg();
+        assert_eq!(coins, (0..1000).map(|i| name_coin(&name_of_usize(i))).collect::<Vec<_>>());
+        assert_eq!(levels, (0..1000).map(|i| name_level(&name_of_usize(i))).collect::<Vec<_>>());
+        let heads = coins.iter().filter(|c| **c).count();
+        assert!(heads > 400 && heads < 600);
+        let zeros = levels.iter().filter(|l| **l == 0).count();
+        assert!(zeros > 400 && zeros < 600);
+        assert!(levels.iter().all(|l| *l < 64));
+
+        assert_eq!(set_name_seed(42), 0);
+        assert_eq!(name_seed(), 42);
+        assert!(coins != names.iter().map(name_coin).collect::<Vec<_>>());
+        assert_eq!(set_name_seed(0), 42);
+        assert_eq!(coins, names.iter().map(name_coin).collect::<Vec<_>>());
+    }
+}
+
+mod engine_set_flags {
+    #[test]
+    fn set_flags_mid_run () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        assert_eq!(force(&t), 2);
+        let prior = manage::set_flags(|flags| flags.check_dcg_is_wf = true).unwrap();
+        assert!(!prior.check_dcg_is_wf);
+        set(&c, 2);
+        assert_eq!(force(&t), 3);
+        let prior = manage::set_flags(|flags| flags.check_dcg_is_wf = false).unwrap();
+        assert!(prior.check_dcg_is_wf);
+        manage::init_naive();
+        assert!(manage::set_flags(|flags| flags.check_dcg_is_wf = true).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ignore_nominal_use_structural")]
+    fn set_flags_rejects_identity_changes () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        assert!(manage::set_flags(|flags| flags.ignore_nominal_use_structural = true).is_some());
+        assert!(manage::set_flags(|flags| flags.ignore_nominal_use_structural = false).is_some());
+        let _ = cell(name_of_str("c"), 1);
+        manage::set_flags(|flags| flags.ignore_nominal_use_structural = true);
+    }
+}
+
+mod engine_propagation_budget {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn chain (len:usize) -> (Art<usize>, Art<usize>) {
+        let c : Art<usize> = cell(name_of_str("c"), 0);
+        let mut t : Art<usize> = c.clone();
+        for i in 0..len {
+            let s = t.clone();
+            t = thunk!(name_of_usize(i) =>> ::engine_propagation_budget::succ, s:s);
+        }
+        (c, t)
+    }
+
+    fn succ (s:Art<usize>) -> usize { force(&s) + 1 }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn counters_report_propagation () {
+        manage::init_dcg();
+        let (c, t) = chain(10);
+        assert_eq!(force(&t), 10);
+        let cnt = manage::counters().unwrap();
+        assert_eq!(cnt.prop_depth, 10);
+        assert_eq!(cnt.prop_edges, 10);
+        set(&c, 1);
+        assert_eq!(force(&t), 11);
+        assert!(manage::counters().unwrap().prop_edges >= 10);
+    }
+
+    #[test]
+    fn budget_bounds_propagation () {
+        use std::panic;
+        manage::init_dcg();
+        let (c, t) = chain(10);
+        let prior = manage::set_propagation_budget(PropagationBudget{max_depth:Some(10), max_edges:None});
+        assert_eq!(prior, Some(PropagationBudget::default()));
+        assert_eq!(force(&t), 10);
+        set(&c, 1);
+        let _ = manage::set_propagation_budget(PropagationBudget{max_depth:None, max_edges:Some(5)});
+        let err = panic::catch_unwind(panic::AssertUnwindSafe(|| force(&t))).unwrap_err();
+        let err = err.downcast::<PropagationBudgetExceeded>().unwrap();
+        assert_eq!(err.budget.max_edges, Some(5));
+        assert_eq!(err.edges, 6);
+    }
+}
+
+mod engine_hooks {
+    #[test]
+    fn hooks_follow_namespaces () {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let dirtied : Rc<RefCell<Vec<Name>>> = Rc::new(RefCell::new(vec![]));
+        let recomputed : Rc<RefCell<Vec<(Name, Option<usize>, usize)>>> = Rc::new(RefCell::new(vec![]));
+        let gpu = vec![name_of_str("gpu")];
+        let h1 = { let dirtied = dirtied.clone();
+                   on_dirty(gpu.clone(), move |loc| dirtied.borrow_mut().push(loc.name.clone())) };
+        let h2 = { let recomputed = recomputed.clone();
+                   on_recompute(gpu.clone(), move |loc, old, new| {
+                       recomputed.borrow_mut().push((loc.name.clone(),
+                                                     old.map(|old| *old.downcast_ref::<usize>().unwrap()),
+                                                     *new.downcast_ref::<usize>().unwrap()))
+                   }) };
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = ns(name_of_str("gpu"), || { let c = c.clone(); thunk![[t] { force(&c) * 2 }] });
+        let u : Art<usize> = { let c = c.clone(); thunk![[u] { force(&c) * 3 }] };
+        assert_eq!(force(&t) + force(&u), 5);
+        assert_eq!(*recomputed.borrow(), vec![(name_of_str("t"), None, 2)]);
+        set(&c, 2);
+        assert_eq!(*dirtied.borrow(), vec![name_of_str("t")]);
+        assert_eq!(force(&t) + force(&u), 10);
+        assert_eq!(recomputed.borrow()[1], (name_of_str("t"), Some(2), 4));
+        assert!(remove_hook(h1) && remove_hook(h2));
+        assert!(!remove_hook(h1));
+        set(&c, 3);
+        assert_eq!(force(&t), 6);
+        assert_eq!((dirtied.borrow().len(), recomputed.borrow().len()), (1, 2));
+    }
+}
+
+mod engine_demand_read {
+    #[test]
+    fn demand_records_no_edge () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::reflect;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        let u : Art<()> = { let t = t.clone(); thunk![[u] { demand(&t) }] };
+        force(&u);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let (_, u_nd) = dcg.table.iter().find(|&(l, _)| l.name == name_of_str("u")).unwrap();
+        assert_eq!(reflect::succs_of_node(u_nd).unwrap().len(), 0);
+        let _ = manage::reset_counters();
+        assert_eq!(read(&t), 2);
+        assert_eq!(manage::counters().unwrap().eval, 0);
+        set(&c, 2);
+        demand(&t);
+        assert_eq!(read(&t), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not clean")]
+    fn read_rejects_stale_values () {
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        demand(&t);
+        set(&c, 2);
+        read(&t);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_art_obj {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    trait Shape { fn kind(&self) -> &'static str; fn area(&self) -> usize; }
+    struct Square(usize);
+    struct Rect(usize, usize);
+    impl Shape for Square { fn kind(&self) -> &'static str { "square" } fn area(&self) -> usize { self.0 * self.0 } }
+    impl Shape for Rect   { fn kind(&self) -> &'static str { "rect" }   fn area(&self) -> usize { self.0 * self.1 } }
+
+    #[test]
+    fn art_obj_results_cut_off () {
+        assert!(!register_art_obj::<Shape,_,_>(|a, b| a.kind() == b.kind() && a.area() == b.area(),
+                                               |a| a.area() as u64));
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 20);
+        let shape : Art<ArtObj<Shape>> = { let c = c.clone(); thunk![[shape] {
+            let n = force(&c) / 10;
+            if n % 2 == 0 { ArtObj::new(Rc::new(Square(n)) as Rc<Shape>) }
+            else { ArtObj::new(Rc::new(Rect(n, n + 1)) as Rc<Shape>) }
+        }] };
+        let area : Art<usize> = { let shape = shape.clone(); thunk![[area] { force(&shape).area() }] };
+        assert_eq!(force(&area), 4);
+        assert_eq!(force(&shape).kind(), "square");
+        set(&c, 30);
+        assert_eq!(force(&area), 12);
+        assert_eq!(force(&shape).kind(), "rect");
+        // Re-evaluating `shape` gives an equal (but not identical) object, so `area` is reused.
+        let _ = manage::reset_counters();
+        set(&c, 35);
+        assert_eq!(force(&area), 12);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_cutoff {
+    #[test]
+    fn digest_cutoff () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 10);
+        let big : Art<Vec<usize>> =
+            thunk_cutoff(Cutoff::Digest, NameChoice::Nominal(name_of_str("big")), prog_pt!("big"),
+                         Rc::new(Box::new(|c:Art<usize>, ()| (0..1000).map(|i| i * (force(&c) / 10)).collect())),
+                         c.clone(), ());
+        let sum : Art<usize> = { let big = big.clone(); thunk![[sum] { force(&big).iter().sum() }] };
+        assert_eq!(force(&sum), 499500);
+        let _ = manage::reset_counters();
+        set(&c, 15);
+        assert_eq!(force(&sum), 499500);
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.digest_cmps), (1, 1));
+        set(&c, 20);
+        assert_eq!(force(&sum), 999000);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_change_policy {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn latching_cell () {
+        manage::init_dcg();
+        let c : Art<isize> = cell_change(ChangePolicy::epsilon(5.0, |x:&isize| *x as f64), name_of_str("c"), 100);
+        let d : Art<isize> = { let c = c.clone(); thunk![[d] { force(&c) * 2 }] };
+        assert_eq!(force(&d), 200);
+        // Small changes latch the old value, even as they accumulate.
+        let _ = manage::reset_counters();
+        set(&c, 103);
+        set(&c, 96);
+        assert_eq!(force(&c), 100);
+        assert_eq!(force(&d), 200);
+        assert_eq!(manage::counters().unwrap().eval, 0);
+        set(&c, 106);
+        assert_eq!(force(&d), 212);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+
+    #[test]
+    fn threshold_thunk () {
+        manage::init_dcg();
+        let c : Art<isize> = cell(name_of_str("c"), 100);
+        let t : Art<isize> =
+            thunk_change(ChangePolicy::epsilon(5.0, |x:&isize| *x as f64),
+                         NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                         Rc::new(Box::new(|c:Art<isize>, ()| force(&c) + 1)),
+                         c.clone(), ());
+        let u : Art<isize> = { let t = t.clone(); thunk![[u] { force(&t) * 2 }] };
+        assert_eq!(force(&u), 202);
+        // The observer compares against the result that it saw, so
+        // that small changes do not drift.
+        let _ = manage::reset_counters();
+        set(&c, 104);
+        assert_eq!(force(&u), 202);
+        set(&c, 97);
+        assert_eq!(force(&u), 202);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+        set(&c, 106);
+        assert_eq!(force(&u), 214);
+        assert_eq!(manage::counters().unwrap().eval, 4);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_memo_ignore {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn labeled (c:&Art<usize>, label:&str) -> Art<usize> {
+        thunk(NameChoice::Structural, prog_pt!("labeled"),
+              Rc::new(Box::new(|(c, _label):(Art<usize>, MemoIgnore<String>), ()| force(&c) + 1)),
+              (c.clone(), MemoIgnore(label.to_string())), ())
+    }
+
+    #[test]
+    fn ignored_fields_do_not_affect_identity () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let a = labeled(&c, "first");
+        let b = labeled(&c, "second");
+        assert_eq!(a, b);
+        let _ = manage::reset_counters();
+        assert_eq!((force(&a), force(&b)), (2, 2));
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        assert_eq!(*MemoIgnore(3), 3);
+    }
+}
+
+mod engine_capacity {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn presized_engines () {
+        for engine in vec![Engine::with_capacity(100, 400),
+                           Engine::with_profile(LoadProfile::SmallInteractive),
+                           Engine::with_profile(LoadProfile::LargeBatch)] {
+            let _ = manage::use_engine(engine);
+            let c : Art<usize> = cell(name_of_str("c"), 1);
+            let t = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+            assert_eq!(force(&t), 2);
+            set(&c, 2);
+            assert_eq!(force(&t), 3);
+        };
+        let _ = manage::use_engine(Engine::with_profile(LoadProfile::LargeBatch));
+        assert!(! manage::set_flags(|_| ()).unwrap().check_dcg_is_wf);
+    }
+}
+
+mod engine_share_producers {
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn double (nm:&'static str, c:Art<usize>, runs:Rc<Cell<usize>>) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_str(nm)), prog_pt!("double"),
+              Rc::new(Box::new(|c:Art<usize>, runs:Rc<Cell<usize>>| {
+                  runs.set(runs.get() + 1);
+                  force(&c) * 2
+              })),
+              c, runs)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn equal_producers_share_results () {
+        manage::init_dcg();
+        let _ = manage::set_flags(|flags| flags.share_producers = true);
+        let runs = Rc::new(Cell::new(0));
+        let c = cell(name_of_str("c"), 1);
+        let a = double("a", c.clone(), runs.clone());
+        let b = double("b", c.clone(), runs.clone());
+        assert_eq!((force(&a), force(&b)), (2, 2));
+        assert_eq!(runs.get(), 1);
+        assert_eq!(manage::counters().unwrap().shared, 1);
+        // `b` observes `a`, so changes to `c` reach it through `a`.
+        set(&c, 5);
+        assert_eq!(force(&b), 10);
+        assert_eq!(force(&a), 10);
+        assert_eq!(runs.get(), 2);
+    }
+
+    #[test]
+    fn producers_are_not_shared_by_default () {
+        manage::init_dcg();
+        let runs = Rc::new(Cell::new(0));
+        let c = cell(name_of_str("c"), 1);
+        let a = double("a", c.clone(), runs.clone());
+        let b = double("b", c.clone(), runs.clone());
+        assert_eq!((force(&a), force(&b)), (2, 2));
+        assert_eq!(runs.get(), 2);
+    }
+
+    #[test]
+    fn retyped_thunks_are_not_shared () {
+        manage::init_dcg();
+        let _ = manage::set_flags(|flags| flags.share_producers = true);
+        let runs = Rc::new(Cell::new(0));
+        let c = cell(name_of_str("c"), 1);
+        let a = double("a", c.clone(), runs.clone());
+        assert_eq!(force(&a), 2);
+        // The name of `a` now identifies a cell of another type
+        let _ : Art<String> = cell(name_of_str("a"), "a".to_string());
+        let b = double("b", c.clone(), runs.clone());
+        assert_eq!(force(&b), 2);
+        assert_eq!(runs.get(), 2);
+    }
+}
+
+mod engine_global {
+    use adapton::engine::*;
+    use adapton::global;
+
+    #[test]
+    fn default_engine_is_scoped () {
+        let _ = manage::init_naive();
+        let c : Art<usize> = global::cell(name_of_str("c"), 1);
+        assert!(manage::engine_is_naive());
+        global::set(&c, 2);
+        assert_eq!(global::force(&c), 2);
+        assert!(global::with_engine(|| manage::engine_is_dcg()));
+        assert!(manage::engine_is_naive());
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_effect_only {
+    use std::hash::{Hash, Hasher};
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    /// A zero-sized result whose comparisons fail loudly.
+    #[derive(Debug,Clone)]
+    struct Built;
+    impl PartialEq for Built {
+        fn eq(&self, _other:&Built) -> bool { panic!("compared an effect-only result") }
+    }
+    impl Eq for Built { }
+    impl Hash for Built {
+        fn hash<H:Hasher>(&self, _state:&mut H) { }
+    }
+
+    #[test]
+    fn unit_results_are_not_compared () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let build : Art<Built> = {
+            let c = c.clone();
+            thunk![[build] { let _ = cell(name_of_str("out"), force(&c) * 2); Built }]
+        };
+        let read : Art<usize> = {
+            let build = build.clone();
+            thunk![[read] { let _ = force(&build); 0 }]
+        };
+        assert_eq!(force(&read), 0);
+        let _ = manage::reset_counters();
+        set(&c, 2);
+        assert_eq!(force(&read), 0);
+        // Re-runs `build`, but not its observer.
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+}
+
+mod engine_write_conflicts {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn conflicting_writes_within_an_epoch () {
+        manage::init_dcg();
+        let _ = manage::set_flags(|flags| flags.check_write_conflicts = true);
+        let c : Art<usize> = cell(name_of_str("c"), 0);
+        // Both thunks write the cell `out`; they agree until `c` changes.
+        let a : Art<usize> = { let c = c.clone(); thunk![[a] { force(&cell(name_of_str("out"), force(&c) / 10)) }] };
+        let b : Art<usize> = { let c = c.clone(); thunk![[b] { force(&cell(name_of_str("out"), force(&c) % 10)) }] };
+        assert_eq!((force(&a), force(&b)), (0, 0));
+        set(&c, 10);
+        assert_eq!(force(&a), 1);
+        // The value that `b` writes (0) conflicts with that of `a` (1).
+        let err = catch_unwind(AssertUnwindSafe(|| force(&b))).unwrap_err();
+        let err = err.downcast::<WriteConflict>().unwrap();
+        assert_eq!(err.writes.iter().map(|w| w.value.clone()).collect::<Vec<_>>(), vec!["1", "0"]);
+        assert!(err.writes[0].order < err.writes[1].order);
+        manage::init_dcg();
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_frame_pool {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn frames_reuse_edge_vectors () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let a : Art<usize> = { let c = c.clone(); thunk![[a] { force(&c) + 1 }] };
+        let b : Art<usize> = { let a = a.clone(); thunk![[b] { force(&a) * 2 }] };
+        // While `b` is running, `a` cannot reuse its vector.
+        assert_eq!(force(&b), 4);
+        assert_eq!(manage::counters().unwrap().pool_hits, 0);
+        set(&c, 2);
+        assert_eq!(force(&b), 6);
+        assert_eq!(manage::counters().unwrap().pool_hits, 2);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_art_map {
+    use adapton::engine::*;
+
+    #[test]
+    fn map_and_zip () {
+        manage::init_dcg();
+        let a : Art<usize> = cell(name_of_str("a"), 3);
+        let b : Art<usize> = cell(name_of_str("b"), 4);
+        let parity = art_map(&a, name_of_str("parity"), |a| a % 2);
+        let sum = art_zip(&parity, &b, name_of_str("sum"), |p, b| p + b);
+        assert_eq!((force(&parity), force(&sum)), (1, 5));
+        let _ = manage::reset_counters();
+        set(&a, 5);
+        // Re-maps `a`, but the parity is unchanged: `sum` is reused.
+        assert_eq!(force(&sum), 5);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        set(&b, 10);
+        assert_eq!(force(&sum), 11);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_alloc_policy {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    // The editor sets a cell that a thunk allocated; reports the value
+    // that the reader observes, and the number of evaluations.
+    fn edit_allocated_cell (policy:AllocPolicy) -> (usize, usize) {
+        manage::init_dcg();
+        let _ = manage::set_alloc_policy(policy);
+        let alloc : Art<Art<usize>> = thunk![[alloc] { cell(name_of_str("x"), 1) }];
+        let read : Art<usize> = { let alloc = alloc.clone(); thunk![[read] { force(&force(&alloc)) }] };
+        assert_eq!(force(&read), 1);
+        let x = force(&alloc);
+        let _ = manage::reset_counters();
+        set(&x, 100);
+        let res = force(&read);
+        (res, manage::counters().unwrap().eval)
+    }
+
+    #[test]
+    fn default_policy_re_executes_allocators () {
+        // The allocator re-runs, and re-allocates its own content; so,
+        // the reader observes no change.
+        assert_eq!(edit_allocated_cell(AllocPolicy::default()), (1, 1));
+    }
+
+    #[test]
+    fn allocation_edges_without_changes () {
+        let policy = AllocPolicy{ alloc_changes:false, .. AllocPolicy::default() };
+        assert_eq!(edit_allocated_cell(policy), (100, 1));
+        let policy = AllocPolicy{ dirty_allocators:false, .. AllocPolicy::default() };
+        assert_eq!(edit_allocated_cell(policy), (100, 1));
+    }
+}
+
+mod engine_sink {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn sinks_emit_once_per_change () {
+        manage::init_dcg();
+        let out = Rc::new(RefCell::new(vec![]));
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let half = { let c = c.clone(); thunk![[half] { force(&c) / 2 }] };
+        let _ = { let out = out.clone(); sink(name_of_str("log"), &half, move |v| out.borrow_mut().push(v)) };
+        assert_eq!(manage::flush_sinks(), Some(1));
+        set(&c, 0);
+        assert_eq!(manage::flush_sinks(), Some(0));
+        set(&c, 5);
+        assert_eq!(manage::flush_sinks(), Some(1));
+        // Re-evaluating the sink does not repeat its effect.
+        manage::clear_results();
+        assert_eq!(manage::flush_sinks(), Some(0));
+        assert_eq!(*out.borrow(), vec![0, 2]);
+    }
+}
+
+mod engine_dual {
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::dual::*;
+
+    #[test]
+    fn reports_first_divergence () {
+        let mut dual = DualEngine::from_scratch();
+        // The thunk reads `hidden` without any DCG dependency.
+        let hidden = Rc::new(Cell::new(1));
+        let c = dual.cell(name_of_str("c"), 10);
+        let t = dual.thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                           Rc::new(Box::new(|c:Art<usize>, hidden:Rc<Cell<usize>>| force(&c) + hidden.get())),
+                           c.clone(), hidden.clone());
+        assert_eq!(dual.force(&t), 11);
+        dual.set(&c, 20);
+        assert_eq!(dual.force(&t), 21);
+        hidden.set(2);
+        let err = catch_unwind(AssertUnwindSafe(|| dual.force(&t))).unwrap_err();
+        let err = err.downcast::<DualDivergence>().unwrap();
+        assert_eq!((err.first.as_str(), err.second.as_str()), ("21", "22"));
+        assert_eq!(err.op, 5);
+        assert_eq!(err.ops[err.op], "force(#1)");
+    }
+}
+
+mod engine_fuzz {
+    use adapton::fuzz::*;
+
+    #[test]
+    fn random_programs_are_well_formed () {
+        if let Some(repro) = fuzz(0, 100, 40) {
+            panic!("{}", repro.test_source())
+        }
+    }
+
+    #[test]
+    fn shrinks_to_minimal_program () {
+        // A failure that needs a set of 7, after a thunk.
+        let fails = |ops:&[Op]| {
+            ops.iter().skip_while(|op| match **op { Op::Thunk{..} => false, _ => true })
+                .any(|op| match *op { Op::Set{val:7, ..} => true, _ => false })
+        };
+        let mut ops = program(1, 50);
+        ops.insert(10, Op::Thunk{ns:Some(2), name:Some(3), deps:vec![1, 2], alloc:true});
+        ops.push(Op::Set{art:5, val:7});
+        let ops = shrink(ops, &fails);
+        assert_eq!(ops, vec![Op::Thunk{ns:None, name:Some(0), deps:vec![], alloc:false},
+                             Op::Set{art:0, val:7}]);
+    }
+}
+
+mod engine_annotations {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+
+    #[test]
+    fn annotations_survive_recomputation () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        annotate(&t, "region", "1:0-1:5".to_string());
+        annotate(&t, "rule", "inc".to_string());
+        annotate(&t, "region", "1:0-2:0".to_string());
+        assert_eq!(force(&t), 2);
+        set(&c, 2);
+        assert_eq!(force(&t), 3);
+        let expected = vec![("region", "1:0-2:0".to_string()), ("rule", "inc".to_string())];
+        assert_eq!(annotations(&t), expected);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        assert_eq!(dcg.annotations.values().collect::<Vec<_>>(), vec![&expected]);
+        assert!(manage::dot_of_dcg(&DotFilter::default()).unwrap().contains("rule: inc"));
+        manage::clear();
+        assert_eq!(annotations(&t), vec![]);
+    }
+}
+
+mod engine_modes {
+    #[cfg(feature = "counters")]
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::testing::*;
+    #[cfg(feature = "counters")]
+    use std::rc::Rc;
+
+    #[cfg(feature = "counters")]
+    /// The sums of the prefixes of ten cells, by nominal thunks, before
+    /// and after an edit.
+    fn prefix_sums (mode:Mode) -> (Vec<usize>, Vec<usize>) {
+        let cells : Vec<Art<usize>> = ns(name_of_str("in"), || (0..10).map(|i| cell(name_of_usize(i), i)).collect());
+        let sums = |cells:&Vec<Art<usize>>| -> Vec<usize> {
+            let mut prior : Option<Art<usize>> = None;
+            let ts : Vec<Art<usize>> = cells.iter().enumerate().map(|(i, c)| {
+                let t = thunk(NameChoice::Nominal(name_of_usize(i)), prog_pt!("prefix_sum"),
+                              Rc::new(Box::new(|(c, prior):(Art<usize>, Option<Art<usize>>), ()| {
+                                  force(&c) + prior.map_or(0, |p| force(&p))
+                              })), (c.clone(), prior.clone()), ());
+                prior = Some(t.clone());
+                t
+            }).collect();
+            ts.iter().map(|t| force(t)).collect()
+        };
+        let before = ns(name_of_str("sums"), || sums(&cells));
+        set(&cells[5], 50);
+        let (after, cnt) = counts(|| ns(name_of_str("sums"), || sums(&cells)));
+        if mode == Mode::Nominal { assert_eq!(cnt.eval, 5) };
+        (before, after)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn modes_agree () {
+        let (before, after) = for_each_mode(prefix_sums);
+        assert_eq!(before[9], 45);
+        assert_eq!(after[9], 90);
+    }
+
+    #[test]
+    fn modes_use_fresh_engines () {
+        manage::init_naive();
+        let modes = for_modes(&MODES, |mode| {
+            // Each mode's engine is fresh
+            assert_eq!(manage::footprint().map(|f| f.nodes), if mode == Mode::Naive { None } else { Some(0) });
+            let c = cell(name_of_str("c"), 1usize);
+            let nominal = match art_key(&c).map(|k| k.loc().id) {
+                Some(::adapton::reflect::LocId::Nominal) => Some(true),
+                Some(::adapton::reflect::LocId::Structural(_)) => Some(false),
+                None => None,
+            };
+            assert_eq!(nominal, match mode { Mode::Nominal => Some(true), Mode::Structural => Some(false), Mode::Naive => None });
+            assert_eq!(force(&c), 1);
+        });
+        assert_eq!(modes, ());
+        // The prior engine is restored
+        assert!(manage::engine_is_naive());
+    }
+
+    #[test]
+    #[should_panic(expected = "for_modes: the Naive mode gives true, whereas the Nominal mode gives false")]
+    fn modes_that_disagree_panic () {
+        for_modes(&MODES, |_mode| manage::engine_is_naive());
+    }
+}
+
+mod engine_display {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+    use std::rc::Rc;
+
+    #[test]
+    fn names_print_concisely () {
+        let n = name_pair(name_of_str("doc1"), name_pair(name_of_string("a b".to_string()), name_of_usize(3)));
+        assert_eq!(format!("{}", n), "(\"doc1\", (\"a b\", 3))");
+        assert_eq!(format!("{:#}", n), "(doc1, (\"a b\", 3))");
+        assert_eq!(format!("{:#.8}", n), "(doc1, …");
+        assert_eq!(format!("{:.6}", name_of_str("abc")), "\"abc\"");
+        assert_eq!(format!("{:.4}", name_of_str("abc")), "\"ab…");
+        // The canonical form still parses back
+        assert_eq!(Name::parse(&format!("{}", n)), Some(n));
+    }
+
+    #[test]
+    fn locations_print_as_paths () {
+        manage::init_dcg();
+        let c = ns(name_of_str("doc1"), || ns(name_of_str("list"), || cell(name_of_usize(3), 3usize)));
+        let loc = art_key(&c).unwrap().loc();
+        assert_eq!(format!("{}", loc), "/doc1/list/3");
+        assert_eq!(format!("{:.8}", loc), "…/list/3");
+        assert_eq!(format!("{}", reflect::path_display(&loc.path)), "/doc1/list");
+        let t = thunk(NameChoice::Structural, prog_pt!("succ"),
+                      Rc::new(Box::new(|c:Art<usize>, ()| force(&c) + 1)), c.clone(), ());
+        let loc = art_key(&t).unwrap().loc();
+        match loc.id {
+            reflect::LocId::Structural(hash) => assert_eq!(format!("{}", loc), format!("/@{:x}", hash)),
+            reflect::LocId::Nominal => panic!("{} is nominal", loc),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "read: /doc1/t is not clean")]
+    fn panics_give_locations () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = ns(name_of_str("doc1"), || { let c = c.clone(); thunk![[t] { force(&c) + 1 }] });
+        demand(&t);
+        set(&c, 2);
+        read(&t);
+    }
+
+    #[test]
+    fn dot_labels_give_locations () {
+        manage::init_dcg();
+        let c = ns(name_of_str("doc1"), || cell(name_of_str("c"), 1usize));
+        let dot = manage::dot_of_dcg(&DotFilter{ cluster_namespaces:true, ..DotFilter::default() }).unwrap();
+        assert!(dot.contains("label=\"/doc1\""), "{}", dot);
+        assert!(dot.contains("/doc1/c"), "{}", dot);
+        let _ = c;
+    }
+}
+
+mod engine_record_only {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+    use std::rc::Rc;
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn recording_reevaluates_each_force () {
+        manage::init_recording();
+        let c = cell(name_of_str("c"), 1usize);
+        let t = { let c = c.clone(); thunk!([Some(name_of_str("t"))]? { let _ = get!(c); 0 }) };
+        let u = { let t = t.clone(); thunk!([Some(name_of_str("u"))]? get!(t) + get!(t) + 1) };
+        assert_eq!(force(&u), 1);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+        // No cache hits, and no cutoff: `t` re-evaluates, although `c`
+        // did not change, and `u` does, although `t`'s result did not
+        assert_eq!(force(&u), 1);
+        set(&c, 2);
+        assert_eq!(force(&u), 1);
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.cutoff), (6, 0));
+    }
+
+    #[test]
+    fn recording_gives_the_current_dependencies () {
+        manage::init_naive();
+        let ((), g) = reflect::record_dependencies(|| {
+            let flag = cell(name_of_str("flag"), true);
+            let a = cell(name_of_str("a"), 1usize);
+            let b = cell(name_of_str("b"), 2usize);
+            let pick = {
+                let flag = flag.clone();
+                thunk!([Some(name_of_str("pick"))]? if get!(flag) { get!(a) } else { get!(b) })
+            };
+            assert_eq!(force(&pick), 1);
+            set(&flag, false);
+            assert_eq!(force(&pick), 2);
+        });
+        assert!(manage::engine_is_naive());
+        let deps : Vec<(&str, &str)> = g.edges.iter().map(|e| (e.source.as_str(), e.target.as_str())).collect();
+        assert_eq!(deps, vec![("\"pick\"", "\"flag\""), ("\"pick\"", "\"b\"")]);
+        assert!(g.edges.iter().all(|e| ! e.dirty));
+        assert_eq!(g.nodes.len(), 4);
+    }
+}
+
+mod engine_granularity {
+    #[cfg(feature = "counters")]
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::granularity::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    #[cfg(feature = "counters")]
+    use std::rc::Rc;
+
+    #[cfg(feature = "counters")]
+    /// The maximum of 256 items, in chunks; each step changes one item.
+    fn max (s:&Strategy, step:usize) -> usize {
+        let items : Vec<usize> = (0..256).map(|i| if i == step * 7 % 256 { 1000 + step } else { i }).collect();
+        let maxs : Vec<Art<usize>> = s.chunks(items.len()).into_iter().enumerate().map(|(i, r)| {
+            thunk(s.choice(&name_of_str("max"), i), prog_pt!("max"),
+                  Rc::new(Box::new(|xs:Vec<usize>, ()| *xs.iter().max().unwrap())), items[r].to_vec(), ())
+        }).collect();
+        maxs.iter().map(|t| force(t)).max().unwrap()
+    }
+
+    #[test]
+    fn strategies_chunk_and_name () {
+        let s = Strategy{chunk:3, density:0};
+        assert_eq!(s.chunks(7), vec![0..3, 3..6, 6..7]);
+        assert_eq!(s.chunks(0), vec![]);
+        assert_eq!(s.name(&name_of_str("a"), 4), Some(name_pair(name_of_str("a"), name_of_usize(4))));
+        let sparse = Strategy{chunk:3, density:2};
+        let named = (0..1000).filter(|&i| sparse.name(&name_of_str("a"), i).is_some()).count();
+        assert!(named > 150 && named < 350, "{} named", named);
+        assert_eq!(grid(&[1, 8], &[0, 1]),
+                   vec![Strategy{chunk:1, density:0}, Strategy{chunk:1, density:1},
+                        Strategy{chunk:8, density:0}, Strategy{chunk:8, density:1}]);
+        let r = random(3, 20, 16, 4);
+        assert_eq!(r, random(3, 20, 16, 4));
+        assert!(r.iter().all(|s| s.chunk >= 1 && s.chunk <= 16 && s.density <= 4));
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn explore_measures_each_strategy () {
+        manage::init_naive();
+        let strategies = vec![Strategy{chunk:1, density:0}, Strategy{chunk:16, density:0}, Strategy{chunk:16, density:8}];
+        let reports = explore(&strategies, 4, max);
+        assert!(manage::engine_is_naive());
+        assert_eq!(reports.iter().map(|r| r.strategy).collect::<Vec<_>>(), strategies);
+        assert_eq!(reports[0].initial.eval, 256);
+        assert_eq!(reports[1].initial.eval, 16);
+        // Each edit re-evaluates the chunks of its item, and of the
+        // item of the prior step: 0 (items 7 and 0), 0 (14, 7), 1 and
+        // 0 (21, 14), and 1 (28, 21)
+        assert_eq!(reports[1].edits.eval, 5);
+        assert!(reports[0].footprint.nodes > reports[1].footprint.nodes);
+        assert!(reports[0].footprint.bytes > reports[1].footprint.bytes);
+        // The structural chunks of prior steps remain in the DCG
+        assert!(reports[2].footprint.nodes >= reports[1].footprint.nodes);
+        // The finest chunks re-evaluate two thunks per edit
+        assert_eq!(reports[0].edits.eval, 8);
+        assert_eq!(best(&reports).map(|r| r.strategy), Some(Strategy{chunk:16, density:0}));
+        let table = render(&reports);
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.lines().next().unwrap().contains("edit-evals"));
+    }
+
+    #[test]
+    fn explore_rejects_inconsistent_strategies () {
+        manage::init_dcg();
+        let res = catch_unwind(AssertUnwindSafe(|| explore(&grid(&[1, 2], &[0]), 1, |s:&Strategy, step:usize| s.chunk * step)));
+        let msg = res.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("at step 1"), "{}", msg);
+    }
+}
+
+mod engine_acc_cell {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    /// The maximum of some readings, as a monoid.
+    #[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+    struct Max(Option<u32>);
+
+    impl Monoid for Max {
+        fn identity () -> Self { Max(None) }
+        fn combine (&self, other:&Self) -> Self { if self.0 >= other.0 { *self } else { *other } }
+    }
+
+    #[test]
+    fn acc_add_combines_and_skips_the_identity () {
+        manage::init_dcg();
+        let peak = Engine::acc_cell(name_of_str("peak"), Max::identity());
+        let shown = { let peak = peak.clone(); thunk!([Some(name_of_str("shown"))]? force_acc(&peak).0.unwrap_or(0)) };
+        assert_eq!(force(&shown), 0);
+        acc_add(&peak, Max(Some(7)));
+        acc_add(&peak, Max(Some(3)));
+        assert_eq!(force(&shown), 7);
+        let evals = manage::counters().unwrap().eval;
+        // The identity, and a delta that leaves the maximum as is
+        assert_eq!(acc_add(&peak, Max(None)), SetOutcome{changed:false, observers_dirtied:0});
+        assert_eq!(acc_add(&peak, Max(Some(5))), SetOutcome{changed:false, observers_dirtied:0});
+        assert_eq!(force(&shown), 7);
+        assert_eq!(manage::counters().unwrap().eval, evals);
+        assert_eq!(acc_add(&peak, Max(Some(9))), SetOutcome{changed:true, observers_dirtied:1});
+        assert_eq!(force(&shown), 9);
+    }
+
+    #[test]
+    fn acc_cell_feeds_a_dashboard () {
+        manage::init_dcg();
+        let requests : AccArt<u64> = Engine::acc_cell(name_of_str("requests"), 0);
+        let errors : AccArt<u64> = Engine::acc_cell(name_of_str("errors"), 0);
+        let rate = {
+            let (requests, errors) = (requests.clone(), errors.clone());
+            thunk!([Some(name_of_str("rate"))]? {
+                let n = force_acc(&requests);
+                if n == 0 { 0 } else { 100 * force_acc(&errors) / n }
+            })
+        };
+        assert_eq!(force(&rate), 0);
+        for i in 0..50u64 { acc_add(&requests, 1); acc_add(&errors, if i % 10 == 0 { 1 } else { 0 }); }
+        assert_eq!(force(&rate), 10);
+        // Re-allocating the cell overwrites it, as `cell` does
+        let requests = Engine::acc_cell(name_of_str("requests"), 10u64);
+        assert_eq!(force_acc(&requests), 10);
+        assert_eq!(force(&rate), 50);
+    }
+}
+
+mod engine_verify {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    #[cfg(feature = "counters")]
+    use adapton::reflect;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    thread_local!(static TICKS: Cell<usize> = Cell::new(0));
+
+    fn tick () -> usize {
+        TICKS.with(|t| { t.set(t.get() + 1); t.get() })
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn verification_samples_the_cache_hits () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.verify_every = Some(2));
+        let c = cell(name_of_str("c"), 1usize);
+        let t = { let c = c.clone(); thunk!([Some(name_of_str("t"))]? force(&c) + 1) };
+        assert_eq!(force(&t), 2);
+        let before = manage::counters().unwrap();
+        for _ in 0..4 { assert_eq!(force(&t), 2) };
+        let after = manage::counters().unwrap();
+        assert_eq!(after.verifications - before.verifications, 2);
+        assert_eq!(after.eval, before.eval);
+        assert!(manage::take_nondeterminism().is_empty());
+        // Off by default
+        manage::init_dcg();
+        let t = thunk!([Some(name_of_str("t"))]? tick());
+        let first = force(&t);
+        for _ in 0..4 { assert_eq!(force(&t), first) };
+        assert_eq!(manage::counters().unwrap().verifications, 0);
+        assert!(manage::take_nondeterminism().is_empty());
+    }
+
+    #[test]
+    fn verification_flags_nondeterminism_and_keeps_the_dcg () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.verify_every = Some(1));
+        let _ = register_type::<usize>();
+        let c = cell(name_of_str("c"), 1usize);
+        let clock = thunk!([Some(name_of_str("clock"))]? tick());
+        let sum = {
+            let (c, clock) = (c.clone(), clock.clone());
+            thunk!([Some(name_of_str("sum"))]? force(&c) + { let _ = force(&clock); 0 })
+        };
+        assert_eq!(force(&sum), 1);
+        let first = force(&clock);
+        let found = manage::take_nondeterminism();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].prog_pt.symbol.contains("tick"));
+        assert_eq!(found[0].cached, Some(format!("{}", first)));
+        assert_eq!(found[0].fresh, Some(format!("{}", first + 1)));
+        assert!(found[0].explain().contains("is nondeterministic"), "{}", found[0].explain());
+        // The re-evaluation changed neither the cached result, nor the
+        // edges of the thunks: change propagation proceeds as before.
+        assert_eq!(force(&clock), first);
+        let _ = manage::take_nondeterminism();
+        set(&c, 5);
+        assert_eq!(force(&sum), 5);
+        assert_eq!(force(&clock), first);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn verification_discards_the_allocations () {
+        manage::init_dcg();
+        let _ = register_type::<usize>();
+        manage::set_flags(|f| f.verify_every = Some(1));
+        let t = thunk!([Some(name_of_str("t"))]? {
+            let stamp = cell(name_of_str("stamp"), tick());
+            force(&stamp)
+        });
+        let first = force(&t);
+        assert_eq!(force(&t), first);
+        assert_eq!(manage::take_nondeterminism().len(), 1);
+        assert_eq!(manage::counters().unwrap().verifications, 1);
+        // The nominal cell keeps the value of the first evaluation
+        let g = reflect::dcg_graph_now().unwrap();
+        let values : Vec<_> = g.nodes.iter().map(|nd| nd.value.clone()).collect();
+        assert_eq!(values, vec![Some(format!("{}", first)), Some(format!("{}", first))]);
+    }
+}
+
+mod engine_graph_export {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+    use adapton::reflect::graph::Kind;
+    use adapton::testing;
+    use std::time::Duration;
+
+    #[test]
+    fn graph_gives_the_attributes () {
+        manage::init_dcg();
+        testing::use_step_clock(Some(Duration::from_millis(1)));
+        manage::set_auto_inline(Some(Duration::from_nanos(1)));
+        let _ = register_type::<usize>();
+        let c = cell(name_of_str("c"), 1usize);
+        let p = pure_cell(name_of_str("p"), 10usize);
+        let t = ns(name_of_str("a"), || ns(name_of_str("b"), || {
+            let c = c.clone();
+            thunk!([Some(name_of_str("t"))]? force_labeled(&c, "input") + force_pure(&p))
+        }));
+        assert_eq!(force(&t), 11);
+        testing::use_step_clock(None);
+        set(&c, 2);
+        let g = reflect::dcg_graph_now().unwrap();
+        let kinds : Vec<_> = g.nodes.iter().map(|nd| (nd.kind, nd.namespace.as_str(), nd.dirty, nd.value.clone())).collect();
+        assert_eq!(kinds, vec![(Kind::Thunk, "a/b", true, Some(String::from("11"))),
+                               (Kind::Cell, "", false, Some(String::from("2"))),
+                               (Kind::Pure, "", false, Some(String::from("10")))]);
+        assert_eq!(g.nodes[0].prog_pt.as_ref().map(|p| p.contains("force_labeled")), Some(true));
+        assert!(g.nodes[0].cost.unwrap() >= Duration::from_millis(1));
+        assert_eq!(g.nodes[1].cost, None);
+        // The force of the pure cell adds no edge
+        assert_eq!(g.edges.len(), 1);
+        let e = &g.edges[0];
+        assert_eq!((&e.source, &e.target), (&g.nodes[0].id, &g.nodes[1].id));
+        assert_eq!((e.effect.clone(), e.dirty, e.label), (reflect::Effect::Force, true, Some("input")));
+        manage::init_naive();
+        assert_eq!(reflect::dcg_graph_now(), None);
+    }
+
+    #[test]
+    fn graph_formats_escape_their_strings () {
+        manage::init_dcg();
+        let _ = register_type_with::<String,_,_>(|s| s.clone(), |s| reflect::Val::Const(reflect::Const::String(s.clone())));
+        let c = cell(name_of_str("<c>"), String::from("\"a\" & <b>\n"));
+        let t = { let c = c.clone(); thunk!([Some(name_of_str("t"))]? force(&c).len()) };
+        assert_eq!(force(&t), 10);
+        let g = reflect::dcg_graph_now().unwrap();
+        let xml = g.graphml();
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains("<data key=\"value\">&quot;a&quot; &amp; &lt;b&gt;\n</data>"), "{}", xml);
+        assert_eq!(xml.matches("<node ").count(), 2);
+        assert_eq!(xml.matches("<edge ").count(), 1);
+        assert!(! xml.contains("<c>"));
+        let json = g.cytoscape_json();
+        assert!(json.contains(r#""value":"\"a\" & <b>\n""#), "{}", json);
+        assert!(json.contains(r#""id":"e0","source":"#), "{}", json);
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+    }
+}
+
+mod engine_retype {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    #[cfg(feature = "counters")]
+    use adapton::reflect;
+    #[cfg(feature = "counters")]
+    use adapton::reflect::trace::{AllocCase, Effect};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[cfg(feature = "counters")]
+    /// The content of the cell named `n`, if it holds a `usize`, as
+    /// observed by a thunk.
+    fn lookup (n:&'static str) -> Art<Option<usize>> {
+        thunk(NameChoice::Nominal(name_pair(name_of_str("lookup"), name_of_str(n))), prog_pt!("lookup"),
+              Rc::new(Box::new(|n:&'static str, ()| find_cell::<usize>(name_of_str(n)).map(|c| force(&c)))), n, ())
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn cell_becomes_thunk_of_another_type () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.check_dcg_is_wf = true);
+        let n = cell(name_of_str("n"), 1usize);
+        let t = lookup("n");
+        assert_eq!(force(&t), Some(1));
+        manage::reset_counters();
+        reflect::dcg_reflect_begin();
+        let s = thunk(NameChoice::Nominal(name_of_str("n")), prog_pt!("s"),
+                      Rc::new(Box::new(|(), ()| String::from("one"))), (), ());
+        let traces = reflect::dcg_reflect_end();
+        // The allocation dirties the observer of the cell
+        assert!(traces.iter().any(|tr| match tr.effect {
+            Effect::Alloc(AllocCase::LocRetyped, _) => tr.extent.iter().any(|sub| match sub.effect { Effect::Dirty => true, _ => false }),
+            _ => false,
+        }));
+        assert_eq!(manage::counters().unwrap().nominal.retyped, 1);
+        assert_eq!(force(&s), "one");
+        assert_eq!(force(&t), None);
+        // The art of the cell is stale
+        let stale = catch_unwind(AssertUnwindSafe(|| force(&n)));
+        let msg = stale.err().and_then(|e| e.downcast_ref::<String>().cloned()).unwrap();
+        assert!(msg.contains("dynamic type error"), "{}", msg);
+        // And back, to a cell of the first type; the observer no
+        // longer observes the name, so it does not re-evaluate
+        let n = cell(name_of_str("n"), 2usize);
+        assert_eq!(force(&n), 2);
+        assert_eq!(force(&t), None);
+        assert!(catch_unwind(AssertUnwindSafe(|| force(&s))).is_err());
+    }
+
+    #[test]
+    fn thunk_becomes_cell_of_another_type () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.check_dcg_is_wf = true);
+        let c = cell(name_of_str("c"), 3usize);
+        // The outer thunk allocates and observes the inner thunk
+        let outer = thunk(NameChoice::Nominal(name_of_str("outer")), prog_pt!("outer"),
+                          Rc::new(Box::new(|c:Art<usize>, ()| {
+                              let inner = thunk(NameChoice::Nominal(name_of_str("inner")), prog_pt!("inner"),
+                                                Rc::new(Box::new(|c:Art<usize>, ()| force(&c) + 1)), c, ());
+                              force(&inner) * 10
+                          })), c.clone(), ());
+        assert_eq!(force(&outer), 40);
+        let s = cell(name_of_str("inner"), String::from("four"));
+        assert_eq!(force(&s), "four");
+        // The inner thunk's edges are gone: changing its input dirties nothing
+        assert_eq!(set_report(&c, 4).observers_dirtied, 0);
+        // The outer thunk re-evaluates, and replaces the cell with its thunk
+        assert_eq!(force(&outer), 50);
+        assert_eq!(find_cell::<String>(name_of_str("inner")), None);
+        assert_eq!(set_report(&c, 5).observers_dirtied, 2);
+        assert_eq!(force(&outer), 60);
+    }
+
+    #[test]
+    fn mapped_observers_of_a_retyped_cell () {
+        manage::init_dcg();
+        let n = cell(name_of_str("n"), (1usize, 2usize));
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|(), ()| match find_cell::<(usize, usize)>(name_of_str("n")) {
+                          Some(n) => force_map(&n, |_, p| p.0),
+                          None => 0,
+                      })), (), ());
+        assert_eq!(force(&t), 1);
+        let s = cell(name_of_str("n"), 5usize);
+        // Dirtying the new cell passes the (stale) mapped observation
+        set(&s, 6);
+        assert_eq!(force(&t), 0);
+        assert!(catch_unwind(AssertUnwindSafe(|| set(&n, (7, 8)))).is_err());
+        let _ = cell(name_of_str("n"), (7usize, 8usize));
+        assert_eq!(force(&t), 0);
+    }
+}
+
+mod engine_force_metrics {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::testing;
+    use std::time::Duration;
+
+    fn chain (c:&Art<usize>, len:usize) -> Art<usize> {
+        let mut t = c.clone();
+        for i in 0..len {
+            let prev = t.clone();
+            t = thunk!([Some(name_of_usize(i))]? get!(prev) / 2 + 1);
+        }
+        t
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn metrics_of_a_chain () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 100usize);
+        let t = chain(&c, 4);
+        let (res, m) = force_with_metrics(&t);
+        assert_eq!((res, m.visited, m.reruns, m.cleaned, m.cutoffs), (8, 0, 4, 0, 0));
+        // Nothing changed: the force checks the root, only
+        let (_, m) = force_with_metrics(&t);
+        assert_eq!((m.visited, m.reruns, m.cleaned, m.cutoffs), (1, 0, 0, 0));
+        // The change stops at the first thunk: 101/2+1 == 100/2+1
+        set(&c, 101);
+        let (res, m) = force_with_metrics(&t);
+        assert_eq!((res, m.visited, m.reruns, m.cleaned, m.cutoffs), (8, 4, 1, 3, 1));
+        // The change stops at the third thunk; the re-evaluations check
+        // the first two thunks again
+        set(&c, 102);
+        let (res, m) = force_with_metrics(&t);
+        assert_eq!((res, m.visited, m.reruns, m.cleaned, m.cutoffs), (8, 6, 3, 1, 1));
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn metrics_add_to_the_counters () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 100usize);
+        let t = chain(&c, 3);
+        manage::reset_counters();
+        let outer = thunk!([Some(name_of_str("outer"))]? force_with_metrics(&t).1.reruns);
+        assert_eq!(force(&outer), 3);
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.visit), (4, 0));
+    }
+
+    #[test]
+    fn metrics_time_the_force () {
+        manage::init_dcg();
+        testing::use_step_clock(Some(Duration::from_millis(1)));
+        let c = cell(name_of_str("c"), 100usize);
+        let t = chain(&c, 2);
+        let (_, m) = force_with_metrics(&t);
+        testing::use_step_clock(None);
+        assert!(m.time >= Duration::from_millis(1), "{:?}", m);
+        manage::init_naive();
+        let c = cell(name_of_str("c"), 100usize);
+        let (res, m) = force_with_metrics(&chain(&c, 2));
+        assert_eq!((res, m.reruns, m.visited), (26, 0, 0));
+    }
+}
+
+mod engine_last_writer {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+    use adapton::reflect::trace::{Effect, EffectEdge};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[test]
+    fn cells_record_their_writers () {
+        manage::init_dcg();
+        let alice = name_of_str("alice");
+        let c = with_writer(alice.clone(), || cell(name_of_str("c"), 1usize));
+        assert_eq!(Engine::last_writer(&c), Some(alice.clone()));
+        // Reallocating the cell is a write, too
+        with_writer(name_of_str("bob"), || cell(name_of_str("c"), 2usize));
+        assert_eq!(Engine::last_writer(&c), Some(name_of_str("bob")));
+        with_writer(alice.clone(), || update(&c, |n| n + 1));
+        assert_eq!(Engine::last_writer(&c), Some(alice.clone()));
+        // Thunks write without a writer
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|(), ()| cell(name_of_str("d"), 7usize))), (), ());
+        let d = with_writer(alice.clone(), || force(&t));
+        assert_eq!(Engine::last_writer(&d), None);
+        assert_eq!(Engine::last_writer(&t), None);
+        manage::init_naive();
+        let c = cell(name_of_str("c"), 1usize);
+        assert_eq!(with_writer(alice, || Engine::last_writer(&c)), None);
+    }
+
+    #[test]
+    fn traces_give_the_writers () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1usize);
+        let t = { let c = c.clone(); thunk![ get!(c) + 1 ] };
+        assert_eq!(force(&t), 2);
+        reflect::dcg_reflect_begin();
+        with_writer(name_of_str("alice"), || set(&c, 2));
+        set(&c, 3);
+        let traces = reflect::dcg_reflect_end();
+        let writes : Vec<_> = traces.iter().map(|tr| match (&tr.effect, &tr.edge) {
+            (&Effect::Write(ref w), &EffectEdge::Fwd(ref e)) => (w.clone(), e.succ.loc.name.clone(), tr.extent.len()),
+            _ => panic!("unexpected trace: {:?}", tr),
+        }).collect();
+        // The first write dirties the thunk; the second, nothing
+        assert_eq!(writes, vec![(Some(name_of_str("alice")), name_of_str("c"), 1),
+                                (None, name_of_str("c"), 0)]);
+        assert_eq!(reflect::trace::trace_count(&traces, None).dirty.0, 1);
+    }
+
+    #[test]
+    fn writers_nest_and_survive_panics () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1usize);
+        with_writer(name_of_str("outer"), || {
+            with_writer(name_of_str("inner"), || set(&c, 2));
+            assert_eq!(Engine::last_writer(&c), Some(name_of_str("inner")));
+            let _ = catch_unwind(AssertUnwindSafe(|| with_writer(name_of_str("failing"), || panic!("failing"))));
+            set(&c, 3);
+        });
+        assert_eq!(Engine::last_writer(&c), Some(name_of_str("outer")));
+    }
+}
+
+mod engine_quantized {
+    #[cfg(feature = "counters")]
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::f64;
+    use std::hash::{Hash, Hasher};
+    #[cfg(feature = "counters")]
+    use std::rc::Rc;
+
+    fn hash<T:Hash> (x:&T) -> u64 { let mut h = DefaultHasher::new(); x.hash(&mut h); h.finish() }
+
+    #[cfg(feature = "counters")]
+    fn scale (x:Quantized<f64>) -> Art<Quantized<f64>> {
+        thunk(NameChoice::Structural, prog_pt!("scale"),
+              Rc::new(Box::new(|x:Quantized<f64>, ()| Quantized::new(x.quantized() * 2.0, x.1))), x, ())
+    }
+
+    #[test]
+    fn quantized_numbers_compare_by_their_buckets () {
+        let q = |v:f64| Quantized::new(v, 0.5);
+        assert_eq!(q(1.1), q(0.9));
+        assert_eq!(hash(&q(1.1)), hash(&q(0.9)));
+        assert!(q(1.1) != q(1.3));
+        assert_eq!(q(f64::NAN), q(-f64::NAN));
+        assert!(q(f64::NAN) != q(0.0));
+        assert_eq!(q(0.0), q(-0.0));
+        assert_eq!(q(f64::INFINITY), q(f64::INFINITY));
+        assert!(Quantized::new(1.0, 0.5) != Quantized::new(1.0, 0.25));
+        assert_eq!(q(1.1).quantized(), 1.0);
+        assert_eq!(q(1.1).bucket(), Some(2));
+        assert_eq!(q(f64::NAN).bucket(), None);
+        assert_eq!(Quantized::new(0.1f32 + 0.2f32, 1e-4), Quantized::new(0.3f32, 1e-4));
+    }
+
+    #[test]
+    #[should_panic(expected = "not positive and finite")]
+    fn quantized_numbers_need_a_grid () {
+        Quantized::new(1.0, 0.0);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn structural_thunks_match_quantized_arguments () {
+        manage::init_dcg();
+        let sum = (0..10).fold(0.0, |acc, _| acc + 0.1);
+        assert!(sum != 1.0);
+        assert_eq!(force(&scale(Quantized::new(1.0, 1e-6))).0, 2.0);
+        assert_eq!(force(&scale(Quantized::new(1.0, 1e-6))).0, 2.0);
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.create, cnt.quantized_hits), (1, 0));
+        // A rounding error matches the same thunk
+        assert_eq!(force(&scale(Quantized::new(sum, 1e-6))).0, 2.0);
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.create, cnt.eval, cnt.quantized_hits), (1, 1, 1));
+        assert_eq!(force(&scale(Quantized::new(1.1, 1e-6))), Quantized::new(2.2, 1e-6));
+        assert_eq!(manage::counters().unwrap().create, 2);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn nominal_thunks_count_quantized_matches () {
+        manage::init_dcg();
+        let t = |x:f64| thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                              Rc::new(Box::new(|x:Quantized<f64>, ()| x.bucket())), Quantized::new(x, 0.01), ());
+        assert_eq!(force(&t(0.5)), Some(50));
+        assert_eq!(force(&t(0.501)), Some(50));
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.nominal.same_arg, cnt.quantized_hits), (1, 1, 1));
+        assert_eq!(force(&t(0.52)), Some(52));
+        assert_eq!(manage::counters().unwrap().nominal.arg_overwrite, 1);
+    }
+}
+
+mod engine_dump_text {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::testing::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[derive(Debug,Clone,PartialEq,Eq,Hash)]
+    struct Opaque(usize);
+
+    /// A cell, and a thunk (in a namespace with spaces in its name)
+    /// that observes it, with a label, and allocates a structural thunk.
+    fn graph () -> (Art<usize>, Art<usize>) {
+        let c = cell(name_pair(name_of_str("c"), name_of_usize(1)), 2usize);
+        let t = ns(name_of_str("the ns"), || {
+            thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                  Rc::new(Box::new(|c:Art<usize>, ()| {
+                      let o = thunk(NameChoice::Structural, prog_pt!("o"),
+                                    Rc::new(Box::new(|n:usize, ()| Opaque(n))), 7usize, ());
+                      force_labeled(&c, "input") + force(&o).0
+                  })), c.clone(), ())
+        });
+        (c, t)
+    }
+
+    #[test]
+    fn dump_text_round_trips () {
+        manage::init_dcg();
+        register_type::<usize>();
+        let (c, t) = graph();
+        assert_eq!(force(&t), 9);
+        set(&c, 3);
+        let text = Engine::dump_text().unwrap();
+        let dcg = parse_dcg(&text).unwrap();
+        assert_eq!(dcg.to_string(), text);
+        assert_eq!(dcg.nodes.len(), 3);
+        let t = &dcg.nodes["\"the ns\"/\"t\""];
+        assert_eq!((t.kind, t.value.as_ref().map(|v| v.as_str())), (NodeKind::Thunk, Some("9")));
+        // The pure structural thunk has no observation edge
+        assert_eq!(t.edges.len(), 2);
+        assert!(t.edges[0].to_string().starts_with("alloc \"the ns\"/@"), "{}", t.edges[0]);
+        assert_eq!(t.edges[1].to_string(), "force (\"c\", 1) dirty : input");
+        let o = &dcg.nodes[&t.edges[0].target];
+        assert_eq!(o.value, Some("_".to_string()));
+        assert_dcg(&text);
+        manage::init_naive();
+        assert_eq!(Engine::dump_text(), None);
+    }
+
+    #[test]
+    fn assert_dcg_lists_the_differences () {
+        manage::init_dcg();
+        register_type::<usize>();
+        let (c, t) = graph();
+        force(&t);
+        set(&c, 3);
+        let err = catch_unwind(AssertUnwindSafe(|| assert_dcg("
+            cell (\"c\", 1) = 2
+            thunk \"the ns\"/\"t\" = _
+              force (\"c\", 1) : input
+            # the structural thunk is missing
+            cell \"d\" = 1
+        "))).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("(\"c\", 1): value Some(\"3\"), expected Some(\"2\")"), "{}", msg);
+        assert!(msg.contains("missing node \"d\""), "{}", msg);
+        assert!(msg.contains("expected [force (\"c\", 1) : input]"), "{}", msg);
+        assert!(msg.contains("extra node \"the ns\"/@"), "{}", msg);
+        assert!(! msg.contains("\"the ns\"/\"t\": value"), "{}", msg);
+    }
+
+    #[test]
+    fn parse_dcg_rejects_malformed_lines () {
+        assert_eq!(parse_dcg("# a comment\n\ncell \"a b\"/(1, 2)/@ff"), Ok(parse_dcg("cell \"a b\"/(1, 2)/@ff").unwrap()));
+        assert!(parse_dcg("cell \"a\" = 1\n  force \"b\"").unwrap_err().contains("line 2"));
+        assert!(parse_dcg("thunk \"a\"\n  read \"b\"").unwrap_err().contains("expected an edge"));
+        assert!(parse_dcg("thunk a").unwrap_err().contains("invalid key: a"));
+        assert!(parse_dcg("thunk \"a\" 1").unwrap_err().contains("unexpected"));
+        assert!(parse_dcg("cell \"a\"\ncell \"a\"").unwrap_err().contains("a second node"));
+        let dcg = parse_dcg("thunk \"t\" = a\\nb\n  alloc @1f\n  force \"c\" : a label").unwrap();
+        assert_eq!(dcg.nodes["\"t\""].value, Some("a\nb".to_string()));
+        assert_eq!(dcg.nodes["\"t\""].edges[1].label, Some("a label".to_string()));
+        assert_eq!(parse_dcg(&dcg.to_string()), Ok(dcg));
+    }
+}
+
+mod engine_force_all {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    #[cfg(feature = "counters")]
+    use adapton::reflect;
+    #[cfg(feature = "counters")]
+    use adapton::reflect::trace::EffectEdge;
+    use std::rc::Rc;
+
+    fn add (name:&str, a:Art<usize>, k:usize) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_string(name.to_string())), prog_pt!("add"),
+              Rc::new(Box::new(|a:Art<usize>, k:usize| force(&a) + k)), a, k)
+    }
+
+    /// A thunk that observes `FANIN` thunks, each of which ignores the
+    /// value of its cell; and `ROOTS` thunks that observe it.
+    const FANIN : usize = 50;
+    const ROOTS : usize = 10;
+    fn shared () -> (Vec<Art<usize>>, Vec<Art<usize>>) {
+        let cells : Vec<Art<usize>> = (0..FANIN).map(|i| cell(name_of_usize(i), i)).collect();
+        let zeros : Vec<Art<usize>> = cells.iter().enumerate().map(|(i, c)| {
+            thunk(NameChoice::Nominal(name_pair(name_of_str("zero"), name_of_usize(i))), prog_pt!("zero"),
+                  Rc::new(Box::new(|c:Art<usize>, ()| { let _ = force(&c); 0 })), c.clone(), ())
+        }).collect();
+        let sum = thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+                        Rc::new(Box::new(|zs:Rc<Vec<Art<usize>>>, ()| zs.iter().map(|z| force(z)).sum::<usize>())),
+                        Rc::new(zeros), ());
+        let roots = (0..ROOTS).map(|i| add(&format!("root{}", i), sum.clone(), i)).collect();
+        (cells, roots)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn force_all_cleans_shared_thunks_once () {
+        manage::init_dcg();
+        let (cells, roots) = shared();
+        assert_eq!(Engine::force_all(&roots, ForceOrder::Demand), (0..ROOTS).collect::<Vec<_>>());
+        for (i, c) in cells.iter().enumerate() { set(c, i + 1) };
+        manage::reset_counters();
+        assert_eq!(Engine::force_all(&roots, ForceOrder::Demand), (0..ROOTS).collect::<Vec<_>>());
+        let cnt = manage::counters().unwrap();
+        // The thunks over the cells, only; the later roots find the sum cleaned
+        assert_eq!(cnt.eval, FANIN);
+        assert_eq!(cnt.batch_hits, ROOTS - 1);
+        assert_eq!(cnt.clean, FANIN + ROOTS);
+
+        // Forced separately, each root re-checks the sum
+        for (i, c) in cells.iter().enumerate() { set(c, i + 2) };
+        manage::reset_counters();
+        for root in roots.iter() { force(root); }
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.batch_hits), (FANIN, 0));
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn topological_order_forces_dependencies_first () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1usize);
+        let mid = add("mid", c.clone(), 10);
+        let top = add("top", mid.clone(), 100);
+        assert_eq!(Engine::force_all(&vec![top.clone(), mid.clone()], ForceOrder::Topological), vec![111, 11]);
+        // The root that the editor forces first
+        let first = |order| {
+            reflect::dcg_reflect_begin();
+            let res = Engine::force_all(&vec![top.clone(), mid.clone()], order);
+            let first = reflect::dcg_reflect_end().into_iter().filter_map(|tr| match tr.edge {
+                EffectEdge::Fwd(ref edge) if edge.loc.is_none() => Some(edge.succ.loc.clone()),
+                _ => None,
+            }).next().unwrap();
+            (res, first)
+        };
+        set(&c, 2);
+        manage::reset_counters();
+        assert_eq!(first(ForceOrder::Topological), (vec![112, 12], art_key(&mid).unwrap().loc()));
+        // Forcing `mid` first, `top` finds it cleaned
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.batch_hits), (2, 2));
+        set(&c, 3);
+        assert_eq!(first(ForceOrder::Demand), (vec![113, 13], art_key(&top).unwrap().loc()));
+        // Cells and repeated roots
+        assert_eq!(Engine::force_all(&vec![c.clone(), mid.clone(), c], ForceOrder::Topological), vec![3, 13, 3]);
+    }
+
+    #[test]
+    fn force_all_outside_the_batch_is_force () {
+        manage::init_naive();
+        let (_, roots) = shared();
+        assert_eq!(Engine::force_all(&roots, ForceOrder::Topological), (0..ROOTS).collect::<Vec<_>>());
+        manage::init_dcg();
+        let (cells, roots) = shared();
+        Engine::force_all(&roots, ForceOrder::Demand);
+        // After the batch, a change reaches each root
+        set(&cells[0], 7);
+        manage::reset_counters();
+        assert_eq!(force(&roots[3]), 3);
+        assert_eq!(manage::counters().unwrap().batch_hits, 0);
+    }
+}
+
+mod engine_strict_naming {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    fn structural (n:usize) -> Art<usize> {
+        thunk(NameChoice::Structural, prog_pt!("structural"), Rc::new(Box::new(|n:usize, ()| n)), n, ())
+    }
+
+    fn nominal (n:usize) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_usize(n)), prog_pt!("nominal"), Rc::new(Box::new(|n:usize, ()| n)), n, ())
+    }
+
+    #[test]
+    fn mixes_are_permitted_by_default () {
+        manage::init_dcg();
+        assert_eq!(ns(name_of_str("a"), || force(&structural(1)) + force(&nominal(2))), 3);
+    }
+
+    #[test]
+    fn strict_naming_rejects_structural_thunks_in_namespaces () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.strict_naming = true);
+        assert_eq!(force(&structural(1)), 1);
+        assert_eq!(ns(name_of_str("a"), || force(&nominal(2))), 2);
+        let outer = thunk(NameChoice::Nominal(name_of_str("outer")), prog_pt!("outer"),
+                          Rc::new(Box::new(|(), ()| ns(name_of_str("a"), || force(&structural(3))))), (), ());
+        let err = catch_unwind(AssertUnwindSafe(|| force(&outer))).unwrap_err();
+        let v = err.downcast_ref::<NamingViolation>().unwrap();
+        assert_eq!(v.prog_pt, prog_pt!("structural"));
+        assert!(v.namespace.contains("\"a\""), "{:?}", v);
+        assert!(v.producer.as_ref().unwrap().contains("outer"), "{:?}", v);
+    }
+
+    #[test]
+    fn the_allowlist_permits_namespaces_and_those_beneath () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.strict_naming = true);
+        manage::allow_structural_in(vec![name_of_str("lib")]);
+        manage::allow_structural_in(vec![name_of_str("lib")]);
+        assert_eq!(manage::set_flags(|_| ()).unwrap().structural_namespaces.len(), 1);
+        let res = ns(name_of_str("lib"), || ns(name_of_str("x"), || force(&structural(1))));
+        assert_eq!(res, 1);
+        assert!(catch_unwind(|| ns(name_of_str("app"), || structural(2))).is_err());
+    }
+
+    #[test]
+    fn structural_mode_is_exempt () {
+        manage::init_dcg();
+        manage::set_flags(|f| { f.strict_naming = true; f.ignore_nominal_use_structural = true });
+        assert_eq!(ns(name_of_str("a"), || force(&nominal(2))), 2);
+    }
+}
+
+mod engine_art_from_key {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn keys_round_trip_as_strings () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1usize);
+        let t = ns(name_pair(name_of_str("a"), name_of_usize(1)), || {
+            thunk(NameChoice::Structural, prog_pt!("t"),
+                  Rc::new(Box::new(|c:Art<usize>, ()| get!(c) + 1)), c.clone(), ())
+        });
+        let handle = art_key(&t).unwrap().to_string();
+        assert!(handle.starts_with("(\"a\", 1)/@"), "{}", handle);
+        let key = ArtKey::parse(&handle).unwrap();
+        assert_eq!(Some(key.clone()), art_key(&t));
+        let t2 : Art<usize> = Engine::art_from_key(key).unwrap();
+        assert_eq!(get!(t2), 2);
+        set(&c, 5);
+        assert_eq!(get!(t2), 6);
+    }
+
+    #[test]
+    fn unknown_keys_give_none () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1usize);
+        let key = art_key(&c).unwrap();
+        for s in vec!["", "\"c", "\"c\"/", "\"d\"", "\"x\"/\"c\"", "@zz", "c"] {
+            assert_eq!(ArtKey::parse(s), None, "{}", s);
+        }
+        // Keys of other engines
+        let prior = manage::use_engine(Engine::with_capacity(0, 0));
+        assert_eq!(ArtKey::parse("\"c\""), None);
+        assert!(Engine::art_from_key::<usize>(key.clone()).is_none());
+        drop(manage::use_engine(prior));
+        assert!(Engine::art_from_key::<usize>(key.clone()).is_some());
+        // Keys of dropped nodes
+        manage::clear();
+        assert!(Engine::art_from_key::<usize>(key).is_none());
+        assert_eq!(ArtKey::parse("\"c\""), None);
+    }
+}
+
+mod engine_testing {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::testing::*;
+    #[cfg(feature = "counters")]
+    use std::env;
+    #[cfg(feature = "counters")]
+    use std::fs;
+    #[cfg(feature = "counters")]
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn counts_add_to_the_counters () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![[t] get!(c) + 1] };
+        force(&t);
+        let before = manage::counters().unwrap();
+        let (res, cnt) = counts(|| { set(&c, 2); force(&t) });
+        assert_eq!(res, 3);
+        assert_eq!((cnt.eval, cnt.dirty, cnt.create), (1, 1, 0));
+        let mut total = before.clone();
+        total.add(&cnt);
+        assert_eq!(manage::counters().unwrap(), total);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn failed_expectations_list_the_counts () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![[t] get!(c) + 1] };
+        let err = catch_unwind(AssertUnwindSafe(|| assert_counts!(force(&t); eval == 0, nominal.fresh <= 1))).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("✗ eval"), "{}", msg);
+        assert!(msg.contains("  nominal.fresh"), "{}", msg);
+        assert!(msg.contains("actual: 1"), "{}", msg);
+        assert!(msg.contains("all counts:"), "{}", msg);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn golden_files_hold_renderings () {
+        manage::init_dcg();
+        let dir = env::temp_dir().join(format!("adapton-golden-{}", ::std::process::id()));
+        let file = dir.join("counts.txt");
+        let c = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![[t] get!(c) + 1] };
+        let (_, cnt) = counts(|| force(&t));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, render_counts(&cnt)).unwrap();
+        assert_golden(&file, &render_counts(&cnt));
+        // The panic lists the lines that differ, only
+        let err = catch_unwind(|| assert_golden(&file, &render_counts(&Cnt{eval:2, ..cnt.clone()}))).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("   3 -     eval: 1,\n   3 +     eval: 2,\n"), "{}", msg);
+        assert!(! msg.contains("create"), "{}", msg);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn step_clocks_measure_deterministically () {
+        const G : MemoGroup = MemoGroup("g");
+        manage::init_dcg();
+        use_step_clock(Some(Duration::from_millis(1)));
+        let inner = thunk_group(G, NameChoice::Nominal(name_of_str("inner")), prog_pt!("inner"),
+                                Rc::new(Box::new(|x:usize, ()| x)), 1, ());
+        let outer = thunk_group(G, NameChoice::Nominal(name_of_str("outer")), prog_pt!("outer"),
+                                Rc::new(Box::new(|t:Art<usize>, ()| force(&t) + 1)), inner, ());
+        assert_eq!(force(&outer), 2);
+        use_step_clock(None);
+        // The inner evaluation takes one step, the outer three
+        assert_eq!(manage::group_stats(G).unwrap().time, Duration::from_millis(4));
+    }
+}
+
+mod engine_compaction {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::engine::manage::*;
+    use std::rc::Rc;
+
+    #[cfg(feature = "counters")]
+    /// A chain of `n` pass-through thunks beneath the thunk of `n`:
+    /// each allocates and observes the one beneath it.
+    fn chain (n:usize, c:Art<usize>) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_usize(n)), prog_pt!("chain"),
+              Rc::new(Box::new(|n:usize, c:Art<usize>| {
+                  if n == 0 { force(&c) } else { force(&chain(n - 1, c)) + 1 }
+              })),
+              n, c)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn chains_merge_into_their_heads () {
+        init_dcg();
+        let c = cell(name_of_str("c"), 0);
+        let head = chain(10, c.clone());
+        assert_eq!(force(&head), 10);
+        let report = compact_chains().unwrap();
+        assert_eq!((report.chains, report.merged), (1, 10));
+        assert_eq!(report.edges, 10 * 3);
+        // The merge takes effect as the head re-evaluates, in place of
+        // the chain
+        set(&c, 1);
+        reset_counters();
+        assert_eq!(force(&head), 11);
+        assert_eq!(counters().unwrap().eval, 1);
+        assert_eq!(compaction_report().unwrap().inlined, 10);
+        // Thereafter, changes propagate to the head directly
+        assert_eq!(set_report(&c, 2).observers_dirtied, 1);
+        reset_counters();
+        assert_eq!(force(&head), 12);
+        assert_eq!(counters().unwrap().eval, 1);
+        assert_eq!(compaction_report().unwrap().inlined, 20);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn uncompacted_chains_memoize_again () {
+        init_dcg();
+        let c = cell(name_of_str("c"), 0);
+        let head = chain(5, c.clone());
+        assert_eq!(force(&head), 5);
+        compact_chains();
+        set(&c, 1);
+        assert_eq!(force(&head), 6);
+        assert_eq!(uncompact_chains(), Some(5));
+        assert_eq!(compaction_report(), Some(CompactionReport::default()));
+        set(&c, 2);
+        assert_eq!(force(&head), 7);
+        assert_eq!(set_report(&c, 3).observers_dirtied, 6);
+        reset_counters();
+        assert_eq!(force(&head), 8);
+        assert_eq!(counters().unwrap().eval, 6);
+    }
+
+    #[test]
+    fn thunks_of_the_outer_layer_are_not_merged () {
+        init_dcg();
+        let c = cell(name_of_str("c"), 0);
+        let t = thunk![[t] force(&c) % 2];
+        let head = { let t = t.clone(); thunk![[head] force(&t)] };
+        assert_eq!(force(&head), 0);
+        // `t` is allocated at the outer layer, not by its observer
+        assert_eq!(compact_chains().unwrap().merged, 0);
+    }
+}
+
+mod engine_force_lens {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    #[derive(Hash,PartialEq,Eq,Clone,Debug)]
+    struct Config { a:usize, b:usize, c:usize }
+
+    #[cfg(feature = "counters")]
+    fn field (n:&'static str, conf:&Art<Config>, lens:fn(&Config) -> usize) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_str(n)), prog_pt!("field"),
+              Rc::new(Box::new(|conf:Art<Config>, lens:fn(&Config) -> usize| force_lens(&conf, lens))),
+              conf.clone(), lens)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn observers_of_other_fields_stay_clean () {
+        manage::init_dcg();
+        let conf = cell(name_of_str("conf"), Config{a:1, b:2, c:3});
+        let a = field("a", &conf, |c| c.a);
+        let b = field("b", &conf, |c| c.b);
+        assert_eq!((force(&a), force(&b)), (1, 2));
+        assert_eq!(set_report(&conf, Config{a:1, b:2, c:4}).observers_dirtied, 0);
+        assert_eq!(set_report(&conf, Config{a:1, b:5, c:4}).observers_dirtied, 1);
+        manage::reset_counters();
+        assert_eq!((force(&a), force(&b)), (1, 5));
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+
+    #[test]
+    fn each_projection_of_a_cell_is_observed () {
+        manage::init_dcg();
+        let conf = cell(name_of_str("conf"), Config{a:1, b:2, c:3});
+        let sum = thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+                        Rc::new(Box::new(|conf:Art<Config>, ()| {
+                            force_lens(&conf, |c| c.a) + force_lens(&conf, |c| c.b) + force_lens(&conf, |c| c.c)
+                        })),
+                        conf.clone(), ());
+        assert_eq!(force(&sum), 6);
+        set(&conf, Config{a:1, b:2, c:10});
+        assert_eq!(force(&sum), 13);
+        set(&conf, Config{a:1, b:7, c:10});
+        assert_eq!(force(&sum), 18);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn forcing_a_cell_subsumes_its_projections () {
+        manage::init_dcg();
+        let conf = cell(name_of_str("conf"), Config{a:1, b:2, c:3});
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|conf:Art<Config>, ()| force_lens(&conf, |c| c.a) + force(&conf).c)),
+                      conf.clone(), ());
+        assert_eq!(force(&t), 4);
+        assert_eq!(set_report(&conf, Config{a:1, b:9, c:3}).observers_dirtied, 1);
+        manage::reset_counters();
+        assert_eq!(force(&t), 4);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+    }
+}
+
+mod engine_cell_thunked {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A lazy cell whose initial value is `val`, counting its
+    /// initializations in `inits`.
+    fn lazy (n:&'static str, val:usize, inits:&Rc<Cell<usize>>) -> Art<usize> {
+        let inits = inits.clone();
+        Engine::cell_thunked(name_of_str(n), move || { inits.set(inits.get() + 1); val })
+    }
+
+    #[test]
+    fn lazy_cells_initialize_on_first_force () {
+        manage::init_dcg();
+        let inits = Rc::new(Cell::new(0));
+        let c = lazy("c", 1, &inits);
+        let t = { let c = c.clone(); thunk![[t] get!(c) + 1] };
+        assert_eq!(inits.get(), 0);
+        assert_eq!(force(&t), 2);
+        assert_eq!(force(&c), 1);
+        assert_eq!(inits.get(), 1);
+        // Once set, the cell is a cell, and its observers re-evaluate
+        assert_eq!(set_report(&c, 5), SetOutcome{changed:true, observers_dirtied:1});
+        assert_eq!(force(&t), 6);
+        assert!(! set_report(&c, 5).changed);
+        assert_eq!(inits.get(), 1);
+    }
+
+    #[test]
+    fn writes_before_forces_skip_initialization () {
+        manage::init_dcg();
+        let inits = Rc::new(Cell::new(0));
+        let (a, b) = (lazy("a", 1, &inits), lazy("b", 2, &inits));
+        set(&a, 10);
+        let b2 = cell(name_of_str("b"), 20usize);
+        assert_eq!((force(&a), force(&b), force(&b2)), (10, 20, 20));
+        // Re-allocating a lazy cell returns it, as is
+        assert_eq!(force(&lazy("a", 1, &inits)), 10);
+        assert_eq!(inits.get(), 0);
+        // `update` initializes the cell, without observing it
+        let c = lazy("c", 3, &inits);
+        assert!(update(&c, |x| x * 2).changed);
+        assert_eq!(force(&c), 6);
+        assert_eq!(inits.get(), 1);
+    }
+
+    #[test]
+    fn naive_lazy_cells_are_eager () {
+        manage::init_naive();
+        let inits = Rc::new(Cell::new(0));
+        let c = lazy("c", 1, &inits);
+        assert_eq!(inits.get(), 1);
+        assert_eq!(force(&c), 1);
+    }
+}
+
+mod engine_seed {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    /// The (rendered) locations of a structural thunk, and of a
+    /// structural thunk within a namespace, in `engine`.
+    fn structural_locs (engine:Engine) -> Vec<String> {
+        manage::use_engine(engine);
+        let t = thunk(NameChoice::Structural, prog_pt!("t"),
+                      Rc::new(Box::new(|x:usize, ()| x + 1)), 41, ());
+        let u = ns(name_of_str("ns"), || {
+            thunk(NameChoice::Structural, prog_pt!("u"),
+                  Rc::new(Box::new(|x:usize, ()| x * 2)), 21, ())
+        });
+        assert_eq!((force(&t), force(&u)), (42, 42));
+        vec![format!("{:?}", t), format!("{:?}", u)]
+    }
+
+    #[test]
+    fn seeds_determine_structural_ids () {
+        let a = structural_locs(Engine::with_seed(7));
+        assert_eq!(a, structural_locs(Engine::with_seed(7)));
+        assert!(a != structural_locs(Engine::with_seed(8)));
+        assert_eq!(manage::seed(), Some(8));
+        // The seed zero is the default (without `ADAPTON_SEED`)
+        let zero = structural_locs(Engine::with_seed(0));
+        let default = structural_locs(Engine::with_capacity(0, 0));
+        if manage::seed() == Some(0) { assert_eq!(zero, default) };
+    }
+
+    #[test]
+    fn builders_take_seeds () {
+        let (engine, _) = EngineBuilder::new().with_seed(7).build();
+        assert_eq!(structural_locs(engine), structural_locs(Engine::with_seed(7)));
+        manage::init_naive();
+        assert_eq!(manage::seed(), None);
+    }
+}
+
+mod engine_sampling {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    /// A chain of `n` thunks, each of which adds one to the next, ending
+    /// at the cell `c`; each thunk has a distinct program point.
+    fn chain (c:&Art<usize>, n:usize) -> Art<usize> {
+        let pts = ["link0", "link1", "link2", "link3"];
+        (0..n).fold(c.clone(), |a, i| {
+            thunk(NameChoice::Nominal(name_of_usize(i)), prog_pt!(pts[i % 4]),
+                  Rc::new(Box::new(|a:Art<usize>, ()| force(&a) + 1)), a, ())
+        })
+    }
+
+    fn samples (every:Option<usize>) -> SampleStats {
+        manage::init_dcg();
+        manage::set_flags(|f| f.sample_every = every);
+        let c = cell(name_of_str("c"), 0);
+        let t = chain(&c, 100);
+        assert_eq!(force(&t), 100);
+        set(&c, 1);
+        assert_eq!(force(&t), 101);
+        manage::sample_stats().unwrap()
+    }
+
+    #[test]
+    fn sampling_is_off_by_default () {
+        assert_eq!(samples(None), SampleStats::default());
+    }
+
+    #[test]
+    fn samples_are_taken_at_the_rate () {
+        // Each thunk begins, and forces its successor, twice; the
+        // change propagation examines the edge of each thunk, and as
+        // the thunks re-evaluate, the (clean) edges of all but the last
+        // one again.
+        let all = samples(Some(1));
+        assert_eq!(all.samples, 599);
+        assert_eq!(all.produce.values().sum::<usize>(), 400);
+        assert_eq!(all.produce.get(&prog_pt!("link1")), Some(&100));
+        assert_eq!(all.clean.len(), 100);
+        assert_eq!(all.clean.values().sum::<usize>(), 199);
+        let some = samples(Some(7));
+        assert_eq!(some.samples, (599 + 6) / 7);
+        assert_eq!(some.produce.values().sum::<usize>() + some.clean.values().sum::<usize>(), some.samples);
+    }
+
+    #[test]
+    fn reset_sample_stats () {
+        let all = samples(Some(1));
+        assert_eq!(manage::reset_sample_stats(), Some(all));
+        assert_eq!(manage::sample_stats(), Some(SampleStats::default()));
+        manage::init_naive();
+        assert_eq!(manage::sample_stats(), None);
+    }
+}
+
+mod engine_name_algebra {
+    use adapton::engine::*;
+
+    /// The names of a binary tree of forks and pairs, of depth `d`
+    /// below `n`.
+    fn tree (n:Name, d:usize, out:&mut Vec<Name>) {
+        out.push(n.clone());
+        if d == 0 { return };
+        let (l, r) = name_fork(n.clone());
+        tree(l, d - 1, out);
+        tree(r, d - 1, out);
+        tree(name_pair(n, name_of_usize(d)), d - 1, out);
+    }
+
+    #[test]
+    fn parents_and_prefixes () {
+        let root = name_of_str("root");
+        let mut names = vec![];
+        tree(root.clone(), 4, &mut names);
+        for n in names.iter() {
+            assert!(name::is_prefix_of(&root, n));
+            assert!(name::is_prefix_of(n, n));
+            match name::parent(n) {
+                // The parents have the hashes of their constructions
+                Some(p) => assert!(names.contains(&p) && name::is_prefix_of(&p, n) && ! name::is_prefix_of(n, &p)),
+                None => assert_eq!(*n, root),
+            }
+        }
+        // Pairs derive from their left components only
+        let item = name_pair(name_of_str("seq"), name_of_str("root"));
+        assert!(! name::is_prefix_of(&root, &item));
+        assert_eq!(name::parent(&name_pair(name_of_hash64(7), name_unit())), None);
+    }
+
+    #[test]
+    fn common_ancestors () {
+        let (l, r) = name_fork(name_of_str("root"));
+        let (ll, lr) = name_fork(l.clone());
+        let deep = (0..10000).fold(lr.clone(), |n, _| name_fork(n).1);
+        assert_eq!(name::common_ancestor(&ll, &deep), Some(l.clone()));
+        assert_eq!(name::common_ancestor(&deep, &lr), Some(lr.clone()));
+        assert_eq!(name::common_ancestor(&r, &deep), Some(name_of_str("root")));
+        assert_eq!(name::common_ancestor(&r, &name_of_usize(1)), None);
+    }
+
+    #[test]
+    fn lexicographic_order () {
+        let mut names = vec![];
+        tree(name_of_str("root"), 4, &mut names);
+        tree(name_of_str("other"), 2, &mut names);
+        let mut sorted = names.clone();
+        sorted.reverse();
+        sorted.sort_by(name::cmp);
+        // The descendants of each name follow it, contiguously
+        for (i, n) in sorted.iter().enumerate() {
+            let desc = names.iter().filter(|m| name::is_prefix_of(n, m)).count();
+            assert!(sorted[i..i + desc].iter().all(|m| name::is_prefix_of(n, m)), "{}", n);
+        }
+        assert_eq!(sorted[0], name_of_str("other"));
+    }
+
+    #[test]
+    fn paths () {
+        let (a, b, c) = (name_of_str("a"), name_of_str("b"), name_of_str("c"));
+        let ab = vec![a.clone(), b.clone()];
+        let ac = vec![a.clone(), c.clone()];
+        assert!(name::path_is_prefix_of(&ab[..1], &ab));
+        assert!(! name::path_is_prefix_of(&ab, &ac));
+        assert_eq!(name::path_common_ancestor(&ab, &ac), vec![a.clone()]);
+        let mut paths = vec![ac.clone(), ab.clone(), vec![a.clone()], vec![]];
+        paths.sort_by(|p, q| name::path_cmp(p, q));
+        assert_eq!(paths, vec![vec![], vec![a], ab, ac]);
+    }
+}
+
+mod engine_poison {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::panic;
+
+    fn poison_on_panic () {
+        manage::init_dcg();
+        manage::set_flags(|f| f.poison_on_panic = true);
+    }
+
+    /// The thunk of the quotient of 12 by the content of `den`.
+    fn div (den:&Art<usize>) -> Art<usize> {
+        let den = den.clone();
+        thunk![[div] { 12 / force(&den) }]
+    }
+
+    #[test]
+    fn a_panic_poisons_the_engine () {
+        poison_on_panic();
+        let den = cell(name_of_str("den"), 0);
+        let t = div(&den);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| force(&t))).is_err());
+        let poison = manage::poisoned().unwrap();
+        assert!(poison.panic.contains("divide by zero"), "{:?}", poison);
+        // Every later call raises the poisoning, without changing the engine
+        for call in vec![Box::new(|| { force(&t); }) as Box<Fn()>,
+                         Box::new(|| set(&den, 3)),
+                         Box::new(|| { update(&den, |d| d + 1); }),
+                         Box::new(|| { let _ = compare_and_set(&den, &5, 3); }),
+                         Box::new(|| { cell(name_of_str("other"), 1); }),
+                         Box::new(|| { div(&den); })] {
+            let err = panic::catch_unwind(panic::AssertUnwindSafe(|| call())).unwrap_err();
+            assert_eq!(err.downcast::<EnginePoisoned>().ok().map(|p| *p), Some(poison.clone()));
+        }
+        assert_eq!(try_force(&t), Err(EngineError::Poisoned(poison.clone())));
+        assert_eq!(try_set(&den, 3), Err(EngineError::Poisoned(poison)));
+        // Until the host replaces the engine
+        manage::init_dcg();
+        assert_eq!(manage::poisoned(), None);
+        let den = cell(name_of_str("den"), 3);
+        assert_eq!(try_force(&div(&den)), Ok(4));
+    }
+
+    #[test]
+    fn try_force_returns_the_poisoning () {
+        poison_on_panic();
+        let den = cell(name_of_str("den"), 4);
+        let t = div(&den);
+        assert_eq!(try_force(&t), Ok(3));
+        assert!(try_set(&den, 0).unwrap().changed);
+        match try_force(&t) {
+            Err(EngineError::Poisoned(poison)) => assert!(poison.panic.contains("divide by zero")),
+            res => panic!("{:?}", res),
+        }
+    }
+
+    #[test]
+    fn panics_do_not_poison_by_default () {
+        manage::init_dcg();
+        let den = cell(name_of_str("den"), 0);
+        let t = div(&den);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| force(&t))).is_err());
+        assert_eq!(manage::poisoned(), None);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| try_force(&t))).is_err());
+    }
+
+    #[test]
+    fn uses_of_dropped_engines_do_not_poison () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        poison_on_panic();
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| force(&c))).is_err());
+        assert_eq!(manage::poisoned(), None);
+    }
+}
+
+#[cfg(feature = "counters")]
+mod engine_nominal_matches {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+    use adapton::reflect::trace::{AllocCase, Effect};
+    use std::panic;
+    use std::rc::Rc;
+
+    fn add (n:&'static str, c:Art<usize>, k:usize) -> Art<usize> {
+        thunk(NameChoice::Nominal(name_of_str(n)), prog_pt!("add"),
+              Rc::new(Box::new(|(c, k):(Art<usize>, usize), ()| force(&c) + k)), (c, k), ())
+    }
+
+    #[test]
+    fn nominal_matches_count_each_outcome () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        assert_eq!(force(&add("t", c.clone(), 1)), 2);
+        let _ = add("t", c.clone(), 1);
+        assert_eq!(force(&add("t", c.clone(), 2)), 3);
+        let _ : Art<usize> = cell(name_of_str("u"), 0);
+        reflect::dcg_reflect_begin();
+        assert_eq!(force(&add("u", c.clone(), 3)), 4);
+        let traces = reflect::dcg_reflect_end();
+        assert!(traces.iter().any(|tr| match tr.effect { Effect::Alloc(AllocCase::LocReplaced, _) => true, _ => false }));
+        let mismatch = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("other"),
+                  Rc::new(Box::new(|c:Art<usize>, ()| force(&c))), c.clone(), ())
+        }));
+        assert!(mismatch.is_err());
+        assert_eq!(manage::counters().unwrap().nominal,
+                   NominalMatches{fresh:1, same_arg:1, arg_overwrite:1, producer_mismatch:1, mut_to_comp:1, retyped:0});
+    }
+
+    #[test]
+    fn nominal_matches_are_per_epoch () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t = add("t", c.clone(), 1);
+        let (epoch, matches) = manage::nominal_matches().unwrap();
+        assert_eq!(matches.fresh, 1);
+        set(&c, 2);
+        assert_eq!(manage::nominal_matches().unwrap(), (epoch + 1, NominalMatches::default()));
+        let _ = add("t", c.clone(), 1);
+        assert_eq!(force(&t), 3);
+        assert_eq!(manage::nominal_matches().unwrap(), (epoch + 1, NominalMatches{same_arg:1, ..NominalMatches::default()}));
+        // The counters sum the epochs
+        assert_eq!(manage::counters().unwrap().nominal, NominalMatches{fresh:1, same_arg:1, ..NominalMatches::default()});
+    }
+}
+
+mod engine_drop {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::panic;
+
+    #[test]
+    fn drop_reports_the_outstanding_arts () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        let gone : Art<usize> = cell(name_of_str("gone"), 3);
+        drop(gone);
+        assert_eq!(force(&t), 2);
+        let _spec = Engine::speculate(|| ());
+        drop(manage::init_dcg());
+        let report = manage::engine_drop_report().unwrap();
+        assert_eq!(report.speculations, 1);
+        if cfg!(debug_assertions) {
+            let mut leaked : Vec<String> = report.leaked.iter().map(|loc| format!("{:?}", loc.name)).collect();
+            leaked.sort();
+            assert_eq!(leaked, vec![format!("{:?}", name_of_str("c")), format!("{:?}", name_of_str("t"))]);
+        } else {
+            assert_eq!(report.leaked, vec![]);
+        }
+        for a in vec![&t, &c] {
+            match try_force(a) {
+                Err(EngineError::Gone(gone)) => assert_eq!(gone.engine, report.engine),
+                res => panic!("{:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn uses_after_drop_raise_engine_gone () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        // Even if the next engine has a cell of the same name
+        manage::init_dcg();
+        let d : Art<usize> = cell(name_of_str("c"), 2);
+        for use_c in vec![Box::new(|c:&Art<usize>| { force(c); }) as Box<Fn(&Art<usize>)>,
+                          Box::new(|c:&Art<usize>| set(c, 3))] {
+            let err = panic::catch_unwind(panic::AssertUnwindSafe(|| use_c(&c))).unwrap_err();
+            let gone = err.downcast::<EngineGone>().unwrap();
+            assert_eq!(gone.engine, manage::engine_drop_report().unwrap().engine);
+        }
+        assert_eq!(force(&d), 2);
+        manage::init_naive();
+        let err = panic::catch_unwind(panic::AssertUnwindSafe(|| force(&d))).unwrap_err();
+        assert!(err.downcast::<EngineGone>().is_ok());
+    }
+
+    #[test]
+    fn swapped_out_engines_are_not_gone () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let saved = manage::init_dcg();
+        let _d : Art<usize> = cell(name_of_str("d"), 2);
+        manage::use_engine(saved);
+        assert_eq!(try_force(&c), Ok(1));
+    }
+}
+
+mod engine_dependents {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn dependents_are_the_observers_by_depth () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let a : Art<usize> = { let c = c.clone(); thunk![[a] { force(&c) + 1 }] };
+        let b : Art<usize> = { let c = c.clone(); thunk![[b] { force(&c) * 2 }] };
+        let top : Art<usize> = { let (a, b) = (a.clone(), b.clone()); thunk![[top] { force(&a) + force(&b) }] };
+        let _unforced : Art<usize> = { let c = c.clone(); thunk![[unforced] { force(&c) }] };
+        assert_eq!(force(&top), 4);
+
+        let key = |t:&Art<usize>| art_key(t).unwrap();
+        assert_eq!(Engine::dependents(&c, 0), vec![]);
+        let mut direct = Engine::dependents(&c, 1);
+        direct.sort_by_key(|k| format!("{:?}", k.loc()));
+        assert_eq!(direct, vec![key(&a), key(&b)]);
+        // The diamond's top is listed once, after the nearer thunks
+        let all = Engine::dependents(&c, 10);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2], key(&top));
+        assert_eq!(Engine::dependents(&a, 10), vec![key(&top)]);
+        assert_eq!(Engine::dependents(&top, 10), vec![]);
+    }
+
+    #[test]
+    fn dependents_of_untracked_arts_are_empty () {
+        manage::init_dcg();
+        assert_eq!(Engine::dependents(&put(1), 1), vec![]);
+        manage::init_naive();
+        let c = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![ get!(c) ] };
+        assert_eq!(get!(t), 1);
+        assert_eq!(Engine::dependents(&c, 1), vec![]);
+    }
+}
+
+mod engine_fanout {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    /// A thunk that observes each of `n` thunks (twice), each of
+    /// which observes whether its cell is even.
+    fn parities (n:usize) -> (Vec<Art<usize>>, Art<usize>) {
+        let cells : Vec<Art<usize>> = (0..n).map(|i| cell(name_of_usize(i), i)).collect();
+        let evens : Vec<Art<bool>> = cells.iter().enumerate().map(|(i, c)| {
+            thunk(NameChoice::Nominal(name_pair(name_of_str("even"), name_of_usize(i))), prog_pt!("even"),
+                  Rc::new(Box::new(|c:Art<usize>, ()| force(&c) % 2 == 0)), c.clone(), ())
+        }).collect();
+        let count = thunk(NameChoice::Nominal(name_of_str("count")), prog_pt!("count"),
+                          Rc::new(Box::new(|evens:Rc<Vec<Art<bool>>>, ()| {
+                              evens.iter().chain(evens.iter()).filter(|e| force(e)).count()
+                          })),
+                          Rc::new(evens), ());
+        (cells, count)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn high_fanout_dirties_and_cleans_each_edge () {
+        manage::init_dcg();
+        let n = 1000;
+        let (cells, count) = parities(n);
+        assert_eq!(force(&count), n);
+        // An unobservable change cleans the edges of `count`
+        manage::reset_counters();
+        set(&cells[10], 12);
+        assert_eq!(force(&count), n);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        // An observable change dirties them, and re-evaluates `count`
+        manage::reset_counters();
+        set(&cells[999], 1000);
+        assert_eq!(force(&count), n + 2);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+        set(&cells[0], 1);
+        assert_eq!(force(&count), n);
+    }
+
+    #[test]
+    fn low_fanout_finds_each_edge () {
+        manage::init_dcg();
+        let (cells, count) = parities(3);
+        assert_eq!(force(&count), 4);
+        set(&cells[1], 2);
+        assert_eq!(force(&count), 6);
+    }
+}
+
+mod engine_storage {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    fn spill_dir (test:&str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("adapton-spill-{}-{}", test, ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn render (dir:&PathBuf, c:Art<usize>) -> Art<String> {
+        thunk_storage(Storage::Disk(dir.clone()), NameChoice::Nominal(name_of_str("render")),
+                      prog_pt!("render"),
+                      Rc::new(Box::new(|c:Art<usize>, ()| "x".repeat(force(&c)))),
+                      c, ())
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn disk_storage_spills_and_reloads () {
+        let dir = spill_dir("reload");
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1000);
+        let r = render(&dir, c.clone());
+        let len = { let r = r.clone(); thunk![[len] { force(&r).len() / 1000 }] };
+        assert_eq!(force(&len), 1);
+        let cnt = manage::reset_counters().unwrap();
+        assert_eq!((cnt.eval, cnt.spills, cnt.reloads), (2, 1, 0));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A force reloads the result
+        assert_eq!(force(&r).len(), 1000);
+        let cnt = manage::reset_counters().unwrap();
+        assert_eq!((cnt.eval, cnt.reloads), (0, 1));
+
+        // Change propagation compares digests; an equal result spares the observer
+        set(&c, 1001);
+        assert_eq!(force(&len), 1);
+        assert_eq!(manage::reset_counters().unwrap().eval, 2);
+        set(&c, 1001);
+        assert_eq!(force(&len), 1);
+        assert_eq!(manage::reset_counters().unwrap().eval, 0);
+
+        // Without its file, the thunk re-evaluates
+        for f in fs::read_dir(&dir).unwrap() { fs::remove_file(f.unwrap().path()).unwrap() }
+        assert_eq!(force(&r).len(), 1001);
+        assert_eq!(manage::reset_counters().unwrap().eval, 1);
+
+        // Dropping the result removes the file
+        manage::clear_results();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_storage_restores_the_result () {
+        let dir = spill_dir("memory");
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 3);
+        let r = render(&dir, c.clone());
+        assert_eq!(force(&r), "xxx");
+        let r = thunk_storage(Storage::Memory, NameChoice::Nominal(name_of_str("render")),
+                              prog_pt!("render"),
+                              Rc::new(Box::new(|c:Art<usize>, ()| "x".repeat(force(&c)))),
+                              c, ());
+        manage::reset_counters();
+        assert_eq!(force(&r), "xxx");
+        assert_eq!(force(&r), "xxx");
+        let cnt = manage::counters().unwrap();
+        assert_eq!((cnt.eval, cnt.reloads), (0, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removing_thunks_removes_their_files () {
+        let dir = spill_dir("remove");
+        let files = || fs::read_dir(&dir).unwrap().count();
+        let spill = || { let r = render(&dir, cell(name_of_str("c"), 3)); force(&r); };
+        manage::init_dcg();
+        spill();
+        assert_eq!(files(), 1);
+        manage::clear();
+        assert_eq!(files(), 0);
+        ns(name_of_str("ns"), || spill());
+        assert_eq!(files(), 1);
+        manage::trim_ns(vec![name_of_str("ns")], TrimPolicy::DropAll);
+        assert_eq!(files(), 0);
+        spill();
+        assert_eq!(files(), 1);
+        manage::init_naive();
+        assert_eq!(files(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn engines_share_a_spill_directory () {
+        let dir = spill_dir("share");
+        manage::init_dcg();
+        let r1 = render(&dir, cell(name_of_str("c"), 1));
+        assert_eq!(force(&r1), "x");
+        manage::reset_counters();
+        let e1 = manage::use_engine(Engine::Naive);
+        manage::init_dcg();
+        let r2 = render(&dir, cell(name_of_str("c"), 2));
+        assert_eq!(force(&r2), "xx");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        manage::use_engine(e1);
+        assert_eq!(force(&r1), "x");
+        assert_eq!(manage::reset_counters().unwrap().eval, 0);
+        manage::init_naive();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+mod engine_memo_groups {
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use std::rc::Rc;
+
+    const TYPECHECK : MemoGroup = MemoGroup("typecheck");
+
+    fn check (i:usize, c:Art<usize>) -> Art<usize> {
+        thunk_group(TYPECHECK, NameChoice::Nominal(name_of_usize(i)), prog_pt!("check"),
+                    Rc::new(Box::new(|c:Art<usize>, ()| force(&c) % 2)), c, ())
+    }
+
+    #[test]
+    fn group_stats_count_the_group () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 3);
+        let checks : Vec<Art<usize>> = (0..4).map(|i| check(i, c.clone())).collect();
+        let other = { let c = c.clone(); thunk![[other] { force(&c) + 1 }] };
+        assert_eq!(force(&checks[0]) + force(&checks[1]) + force(&other), 6);
+        let stats = manage::group_stats(TYPECHECK).unwrap();
+        assert_eq!((stats.thunks, stats.cached, stats.evals), (4, 2, 2));
+        set(&c, 5);
+        assert_eq!(force(&checks[0]), 1);
+        assert_eq!(manage::group_stats(TYPECHECK).unwrap().evals, 3);
+        assert_eq!(manage::group_stats(MemoGroup("other")).unwrap(), GroupStats::default());
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn invalidate_group_flushes_the_group () {
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 3);
+        let user = { let c = c.clone(); thunk![[user] { force(&check(0, c.clone())) + force(&check(1, c.clone())) }] };
+        assert_eq!(force(&user), 2);
+        assert_eq!(manage::invalidate_group(TYPECHECK), Some(2));
+        assert_eq!(manage::group_stats(TYPECHECK).unwrap().cached, 0);
+        manage::reset_counters();
+        assert_eq!(force(&user), 2);
+        // The group re-evaluates; its unchanged results spare the user
+        assert_eq!(manage::counters().unwrap().eval, 2);
+        assert_eq!(manage::group_stats(TYPECHECK).unwrap().cached, 2);
+        assert_eq!(manage::check_dcg_is_wf(), Some(()));
+    }
+}
+
+mod engine_speculate {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn discard_restores_the_dcg () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) * 10 }] };
+        assert_eq!(force(&t), 20);
+        manage::reset_counters();
+        let spec = Engine::speculate(|| {
+            set(&c, 3);
+            let d = cell(name_of_str("d"), 7);
+            force(&t) + force(&d)
+        });
+        assert_eq!(*spec.result(), 37);
+        assert_eq!(spec.discard(), 37);
+        assert_eq!(force(&c), 2);
+        assert_eq!(force(&t), 20);
+        // The cached result of `t` is restored, rather than re-computed;
+        // the counters include the speculative work
+        assert_eq!(manage::reset_counters().unwrap().eval, 1);
+        assert_eq!(manage::check_dcg_is_wf(), Some(()));
+    }
+
+    #[test]
+    fn nested_commit_joins_the_outer_speculation () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) + 1 }] };
+        assert_eq!(force(&t), 3);
+        let outer = Engine::speculate(|| set(&c, 5));
+        let inner = Engine::speculate(|| { set(&c, 6); force(&t) });
+        assert_eq!(inner.commit(), 7);
+        assert_eq!(force(&t), 7);
+        drop(outer);
+        assert_eq!(force(&t), 3);
+        assert_eq!(force(&c), 2);
+    }
+
+    #[test]
+    fn commit_keeps_the_changes () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] { force(&c) * 2 }] };
+        assert_eq!(Engine::speculate(|| { set(&c, 4); force(&t) }).commit(), 8);
+        assert_eq!(force(&t), 8);
+        assert_eq!(manage::check_dcg_is_wf(), Some(()));
+    }
+
+    #[test]
+    fn panic_discards_the_changes () {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 2);
+        let t : Art<usize> = { let c = c.clone(); thunk![[t] {
+            let x = force(&c); if x == 0 { panic!("zero") }; 10 / x }] };
+        assert_eq!(force(&t), 5);
+        let r = catch_unwind(AssertUnwindSafe(|| Engine::speculate(|| { set(&c, 0); force(&t) })));
+        assert!(r.is_err());
+        assert_eq!(force(&c), 2);
+        assert_eq!(force(&t), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "a later speculation is still open")]
+    fn speculations_end_innermost_first () {
+        manage::init_dcg();
+        let outer = Engine::speculate(|| ());
+        let _inner = Engine::speculate(|| ());
+        outer.commit();
+    }
+}
+
+mod engine_reevaluations {
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn reevaluations_explain_their_causes () {
+        manage::init_dcg();
+        let _ = register_type::<usize>();
+        let _ = manage::set_flags(|flags| flags.record_reevaluations = true);
+        let x : Art<usize> = cell(name_of_str("x"), 1);
+        let y : Art<usize> = cell(name_of_str("y"), 10);
+        let sum = { let (x, y) = (x.clone(), y.clone()); thunk![[sum] { force(&x) + force(&y) }] };
+        let out = { let sum = sum.clone(); thunk![[out] { force_labeled(&sum, "total") * 2 }] };
+        assert_eq!(force(&out), 22);
+        assert_eq!(manage::take_reevaluations(), vec![]);
+        set(&y, 20);
+        assert_eq!(force(&out), 42);
+        let reevals = manage::take_reevaluations();
+        assert_eq!(reevals.iter().map(|r| (r.old.clone(), r.new.clone())).collect::<Vec<_>>(),
+                   vec![(Some("10".to_string()), Some("20".to_string())),
+                        (Some("11".to_string()), Some("21".to_string()))]);
+        assert!(reevals[0].explain().contains("went from 10 to 20"), "{}", reevals[0].explain());
+        assert!(reevals[1].explain().contains("(total) went from 11 to 21"), "{}", reevals[1].explain());
+    }
+}
+
+mod engine_begin_clean {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn chain (c:&Art<usize>) -> Art<usize> {
+        let a = { let c = c.clone(); thunk![[a] { force(&c) + 1 }] };
+        let b = { let a = a.clone(); thunk![[b] { force(&a) * 2 }] };
+        b
+    }
+
+    #[test]
+    fn steps_until_done () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let b = chain(&c);
+        assert_eq!(force(&b), 4);
+        set(&c, 2);
+        let mut prop = begin_clean(&b);
+        let mut produces = 0;
+        let res = loop {
+            match prop.step() {
+                StepResult::Progress => (),
+                StepResult::NeedsProduce(_) => produces += 1,
+                StepResult::Done(v) => break v,
+            }
+        };
+        assert_eq!(res, 6);
+        assert!(produces > 0);
+        assert_eq!(prop.depth(), 0);
+        // Unchanged inputs: propagation finds nothing to re-evaluate.
+        let mut prop = begin_clean(&b);
+        let res = loop {
+            match prop.step() {
+                StepResult::Progress => (),
+                StepResult::NeedsProduce(l) => panic!("unexpected re-evaluation: {:?}", l),
+                StepResult::Done(v) => break v,
+            }
+        };
+        assert_eq!(res, 6);
+    }
+
+    #[test]
+    fn cancel_midway () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let b = chain(&c);
+        assert_eq!(force(&b), 4);
+        set(&c, 5);
+        let mut prop = begin_clean(&b);
+        assert_eq!(prop.step(), StepResult::Progress);
+        assert!(prop.depth() > 0);
+        prop.cancel();
+        assert_eq!(force(&b), 12);
+    }
+}
+
+mod engine_ns_iter {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn cells (n:usize, fork:bool) -> Art<Vec<Art<usize>>> {
+        thunk![[cells] {
+            (0..n).map(|i| {
+                if fork { ns_iter(name_of_str("i"), i, || cell(name_of_str("x"), i)) }
+                else    { cell(name_of_str("x"), i) }
+            }).collect::<Vec<_>>()
+        }]
+    }
+
+    #[test]
+    fn iterations_get_distinct_names () {
+        manage::init_dcg();
+        let cs = force(&cells(3, true));
+        assert_eq!(cs.iter().map(|c| force(c)).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn wf_check_counts_name_reuse () {
+        manage::init_dcg();
+        let _ = manage::set_flags(|flags| flags.check_dcg_is_wf = true);
+        manage::reset_counters();
+        let _ = force(&cells(3, true));
+        assert_eq!(manage::counters().unwrap().name_reuse, 0);
+        manage::init_dcg();
+        let _ = manage::set_flags(|flags| flags.check_dcg_is_wf = true);
+        let cs = force(&cells(3, false));
+        assert_eq!(manage::counters().unwrap().name_reuse, 2);
+        // Each allocation overwrites the prior one.
+        assert_eq!(cs.iter().map(|c| force(c)).collect::<Vec<_>>(), vec![2, 2, 2]);
+    }
+}
+
+mod engine_trim_ns {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    /// A document: its cell, and a thunk beneath its namespace (`doc`)
+    /// that a thunk outside (`len`) observes.
+    fn doc (c:&Art<usize>) -> (Art<usize>, Art<usize>) {
+        let t = ns(name_of_str("doc"), || { let c = c.clone(); thunk![[t] { force(&c) * 2 }] });
+        let len = { let t = t.clone(); thunk![[len] { force(&t) + 1 }] };
+        (t, len)
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn drop_results () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let (t, len) = doc(&c);
+        assert_eq!(force(&len), 3);
+        assert_eq!(manage::trim_ns(vec![name_of_str("doc")], TrimPolicy::DropResults), Some(1));
+        manage::reset_counters();
+        assert_eq!(force(&t), 2);
+        assert_eq!(manage::counters().unwrap().eval, 1);
+        set(&c, 2);
+        assert_eq!(force(&len), 5);
+    }
+
+    #[test]
+    fn drop_clean_nodes_keeps_used_nodes () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let (_t, len) = doc(&c);
+        assert_eq!(force(&len), 3);
+        // `len`, outside the namespace, uses the thunk beneath it.
+        assert_eq!(manage::trim_ns(vec![name_of_str("doc")], TrimPolicy::DropCleanNodes), Some(0));
+        let unused = ns(name_of_str("doc"), || { let c = c.clone(); thunk![[u] { force(&c) }] });
+        assert_eq!(force(&unused), 1);
+        assert_eq!(manage::trim_ns(vec![name_of_str("doc")], TrimPolicy::DropCleanNodes), Some(1));
+        assert_eq!(force(&len), 3);
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn drop_all_invalidates_users () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let len = {
+            let c = c.clone();
+            thunk![[len] {
+                let t = ns(name_of_str("doc"), || { let c = c.clone(); thunk![[t] { force(&c) * 2 }] });
+                force(&t) + 1
+            }]
+        };
+        assert_eq!(force(&len), 3);
+        assert_eq!(manage::trim_ns(vec![name_of_str("doc")], TrimPolicy::DropAll), Some(1));
+        manage::reset_counters();
+        assert_eq!(force(&len), 3);
+        assert_eq!(manage::counters().unwrap().eval, 2);
+        set(&c, 3);
+        assert_eq!(force(&len), 7);
+    }
+
+    #[test]
+    fn drop_all_dirties_the_observers_of_users () {
+        manage::init_dcg();
+        let x : Art<usize> = ns(name_of_str("doc"), || cell(name_of_str("x"), 1));
+        let u = { let x = x.clone(); thunk![[u] { force(&x) * 10 }] };
+        let o = { let u = u.clone(); thunk![[o] { force(&u) }] };
+        assert_eq!(force(&o), 10);
+        assert_eq!(manage::trim_ns(vec![name_of_str("doc")], TrimPolicy::DropAll), Some(1));
+        let _ = ns(name_of_str("doc"), || cell(name_of_str("x"), 5usize));
+        assert_eq!(force(&o), 50);
+    }
+}
+
+mod engine_type_tags {
+    use std::rc::Rc;
+    use std::panic::catch_unwind;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn message (err:Box<::std::any::Any + Send>) -> String {
+        match err.downcast::<String>() { Ok(s) => *s, Err(_) => String::new() }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn result_type_error_names_types () {
+        let err = catch_unwind(|| {
+            manage::init_dcg();
+            let x = cell(name_of_str("x"), 1usize);
+            let _ : Art<bool> = cell(name_of_str("x"), true);
+            force(&x)
+        }).unwrap_err();
+        let msg = message(err);
+        assert!(msg.contains("expected usize, found bool"), "{}", msg);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn producer_type_error_names_types () {
+        let err = catch_unwind(|| {
+            manage::init_dcg();
+            let _ = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("p"),
+                          Rc::new(Box::new(|n:usize, ()| n)), 1, ());
+            let _ = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("p"),
+                          Rc::new(Box::new(|s:String, ()| s.len())), String::from("s"), ());
+        }).unwrap_err();
+        let msg = message(err);
+        assert!(msg.contains("dynamic type error") && msg.contains("alloc::string::String"), "{}", msg);
+    }
+}
+
+mod engine_dirty_roots {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn roots_of_changed_cells () {
+        manage::init_dcg();
+        let a : Art<usize> = cell(name_of_str("a"), 1);
+        let b : Art<usize> = cell(name_of_str("b"), 2);
+        let inner = { let a = a.clone(); thunk![[inner] { force(&a) + 1 }] };
+        let out1 = { let inner = inner.clone(); thunk![[out1] { force(&inner) * 2 }] };
+        let out2 = { let b = b.clone(); thunk![[out2] { force(&b) * 3 }] };
+        assert_eq!((force(&out1), force(&out2)), (4, 6));
+        assert!(dirty_roots().is_empty());
+        set(&a, 5);
+        assert_eq!(dirty_roots(), vec![art_key(&out1).unwrap()]);
+        set(&b, 7);
+        assert_eq!(dirty_roots().len(), 2);
+        assert_eq!(force(&out1), 12);
+        assert_eq!(dirty_roots(), vec![art_key(&out2).unwrap()]);
+        assert_eq!(dirty_roots()[0].loc(), art_key(&out2).unwrap().loc());
+        assert_eq!(art_key(&put(1)), None);
+    }
+}
+
+mod engine_name_order {
+    use adapton::engine::*;
+
+    fn names () -> Vec<Name> {
+        let (l, r) = name_fork(name_of_str("f"));
+        vec![ name_unit(), name_of_hash64(0xbeef), name_of_str("a \"quoted\" \\ name"),
+              name_of_usize(7), name_of_isize(-7),
+              name_pair(name_of_str("x"), name_pair(name_unit(), name_of_usize(1))),
+              l, r ]
+    }
+
+    #[test]
+    fn parse_inverts_display () {
+        for n in names() {
+            assert_eq!(Name::parse(&n.to_string()), Some(n.clone()), "{}", n);
+        }
+        assert_eq!(name_pair(name_of_str("a"), name_of_usize(1)).to_string(), "(\"a\", 1)");
+        assert_eq!(Name::parse("(\"a\",1)"), Some(name_pair(name_of_str("a"), name_of_usize(1))));
+        assert_eq!(Name::parse("(\"a\", 1"), None);
+        assert_eq!(Name::parse("-1"), None);
+        assert_eq!(Name::parse("(#_, ())"), None);
+    }
+
+    #[test]
+    fn canonical_order () {
+        let mut ns = names();
+        ns.reverse();
+        ns.sort();
+        assert_eq!(ns, names());
+        let mut strs = vec![name_of_str("b"), name_of_str("c"), name_of_str("a")];
+        strs.sort();
+        assert_eq!(strs, vec![name_of_str("a"), name_of_str("b"), name_of_str("c")]);
+        assert!(name_of_usize(2) < name_of_usize(10));
+        assert!(name_of_hash64(1) < name_of_hash64(2));
+    }
+}
+
+mod engine_dot_filter {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn edges (dot:&str) -> usize { dot.lines().filter(|l| l.contains(" -> ")).count() }
+
+    /// A chain `c <- t0 <- t1 <- ... <- t9`, in namespace `chain`.
+    fn chain () -> (Art<usize>, Art<usize>) {
+        let c : Art<usize> = cell(name_of_str("c"), 0);
+        let mut t = c.clone();
+        for i in 0..10 {
+            t = ns(name_of_str("chain"), || {
+                let t = t.clone();
+                thunk(NameChoice::Nominal(name_of_usize(i)), prog_pt!("link"),
+                      Rc::new(Box::new(|t:Art<usize>, ()| force(&t) + 1)), t, ())
+            });
+        }
+        (c, t)
+    }
+
+    #[test]
+    fn filters () {
+        manage::init_dcg();
+        let (c, t) = chain();
+        assert_eq!(force(&t), 10);
+        let all = manage::dot_of_dcg(&DotFilter::default()).unwrap();
+        assert_eq!(edges(&all), 10);
+        let around = DotFilter{ around:Some((name_of_usize(5), 1)), ..DotFilter::default() };
+        assert_eq!(edges(&manage::dot_of_dcg(&around).unwrap()), 2);
+        let capped = DotFilter{ max_edges:Some(3), ..DotFilter::default() };
+        let dot = manage::dot_of_dcg(&capped).unwrap();
+        assert_eq!(edges(&dot), 3);
+        assert!(dot.contains("truncated: 7 more edges"));
+        let clustered = DotFilter{ cluster_namespaces:true, ..DotFilter::default() };
+        assert_eq!(manage::dot_of_dcg(&clustered).unwrap().matches("subgraph cluster_").count(), 2);
+        // Only the dirty observations remain.
+        set(&c, 1);
+        let dirty = DotFilter{ hide_clean_observes:true, ..DotFilter::default() };
+        assert_eq!(edges(&manage::dot_of_dcg(&dirty).unwrap()), 10);
+        assert_eq!(force(&t), 11);
+        assert_eq!(edges(&manage::dot_of_dcg(&dirty).unwrap()), 0);
+    }
+}
+
+mod engine_invariant {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn rechecked_after_edits () {
+        manage::init_dcg();
+        let lo : Art<usize> = cell(name_of_str("lo"), 1);
+        let hi : Art<usize> = cell(name_of_str("hi"), 5);
+        let inv = { let (lo, hi) = (lo.clone(), hi.clone());
+                    invariant(name_of_str("ordered"), move || force(&lo) <= force(&hi)) };
+        let sum = { let (lo, hi) = (lo.clone(), hi.clone());
+                    thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+                          Rc::new(Box::new(|(lo, hi):(Art<usize>, Art<usize>), ()| force(&lo) + force(&hi))),
+                          (lo, hi), ()) };
+        assert!(force(&inv));
+        let seen = Rc::new(RefCell::new(vec![]));
+        { let seen = seen.clone();
+          let _ = manage::set_invariant_hook(Some(Rc::new(move |v:&InvariantViolation| seen.borrow_mut().push(v.name.clone())))); }
+        // No edits: no re-check.
+        assert_eq!(force(&sum), 6);
+        assert!(manage::take_invariant_violations().is_empty());
+        // An edit that breaks the invariant; the next force re-checks it.
+        set(&lo, 7);
+        assert_eq!(force(&sum), 12);
+        let vs = manage::take_invariant_violations();
+        assert_eq!(vs.len(), 1);
+        assert_eq!(vs[0].name, name_of_str("ordered"));
+        assert_eq!(vs[0].edits.len(), 1);
+        assert_eq!(vs[0].observed.len(), 2);
+        assert!(vs[0].explain().contains("invariant \"ordered\" failed"));
+        assert_eq!(*seen.borrow(), vec![name_of_str("ordered")]);
+        // Repair it.
+        set(&hi, 9);
+        assert_eq!(manage::check_invariants(), Some(0));
+        assert!(manage::take_invariant_violations().is_empty());
+    }
+}
+
+mod engine_type_registry {
+    use std::rc::Rc;
+    use std::any::Any;
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+
+    #[derive(Debug,Clone,Hash,PartialEq,Eq)]
+    struct Secret(usize);
+
+    #[test]
+    fn renders_registered_types () {
+        manage::init_dcg();
+        let c : Art<Secret> = cell(name_of_str("c"), Secret(7));
+        let t : Art<usize> = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                                   Rc::new(Box::new(|c:Art<Secret>, ()| force(&c).0)), c.clone(), ());
+        assert_eq!(force(&t), 7);
+        let v : &Any = &Secret(7);
+        assert_eq!(render_any(v), None);
+        assert!(!manage::dot_of_dcg(&DotFilter::default()).unwrap().contains("hidden"));
+        assert!(register_type_with::<Secret,_,_>(
+            |_| "hidden".to_string(),
+            |_| reflect::Val::Const(reflect::Const::String("hidden".to_string()))).is_none());
+        assert_eq!(render_any(v), Some("hidden".to_string()));
+        assert_eq!(type_renderer::<Secret>().unwrap().type_name, ::std::any::type_name::<Secret>());
+        // The reflected DCG, and its dot output, use the renderer.
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let hidden = reflect::Val::Const(reflect::Const::String("hidden".to_string()));
+        assert!(dcg.table.values().any(|node| match *node {
+            reflect::Node::Ref(ref nd) => nd.value == hidden,
+            _ => false,
+        }));
+        assert!(manage::dot_of_dcg(&DotFilter::default()).unwrap().contains("\\nhidden\"]"));
+        // Re-registering replaces the renderer.
+        assert!(register_type::<Secret>().is_some());
+        assert_eq!(render_any(v), Some("Secret(7)".to_string()));
+    }
+}
+
+mod engine_loc_intern {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn reallocations_hit () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|c:Art<usize>, ()| {
+                          let d = ns(name_of_str("inner"), || cell(name_of_str("d"), force(&c) * 2));
+                          force(&d)
+                      })), c.clone(), ());
+        assert_eq!(force(&t), 2);
+        let stats = manage::loc_intern_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (0, 3));
+        // Each re-evaluation re-allocates `d`, in the same namespace.
+        for i in 2..5 {
+            set(&c, i);
+            assert_eq!(force(&t), i * 2);
+        }
+        let stats = manage::loc_intern_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+        // Everything is still in use.
+        assert_eq!(manage::loc_intern_gc(), Some(0));
+        manage::clear();
+        assert_eq!(manage::loc_intern_stats().unwrap().misses, 3);
+        let _ = cell(name_of_str("c"), 1);
+        assert_eq!(manage::loc_intern_stats().unwrap().misses, 4);
+    }
+
+    #[test]
+    fn gc_reclaims_unused () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let sum = |n:usize| thunk(NameChoice::Structural, prog_pt!("plus"),
+                                  Rc::new(Box::new(|(c, n):(Art<usize>, usize), ()| force(&c) + n)), (c.clone(), n), ());
+        for n in 0..10 { assert_eq!(force(&sum(n)), n + 1) }
+        assert_eq!(manage::trim_ns(vec![], TrimPolicy::DropAll), Some(11));
+        drop(c);
+        assert_eq!(manage::loc_intern_gc(), Some(11));
+        assert_eq!(manage::loc_intern_stats().unwrap().reclaimed, 11);
+    }
+}
+
+mod engine_builder {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    #[test]
+    fn declared_inputs () {
+        let mut b = EngineBuilder::new().with_capacity(16);
+        let x : Input<usize> = b.declare_cell(name_of_str("x"), 1);
+        let y : Input<usize> = b.declare_cell(name_of_str("y"), 2);
+        let (engine, inputs) = b.build();
+        assert_eq!(inputs.declared(), vec![(name_of_str("x"), "usize"), (name_of_str("y"), "usize")]);
+        let _ = manage::use_engine(engine);
+        let sum = thunk(NameChoice::Nominal(name_of_str("sum")), prog_pt!("sum"),
+                        Rc::new(Box::new(|(x, y):(Art<usize>, Art<usize>), ()| force(&x) + force(&y))),
+                        (inputs.get(&x), inputs.get(&y)), ());
+        assert_eq!(force(&sum), 3);
+        inputs.set(&y, 5);
+        assert_eq!(force(&sum), 6);
+        assert_eq!(inputs.get(&x), inputs.get(&x.clone()));
+    }
+
+    #[test]
+    #[should_panic(expected = "already declared")]
+    fn duplicate_names () {
+        let mut b = EngineBuilder::new();
+        let _ : Input<usize> = b.declare_cell(name_of_str("x"), 1);
+        let _ : Input<bool> = b.declare_cell(name_of_str("x"), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "from another EngineBuilder")]
+    fn foreign_handles () {
+        let mut a = EngineBuilder::new();
+        let x : Input<usize> = a.declare_cell(name_of_str("x"), 1);
+        let (_, inputs) = EngineBuilder::new().build();
+        let _ = inputs.get(&x);
+    }
+}
+
+mod engine_dedup_observes {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+
+    /// The number of DCG edges of the thunk named `n`.
+    fn edges (n:&'static str) -> usize {
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        dcg.table.iter().filter(|&(loc, _)| loc.name == name_of_str(n))
+            .map(|(_, node)| reflect::succs_of_node(node).unwrap().len()).sum()
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn repeated_forces () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|c:Art<usize>, ()| (0..10000).map(|_| force(&c)).sum::<usize>())),
+                      c.clone(), ());
+        let _ = manage::reset_counters();
+        assert_eq!(force(&t), 10000);
+        assert_eq!(edges("t"), 1);
+        assert_eq!(manage::counters().unwrap().dup_observes, 9999);
+        set(&c, 2);
+        assert_eq!(force(&t), 20000);
+        assert_eq!(edges("t"), 1);
+    }
+
+    #[test]
+    fn mapped_forces_are_subsumed () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 1);
+        // A mapped observation, then an observation of the entire value.
+        let t = thunk(NameChoice::Nominal(name_of_str("t")), prog_pt!("t"),
+                      Rc::new(Box::new(|c:Art<usize>, ()| {
+                          let parity = force_map(&c, |_, x| x % 2);
+                          let more = (0..100).map(|_| force_map(&c, |_, x| x % 2)).sum::<usize>();
+                          parity + more + force(&c)
+                      })),
+                      c.clone(), ());
+        assert_eq!(force(&t), 1 + 100 + 1);
+        assert_eq!(edges("t"), 1);
+        // The parity does not change, but the value does.
+        set(&c, 3);
+        assert_eq!(force(&t), 1 + 100 + 3);
+    }
+}
+
+mod engine_reflect_namespaces {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::reflect;
+
+    #[test]
+    fn groups_by_subsystem () {
+        manage::init_dcg();
+        let (ui, db) = (name_of_str("ui"), name_of_str("db"));
+        let rows : Art<usize> = ns(db.clone(), || cell(name_of_str("rows"), 3));
+        let count = ns(db.clone(), || ns(name_of_str("stats"), || {
+            thunk(NameChoice::Structural, prog_pt!("count"),
+                  Rc::new(Box::new(|rows:Art<usize>, ()| force(&rows) * 2)), rows.clone(), ())
+        }));
+        let label = ns(ui.clone(), || {
+            thunk(NameChoice::Nominal(name_of_str("label")), prog_pt!("label"),
+                  Rc::new(Box::new(|count:Art<usize>, ()| format!("{} rows", force(&count)))), count.clone(), ())
+        });
+        assert_eq!(force(&label), "6 rows");
+        set(&rows, 4);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let groups = reflect::group_by_namespace(&dcg, 1);
+        assert_eq!(groups.keys().cloned().collect::<Vec<_>>(), vec![vec![db.clone()], vec![ui.clone()]]);
+        assert_eq!(groups[&vec![db.clone()]].len(), 2);
+        assert!(groups[&vec![db.clone()]].iter().all(|loc| loc.in_namespace(&[db.clone()])));
+        let stats = reflect::group_by_namespace(&dcg, 2);
+        let count_loc = stats[&vec![db.clone(), name_of_str("stats")]][0];
+        match count_loc.id {
+            reflect::LocId::Structural(hash) => assert_eq!(count_loc.name, name_of_hash64(hash)),
+            reflect::LocId::Nominal => panic!("expected a structural id"),
+        };
+        let sums = reflect::summarize_namespaces(&dcg, 1);
+        let db_sum = &sums[&vec![db.clone()]];
+        assert_eq!((db_sum.comp_nodes, db_sum.ref_nodes, db_sum.structural), (1, 1, 1));
+        assert_eq!((db_sum.edges, db_sum.dirty_edges), (1, 1));
+        let ui_sum = &sums[&vec![ui]];
+        assert_eq!((ui_sum.comp_nodes, ui_sum.edges, ui_sum.dirty_edges), (1, 1, 1));
+    }
+}
+
+mod engine_idle_cleaner {
+    use std::rc::Rc;
+    use adapton::macros::*;
+    use adapton::engine::*;
+
+    fn chain (c:&Art<usize>, len:usize) -> Art<usize> {
+        let mut t = c.clone();
+        for i in 0..len {
+            t = thunk(NameChoice::Nominal(name_of_usize(i)), prog_pt!("link"),
+                      Rc::new(Box::new(|t:Art<usize>, ()| force(&t) + 1)), t, ());
+        }
+        t
+    }
+
+    #[test]
+    fn cleans_in_idle_time () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 0);
+        let t = chain(&c, 10);
+        let mut cleaner = IdleCleaner::new();
+        cleaner.watch(&t);
+        assert!(cleaner.run(1000));
+        assert_eq!(force(&t), 10);
+        set(&c, 1);
+        let mut runs = 0;
+        while ! cleaner.run(3) { runs += 1 }
+        assert!(runs > 1);
+        let _ = manage::reset_counters();
+        assert_eq!(force(&t), 11);
+        assert_eq!(manage::counters().unwrap().eval, 0);
+        // Forcing changes nothing: nothing to do.
+        assert!(cleaner.run(0));
+    }
+
+    #[test]
+    fn foreground_takes_over () {
+        manage::init_dcg();
+        let c : Art<usize> = cell(name_of_str("c"), 0);
+        let t = chain(&c, 10);
+        let u = ns(name_of_str("u"), || chain(&c, 5));
+        let mut cleaner = IdleCleaner::new();
+        cleaner.watch(&t);
+        cleaner.watch(&u);
+        assert!(cleaner.run(1000));
+        set(&c, 1);
+        assert!(!cleaner.run(4));
+        // The foreground forces (and changes) while the cleaner is mid-way.
+        assert_eq!(force(&u), 6);
+        assert!(!cleaner.run(4));
+        set(&c, 2);
+        assert_eq!(force(&t), 12);
+        while ! cleaner.run(4) { }
+        assert!(cleaner.is_idle());
+        let _ = manage::reset_counters();
+        assert_eq!((force(&t), force(&u)), (12, 7));
+        assert_eq!(manage::counters().unwrap().eval, 0);
+    }
 }